@@ -59,10 +59,15 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
         },
         ("routes", Some(routes_matches)) => match routes_matches.subcommand() {
             ("list", Some(submatches)) => client.get_routes(submatches),
+            ("inspect", Some(submatches)) => client.get_route_details(submatches),
             ("set", Some(submatches)) => client.put_route_static(submatches),
             ("set-all", Some(submatches)) => client.put_routes_static(submatches),
             _ => Err(Error::UsageErr("ilp-cli help routes")),
         },
+        ("settlement", Some(settlement_matches)) => match settlement_matches.subcommand() {
+            ("reconcile", Some(submatches)) => client.reconcile_settlements(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help settlement")),
+        },
         ("settlement-engines", Some(settlement_matches)) => match settlement_matches.subcommand() {
             ("set-all", Some(submatches)) => client.put_settlement_engines(submatches),
             _ => Err(Error::UsageErr("ilp-cli help settlement-engines")),
@@ -260,13 +265,29 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /routes/detail
+    fn get_route_details(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/routes/detail", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // PUT /routes/static/:prefix
     fn put_route_static(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
+        let destinations: Vec<&str> = matches.values_of("destination").unwrap().collect();
+        // A single destination without a weight is sent as a plain username
+        let body = match static_route_value(&destinations.join(","))? {
+            serde_json::Value::String(username) => username,
+            members => members.to_string(),
+        };
         self.client
             .put(&format!("{}/routes/static/{}", self.url, args["prefix"]))
             .bearer_auth(auth)
-            .body(args["destination"].to_string())
+            .body(body)
             .send()
             .map_err(Error::SendErr)
     }
@@ -274,10 +295,14 @@ impl NodeClient<'_> {
     // PUT routes/static
     fn put_routes_static(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, route_pairs) = unflatten_pairs(matches);
+        let mut routes = HashMap::new();
+        for (prefix, destination) in route_pairs {
+            routes.insert(prefix, static_route_value(destination)?);
+        }
         self.client
             .put(&format!("{}/routes/static", self.url))
             .bearer_auth(auth)
-            .json(&route_pairs)
+            .json(&routes)
             .send()
             .map_err(Error::SendErr)
     }
@@ -285,14 +310,45 @@ impl NodeClient<'_> {
     // PUT /settlement/engines
     fn put_settlement_engines(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, engine_pairs) = unflatten_pairs(matches);
+        let mut tokens = HashMap::new();
+        if let Some(token_matches) = matches.values_of("token") {
+            let halves: Vec<&str> = token_matches.collect();
+            for pair in halves.windows(2).step_by(2) {
+                tokens.insert(pair[0], pair[1]);
+            }
+        }
+        // Engines with a token are sent as objects, the others as plain URLs
+        let engines: HashMap<&str, serde_json::Value> = engine_pairs
+            .into_iter()
+            .map(|(asset_code, url)| match tokens.get(asset_code) {
+                Some(token) => (
+                    asset_code,
+                    serde_json::json!({ "url": url, "auth_token": token }),
+                ),
+                None => (asset_code, serde_json::Value::from(url)),
+            })
+            .collect();
         self.client
             .put(&format!("{}/settlement/engines", self.url))
             .bearer_auth(auth)
-            .json(&engine_pairs)
+            .json(&engines)
             .send()
             .map_err(Error::SendErr)
     }
 
+    // GET /settlement/reconciliation
+    // POST /settlement/reconciliation
+    fn reconcile_settlements(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        let url = format!("{}/settlement/reconciliation", self.url);
+        let request = if matches.is_present("apply") {
+            self.client.post(&url)
+        } else {
+            self.client.get(&url)
+        };
+        request.bearer_auth(auth).send().map_err(Error::SendErr)
+    }
+
     // PUT /tracing-level
     fn put_tracing_level(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
@@ -379,6 +435,27 @@ fn extract_args<'a>(matches: &'a ArgMatches) -> (&'a str, HashMap<&'a str, &'a s
     (auth, args)
 }
 
+/// Converts the destination of a static route, given as comma-separated usernames
+/// optionally followed by `:weight` (e.g. "alice:2,bob"), to its JSON representation
+fn static_route_value(destination: &str) -> Result<serde_json::Value, Error> {
+    if !destination.contains(&[',', ':'][..]) {
+        return Ok(serde_json::Value::from(destination));
+    }
+    let mut members = Vec::new();
+    for member in destination.split(',') {
+        let mut parts = member.splitn(2, ':');
+        let username = parts.next().unwrap_or_default();
+        let weight: u32 = match parts.next() {
+            Some(weight) => weight
+                .parse()
+                .map_err(|_| Error::UsageErr("ilp-cli help routes"))?,
+            None => 1,
+        };
+        members.push(serde_json::json!({ "username": username, "weight": weight }));
+    }
+    Ok(serde_json::Value::from(members))
+}
+
 fn unflatten_pairs<'a>(matches: &'a ArgMatches) -> (&'a str, HashMap<&'a str, &'a str>) {
     let mut pairs = HashMap::new();
     if let Some(halve_matches) = matches.values_of("halve") {
@@ -164,10 +164,18 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn routes_inspect() {
+        should_parse(&[
+            "ilp-cli routes inspect --auth foo", // minimal
+        ]);
+    }
+
     #[test]
     fn routes_set() {
         should_parse(&[
             "ilp-cli routes set foo --destination bar --auth baz", // minimal
+            "ilp-cli routes set foo --destination bar:2 --destination qux --auth baz", // multipath
         ]);
     }
 
@@ -177,16 +185,26 @@ mod interface_tests {
             "ilp-cli routes set-all --auth foo", // minimal
             "ilp-cli routes set-all --auth foo --pair bar qux", // one
             "ilp-cli routes set-all --auth foo --pair bar qux --pair baz qaz", // two
+            "ilp-cli routes set-all --auth foo --pair bar qux:2,qaz", // multipath
             "ilp-cli routes set-all --auth foo --pair bar qux --pair baz qaz --pair spam eggs --pair foobar foobaz", // many
         ])
     }
 
+    #[test]
+    fn settlement_reconcile() {
+        should_parse(&[
+            "ilp-cli settlement reconcile --auth foo", // minimal
+            "ilp-cli settlement reconcile --auth foo --apply", // apply corrections
+        ]);
+    }
+
     #[test]
     fn settlement_engines_set_all() {
         should_parse(&[
             "ilp-cli settlement-engines set-all --auth foo", // minimal
             "ilp-cli settlement-engines set-all --auth foo --pair ABC bar", // one
             "ilp-cli settlement-engines set-all --auth foo --pair ABC bar --pair DEF qux", // two
+            "ilp-cli settlement-engines set-all --auth foo --pair ABC bar --token ABC secret", // authenticated
             "ilp-cli settlement-engines set-all --auth foo --pair ABC bar --pair DEF qux --pair GHI baz --pair JKL qaz --pair MNO spam --pair PQR ham --pair STU eggs", // many
         ]);
     }
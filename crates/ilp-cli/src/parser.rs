@@ -14,7 +14,13 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
         ]),
         pay(),
         rates().subcommands(vec![rates_list(), rates_set_all()]),
-        routes().subcommands(vec![routes_list(), routes_set(), routes_set_all()]),
+        routes().subcommands(vec![
+            routes_list(),
+            routes_inspect(),
+            routes_set(),
+            routes_set_all(),
+        ]),
+        settlement().subcommands(vec![settlement_reconcile()]),
         settlement_engines().subcommands(vec![settlement_engines_set_all()]),
         status(),
         logs(),
@@ -350,6 +356,11 @@ fn routes_list<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("list").about("View this node's routing table")
 }
 
+fn routes_inspect<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("inspect")
+        .about("View all the routes this node has for each prefix and the routing tables received from its peers")
+}
+
 fn routes_set<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("set")
         .about("Configure a single static route on this node")
@@ -363,7 +374,9 @@ fn routes_set<'a, 'b>() -> App<'a, 'b> {
                 .long("destination")
                 .takes_value(true)
                 .required(true)
-                .help("The destination to associate with the provided prefix"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("The destination to associate with the provided prefix, optionally followed by :weight; may appear multiple times to spread the packets across several destinations"),
         ])
 }
 
@@ -375,7 +388,21 @@ fn routes_set_all<'a, 'b>() -> App<'a, 'b> {
                 .long("pair")
                 .number_of_values(2)
                 .multiple(true)
-                .help("A set of space-separated key/value pairs, representing a route and its destination; may appear multiple times"),
+                .help("A set of space-separated key/value pairs, representing a route and its destination; may appear multiple times. The destination may list several comma-separated usernames, each optionally followed by :weight"),
+        )
+}
+
+fn settlement<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("settlement").about("Operations for interacting with settlements")
+}
+
+fn settlement_reconcile<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reconcile")
+        .about("Compare the settlements recorded by this node with the ones reported by the settlement engines")
+        .arg(
+            Arg::with_name("apply")
+                .long("apply")
+                .help("Credit the missed incoming settlements and refund the outgoing settlements which were never sent"),
         )
 }
 
@@ -394,6 +421,13 @@ fn settlement_engines_set_all<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .help("A set of space-separated key/value pairs, representing an asset code and a settlement engine; may appear multiple times"),
         )
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .number_of_values(2)
+                .multiple(true)
+                .help("A set of space-separated key/value pairs, representing an asset code and the token authenticating the requests to and from its settlement engine; may appear multiple times"),
+        )
 }
 
 fn logs<'a, 'b>() -> App<'a, 'b> {
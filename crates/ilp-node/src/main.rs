@@ -87,6 +87,10 @@ async fn main() {
             .long("settlement_api_bind_address")
             .takes_value(true)
             .help("IP address and port to listen for the Settlement Engine API"),
+        Arg::with_name("settlement_api_auth_token")
+            .long("settlement_api_auth_token")
+            .takes_value(true)
+            .help("Bearer token the settlement engines which were not configured with an auth_token of their own must present when calling the Settlement Engine API. If it is not set, those engines are not authenticated."),
         Arg::with_name("default_spsp_account")
             .long("default_spsp_account")
             .takes_value(true)
@@ -95,6 +99,54 @@ async fn main() {
             .long("route_broadcast_interval")
             .takes_value(true)
             .help("Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("min_route_update_interval")
+            .long("min_route_update_interval")
+            .takes_value(true)
+            .help("Minimum interval, defined in milliseconds, between the route updates the node broadcasts to each other node using CCP. Only has an effect if it is larger than route_broadcast_interval. Defaults to no minimum."),
+        Arg::with_name("max_route_update_size")
+            .long("max_route_update_size")
+            .takes_value(true)
+            .help("Maximum size, defined in bytes, of the packets the node sends route updates in using CCP. Larger updates are split into several packets. Defaults to 32767 bytes."),
+        Arg::with_name("route_scoring.path_length")
+            .long("route_scoring.path_length")
+            .takes_value(true)
+            .help("Cost of each hop in the path of the routes received from other nodes. Defaults to 1."),
+        Arg::with_name("route_scoring.fee")
+            .long("route_scoring.fee")
+            .takes_value(true)
+            .help("Cost of each percent of fee advertised for the routes received from other nodes. Defaults to 0."),
+        Arg::with_name("route_scoring.latency")
+            .long("route_scoring.latency")
+            .takes_value(true)
+            .help("Cost of each millisecond of latency of the routes received from other nodes, \
+                including the latency measured to the node they were received from. Defaults to 0."),
+        Arg::with_name("route_scoring.liquidity")
+            .long("route_scoring.liquidity")
+            .takes_value(true)
+            .help("Cost saved for each order of magnitude of liquidity advertised for the routes received from other nodes. Defaults to 0."),
+        Arg::with_name("route_failover.max_retries")
+            .long("route_failover.max_retries")
+            .takes_value(true)
+            .help("Number of alternate next hops a packet rejected with a temporary (T-class) error is retried on, as long as it has not expired. \
+                Defaults to 0, in which case packets are never retried."),
+        Arg::with_name("parent_failover.primary_parent")
+            .long("parent_failover.primary_parent")
+            .takes_value(true)
+            .help("Username of the parent account the node prefers to get its address from when it has several parents. \
+                If it is set, the node sends an ILDCP request to each of its parents on an interval and switches to the address assigned by \
+                another parent if the one it got its address from stops answering."),
+        Arg::with_name("parent_failover.check_interval")
+            .long("parent_failover.check_interval")
+            .default_value("30000")
+            .help("Interval, defined in milliseconds, on which the node sends an ILDCP request to each of its parents."),
+        Arg::with_name("parent_failover.max_failures")
+            .long("parent_failover.max_failures")
+            .default_value("3")
+            .help("Number of consecutive ILDCP requests the parent the node got its address from may fail before the node switches to another parent."),
+        Arg::with_name("parent_failover.failback_checks")
+            .long("parent_failover.failback_checks")
+            .default_value("3")
+            .help("Number of consecutive ILDCP requests a parent the node prefers over the one it got its address from must answer before the node switches back to it."),
         Arg::with_name("exchange_rate.provider")
             .long("exchange_rate.provider")
             .takes_value(true)
@@ -113,6 +165,29 @@ async fn main() {
                 For example, take an incoming packet with an amount of 100. If the \
                 exchange rate is 1:0.5 and the spread is 0.01, the amount on the \
                     outgoing packet would be 198 (instead of 200 without the spread)."),
+        Arg::with_name("exchange_rate.max_rate_age")
+            .long("exchange_rate.max_rate_age")
+            .takes_value(true)
+            .help("Maximum age, defined in milliseconds, of the exchange rates used to convert packets. \
+                Cross-currency packets are rejected if either asset's rate was updated longer ago than this. \
+                If this is not set, rates never expire."),
+        Arg::with_name("exchange_rate.track_remainders")
+            .long("exchange_rate.track_remainders")
+            .takes_value(true)
+            .help("Whether to accumulate the fractions of a unit truncated when converting packets between each pair of accounts \
+                and add them to the packets forwarded between those accounts once they add up to whole units. Defaults to false."),
+        Arg::with_name("revenue.reporting_asset")
+            .long("revenue.reporting_asset")
+            .default_value("USD")
+            .help("Asset the fees earned on forwarded packets are reported in, in addition to the asset they were earned in."),
+        Arg::with_name("revenue.reporting_scale")
+            .long("revenue.reporting_scale")
+            .default_value("9")
+            .help("Scale of the revenue amounts reported in revenue.reporting_asset."),
+        Arg::with_name("revenue.bucket_interval")
+            .long("revenue.bucket_interval")
+            .default_value("3600000")
+            .help("Length, defined in milliseconds, of the periods the revenue report is broken down into."),
         Arg::with_name("prometheus.bind_address")
             .long("prometheus.bind_address")
             .takes_value(true)
@@ -6,7 +6,7 @@ use crate::instrumentation::google_pubsub::{create_google_pubsub_wrapper, Pubsub
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
         use interledger::errors::ApiError;
-        use secrecy::{ExposeSecret, SecretString};
+        use secrecy::ExposeSecret;
         use tracing::debug_span;
         use tracing_appender::non_blocking::NonBlocking;
         use tracing_futures::Instrument;
@@ -33,29 +33,37 @@ use bytes::Bytes;
 use futures::TryFutureExt;
 use hex::FromHex;
 use interledger::{
-    api::{NodeApi, NodeStore},
+    api::{NodeApi, NodeStore, ParentFailover},
     btp::{btp_service_as_filter, connect_client, BtpOutgoingService, BtpStore},
-    ccp::{CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
+    ccp::{
+        CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RouteDampening,
+        RouteDetailsStore, RoutePolicyStore, RouteScoring, RoutingRelation,
+    },
     errors::*,
     http::{HttpClientService, HttpServer as IlpOverHttpServer, HttpStore},
     ildcp::IldcpService,
     packet::Address,
     packet::{ErrorCode, RejectBuilder},
-    rates::{ExchangeRateFetcher, ExchangeRateStore},
+    rates::{
+        AggregationConfig, ExchangeRateFetcher, ExchangeRateStore, ExchangeRateSubscriptionStore,
+        Rate, RateHistoryStore, RateSource,
+    },
     router::{Router, RouterStore},
     service::{
         outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore, OutgoingRequest,
         Username,
     },
     service_util::{
-        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
-        MaxPacketAmountService, RateLimitService, RateLimitStore, ValidatorService,
+        BalanceAlertStore, BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
+        FeeStore, MaxPacketAmountService, RateLimitService, RateLimitStore, RemainderStore,
+        RevenueStore, RevenueTracker, ValidatorService,
     },
     settlement::{
-        api::{create_settlements_filter, SettlementMessageService},
+        api::{create_authenticated_settlements_filter, SettlementMessageService},
         core::{
             idempotency::IdempotentStore,
-            types::{LeftoversStore, SettlementStore},
+            reconciliation::SettlementJournalStore,
+            types::{LeftoversStore, SettlementQueueStore, SettlementStore},
         },
     },
     store::account::Account,
@@ -63,8 +71,11 @@ use interledger::{
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
+use ring::hmac;
+use secrecy::SecretString;
 use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
 use std::{
+    collections::HashMap,
     convert::TryFrom,
     net::SocketAddr,
     str::{self, FromStr},
@@ -79,12 +90,13 @@ use warp::{self, Filter};
 #[cfg(feature = "redis")]
 use crate::redis_store::*;
 #[cfg(feature = "balance-tracking")]
-use interledger::service_util::BalanceService;
+use interledger::service_util::{BalanceAlerts, BalanceService};
 
 #[doc(hidden)]
 pub use interledger::rates::ExchangeRateProvider;
 
 static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
+static ROUTE_AUTH_SECRET_GENERATION_STRING: &str = "ilp_route_auth_secret";
 
 fn default_settlement_api_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7771))
@@ -128,6 +140,38 @@ where
     })
 }
 
+fn deserialize_route_auth_anchors<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, [u8; 32]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(prefix, anchor)| {
+            <[u8; 32]>::from_hex(anchor)
+                .map(|anchor| (prefix, anchor))
+                .map_err(|err| {
+                    DeserializeError::custom(format!(
+                        "Invalid route auth anchor (must be 32 hex-encoded bytes): {:?}",
+                        err
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Derives the secret the route for the node's address is authenticated with
+fn generate_route_auth_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
+    let mut route_auth_secret: [u8; 32] = [0; 32];
+    let sig = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        ROUTE_AUTH_SECRET_GENERATION_STRING.as_bytes(),
+    );
+    route_auth_secret.copy_from_slice(sig.as_ref());
+    route_auth_secret
+}
+
 fn deserialize_optional_username<'de, D>(deserializer: D) -> Result<Option<Username>, D::Error>
 where
     D: Deserializer<'de>,
@@ -148,8 +192,9 @@ pub struct ExchangeRateConfig {
     /// Defaults to 60000ms (60 seconds).
     #[serde(default = "ExchangeRateConfig::default_poll_interval")]
     pub poll_interval: u64,
-    /// The number of consecutive failed polls to the exchange rate provider
-    /// that the connector will tolerate before invalidating the exchange rate cache.
+    /// The number of consecutive failed polls to the exchange rate `provider`
+    /// that the connector will tolerate before invalidating its rates.
+    /// Each of the `sources` has its own tolerance instead.
     #[serde(default = "ExchangeRateConfig::default_poll_failure_tolerance")]
     pub poll_failure_tolerance: u32,
     /// API to poll for exchange rates. Currently the supported options are:
@@ -159,14 +204,35 @@ pub struct ExchangeRateConfig {
     /// instead use the rates configured via the HTTP API.
     #[serde(default)]
     pub provider: Option<ExchangeRateProvider>,
+    /// Additional exchange rate APIs to poll concurrently with the `provider`.
+    /// Their prices are combined according to the `aggregation` settings.
+    #[serde(default)]
+    pub sources: Vec<RateSource>,
+    /// How the prices of the `provider` and the `sources` are combined
+    /// and which outliers are dropped.
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
     /// Spread, as a fraction, to add on top of the exchange rate.
     /// This amount is kept as the node operator's profit, or may cover
     /// fluctuations in exchange rates.
     /// For example, take an incoming packet with an amount of 100. If the
     /// exchange rate is 1:2 and the spread is 0.01, the amount on the
     /// outgoing packet would be 198 (instead of 200 without the spread).
+    /// It may be given as a number or, to avoid any loss of precision, as a
+    /// string containing a decimal or a fraction.
     #[serde(default)]
-    pub spread: f64,
+    pub spread: Rate,
+    /// Maximum age, defined in milliseconds, of the rates used to convert
+    /// cross-currency packets. Packets whose conversion would rely on an older
+    /// rate are rejected. If this value is not set, rates never expire.
+    #[serde(default)]
+    pub max_rate_age: Option<u64>,
+    /// Whether to accumulate the fractions of a unit truncated when converting
+    /// packets between each pair of accounts, and add them to the packets
+    /// forwarded between those accounts once they add up to whole units.
+    /// Defaults to false, in which case they are kept by the node.
+    #[serde(default)]
+    pub track_remainders: bool,
 }
 
 impl ExchangeRateConfig {
@@ -178,6 +244,142 @@ impl ExchangeRateConfig {
     }
 }
 
+/// Configuration for the alerts raised when account balances cross their thresholds.
+/// The thresholds themselves are configured per account.
+#[derive(Deserialize, Clone)]
+pub struct BalanceAlertConfig {
+    /// URL to which every alert is POSTed as JSON. If it is not set,
+    /// alerts are only logged, counted in the metrics and saved to the store.
+    #[serde(default)]
+    pub webhook_url: Option<Url>,
+    /// Interval, defined in milliseconds, on which the node checks whether account
+    /// balances have stayed above their settle threshold for too long.
+    /// Defaults to 10000ms (10 seconds).
+    #[serde(default = "BalanceAlertConfig::default_check_interval")]
+    pub check_interval: u64,
+}
+
+impl BalanceAlertConfig {
+    fn default_check_interval() -> u64 {
+        10_000
+    }
+}
+
+impl Default for BalanceAlertConfig {
+    fn default() -> Self {
+        BalanceAlertConfig {
+            webhook_url: None,
+            check_interval: BalanceAlertConfig::default_check_interval(),
+        }
+    }
+}
+
+/// Configuration for the accounting of the fees the node earns on the packets it forwards.
+#[derive(Deserialize, Clone)]
+pub struct RevenueConfig {
+    /// Asset the revenue is reported in, in addition to the asset it was earned in.
+    /// Defaults to USD.
+    #[serde(default = "RevenueConfig::default_reporting_asset")]
+    pub reporting_asset: String,
+    /// Scale of the amounts reported in the `reporting_asset`. Defaults to 9.
+    #[serde(default = "RevenueConfig::default_reporting_scale")]
+    pub reporting_scale: u8,
+    /// Length, defined in milliseconds, of the periods the revenue is broken down into.
+    /// Defaults to 3600000ms (1 hour).
+    #[serde(default = "RevenueConfig::default_bucket_interval")]
+    pub bucket_interval: u64,
+    /// Time, defined in milliseconds, for which the revenue of a period is kept.
+    /// Defaults to 2592000000ms (30 days).
+    #[serde(default = "RevenueConfig::default_retention")]
+    pub retention: u64,
+}
+
+impl RevenueConfig {
+    fn default_reporting_asset() -> String {
+        "USD".to_string()
+    }
+    fn default_reporting_scale() -> u8 {
+        9
+    }
+    fn default_bucket_interval() -> u64 {
+        3_600_000
+    }
+    fn default_retention() -> u64 {
+        2_592_000_000
+    }
+}
+
+impl Default for RevenueConfig {
+    fn default() -> Self {
+        RevenueConfig {
+            reporting_asset: RevenueConfig::default_reporting_asset(),
+            reporting_scale: RevenueConfig::default_reporting_scale(),
+            bucket_interval: RevenueConfig::default_bucket_interval(),
+            retention: RevenueConfig::default_retention(),
+        }
+    }
+}
+
+/// Configuration for retrying the packets rejected by their next hop
+/// on the alternate next hops the node knows for their destination.
+#[derive(Deserialize, Clone, Default)]
+pub struct RouteFailoverConfig {
+    /// Number of alternate next hops a packet rejected with a temporary (T-class)
+    /// error is retried on, as long as it has not expired.
+    /// Defaults to 0, in which case packets are never retried.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Number of retries for the packets whose destination starts with each prefix,
+    /// instead of `max_retries`. The longest matching prefix is used.
+    #[serde(default)]
+    pub prefix_max_retries: HashMap<String, u32>,
+}
+
+/// Configuration for getting the node's address from another of its parents
+/// when the parent it got it from becomes unreachable.
+#[derive(Deserialize, Clone)]
+pub struct ParentFailoverConfig {
+    /// Username of the parent account the node prefers to get its address from.
+    /// The node only checks whether its parents are reachable if it is set.
+    #[serde(default, deserialize_with = "deserialize_optional_username")]
+    pub primary_parent: Option<Username>,
+    /// Interval, defined in milliseconds, on which the node sends an ILDCP request
+    /// to each of its parents. Defaults to 30000ms (30 seconds).
+    #[serde(default = "ParentFailoverConfig::default_check_interval")]
+    pub check_interval: u64,
+    /// Number of consecutive ILDCP requests the parent the node got its address from
+    /// may fail before the node switches to another parent. Defaults to 3.
+    #[serde(default = "ParentFailoverConfig::default_max_failures")]
+    pub max_failures: u32,
+    /// Number of consecutive ILDCP requests a parent the node prefers over the one it got
+    /// its address from must answer before the node switches back to it. Defaults to 3.
+    #[serde(default = "ParentFailoverConfig::default_failback_checks")]
+    pub failback_checks: u32,
+}
+
+impl ParentFailoverConfig {
+    fn default_check_interval() -> u64 {
+        30_000
+    }
+    fn default_max_failures() -> u32 {
+        3
+    }
+    fn default_failback_checks() -> u32 {
+        3
+    }
+}
+
+impl Default for ParentFailoverConfig {
+    fn default() -> Self {
+        ParentFailoverConfig {
+            primary_parent: None,
+            check_interval: ParentFailoverConfig::default_check_interval(),
+            max_failures: ParentFailoverConfig::default_max_failures(),
+            failback_checks: ParentFailoverConfig::default_failback_checks(),
+        }
+    }
+}
+
 /// An all-in-one Interledger node that includes sender and receiver functionality,
 /// a connector, and a management API.
 /// Will connect to the database at the given URL; see the crate features defined in
@@ -207,17 +409,58 @@ pub struct InterledgerNode {
     /// IP address and port to listen for the Settlement Engine API
     #[serde(default = "default_settlement_api_bind_address")]
     pub settlement_api_bind_address: SocketAddr,
+    /// Bearer token the settlement engines which were not configured with an `auth_token`
+    /// of their own must present when calling the Settlement Engine API.
+    /// If it is not set, those engines are not authenticated.
+    #[serde(default)]
+    pub settlement_api_auth_token: Option<SecretString>,
     /// When SPSP payments are sent to the root domain, the payment pointer is resolved
     /// to <domain>/.well-known/pay. This value determines which account those payments
     /// will be sent to.
     #[serde(default, deserialize_with = "deserialize_optional_username")]
     pub default_spsp_account: Option<Username>,
+    /// Hosts of the payment pointers accounts may request quotes for payments to.
+    /// Resolving a payment pointer makes the node send a request to its host, so
+    /// only the admin may quote payments to other payment pointers.
+    #[serde(default)]
+    pub spsp_allowed_hosts: Vec<String>,
     /// Interval, defined in milliseconds, on which the node will broadcast routing
     /// information to other nodes using CCP. Defaults to 30000ms (30 seconds).
     pub route_broadcast_interval: Option<u64>,
+    /// Minimum interval, defined in milliseconds, between the route updates the node
+    /// broadcasts to each node using CCP. The updates are combined until it has passed.
+    /// Only has an effect if it is larger than the `route_broadcast_interval`.
+    pub min_route_update_interval: Option<u64>,
+    /// Maximum size, defined in bytes, of the packets the node sends route updates in
+    /// using CCP. Larger updates are split into several packets. Defaults to 32767 bytes.
+    pub max_route_update_size: Option<usize>,
+    /// Parameters of the dampening of the routes other nodes keep withdrawing and
+    /// advertising again using CCP. Routes are not dampened unless it is set.
+    #[serde(default)]
+    pub route_dampening: Option<RouteDampening>,
+    #[serde(default)]
+    /// Weights used to pick the best of the routes received from other nodes using CCP.
+    pub route_scoring: RouteScoring,
+    #[serde(default)]
+    /// Configuration for retrying rejected packets on alternate next hops.
+    pub route_failover: RouteFailoverConfig,
+    /// Anchors of the hash chains the routes received for each prefix using CCP are
+    /// authenticated against, as 32 hex-encoded bytes. The owner of a prefix logs
+    /// the anchor of the route for its address when it starts.
+    #[serde(default, deserialize_with = "deserialize_route_auth_anchors")]
+    pub route_auth_anchors: HashMap<String, [u8; 32]>,
+    #[serde(default)]
+    /// Configuration for switching to another parent when the node's parent is unreachable.
+    pub parent_failover: ParentFailoverConfig,
     #[serde(default)]
     /// Configuration for calculating exchange rates between various pairs.
     pub exchange_rate: ExchangeRateConfig,
+    #[serde(default)]
+    /// Configuration for the alerts raised when account balances cross their thresholds.
+    pub balance_alerts: BalanceAlertConfig,
+    #[serde(default)]
+    /// Configuration for the accounting of the fees earned on forwarded packets.
+    pub revenue: RevenueConfig,
     /// Configuration for [Prometheus](https://prometheus.io) metrics collection.
     /// If this configuration is not provided, the node will not collect metrics.
     /// Needs the feature flag "monitoring" to be enabled
@@ -298,14 +541,24 @@ impl InterledgerNode {
             + HttpStore<Account = Account>
             + StreamNotificationsStore<Account = Account>
             + BalanceStore
+            + BalanceAlertStore
             + SettlementStore<Account = Account>
             + ExchangeRateStore
+            + RateHistoryStore
+            + ExchangeRateSubscriptionStore
+            + FeeStore
+            + RevenueStore
+            + RemainderStore
             + BalanceStore
             + SettlementStore<Account = Account>
             + RouterStore<Account = Account>
             + CcpRoutingStore<Account = Account>
+            + RoutePolicyStore
+            + RouteDetailsStore
             + RateLimitStore<Account = Account>
             + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
+            + SettlementJournalStore
+            + SettlementQueueStore
             + IdempotentStore
             + AccountStore<Account = Account>
             + Clone
@@ -321,15 +574,37 @@ impl InterledgerNode {
         let secret_seed = Bytes::from(&self.secret_seed[..]);
         let http_bind_address = self.http_bind_address;
         let settlement_api_bind_address = self.settlement_api_bind_address;
+        let settlement_api_auth_token = self.settlement_api_auth_token.clone();
         let ilp_address_clone = ilp_address.clone();
         let ilp_address_clone2 = ilp_address.clone();
         let admin_auth_token = self.admin_auth_token.clone();
         let default_spsp_account = self.default_spsp_account.clone();
+        let spsp_allowed_hosts = self.spsp_allowed_hosts.clone();
         let route_broadcast_interval = self.route_broadcast_interval;
-        let exchange_rate_provider = self.exchange_rate.provider.clone();
+        let min_route_update_interval = self.min_route_update_interval;
+        let max_route_update_size = self.max_route_update_size;
+        let route_dampening = self.route_dampening;
+        let route_scoring = self.route_scoring;
+        let route_failover = self.route_failover.clone();
+        let route_auth_anchors = self.route_auth_anchors.clone();
+        let parent_failover = self.parent_failover.clone();
+        let route_auth_secret = generate_route_auth_secret(&self.secret_seed);
+        let exchange_rate_sources: Vec<RateSource> = self
+            .exchange_rate
+            .provider
+            .clone()
+            .map(|provider| RateSource::new(provider, self.exchange_rate.poll_failure_tolerance))
+            .into_iter()
+            .chain(self.exchange_rate.sources.iter().cloned())
+            .collect();
+        let exchange_rate_aggregation = self.exchange_rate.aggregation.clone();
         let exchange_rate_poll_interval = self.exchange_rate.poll_interval;
-        let exchange_rate_poll_failure_tolerance = self.exchange_rate.poll_failure_tolerance;
-        let exchange_rate_spread = self.exchange_rate.spread;
+        let exchange_rate_spread = self.exchange_rate.spread.clone();
+        let exchange_rate_max_age = self.exchange_rate.max_rate_age;
+        let track_remainders = self.exchange_rate.track_remainders;
+        let revenue = self.revenue.clone();
+        #[cfg(feature = "balance-tracking")]
+        let balance_alerts = self.balance_alerts.clone();
         #[cfg(feature = "google-pubsub")]
         let google_pubsub = self.google_pubsub.clone();
 
@@ -392,9 +667,35 @@ impl InterledgerNode {
         let outgoing_service =
             StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service);
         #[cfg(feature = "balance-tracking")]
-        let outgoing_service = BalanceService::new(store.clone(), outgoing_service);
-        let outgoing_service =
-            ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
+        let outgoing_service = {
+            let alerts = BalanceAlerts::new(store.clone(), balance_alerts.webhook_url);
+            alerts.spawn_overdue_checker(Duration::from_millis(balance_alerts.check_interval));
+            let balance_service =
+                BalanceService::new(store.clone(), outgoing_service).with_alerts(alerts);
+            balance_service.resume_queued_settlements();
+            balance_service
+        };
+        let mut outgoing_service = ExchangeRateService::new(
+            exchange_rate_spread.clone(),
+            store.clone(),
+            outgoing_service,
+        )
+        .with_revenue(
+            RevenueTracker::new(
+                store.clone(),
+                revenue.reporting_asset,
+                revenue.reporting_scale,
+                Duration::from_millis(revenue.bucket_interval),
+            )
+            .with_retention(Duration::from_millis(revenue.retention)),
+        );
+        if let Some(max_rate_age) = exchange_rate_max_age {
+            outgoing_service =
+                outgoing_service.with_max_rate_age(Duration::from_millis(max_rate_age));
+        }
+        if track_remainders {
+            outgoing_service = outgoing_service.with_remainder_tracking();
+        }
 
         #[cfg(feature = "google-pubsub")]
         let outgoing_service = outgoing_service.wrap(create_google_pubsub_wrapper(google_pubsub));
@@ -411,7 +712,11 @@ impl InterledgerNode {
         }
 
         // Set up the Router and Routing Manager
-        let incoming_service = Router::new(store.clone(), outgoing_service_fwd);
+        let incoming_service = route_failover.prefix_max_retries.into_iter().fold(
+            Router::new(store.clone(), outgoing_service_fwd)
+                .with_max_retries(route_failover.max_retries),
+            |router, (prefix, max_retries)| router.with_prefix_max_retries(prefix, max_retries),
+        );
 
         // Add tracing to track the outgoing request details
         #[cfg(feature = "monitoring")]
@@ -427,6 +732,20 @@ impl InterledgerNode {
         if let Some(ms) = route_broadcast_interval {
             ccp_builder.broadcast_interval(ms);
         }
+        if let Some(ms) = min_route_update_interval {
+            ccp_builder.min_update_interval(ms);
+        }
+        if let Some(bytes) = max_route_update_size {
+            ccp_builder.max_packet_size(bytes);
+        }
+        if let Some(dampening) = route_dampening {
+            ccp_builder.route_dampening(dampening);
+        }
+        ccp_builder
+            .route_scoring(route_scoring)
+            .route_fee(exchange_rate_spread.to_f64())
+            .route_auth_secret(route_auth_secret)
+            .route_auth_anchors(route_auth_anchors);
 
         let incoming_service = ccp_builder.to_service();
         let incoming_service = EchoService::new(store.clone(), incoming_service);
@@ -492,7 +811,15 @@ impl InterledgerNode {
         if let Some(username) = default_spsp_account {
             api.default_spsp_account(username);
         }
+        if let Some(username) = parent_failover.primary_parent.clone() {
+            api.primary_parent(username);
+        }
         api.node_version(env!("CARGO_PKG_VERSION").to_string());
+        api.exchange_rate_spread(exchange_rate_spread);
+        api.spsp_allowed_hosts(spsp_allowed_hosts);
+        if let Some(max_rate_age) = exchange_rate_max_age {
+            api.max_rate_age(Duration::from_millis(max_rate_age));
+        }
 
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
@@ -588,17 +915,34 @@ impl InterledgerNode {
         spawn(warp::serve(api).bind(http_bind_address));
 
         // Settlement API
-        let settlement_api = create_settlements_filter(store.clone(), outgoing_service.clone());
+        let settlement_api = create_authenticated_settlements_filter(
+            store.clone(),
+            outgoing_service.clone(),
+            settlement_api_auth_token,
+        );
         info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
         spawn(warp::serve(settlement_api).bind(settlement_api_bind_address));
 
+        // Parent Failover
+        if let Some(username) = parent_failover.primary_parent {
+            let mut failover = ParentFailover::new(outgoing_service.clone(), store.clone());
+            failover
+                .primary_parent(username)
+                .max_failures(parent_failover.max_failures)
+                .failback_checks(parent_failover.failback_checks);
+            failover.spawn_interval(Duration::from_millis(parent_failover.check_interval));
+        }
+
         // Exchange Rate Polling
-        if let Some(provider) = exchange_rate_provider {
-            let exchange_rate_fetcher = ExchangeRateFetcher::new(
-                provider,
-                exchange_rate_poll_failure_tolerance,
+        if !exchange_rate_sources.is_empty() {
+            let exchange_rate_fetcher = ExchangeRateFetcher::with_sources(
+                exchange_rate_sources,
+                exchange_rate_aggregation,
                 store.clone(),
-            );
+            )
+            .map_err(
+                |err| error!(target: "interledger-node", "Invalid exchange rate sources: {}", err),
+            )?;
             exchange_rate_fetcher
                 .spawn_interval(Duration::from_millis(exchange_rate_poll_interval));
         } else {
@@ -4,9 +4,11 @@ use ilp_node::InterledgerNode;
 use reqwest::Client;
 use secrecy::SecretString;
 use serde_json::{self, json, Value};
+use std::collections::HashMap;
 use std::env;
 use std::time::Duration;
 use tracing::error;
+use warp::Filter;
 
 #[tokio::test]
 async fn coincap() {
@@ -43,8 +45,8 @@ async fn coincap() {
     let obj: Value = serde_json::from_str(&txt).unwrap();
 
     assert_eq!(
-        format!("{}", obj.get("USD").expect("Should have USD rate")).as_str(),
-        "1.0"
+        obj.get("USD").expect("Should have USD rate")["rate"].as_str(),
+        Some("1")
     );
     assert!(obj.get("EUR").is_some());
     assert!(obj.get("JPY").is_some());
@@ -99,10 +101,73 @@ async fn cryptocompare() {
     let obj: Value = serde_json::from_str(&txt).unwrap();
 
     assert_eq!(
-        format!("{}", obj.get("USD").expect("Should have USD rate")).as_str(),
-        "1.0"
+        obj.get("USD").expect("Should have USD rate")["rate"].as_str(),
+        Some("1")
     );
     assert!(obj.get("BTC").is_some());
     assert!(obj.get("ETH").is_some());
     assert!(obj.get("XRP").is_some());
 }
+
+#[tokio::test]
+async fn custom_provider() {
+    let context = TestContext::new();
+
+    // Local stub of a price feed quoting rates in EUR
+    let feed_port = get_open_port(None);
+    let feed = warp::path("prices")
+        .and(warp::header::exact("x-api-key", "secret"))
+        .map(|| warp::reply::json(&json!({"rates": {"usd": "1.25", "JPY": 120}})));
+    tokio::spawn(warp::serve(feed).bind(([127, 0, 0, 1], feed_port)));
+
+    let http_port = get_open_port(None);
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.one",
+        "default_spsp_account": "one",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "exchange_rate": {
+            "poll_interval": 100,
+            "provider": {
+                "custom": {
+                    "url": format!("http://127.0.0.1:{}/prices", feed_port),
+                    "auth_header_name": "X-Api-Key",
+                    "auth_header": "secret",
+                    "records": "$.rates.*",
+                    "price": "$",
+                    "base_currency": "EUR",
+                    "inverse": true,
+                }
+            },
+        },
+    }))
+    .unwrap();
+    node.serve(None).await.unwrap();
+
+    // Wait so our node can poll the feed
+    tokio::time::delay_for(Duration::from_millis(500)).await;
+
+    let ret = Client::new()
+        .get(&format!("http://localhost:{}/rates", http_port))
+        .send()
+        .await
+        .unwrap();
+    let obj: Value = ret.json().await.unwrap();
+
+    let rates: HashMap<&str, &str> = obj
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(code, rate)| (code.as_str(), rate["rate"].as_str().unwrap()))
+        .collect();
+    assert_eq!(rates.len(), 3);
+    assert_eq!(rates["EUR"], "1");
+    assert_eq!(rates["USD"], "0.8");
+    assert_eq!(rates["JPY"], "1/120");
+    assert_eq!(obj["USD"]["source"], json!("127.0.0.1"));
+}
@@ -1,27 +1,42 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
-use interledger_ccp::CcpRoutingAccount;
+use interledger_ccp::{CcpRoutingAccount, RouteDetailsStore, RoutePolicyStore};
 use interledger_errors::NodeStoreError;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{
+    ExchangeRate, ExchangeRateStore, ExchangeRateSubscriptionStore, Rate, RateHistoryStore,
+};
 use interledger_router::RouterStore;
 use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementStore};
+use interledger_service_util::{
+    BalanceAlertStore, BalanceStore, FeeStore, MaxPacketAmountAccount, RevenueStore,
+};
+use interledger_settlement::core::{
+    reconciliation::SettlementJournalStore,
+    types::{
+        LeftoversStore, SettlementAccount, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
+};
 use interledger_stream::StreamNotificationsStore;
+use num_bigint::BigUint;
 use secrecy::SecretString;
 use serde::{de, Deserialize, Serialize};
-use std::{boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr};
-use url::Url;
+use std::{
+    boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr, time::Duration,
+};
 use uuid::Uuid;
 use warp::{self, Filter};
 
+mod parents;
 mod routes;
 
+pub use parents::ParentFailover;
+
 // This enum and the following functions are used to allow clients to send either
 // numbers or strings and have them be properly deserialized into the appropriate
 // integer type.
@@ -58,17 +73,6 @@ where
     }
 }
 
-pub fn map_of_number_or_string<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
-where
-    D: de::Deserializer<'de>,
-{
-    #[derive(Deserialize)]
-    struct Wrapper(#[serde(deserialize_with = "number_or_string")] f64);
-
-    let v = HashMap::<String, Wrapper>::deserialize(deserializer)?;
-    Ok(v.into_iter().map(|(k, Wrapper(v))| (k, v)).collect())
-}
-
 // TODO should the methods from this trait be split up and put into the
 // traits that are more specific to what they're doing?
 // One argument against doing that is that the NodeStore allows admin-only
@@ -122,29 +126,65 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         account_id: Uuid,
     ) -> Result<(), NodeStoreError>;
 
+    /// Sets a single static route which spreads the packets for the prefix across
+    /// several accounts, in proportion to the given weights
+    async fn set_static_multipath_route(
+        &self,
+        prefix: String,
+        members: Vec<(Uuid, u32)>,
+    ) -> Result<(), NodeStoreError>;
+
+    /// Replaces all the static routes at once, including the multipath ones which
+    /// spread the packets for their prefix across several accounts
+    async fn set_static_routes_with_multipath(
+        &self,
+        routes: Vec<(String, Uuid)>,
+        multipath_routes: Vec<(String, Vec<(Uuid, u32)>)>,
+    ) -> Result<(), NodeStoreError>;
+
     /// Sets the default route ("") to be the provided account id
     /// (acts as a catch-all route if all other routes don't match)
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError>;
 
-    /// Sets the default settlement engines to be used for the provided asset codes
+    /// Sets the default settlement engines to be used for the provided asset codes,
+    /// along with the tokens authenticating the requests to and from them
     async fn set_settlement_engines(
         &self,
         // The 'async_trait lifetime is used after recommendation here:
         // https://github.com/dtolnay/async-trait/issues/8#issuecomment-514812245
-        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+        asset_to_engine_map: impl IntoIterator<Item = (String, SettlementEngineDetails)>
+            + Send
+            + 'async_trait,
     ) -> Result<(), NodeStoreError>;
 
     /// Gets the default settlement engine for the provided asset code
     async fn get_asset_settlement_engine(
         &self,
         asset_code: &str,
-    ) -> Result<Option<Url>, NodeStoreError>;
+    ) -> Result<Option<SettlementEngineDetails>, NodeStoreError>;
 }
 
+/// Exchange rates of each asset code, relative to a common base asset.
+/// Each rate may be given as a number or, to avoid any loss of precision,
+/// as a string containing a decimal (e.g. `"0.0123"`) or a fraction (e.g. `"1/3"`)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExchangeRates(
-    #[serde(deserialize_with = "map_of_number_or_string")] HashMap<String, f64>,
-);
+pub struct ExchangeRates(pub HashMap<String, Rate>);
+
+/// An exchange rate as returned by `GET /rates`, along with its source and age
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeRateDetails {
+    #[serde(flatten)]
+    pub rate: ExchangeRate,
+    /// Milliseconds elapsed since the rate was obtained from its source
+    pub age_ms: u64,
+}
+
+impl From<ExchangeRate> for ExchangeRateDetails {
+    fn from(rate: ExchangeRate) -> Self {
+        let age_ms = rate.age().as_millis() as u64;
+        ExchangeRateDetails { rate, age_ms }
+    }
+}
 
 /// AccountSettings is a subset of the user parameters defined in
 /// AccountDetails. Its purpose is to allow a user to modify certain of their
@@ -261,6 +301,14 @@ pub struct AccountDetails {
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
     pub settlement_engine_url: Option<String>,
+    /// Percentage (0-100) of the credit limit (`min_balance`) the account may use
+    /// before an alert is raised
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub credit_limit_alert_percent: Option<u8>,
+    /// Time in milliseconds the balance may stay above the settle threshold
+    /// before an alert is raised
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_threshold_alert_delay: Option<u64>,
 }
 
 pub struct NodeApi<S, I, O, B, A: Account> {
@@ -269,6 +317,7 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     // TODO: Make this a SecretString
     admin_api_token: String,
     default_spsp_account: Option<Username>,
+    primary_parent: Option<Username>,
     incoming_handler: I,
     // The outgoing service is included so that the API can send outgoing
     // requests to specific accounts (namely ILDCP requests)
@@ -279,6 +328,9 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     /// Server secret used to instantiate SPSP/Stream connections
     server_secret: Bytes,
     node_version: Option<String>,
+    exchange_rate_spread: Rate,
+    max_rate_age: Option<Duration>,
+    spsp_allowed_hosts: Vec<String>,
 }
 
 impl<S, I, O, B, A> NodeApi<S, I, O, B, A>
@@ -288,10 +340,20 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + BalanceAlertStore
         + SettlementStore<Account = A>
+        + SettlementJournalStore
+        + SettlementQueueStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
         + StreamNotificationsStore<Account = A>
         + RouterStore
-        + ExchangeRateStore,
+        + RoutePolicyStore
+        + RouteDetailsStore
+        + ExchangeRateStore
+        + RateHistoryStore
+        + ExchangeRateSubscriptionStore
+        + FeeStore
+        + RevenueStore,
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     B: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -300,6 +362,7 @@ where
         + Account
         + HttpAccount
         + SettlementAccount
+        + MaxPacketAmountAccount
         + Serialize
         + Send
         + Sync
@@ -317,11 +380,15 @@ where
             store,
             admin_api_token,
             default_spsp_account: None,
+            primary_parent: None,
             incoming_handler,
             outgoing_handler,
             btp,
             server_secret,
             node_version: None,
+            exchange_rate_spread: Rate::zero(),
+            max_rate_age: None,
+            spsp_allowed_hosts: Vec::new(),
         }
     }
 
@@ -333,23 +400,57 @@ where
         self
     }
 
+    /// Sets the parent the node prefers to get its address from when it has several parents.
+    /// Parents added through the API only change the node's address if they are preferred
+    pub fn primary_parent(&mut self, username: Username) -> &mut Self {
+        self.primary_parent = Some(username);
+        self
+    }
+
     /// Sets the node version
     pub fn node_version(&mut self, version: String) -> &mut Self {
         self.node_version = Some(version);
         self
     }
 
+    /// Sets the spread the node charges, which is used to quote payments
+    pub fn exchange_rate_spread(&mut self, spread: Rate) -> &mut Self {
+        self.exchange_rate_spread = spread;
+        self
+    }
+
+    /// Sets the maximum age of the exchange rates payments are quoted with
+    pub fn max_rate_age(&mut self, max_rate_age: Duration) -> &mut Self {
+        self.max_rate_age = Some(max_rate_age);
+        self
+    }
+
+    /// Sets the hosts of the payment pointers accounts may quote payments to.
+    /// The admin may quote payments to any payment pointer
+    pub fn spsp_allowed_hosts(&mut self, hosts: Vec<String>) -> &mut Self {
+        self.spsp_allowed_hosts = hosts;
+        self
+    }
+
     /// Returns a Warp Filter which exposes the accounts and admin APIs
     pub fn into_warp_filter(self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
         routes::accounts_api(
             self.server_secret,
             self.admin_api_token.clone(),
             self.default_spsp_account,
+            self.primary_parent,
             self.incoming_handler,
             self.outgoing_handler,
             self.btp,
             self.store.clone(),
         )
+        .or(routes::quote_api(
+            self.admin_api_token.clone(),
+            self.exchange_rate_spread,
+            self.max_rate_age,
+            self.spsp_allowed_hosts,
+            self.store.clone(),
+        ))
         .or(routes::node_settings_api(
             self.admin_api_token,
             self.node_version,
@@ -0,0 +1,583 @@
+use crate::NodeStore;
+use futures::TryFutureExt;
+use interledger_ccp::{
+    get_address_from_parent, use_address_from_parent, CcpRoutingAccount, RoutingRelation,
+};
+use interledger_service::{AddressStore, OutgoingService, Username};
+use parking_lot::Mutex;
+use std::{collections::HashMap, marker::PhantomData, sync::Arc, time::Duration};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+/// Number of consecutive ILDCP requests the parent the node got its address from
+/// may fail before the node switches to another parent
+const DEFAULT_MAX_FAILURES: u32 = 3;
+
+/// Number of consecutive ILDCP requests a parent the node prefers over the one it got its
+/// address from must answer before the node switches back to it
+const DEFAULT_FAILBACK_CHECKS: u32 = 3;
+
+/// Returns the parent accounts in the order the node prefers to get its address from them:
+/// the primary parent first, then the others sorted by username
+pub(crate) fn parents_by_preference<A>(
+    accounts: Vec<A>,
+    primary_parent: Option<&Username>,
+) -> Vec<A>
+where
+    A: CcpRoutingAccount,
+{
+    let mut parents: Vec<A> = accounts
+        .into_iter()
+        .filter(|account| account.routing_relation() == RoutingRelation::Parent)
+        .collect();
+    parents.sort_by(|a, b| {
+        let a_key = (Some(a.username()) != primary_parent, a.username().as_ref());
+        let b_key = (Some(b.username()) != primary_parent, b.username().as_ref());
+        a_key.cmp(&b_key)
+    });
+    parents
+}
+
+#[derive(Debug, Default)]
+struct ParentState {
+    /// The parent the node's address was assigned by
+    current: Option<Uuid>,
+    /// Number of consecutive ILDCP requests each parent did not answer
+    failures: HashMap<Uuid, u32>,
+    /// Number of consecutive ILDCP requests each parent answered
+    answers: HashMap<Uuid, u32>,
+}
+
+impl ParentState {
+    /// Records which parents answered, given in order of preference, and returns the one
+    /// the node should get its address from. The current parent is kept until it failed
+    /// too many times in a row, unless a preferred parent answered `failback_checks` times
+    /// in a row. Without a usable current parent, the first parent which answered is used
+    fn select_parent(
+        &mut self,
+        answered: &[(Uuid, bool)],
+        max_failures: u32,
+        failback_checks: u32,
+    ) -> Option<Uuid> {
+        for (id, ok) in answered {
+            if *ok {
+                self.failures.remove(id);
+                *self.answers.entry(*id).or_insert(0) += 1;
+            } else {
+                self.answers.remove(id);
+                *self.failures.entry(*id).or_insert(0) += 1;
+            }
+        }
+        // Forget the parents which were removed
+        let is_parent = |id: &Uuid| answered.iter().any(|(parent, _)| parent == id);
+        self.failures.retain(|id, _| is_parent(id));
+        self.answers.retain(|id, _| is_parent(id));
+
+        let failures = &self.failures;
+        let answers = &self.answers;
+        let current = self.current.filter(|current| {
+            is_parent(current) && failures.get(current).cloned().unwrap_or(0) < max_failures
+        });
+        answered
+            .iter()
+            .find(|(id, ok)| match current {
+                Some(current) if current == *id => true,
+                // Parents which come before the current one are preferred, but only switched
+                // back to once they answered steadily, so that a flapping parent does not
+                // make the node change its address on every check
+                Some(_) => answers.get(id).cloned().unwrap_or(0) >= failback_checks.max(1),
+                None => *ok,
+            })
+            .map(|(id, _)| *id)
+    }
+}
+
+/// Checks that the node's parents are reachable by sending each of them an ILDCP request.
+/// The node gets its address from the primary parent as long as it is reachable. Once
+/// the parent the node got its address from failed to answer too many times in a row,
+/// the node switches to the address assigned by the next parent which answers, and
+/// switches back once a preferred parent answered several times in a row again.
+#[derive(Clone)]
+pub struct ParentFailover<O, S, A> {
+    service: O,
+    store: S,
+    primary_parent: Option<Username>,
+    max_failures: u32,
+    failback_checks: u32,
+    state: Arc<Mutex<ParentState>>,
+    account_type: PhantomData<A>,
+}
+
+impl<O, S, A> ParentFailover<O, S, A>
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A> + AddressStore + Clone + Send + Sync + 'static,
+    A: CcpRoutingAccount + Clone + Send + Sync + 'static,
+{
+    pub fn new(service: O, store: S) -> Self {
+        ParentFailover {
+            service,
+            store,
+            primary_parent: None,
+            max_failures: DEFAULT_MAX_FAILURES,
+            failback_checks: DEFAULT_FAILBACK_CHECKS,
+            state: Arc::new(Mutex::new(ParentState::default())),
+            account_type: PhantomData,
+        }
+    }
+
+    /// Sets the parent the node prefers to get its address from. If it is not set,
+    /// the parents are preferred in the order of their usernames
+    pub fn primary_parent(&mut self, username: Username) -> &mut Self {
+        self.primary_parent = Some(username);
+        self
+    }
+
+    /// Sets the number of consecutive ILDCP requests the parent the node got its address
+    /// from may fail before the node switches to another parent. Defaults to 3
+    pub fn max_failures(&mut self, max_failures: u32) -> &mut Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Sets the number of consecutive ILDCP requests a parent the node prefers over the one
+    /// it got its address from must answer before the node switches back to it. Defaults to 3
+    pub fn failback_checks(&mut self, failback_checks: u32) -> &mut Self {
+        self.failback_checks = failback_checks;
+        self
+    }
+
+    /// Sends an ILDCP request to each parent and switches the node's address
+    /// if it should get it from another parent
+    pub async fn check_parents(&self) -> Result<(), ()> {
+        let accounts = self
+            .store
+            .get_all_accounts()
+            .map_err(|err| error!("Error getting accounts to check parents: {:?}", err))
+            .await?;
+        let parents = parents_by_preference(accounts, self.primary_parent.as_ref());
+
+        let mut addresses = HashMap::new();
+        for parent in parents.iter() {
+            let mut service = self.service.clone();
+            if let Ok(address) = get_address_from_parent(&mut service, parent).await {
+                addresses.insert(parent.id(), address);
+            }
+        }
+        let answered: Vec<(Uuid, bool)> = parents
+            .iter()
+            .map(|parent| (parent.id(), addresses.contains_key(&parent.id())))
+            .collect();
+
+        let (current, selected) = {
+            let mut state = self.state.lock();
+            (
+                state.current,
+                state.select_parent(&answered, self.max_failures, self.failback_checks),
+            )
+        };
+        let (parent, address) =
+            match selected.and_then(|id| parents.iter().find(|parent| parent.id() == id)) {
+                Some(parent) => (parent, addresses.get(&parent.id()).cloned()),
+                None => {
+                    if !parents.is_empty() {
+                        warn!("None of the node's parents answered its ILDCP request");
+                    }
+                    return Ok(());
+                }
+            };
+        // The current parent is kept until it failed too many times
+        let address = match address {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+
+        let address_changed = address != self.store.get_ilp_address();
+        if address_changed || current != Some(parent.id()) {
+            // If we already have the address, e.g. because the API just added the parent,
+            // it only needs to become the default route
+            self.store
+                .set_default_route(parent.id())
+                .map_err(|err| error!("Error setting the default route to parent: {:?}", err))
+                .await?;
+        }
+        if address_changed {
+            if current.is_some() && current != Some(parent.id()) {
+                info!(
+                    "Switching to the address {} assigned by parent {}",
+                    address,
+                    parent.username()
+                );
+            }
+            use_address_from_parent(self.service.clone(), parent, address, &self.store)
+                .map_err(|err| error!("Error using the address assigned by parent: {}", err))
+                .await?;
+        }
+        self.state.lock().current = Some(parent.id());
+        Ok(())
+    }
+
+    /// Spawns a future which calls [`self.check_parents()`](./struct.ParentFailover.html#method.check_parents) every `interval`
+    pub fn spawn_interval(self, interval: Duration) {
+        debug!(
+            "Starting interval to check the node's parents, primary parent: {:?}",
+            self.primary_parent
+        );
+        let interval = async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                // Ignore errors so that they don't cause the Interval to stop
+                let _ = self.check_parents().await;
+            }
+        };
+        tokio::spawn(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccountDetails, AccountSettings};
+    use async_trait::async_trait;
+    use interledger_ccp::RouteControlRequest;
+    use interledger_errors::{AddressStoreError, NodeStoreError};
+    use interledger_ildcp::{is_ildcp_request, IldcpResponseBuilder};
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
+    use interledger_service::{outgoing_service_fn, Account, OutgoingRequest};
+    use interledger_settlement::core::types::SettlementEngineDetails;
+    use std::{collections::HashSet, convert::TryFrom, str::FromStr};
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        id: Uuid,
+        username: Username,
+        ilp_address: Address,
+        relation: RoutingRelation,
+    }
+
+    impl TestAccount {
+        fn new(username: &str, ilp_address: &str, relation: RoutingRelation) -> Self {
+            TestAccount {
+                id: Uuid::new_v4(),
+                username: Username::from_str(username).unwrap(),
+                ilp_address: Address::from_str(ilp_address).unwrap(),
+                relation,
+            }
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &self.ilp_address
+        }
+    }
+
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            self.relation
+        }
+    }
+
+    /// Keeps the node's address, its accounts and the default route in memory.
+    /// Like the real stores, setting the node's address re-addresses its children
+    #[derive(Clone)]
+    struct TestStore {
+        accounts: Arc<Mutex<Vec<TestAccount>>>,
+        ilp_address: Arc<Mutex<Address>>,
+        default_route: Arc<Mutex<Option<Uuid>>>,
+    }
+
+    #[async_trait]
+    impl NodeStore for TestStore {
+        type Account = TestAccount;
+
+        async fn insert_account(
+            &self,
+            _account: AccountDetails,
+        ) -> Result<Self::Account, NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn delete_account(&self, _id: Uuid) -> Result<Self::Account, NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_account(
+            &self,
+            _id: Uuid,
+            _account: AccountDetails,
+        ) -> Result<Self::Account, NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn modify_account_settings(
+            &self,
+            _id: Uuid,
+            _settings: AccountSettings,
+        ) -> Result<Self::Account, NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
+            Ok(self.accounts.lock().clone())
+        }
+
+        async fn set_static_routes<R>(&self, _routes: R) -> Result<(), NodeStoreError>
+        where
+            R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
+        {
+            unimplemented!()
+        }
+
+        async fn set_static_route(
+            &self,
+            _prefix: String,
+            _account_id: Uuid,
+        ) -> Result<(), NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn set_static_multipath_route(
+            &self,
+            _prefix: String,
+            _members: Vec<(Uuid, u32)>,
+        ) -> Result<(), NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn set_static_routes_with_multipath(
+            &self,
+            _routes: Vec<(String, Uuid)>,
+            _multipath_routes: Vec<(String, Vec<(Uuid, u32)>)>,
+        ) -> Result<(), NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
+            *self.default_route.lock() = Some(account_id);
+            Ok(())
+        }
+
+        async fn set_settlement_engines(
+            &self,
+            _asset_to_engine_map: impl IntoIterator<Item = (String, SettlementEngineDetails)>
+                + Send
+                + 'async_trait,
+        ) -> Result<(), NodeStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_asset_settlement_engine(
+            &self,
+            _asset_code: &str,
+        ) -> Result<Option<SettlementEngineDetails>, NodeStoreError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+            for account in self.accounts.lock().iter_mut() {
+                if account.relation == RoutingRelation::Child {
+                    account.ilp_address = ilp_address
+                        .with_suffix(account.username.as_bytes())
+                        .unwrap();
+                }
+            }
+            *self.ilp_address.lock() = ilp_address;
+            Ok(())
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            self.ilp_address.lock().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn switches_to_the_next_parent_when_the_primary_parent_stops_answering() {
+        let primary = TestAccount::new("primary", "example.primary", RoutingRelation::Parent);
+        let secondary = TestAccount::new("secondary", "example.secondary", RoutingRelation::Parent);
+        let child = TestAccount::new("child", "example.unset.child", RoutingRelation::Child);
+        let store = TestStore {
+            accounts: Arc::new(Mutex::new(vec![secondary.clone(), primary.clone(), child])),
+            ilp_address: Arc::new(Mutex::new(Address::from_str("local.host").unwrap())),
+            default_route: Arc::new(Mutex::new(None)),
+        };
+
+        // The parents assign the node addresses under their own, unless they are down
+        let down: Arc<Mutex<HashSet<Uuid>>> = Arc::new(Mutex::new(HashSet::new()));
+        let route_requests: Arc<Mutex<Vec<Uuid>>> = Arc::new(Mutex::new(Vec::new()));
+        let down_clone = down.clone();
+        let route_requests_clone = route_requests.clone();
+        let service = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            if down_clone.lock().contains(&request.to.id()) {
+                return Err(RejectBuilder {
+                    code: ErrorCode::T01_PEER_UNREACHABLE,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build());
+            }
+            if is_ildcp_request(&request.prepare) {
+                Ok(IldcpResponseBuilder {
+                    ilp_address: &request.to.ilp_address.with_suffix(b"node").unwrap(),
+                    asset_scale: 9,
+                    asset_code: "XYZ",
+                }
+                .build()
+                .into())
+            } else {
+                assert!(RouteControlRequest::try_from(&request.prepare).is_ok());
+                route_requests_clone.lock().push(request.to.id());
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let mut failover = ParentFailover::new(service, store.clone());
+        failover
+            .primary_parent(primary.username.clone())
+            .max_failures(2)
+            .failback_checks(2);
+
+        failover.check_parents().await.unwrap();
+        assert_eq!(
+            store.get_ilp_address(),
+            Address::from_str("example.primary.node").unwrap()
+        );
+        assert_eq!(*store.default_route.lock(), Some(primary.id));
+        assert_eq!(*route_requests.lock(), vec![primary.id]);
+        assert_eq!(
+            store.accounts.lock()[2].ilp_address,
+            Address::from_str("example.primary.node.child").unwrap()
+        );
+
+        // The primary parent is kept until it failed to answer too many times in a row
+        down.lock().insert(primary.id);
+        failover.check_parents().await.unwrap();
+        assert_eq!(*store.default_route.lock(), Some(primary.id));
+        failover.check_parents().await.unwrap();
+        assert_eq!(
+            store.get_ilp_address(),
+            Address::from_str("example.secondary.node").unwrap()
+        );
+        assert_eq!(*store.default_route.lock(), Some(secondary.id));
+        assert_eq!(*route_requests.lock(), vec![primary.id, secondary.id]);
+        assert_eq!(
+            store.accounts.lock()[2].ilp_address,
+            Address::from_str("example.secondary.node.child").unwrap()
+        );
+
+        // The node only switches back to the primary parent once it answered
+        // several times in a row, not while it keeps going up and down
+        down.lock().clear();
+        failover.check_parents().await.unwrap();
+        assert_eq!(*store.default_route.lock(), Some(secondary.id));
+        down.lock().insert(primary.id);
+        failover.check_parents().await.unwrap();
+        assert_eq!(*store.default_route.lock(), Some(secondary.id));
+        down.lock().clear();
+        failover.check_parents().await.unwrap();
+        assert_eq!(*store.default_route.lock(), Some(secondary.id));
+        failover.check_parents().await.unwrap();
+        assert_eq!(
+            store.get_ilp_address(),
+            Address::from_str("example.primary.node").unwrap()
+        );
+        assert_eq!(*store.default_route.lock(), Some(primary.id));
+        assert_eq!(
+            store.accounts.lock()[2].ilp_address,
+            Address::from_str("example.primary.node.child").unwrap()
+        );
+    }
+
+    #[test]
+    fn keeps_current_parent_until_it_fails_too_many_times() {
+        let primary = Uuid::new_v4();
+        let secondary = Uuid::new_v4();
+        let mut state = ParentState::default();
+
+        state.current = state.select_parent(&[(primary, true), (secondary, true)], 3, 1);
+        assert_eq!(state.current, Some(primary));
+        state.current = state.select_parent(&[(primary, false), (secondary, true)], 3, 1);
+        assert_eq!(state.current, Some(primary));
+        state.current = state.select_parent(&[(primary, false), (secondary, true)], 3, 1);
+        assert_eq!(state.current, Some(primary));
+        state.current = state.select_parent(&[(primary, false), (secondary, true)], 3, 1);
+        assert_eq!(state.current, Some(secondary));
+    }
+
+    #[test]
+    fn switches_back_once_the_primary_parent_answers() {
+        let primary = Uuid::new_v4();
+        let secondary = Uuid::new_v4();
+        let mut state = ParentState::default();
+
+        state.current = state.select_parent(&[(primary, false), (secondary, true)], 1, 1);
+        assert_eq!(state.current, Some(secondary));
+        // The secondary parent is kept while it has not failed too many times
+        state.current = state.select_parent(&[(primary, false), (secondary, false)], 2, 1);
+        assert_eq!(state.current, Some(secondary));
+        state.current = state.select_parent(&[(primary, true), (secondary, true)], 2, 2);
+        assert_eq!(state.current, Some(secondary));
+        state.current = state.select_parent(&[(primary, true), (secondary, true)], 2, 2);
+        assert_eq!(state.current, Some(primary));
+    }
+
+    #[test]
+    fn does_not_switch_back_to_a_flapping_primary_parent() {
+        let primary = Uuid::new_v4();
+        let secondary = Uuid::new_v4();
+        let mut state = ParentState::default();
+
+        state.current = state.select_parent(&[(primary, false), (secondary, true)], 3, 3);
+        assert_eq!(state.current, Some(secondary));
+        for _ in 0..5 {
+            state.current = state.select_parent(&[(primary, true), (secondary, true)], 3, 3);
+            assert_eq!(state.current, Some(secondary));
+            state.current = state.select_parent(&[(primary, true), (secondary, true)], 3, 3);
+            assert_eq!(state.current, Some(secondary));
+            state.current = state.select_parent(&[(primary, false), (secondary, true)], 3, 3);
+            assert_eq!(state.current, Some(secondary));
+        }
+        // Once it stays up for long enough, the node switches back to it
+        for _ in 0..2 {
+            state.current = state.select_parent(&[(primary, true), (secondary, true)], 3, 3);
+            assert_eq!(state.current, Some(secondary));
+        }
+        state.current = state.select_parent(&[(primary, true), (secondary, true)], 3, 3);
+        assert_eq!(state.current, Some(primary));
+    }
+
+    #[test]
+    fn selects_nothing_if_no_parent_answers() {
+        let mut state = ParentState::default();
+        assert_eq!(state.select_parent(&[(Uuid::new_v4(), false)], 3, 1), None);
+        assert_eq!(state.select_parent(&[], 3, 1), None);
+    }
+}
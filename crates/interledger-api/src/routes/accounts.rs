@@ -1,26 +1,27 @@
-use crate::{number_or_string, AccountDetails, AccountSettings, NodeStore};
+use crate::{
+    number_or_string, parents::parents_by_preference, AccountDetails, AccountSettings, NodeStore,
+};
 use bytes::Bytes;
 use futures::{Future, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
 use interledger_btp::{connect_to_service_account, BtpAccount, BtpOutgoingService};
-use interledger_ccp::{CcpRoutingAccount, Mode, RouteControlRequest, RoutingRelation};
+use interledger_ccp::{
+    get_address_from_parent, request_routes_from_parent, use_address_from_parent,
+    CcpRoutingAccount, ParentError, RoutePolicy, RoutePolicyStore, RoutingRelation,
+};
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount, HttpStore};
-use interledger_ildcp::IldcpRequest;
-use interledger_ildcp::IldcpResponse;
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{
-    Account, AccountStore, AddressStore, IncomingService, OutgoingRequest, OutgoingService,
-    Username,
+    Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
 };
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceAlertStore, BalanceStore};
 use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
 use interledger_spsp::{pay, SpspResponder};
 use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::convert::TryFrom;
 use std::fmt::Debug;
 use tracing::{debug, error, trace};
 use uuid::Uuid;
@@ -44,10 +45,12 @@ struct SpspPayRequest {
     slippage: f64,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn accounts_api<I, O, S, A, B>(
     server_secret: Bytes,
     admin_api_token: String,
     default_spsp_account: Option<Username>,
+    primary_parent: Option<Username>,
     incoming_handler: I,
     outgoing_handler: O,
     btp: BtpOutgoingService<B, A>,
@@ -62,9 +65,11 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + BalanceAlertStore
         + StreamNotificationsStore<Account = A>
         + ExchangeRateStore
-        + RouterStore,
+        + RouterStore
+        + RoutePolicyStore,
     A: BtpAccount
         + CcpRoutingAccount
         + SettlementAccount
@@ -166,6 +171,7 @@ where
     // POST /accounts
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let primary_parent_clone = primary_parent.clone();
     let post_accounts = warp::post()
         .and(warp::path("accounts"))
         .and(warp::path::end())
@@ -176,10 +182,18 @@ where
             let store_clone = store.clone();
             let handler = outgoing_handler_clone.clone();
             let btp = btp_clone.clone();
+            let primary_parent = primary_parent_clone.clone();
             async move {
                 let account = store.insert_account(account_details.clone()).await?;
 
-                connect_to_external_services(handler, account.clone(), store_clone, btp).await?;
+                connect_to_external_services(
+                    handler,
+                    account.clone(),
+                    store_clone,
+                    btp,
+                    primary_parent,
+                )
+                .await?;
                 Ok::<Json, Rejection>(warp::reply::json(&account))
             }
         });
@@ -198,6 +212,7 @@ where
     // PUT /accounts/:username
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let primary_parent_clone = primary_parent.clone();
     let put_account = warp::put()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
@@ -208,6 +223,7 @@ where
         .and_then(move |id: Uuid, account_details: AccountDetails, store: S| {
             let outgoing_handler = outgoing_handler_clone.clone();
             let btp = btp_clone.clone();
+            let primary_parent = primary_parent_clone.clone();
             if account_details.ilp_over_btp_incoming_token.is_some() {
                 // if the BTP token was provided, assume that it's different
                 // from the existing one and drop the connection
@@ -217,7 +233,14 @@ where
             }
             async move {
                 let account = store.update_account(id, account_details).await?;
-                connect_to_external_services(outgoing_handler, account.clone(), store, btp).await?;
+                connect_to_external_services(
+                    outgoing_handler,
+                    account.clone(),
+                    store,
+                    btp,
+                    primary_parent,
+                )
+                .await?;
 
                 Ok::<Json, Rejection>(warp::reply::json(&account))
             }
@@ -262,6 +285,62 @@ where
             }
         });
 
+    // GET /accounts/:username/alerts
+    let get_account_alerts = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("alerts"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let alerts = store.get_balance_alerts(Some(id)).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&alerts))
+        });
+
+    // GET /alerts
+    let get_alerts = warp::get()
+        .and(warp::path("alerts"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let alerts = store.get_balance_alerts(None).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&alerts))
+        });
+
+    // GET /accounts/:username/routes/policy
+    let get_route_policy = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("routes"))
+        .and(warp::path("policy"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let policy = store
+                .get_route_policies()
+                .await?
+                .remove(&id)
+                .unwrap_or_default();
+            Ok::<Json, Rejection>(warp::reply::json(&policy))
+        });
+
+    // PUT /accounts/:username/routes/policy
+    let put_route_policy = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("routes"))
+        .and(warp::path("policy"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, policy: RoutePolicy, store: S| async move {
+            store.set_route_policy(id, policy.clone()).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&policy))
+        });
+
     // DELETE /accounts/:username
     let btp_clone = btp.clone();
     let delete_account = warp::delete()
@@ -292,6 +371,7 @@ where
         .and_then(move |id: Uuid, settings: AccountSettings, store: S| {
             let btp = btp.clone();
             let outgoing_handler = outgoing_handler_clone.clone();
+            let primary_parent = primary_parent.clone();
             async move {
                 if settings.ilp_over_btp_incoming_token.is_some() {
                     // if the BTP token was provided, assume that it's different
@@ -308,6 +388,7 @@ where
                     modified_account.clone(),
                     store,
                     btp,
+                    primary_parent,
                 )
                 .await?;
                 Ok::<Json, Rejection>(warp::reply::json(&modified_account))
@@ -441,6 +522,10 @@ where
         .or(delete_account)
         .or(get_account)
         .or(get_account_balance)
+        .or(get_account_alerts)
+        .or(get_alerts)
+        .or(get_route_policy)
+        .or(put_route_policy)
         .or(put_account_settings)
         .or(incoming_payment_notifications)
         .or(all_payment_notifications)
@@ -500,85 +585,59 @@ async fn get_address_from_parent_and_update_routes<O, A, S>(
     mut service: O,
     parent: A,
     store: S,
+    primary_parent: Option<Username>,
 ) -> Result<(), warp::Rejection>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: CcpRoutingAccount + Clone + Send + Sync + 'static,
     S: NodeStore<Account = A> + AddressStore + Clone + Send + Sync + 'static,
 {
-    debug!(
-        "Getting ILP address from parent account: {} (id: {})",
-        parent.username(),
-        parent.id()
-    );
-    let prepare = IldcpRequest {}.to_prepare();
-    let fulfill = service
-        .send_request(OutgoingRequest {
-            from: parent.clone(), // Does not matter what we put here, they will get the account from the HTTP/BTP credentials
-            to: parent.clone(),
-            prepare,
-            original_amount: 0,
-        })
-        .map_err(|err| {
-            let msg = format!("Error getting ILDCP info: {:?}", err);
-            error!("{}", msg);
-            ApiError::internal_server_error().detail(msg)
-        })
+    let ilp_address = get_address_from_parent(&mut service, &parent)
+        .map_err(parent_error)
         .await?;
 
-    let info = IldcpResponse::try_from(fulfill.into_data().freeze()).map_err(|err| {
-        let msg = format!(
-            "Unable to parse ILDCP response from fulfill packet: {:?}",
-            err
+    // If the node has several parents, it only gets its address from the one it prefers.
+    // It switches to the others if that parent becomes unreachable
+    let accounts = store.get_all_accounts().await?;
+    let preferred = parents_by_preference(accounts, primary_parent.as_ref())
+        .into_iter()
+        .next();
+    if let Some(preferred) = preferred.filter(|preferred| preferred.id() != parent.id()) {
+        debug!(
+            "Not using the address {} assigned by parent {} because the node prefers parent {}",
+            ilp_address,
+            parent.username(),
+            preferred.username()
         );
-        error!("{}", msg);
-        ApiError::internal_server_error().detail(msg)
-    })?;
-    debug!("Got ILDCP response from parent: {:?}", info);
-    let ilp_address = info.ilp_address();
-
-    debug!("ILP address is now: {}", ilp_address);
-    // TODO we may want to make this trigger the CcpRouteManager to request
-    let prepare = RouteControlRequest {
-        mode: Mode::Sync,
-        last_known_epoch: 0,
-        last_known_routing_table_id: [0; 16],
-        features: Vec::new(),
+        // We still want the routes of the parent, e.g. in case the preferred one fails
+        request_routes_from_parent(service, &parent)
+            .map_err(parent_error)
+            .await?;
+        return Ok(());
     }
-    .to_prepare();
 
     // Set the parent to be the default route for everything
     // that starts with their global prefix
     store.set_default_route(parent.id()).await?;
-    // Update our store's address
-    store.set_ilp_address(ilp_address).await?;
-
-    // Get the parent's routes for us
-    debug!("Asking for routes from {:?}", parent.clone());
-    service
-        .send_request(OutgoingRequest {
-            from: parent.clone(),
-            to: parent.clone(),
-            original_amount: prepare.amount(),
-            prepare: prepare.clone(),
-        })
-        .map_err(|err| {
-            let msg = format!("Error getting routes from parent: {:?}", err);
-            error!("{}", msg);
-            ApiError::internal_server_error().detail(msg)
-        })
+    use_address_from_parent(service, &parent, ilp_address, &store)
+        .map_err(parent_error)
         .await?;
-
     Ok(())
 }
 
+// Getting the address or routes from a parent only fails because of the parent
+// or the node's own store, which the API client can do nothing about
+fn parent_error(err: ParentError) -> Rejection {
+    Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
+}
+
 // Helper function which gets called whenever a new account is added or
 // modified.
 // Performed actions:
 // 1. If they have a BTP uri configured: connect to their BTP socket
 // 2. If they are a parent:
 // 2a. Perform an ILDCP Request to get the address assigned to us by them, and
-// update our store's address to that value
+// update our store's address to that value, unless we prefer another parent
 // 2b. Perform a RouteControl Request to make them send us any new routes
 // 3. If they have a settlement engine endpoitn configured: Make a POST to the
 //    engine's account creation endpoint with the account's id
@@ -587,6 +646,7 @@ async fn connect_to_external_services<O, A, S, B>(
     account: A,
     store: S,
     btp: BtpOutgoingService<B, A>,
+    primary_parent: Option<Username>,
 ) -> Result<A, warp::reject::Rejection>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -604,7 +664,13 @@ where
     // If we added a parent, get the address assigned to us by
     // them and update all of our routes
     if account.routing_relation() == RoutingRelation::Parent {
-        get_address_from_parent_and_update_routes(service, account.clone(), store.clone()).await?;
+        get_address_from_parent_and_update_routes(
+            service,
+            account.clone(),
+            store.clone(),
+            primary_parent,
+        )
+        .await?;
     }
 
     // Register the account with the settlement engine
@@ -615,21 +681,20 @@ where
         .get_asset_settlement_engine(account.asset_code())
         .await?;
 
-    let settlement_engine_url = account
+    let settlement_engine = account
         .settlement_engine_details()
-        .map(|details| details.url)
         .or(default_settlement_engine);
-    if let Some(se_url) = settlement_engine_url {
+    if let Some(engine) = settlement_engine {
         let id = account.id();
         let http_client = SettlementClient::default();
         trace!(
             "Sending account {} creation request to settlement engine: {:?}",
             id,
-            se_url.clone()
+            engine.url
         );
 
         let response = http_client
-            .create_engine_account(id, se_url.clone())
+            .create_engine_account(id, engine.clone())
             .map_err(|err| {
                 Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
             })
@@ -645,7 +710,13 @@ where
             // prefund the absolute value
             if amount_to_settle > 0 {
                 http_client
-                    .send_settlement(id, se_url, amount_to_settle, account.asset_scale())
+                    .send_settlement(
+                        id,
+                        engine,
+                        amount_to_settle,
+                        account.asset_scale(),
+                        &Uuid::new_v4().to_hyphenated().to_string(),
+                    )
                     .map_err(|err| {
                         Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
                     })
@@ -665,6 +736,7 @@ where
 #[cfg(test)]
 mod tests {
     use crate::routes::test_helpers::*;
+    use serde_json::{json, Value};
     // TODO: Add test for GET /accounts/:username/spsp and /.well_known
 
     #[tokio::test]
@@ -735,6 +807,78 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_account_alerts() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/alerts", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/alerts", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/alerts", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_all_alerts() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/alerts", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/alerts", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_manage_route_policies() {
+        let api = test_accounts_api();
+        let policy = json!({"import_deny": ["example.private"], "max_routes": 100});
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/routes/policy",
+            "admin",
+            Some(policy.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let saved: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(saved, policy);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/routes/policy", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/routes/policy",
+            "admin",
+            Some(json!({"max_routes": "many"})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/routes/policy",
+            "password",
+            Some(policy),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/routes/policy",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_modify_accounts_settings() {
         let api = test_accounts_api();
@@ -1,25 +1,132 @@
-use crate::{ExchangeRates, NodeStore};
+use crate::{ExchangeRateDetails, ExchangeRates, NodeStore};
 use bytes::Bytes;
-use futures::TryFutureExt;
+use chrono::{DateTime, Utc};
+use futures::{future, stream, Future, FutureExt, StreamExt, TryFutureExt};
+use interledger_ccp::RouteDetailsStore;
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{
+    ExchangeRate, ExchangeRateStore, ExchangeRateSubscriptionStore, RateHistoryStore,
+    API_RATE_SOURCE,
+};
 use interledger_router::RouterStore;
 use interledger_service::{Account, AccountStore, AddressStore, Username};
-use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
+use interledger_service_util::{FeeSchedule, FeeStore, RevenueStore};
+use interledger_settlement::core::{
+    reconciliation::{reconcile_account, SettlementJournalStore},
+    types::{
+        LeftoversStore, SettlementAccount, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
+    SettlementClient,
+};
+use num_bigint::BigUint;
 use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     iter::FromIterator,
     str::{self, FromStr},
 };
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, reply::Json, Filter, Rejection};
 
+/// A settlement engine configured for an asset code, given either as its URL
+/// or along with the token authenticating the requests to and from it
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum SettlementEngineConfig {
+    Url(Url),
+    Authenticated { url: Url, auth_token: SecretString },
+}
+
+impl From<SettlementEngineConfig> for SettlementEngineDetails {
+    fn from(config: SettlementEngineConfig) -> Self {
+        match config {
+            SettlementEngineConfig::Url(url) => SettlementEngineDetails::new(url),
+            SettlementEngineConfig::Authenticated { url, auth_token } => SettlementEngineDetails {
+                url,
+                auth_token: Some(auth_token),
+            },
+        }
+    }
+}
+
+/// The next hop of a static route, given either as the username of a single account
+/// or as a list of accounts sharing the packets for the prefix in proportion to their weights
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StaticRouteConfig {
+    Single(String),
+    Multipath(Vec<MultipathMember>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct MultipathMember {
+    username: String,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Converts the usernames of a static route to account IDs, along with their weights
+async fn resolve_static_route<S: AccountStore>(
+    store: &S,
+    route: &StaticRouteConfig,
+) -> Result<Vec<(Uuid, u32)>, Rejection> {
+    let members = match route {
+        StaticRouteConfig::Single(username) => vec![(username.as_str(), 1)],
+        StaticRouteConfig::Multipath(members) => members
+            .iter()
+            .map(|member| (member.username.as_str(), member.weight))
+            .collect(),
+    };
+    // At least one member has to get packets
+    if members.iter().all(|(_username, weight)| *weight == 0) {
+        return Err(Rejection::from(ApiError::bad_request()));
+    }
+
+    let mut account_ids = Vec::with_capacity(members.len());
+    for (username, weight) in members {
+        let username =
+            Username::from_str(username).map_err(|_| Rejection::from(ApiError::bad_request()))?;
+        account_ids.push((store.get_account_id_from_username(&username).await?, weight));
+    }
+    Ok(account_ids)
+}
+
+/// Range of the revenue report, both ends being optional
+struct RevenueQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+impl RevenueQuery {
+    fn from_params(params: &HashMap<String, String>) -> Option<Self> {
+        Some(RevenueQuery {
+            since: parse_time_param(params, "since").ok()?,
+            until: parse_time_param(params, "until").ok()?,
+        })
+    }
+}
+
+/// Parses an optional RFC 3339 timestamp from the query parameters
+fn parse_time_param(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<DateTime<Utc>>, chrono::ParseError> {
+    params
+        .get(name)
+        .map(|time| DateTime::parse_from_rfc3339(time).map(|time| time.with_timezone(&Utc)))
+        .transpose()
+}
+
 // TODO add more to this response
 #[derive(Clone, Serialize)]
 struct StatusResponse {
@@ -39,7 +146,16 @@ where
         + AccountStore<Account = A>
         + AddressStore
         + ExchangeRateStore
-        + RouterStore,
+        + RateHistoryStore
+        + ExchangeRateSubscriptionStore
+        + FeeStore
+        + RevenueStore
+        + RouterStore
+        + RouteDetailsStore
+        + SettlementStore<Account = A>
+        + SettlementJournalStore
+        + SettlementQueueStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>,
     A: Account + HttpAccount + Send + Sync + SettlementAccount + Serialize + 'static,
 {
     // Helper filters
@@ -82,7 +198,17 @@ where
         .and(deserialize_json())
         .and(with_store.clone())
         .and_then(|rates: ExchangeRates, store: S| async move {
-            store.set_exchange_rates(rates.0.clone())?;
+            let stored = rates
+                .0
+                .iter()
+                .map(|(code, rate)| {
+                    (
+                        code.clone(),
+                        ExchangeRate::new(rate.clone(), API_RATE_SOURCE),
+                    )
+                })
+                .collect();
+            store.set_exchange_rates(stored).await?;
             Ok::<_, Rejection>(warp::reply::json(&rates))
         });
 
@@ -92,10 +218,98 @@ where
         .and(warp::path::end())
         .and(with_store.clone())
         .and_then(|store: S| async move {
-            let rates = store.get_all_exchange_rates()?;
+            let rates: HashMap<String, ExchangeRateDetails> = store
+                .get_all_exchange_rates()?
+                .into_iter()
+                .map(|(code, rate)| (code, rate.into()))
+                .collect();
             Ok::<_, Rejection>(warp::reply::json(&rates))
         });
 
+    // GET /rates/:asset_code/history
+    // Response: Previous rates of the asset, oldest first
+    let get_rate_history = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_store.clone())
+        .and_then(
+            |asset_code: String, query: HashMap<String, String>, store: S| async move {
+                let since = parse_time_param(&query, "since")
+                    .map_err(|_| Rejection::from(ApiError::bad_request()))?;
+                let history: Vec<ExchangeRateDetails> = store
+                    .get_rate_history(&asset_code, since)
+                    .await?
+                    .into_iter()
+                    .map(ExchangeRateDetails::from)
+                    .collect();
+                Ok::<_, Rejection>(warp::reply::json(&history))
+            },
+        );
+
+    // (Websocket) /rates/updates?assets=ABC,XYZ
+    // Sends the current rates of the given assets (or of every asset if none are given),
+    // then the rates which changed whenever they are updated
+    let rate_updates = warp::path("rates")
+        .and(warp::path("updates"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_store.clone())
+        .map(
+            |ws: warp::ws::Ws, query: HashMap<String, String>, store: S| {
+                let assets = query.get("assets").map(|assets| {
+                    assets
+                        .split(',')
+                        .map(|code| code.trim().to_string())
+                        .filter(|code| !code.is_empty())
+                        .collect()
+                });
+                ws.on_upgrade(move |ws: warp::ws::WebSocket| notify_rate_updates(ws, store, assets))
+            },
+        );
+
+    // PUT /fees
+    let put_fees = warp::put()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(|schedule: FeeSchedule, store: S| async move {
+            store.set_fee_schedule(schedule.clone()).await?;
+            Ok::<_, Rejection>(warp::reply::json(&schedule))
+        });
+
+    // GET /fees
+    let get_fees = warp::get()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            Ok::<_, Rejection>(warp::reply::json(&*store.get_fee_schedule()))
+        });
+
+    // GET /revenue
+    // Response: Fees earned per time bucket and account pair
+    let get_revenue = warp::get()
+        .and(warp::path("revenue"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        // Parse the query in the handler so that invalid times are reported
+        // as a bad request rather than rejected in favor of the other routes
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_store.clone())
+        .and_then(|query: HashMap<String, String>, store: S| async move {
+            let query = RevenueQuery::from_params(&query)
+                .ok_or_else(|| Rejection::from(ApiError::bad_request()))?;
+            let revenue = store.get_revenue(query.since, query.until).await?;
+            Ok::<_, Rejection>(warp::reply::json(&revenue))
+        });
+
     // GET /routes
     // Response: Map of ILP Address prefix -> Username
     let get_routes = warp::get()
@@ -121,8 +335,22 @@ where
             }
         });
 
+    // GET /routes/detail
+    // Response: All the routes for each prefix and the routing tables received from each peer,
+    // as of the last time the routes were recalculated (up to one route broadcast interval ago)
+    let get_route_details = warp::get()
+        .and(warp::path("routes"))
+        .and(warp::path("detail"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let details = store.get_route_details().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&details))
+        });
+
     // PUT /routes/static
-    // Body: Map of ILP Address prefix -> Username
+    // Body: Map of ILP Address prefix -> Username or list of {username, weight}
     let put_static_routes = warp::put()
         .and(warp::path("routes"))
         .and(warp::path("static"))
@@ -130,33 +358,36 @@ where
         .and(admin_only.clone())
         .and(deserialize_json())
         .and(with_store.clone())
-        .and_then(move |routes: HashMap<String, String>, store: S| {
-            async move {
-                // Convert the usernames to account IDs to set the routes in the store
-                let mut usernames: Vec<Username> = Vec::new();
-                for username in routes.values() {
-                    let user = match Username::from_str(&username) {
-                        Ok(u) => u,
-                        Err(_) => return Err(Rejection::from(ApiError::bad_request())),
-                    };
-                    usernames.push(user);
-                }
+        .and_then(
+            move |routes: HashMap<String, StaticRouteConfig>, store: S| {
+                async move {
+                    // Convert the usernames to account IDs to set the routes in the store
+                    let mut resolved: Vec<(String, Vec<(Uuid, u32)>)> = Vec::new();
+                    for (prefix, route) in routes.iter() {
+                        resolved.push((
+                            prefix.to_string(),
+                            resolve_static_route(&store, route).await?,
+                        ));
+                    }
 
-                let mut account_ids: Vec<Uuid> = Vec::new();
-                for username in usernames {
-                    account_ids.push(store.get_account_id_from_username(&username).await?);
+                    let (multipath_routes, single_routes): (Vec<_>, Vec<_>) =
+                        resolved.into_iter().partition(|(prefix, _members)| {
+                            matches!(routes.get(prefix), Some(StaticRouteConfig::Multipath(_)))
+                        });
+                    let single_routes = single_routes
+                        .into_iter()
+                        .map(|(prefix, members)| (prefix, members[0].0))
+                        .collect();
+                    store
+                        .set_static_routes_with_multipath(single_routes, multipath_routes)
+                        .await?;
+                    Ok::<Json, Rejection>(warp::reply::json(&routes))
                 }
-
-                let prefixes = routes.keys().map(|s| s.to_string());
-                store
-                    .set_static_routes(prefixes.zip(account_ids.into_iter()))
-                    .await?;
-                Ok::<Json, Rejection>(warp::reply::json(&routes))
-            }
-        });
+            },
+        );
 
     // PUT /routes/static/:prefix
-    // Body: Username
+    // Body: Username, or JSON list of {username, weight}
     let put_static_route = warp::put()
         .and(warp::path("routes"))
         .and(warp::path("static"))
@@ -167,9 +398,18 @@ where
         .and(with_store.clone())
         .and_then(|prefix: String, body: Bytes, store: S| {
             async move {
-                let username_str =
+                let body =
                     str::from_utf8(&body).map_err(|_| Rejection::from(ApiError::bad_request()))?;
-                let username = Username::from_str(username_str)
+                if body.trim_start().starts_with('[') {
+                    let members: Vec<MultipathMember> = serde_json::from_str(body)
+                        .map_err(|_| Rejection::from(ApiError::bad_request()))?;
+                    let route = StaticRouteConfig::Multipath(members);
+                    let members = resolve_static_route(&store, &route).await?;
+                    store.set_static_multipath_route(prefix, members).await?;
+                    return Ok::<String, Rejection>(body.to_string());
+                }
+
+                let username = Username::from_str(body)
                     .map_err(|_| Rejection::from(ApiError::bad_request()))?;
                 // Convert the username to an account ID to set it in the store
                 let account_id = store.get_account_id_from_username(&username).await?;
@@ -183,13 +423,21 @@ where
         .and(warp::path("settlement"))
         .and(warp::path("engines"))
         .and(warp::path::end())
-        .and(admin_only)
+        .and(admin_only.clone())
         .and(warp::body::json())
-        .and(with_store)
-        .and_then(move |asset_to_url_map: HashMap<String, Url>, store: S| async move {
-            let asset_to_url_map_clone = asset_to_url_map.clone();
+        .and(with_store.clone())
+        .and_then(move |asset_to_engine_map: HashMap<String, SettlementEngineConfig>, store: S| async move {
+            let asset_to_engine_map: HashMap<String, SettlementEngineDetails> = asset_to_engine_map
+                .into_iter()
+                .map(|(asset_code, engine)| (asset_code, engine.into()))
+                .collect();
+            // The tokens are not returned
+            let asset_to_url_map: HashMap<String, Url> = asset_to_engine_map
+                .iter()
+                .map(|(asset_code, engine)| (asset_code.clone(), engine.url.clone()))
+                .collect();
             store
-                .set_settlement_engines(asset_to_url_map.clone()).await?;
+                .set_settlement_engines(asset_to_engine_map).await?;
             // Create the accounts on the settlement engines for any
             // accounts that are using the default settlement engine URLs
             // (This is done in case we modify the globally configured settlement
@@ -207,7 +455,7 @@ where
             for account in accounts {
                 if let Some(details) = account.settlement_engine_details() {
                     if Some(&details.url) == asset_to_url_map.get(account.asset_code()) {
-                        let response = client.create_engine_account(account.id(), details.url)
+                        let response = client.create_engine_account(account.id(), details)
                             .map_err(|err| Rejection::from(ApiError::internal_server_error().detail(err.to_string())))
                             .await?;
                         if response.status().is_success() {
@@ -218,22 +466,144 @@ where
                     }
                 }
             }
-            Ok::<Json, Rejection>(warp::reply::json(&asset_to_url_map_clone))
+            Ok::<Json, Rejection>(warp::reply::json(&asset_to_url_map))
+        });
+
+    // GET /settlement/reconciliation reports the discrepancies between the settlements
+    // recorded by the node and the ones reported by the engines.
+    // POST /settlement/reconciliation also corrects them.
+    let settlement_reconciliation = warp::get()
+        .map(|| false)
+        .or(warp::post().map(|| true))
+        .unify()
+        .and(warp::path("settlement"))
+        .and(warp::path("reconciliation"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store)
+        .and_then(|apply: bool, store: S| async move {
+            let accounts = store.get_all_accounts().await?;
+            let client = SettlementClient::default();
+            let mut reports = Vec::new();
+            for account in accounts
+                .iter()
+                .filter(|account| account.settlement_engine_details().is_some())
+            {
+                reports.push(reconcile_account(&store, &client, account, apply).await?);
+            }
+            Ok::<Json, Rejection>(warp::reply::json(&reports))
         });
 
     get_root
         .or(put_rates)
         .or(get_rates)
+        .or(get_rate_history)
+        .or(rate_updates)
+        .or(put_fees)
+        .or(get_fees)
+        .or(get_revenue)
         .or(get_routes)
+        .or(get_route_details)
         .or(put_static_routes)
         .or(put_static_route)
         .or(put_settlement_engines)
+        .or(settlement_reconciliation)
+}
+
+/// Keeps track of the rates sent to a subscriber so that only the changes are sent
+struct RateSubscription {
+    /// Asset codes the subscriber is interested in, or None for every asset
+    assets: Option<HashSet<String>>,
+    sent: HashMap<String, ExchangeRate>,
+}
+
+impl RateSubscription {
+    fn new(assets: Option<HashSet<String>>) -> Self {
+        RateSubscription {
+            assets,
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Returns the rates whose value changed since they were last sent, and the
+    /// rates which were removed (set to None). Rates which were only refreshed are left out
+    fn changes(
+        &mut self,
+        rates: &HashMap<String, ExchangeRate>,
+    ) -> HashMap<String, Option<ExchangeRateDetails>> {
+        let assets = &self.assets;
+        let rates: HashMap<String, ExchangeRate> = rates
+            .iter()
+            .filter(|(code, _)| assets.iter().all(|assets| assets.contains(*code)))
+            .map(|(code, rate)| (code.clone(), rate.clone()))
+            .collect();
+        let mut changes: HashMap<String, Option<ExchangeRateDetails>> = rates
+            .iter()
+            .filter(|(code, rate)| {
+                self.sent
+                    .get(*code)
+                    .iter()
+                    .all(|sent| sent.rate != rate.rate)
+            })
+            .map(|(code, rate)| (code.clone(), Some(rate.clone().into())))
+            .collect();
+        for code in self.sent.keys().filter(|code| !rates.contains_key(*code)) {
+            changes.insert(code.clone(), None);
+        }
+        self.sent = rates;
+        changes
+    }
+}
+
+fn notify_rate_updates(
+    socket: warp::ws::WebSocket,
+    store: impl ExchangeRateStore + ExchangeRateSubscriptionStore,
+    assets: Option<HashSet<String>>,
+) -> impl Future<Output = ()> {
+    // Subscribe before getting the current rates so that no update is missed
+    let updates = store.subscribe_to_exchange_rates();
+    let mut subscription = RateSubscription::new(assets);
+    let current = subscription.changes(&store.get_all_exchange_rates().unwrap_or_default());
+    let current = warp::ws::Message::text(serde_json::to_string(&current).unwrap());
+
+    let updates = updates.filter_map(move |update| {
+        let message = match update {
+            Ok(rates) => {
+                let changes = subscription.changes(&rates);
+                if changes.is_empty() {
+                    None
+                } else {
+                    Some(Ok(warp::ws::Message::text(
+                        serde_json::to_string(&changes).unwrap(),
+                    )))
+                }
+            }
+            Err(err) => {
+                // The next update will include the changes which were missed
+                warn!("Exchange rate subscriber is lagging behind: {}", err);
+                None
+            }
+        };
+        future::ready(message)
+    });
+
+    stream::once(future::ready(Ok(current)))
+        .chain(updates)
+        .forward(socket)
+        .map(|result| {
+            if let Err(e) = result {
+                error!("websocket send error: {}", e);
+            }
+        })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::routes::test_helpers::{api_call, test_node_settings_api};
+    use super::RateSubscription;
+    use crate::routes::test_helpers::{api_call, test_node_settings_api, RATE_UPDATES};
+    use interledger_rates::{ExchangeRate, Rate};
     use serde_json::{json, Value};
+    use std::{collections::HashMap, sync::Arc};
 
     #[tokio::test]
     async fn gets_status() {
@@ -251,10 +621,104 @@ mod tests {
         let api = test_node_settings_api();
         let resp = api_call(&api, "GET", "/rates", "", None).await;
         assert_eq!(resp.status().as_u16(), 200);
-        assert_eq!(
-            serde_json::from_slice::<Value>(resp.body()).unwrap(),
-            json!({"XYZ":2.0,"ABC":1.0})
-        );
+        let rates: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(rates["ABC"]["rate"], json!("1"));
+        assert_eq!(rates["XYZ"]["rate"], json!("2.5"));
+        assert_eq!(rates["XYZ"]["source"], json!("test"));
+        assert!(rates["XYZ"]["updated_at"].is_string());
+        assert!(rates["XYZ"]["age_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn gets_rate_history() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/rates/XYZ/history", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let history: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(history[0]["rate"], json!("2"));
+        assert_eq!(history[1]["rate"], json!("3"));
+        assert!(history[1]["age_ms"].is_u64());
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/XYZ/history?since=2020-01-01T00:00:00Z",
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(resp.body(), &b"[]"[..]);
+
+        let resp = api_call(&api, "GET", "/rates/XYZ/history?since=now", "", None).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[test]
+    fn only_sends_changed_rates() {
+        let rates = |rates: &[(&str, u64)]| -> HashMap<String, ExchangeRate> {
+            rates
+                .iter()
+                .map(|(code, rate)| {
+                    (
+                        code.to_string(),
+                        ExchangeRate::new(Rate::from(*rate), "test"),
+                    )
+                })
+                .collect()
+        };
+        let mut subscription = RateSubscription::new(Some(
+            vec!["ABC".to_string(), "XYZ".to_string()]
+                .into_iter()
+                .collect(),
+        ));
+        let changes = subscription.changes(&rates(&[("ABC", 1), ("XYZ", 2), ("DEF", 3)]));
+        assert_eq!(changes.len(), 2);
+        let changes = subscription.changes(&rates(&[("ABC", 1), ("XYZ", 3), ("DEF", 4)]));
+        assert_eq!(changes.keys().collect::<Vec<_>>(), vec!["XYZ"]);
+        let changes = subscription.changes(&rates(&[("XYZ", 3)]));
+        assert_eq!(changes.len(), 1);
+        assert!(changes["ABC"].is_none());
+        assert!(subscription.changes(&rates(&[("XYZ", 3)])).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pushes_rate_updates() {
+        let api = test_node_settings_api();
+        let mut client = warp::test::ws()
+            .path("/rates/updates?assets=XYZ")
+            .handshake(api)
+            .await
+            .unwrap();
+        let recv_json = |message: warp::ws::Message| -> Value {
+            serde_json::from_str(message.to_str().unwrap()).unwrap()
+        };
+        // The current rates are sent first
+        let rates = recv_json(client.recv().await.unwrap());
+        assert_eq!(rates["XYZ"]["rate"], json!("2.5"));
+        assert!(rates.get("ABC").is_none());
+
+        let update = |rates: &[(&str, u64)]| {
+            let rates: HashMap<String, ExchangeRate> = rates
+                .iter()
+                .map(|(code, rate)| {
+                    (
+                        code.to_string(),
+                        ExchangeRate::new(Rate::from(*rate), "test"),
+                    )
+                })
+                .collect();
+            RATE_UPDATES.send(Arc::new(rates)).unwrap();
+        };
+        // The rates of other assets are left out
+        update(&[("XYZ", 2), ("ABC", 3)]);
+        let rates = recv_json(client.recv().await.unwrap());
+        assert_eq!(rates, json!({"XYZ": rates["XYZ"]}));
+        assert_eq!(rates["XYZ"]["rate"], json!("2"));
+        // Nothing is sent if the rates were only refreshed
+        update(&[("XYZ", 2), ("ABC", 4)]);
+        update(&[]);
+        let rates = recv_json(client.recv().await.unwrap());
+        assert_eq!(rates, json!({ "XYZ": null }));
     }
 
     #[tokio::test]
@@ -264,17 +728,101 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_get_route_details() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/routes/detail", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let details: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(details["epoch"], 4);
+        assert_eq!(
+            details["prefixes"]["example.remote"][0]["source"],
+            "learned"
+        );
+        assert_eq!(details["peers"][0]["username"], "bob");
+
+        let resp = api_call(&api, "GET", "/routes/detail", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_rates() {
         let api = test_node_settings_api();
-        let rates = json!({"ABC": 1.0});
+        let rates = json!({"ABC": 1.0, "XYZ": "1/3", "DEF": "0.000001"});
         let resp = api_call(&api, "PUT", "/rates", "admin", Some(rates.clone())).await;
         assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"ABC": "1", "XYZ": "1/3", "DEF": "0.000001"})
+        );
+
+        let resp = api_call(&api, "PUT", "/rates", "admin", Some(json!({"ABC": "abc"}))).await;
+        assert_eq!(resp.status().as_u16(), 400);
 
         let resp = api_call(&api, "PUT", "/rates", "wrong", Some(rates)).await;
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_manage_fees() {
+        let api = test_node_settings_api();
+        let fees = json!([
+            {"same_asset": true, "spread": "0"},
+            {"from_account": "alice", "to_asset": "XYZ", "spread": "0.01", "fixed_fee": 10},
+        ]);
+        let resp = api_call(&api, "PUT", "/fees", "admin", Some(fees.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap()[1]["min_fee"],
+            0
+        );
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/fees",
+            "admin",
+            Some(json!([{"spread": "abc"}])),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "PUT", "/fees", "wrong", Some(fees)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/fees", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/fees", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_revenue() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/revenue", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let revenue: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(revenue[0]["from_account"], "alice");
+        assert_eq!(revenue[0]["amount"], 12);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/revenue?since=2020-01-01T00:00:00Z&until=2020-01-02T00:00:00Z",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"[]"[..]);
+
+        let resp = api_call(&api, "GET", "/revenue?since=yesterday", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "GET", "/revenue", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_static_routes() {
         let api = test_node_settings_api();
@@ -286,6 +834,48 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn puts_multipath_static_routes() {
+        let api = test_node_settings_api();
+        let routes = json!({
+            "g.node1": [{"username": "alice", "weight": 3}, {"username": "bob"}],
+            "example.eu": "bob",
+        });
+        let resp = api_call(&api, "PUT", "/routes/static", "admin", Some(routes.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["g.node1"][1]["weight"], 1);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/routes/static",
+            "admin",
+            Some(json!({"g.node1": []})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/routes/static",
+            "admin",
+            Some(json!({"g.node1": [{"username": "alice", "weight": 0}]})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = warp::test::request()
+            .method("PUT")
+            .path("/routes/static/g.node2")
+            .body(r#"[{"username": "alice", "weight": 1}, {"username": "bob", "weight": 0}]"#)
+            .header("Authorization", "Bearer admin")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_single_static_route() {
         let api = test_node_settings_api();
@@ -312,7 +902,10 @@ mod tests {
     #[tokio::test]
     async fn only_admin_can_put_engines() {
         let api = test_node_settings_api();
-        let engines = json!({"ABC": "http://localhost:3000", "XYZ": "http://localhost:3001"});
+        let engines = json!({
+            "ABC": "http://localhost:3000",
+            "XYZ": {"url": "http://localhost:3001", "auth_token": "secret"},
+        });
         let resp = api_call(
             &api,
             "PUT",
@@ -322,8 +915,27 @@ mod tests {
         )
         .await;
         assert_eq!(resp.status().as_u16(), 200);
+        // The tokens are not echoed back
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            body,
+            json!({"ABC": "http://localhost:3000/", "XYZ": "http://localhost:3001/"})
+        );
 
         let resp = api_call(&api, "PUT", "/settlement/engines", "wrong", Some(engines)).await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn only_admin_can_reconcile_settlements() {
+        let api = test_node_settings_api();
+        for method in &["GET", "POST"] {
+            let resp = api_call(&api, method, "/settlement/reconciliation", "admin", None).await;
+            assert_eq!(resp.status().as_u16(), 200);
+            assert_eq!(resp.body(), &b"[]"[..]);
+
+            let resp = api_call(&api, method, "/settlement/reconciliation", "wrong", None).await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+    }
 }
@@ -0,0 +1,393 @@
+use crate::routes::accounts::BEARER_TOKEN_START;
+use interledger_errors::*;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::{ExchangeRateStore, Rate};
+use interledger_router::{find_next_hop, RouterStore};
+use interledger_service::{Account, AccountStore, Username};
+use interledger_service_util::{convert_amount, FeeStore, MaxPacketAmountAccount};
+use interledger_spsp::query;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr, time::Duration};
+use tracing::debug;
+use url::Url;
+use warp::{self, Filter, Rejection};
+
+/// Parameters of a quote request
+struct QuoteQuery {
+    source_account: Username,
+    destination: String,
+    source_amount: u64,
+}
+
+impl QuoteQuery {
+    fn from_params(params: &HashMap<String, String>) -> Option<Self> {
+        Some(QuoteQuery {
+            source_account: Username::from_str(params.get("source_account")?).ok()?,
+            destination: params.get("destination")?.clone(),
+            source_amount: params.get("source_amount")?.parse().ok()?,
+        })
+    }
+}
+
+/// Estimate of the amount a payment would deliver, based on the node's
+/// exchange rates, fees and the source account's maximum packet amount
+#[derive(Debug, Clone, Serialize)]
+pub struct Quote {
+    pub source_account: Username,
+    pub source_asset_code: String,
+    pub source_asset_scale: u8,
+    pub source_amount: u64,
+    /// The payment's destination, resolved from the payment pointer if one was given
+    pub destination_account: Address,
+    /// The account the payment would be forwarded to
+    pub next_hop_account: Username,
+    pub destination_asset_code: String,
+    pub destination_asset_scale: u8,
+    /// Amount forwarded to the next hop, in its asset. Nodes further along
+    /// the path may charge fees and convert the amount again
+    pub destination_amount: u64,
+    /// Maximum amount of each of the payment's packets, in the source asset
+    pub max_packet_amount: u64,
+    /// Number of packets the payment would be split into
+    pub packets: u64,
+    /// Age, in milliseconds, of the oldest exchange rate used to convert the amount
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange_rate_age_ms: Option<u64>,
+}
+
+/// Computes the quote for sending `source_amount` from the account to `destination`.
+/// Like packets, quotes are rejected if they rely on a rate older than `max_rate_age`
+pub async fn quote<S, A>(
+    store: &S,
+    spread: &Rate,
+    max_rate_age: Option<Duration>,
+    from: &A,
+    destination: Address,
+    source_amount: u64,
+) -> Result<Quote, ApiError>
+where
+    S: ExchangeRateStore + FeeStore + RouterStore<Account = A>,
+    A: Account + MaxPacketAmountAccount,
+{
+    let next_hop = find_next_hop(&store.routing_table(), &destination)
+        .map(|(_, account_id)| account_id)
+        .ok_or_else(|| {
+            ApiError::not_found().detail(format!("No route found for {}", destination))
+        })?;
+    let to = store
+        .get_accounts(vec![next_hop])
+        .await?
+        .pop()
+        .ok_or_else(ApiError::account_not_found)?;
+
+    let no_rate = || {
+        ApiError::not_found().detail(format!(
+            "No exchange rate available from {} to {}",
+            from.asset_code(),
+            to.asset_code()
+        ))
+    };
+    let (rate, exchange_rate_age_ms) = if from.asset_code() == to.asset_code() {
+        (Rate::one(), None)
+    } else {
+        let rates = store
+            .get_exchange_rates(&[from.asset_code(), to.asset_code()])
+            .map_err(|_| no_rate())?;
+        let age = rates
+            .iter()
+            .map(|rate| rate.age())
+            .max()
+            .unwrap_or_default();
+        if let Some(max_rate_age) = max_rate_age {
+            if age > max_rate_age {
+                return Err(ApiError::not_found().detail(format!(
+                    "The exchange rate from {} to {} is older than {}ms",
+                    from.asset_code(),
+                    to.asset_code(),
+                    max_rate_age.as_millis()
+                )));
+            }
+        }
+        let rate = rates[0]
+            .rate
+            .checked_div(&rates[1].rate)
+            .ok_or_else(no_rate)?;
+        (rate, Some(age.as_millis() as u64))
+    };
+
+    // Fees are charged per packet, so the amount is converted the way each packet would be
+    let fee_schedule = store.get_fee_schedule();
+    let fee_policy = fee_schedule.policy_for(from, &to);
+    let convert = |amount: u64| {
+        convert_amount(
+            amount,
+            &rate,
+            from.asset_scale(),
+            to.asset_scale(),
+            spread,
+            fee_policy,
+        )
+        .amount
+    };
+    let too_large = || ApiError::bad_request().detail("Amount too large to be converted");
+    let max_packet_amount = from.max_packet_amount().max(1);
+    let full_packets = source_amount / max_packet_amount;
+    let remainder = source_amount % max_packet_amount;
+    let mut destination_amount = 0u64;
+    if full_packets > 0 {
+        destination_amount = convert(max_packet_amount)
+            .and_then(|amount| amount.checked_mul(full_packets))
+            .ok_or_else(too_large)?;
+    }
+    if remainder > 0 {
+        destination_amount = convert(remainder)
+            .and_then(|amount| destination_amount.checked_add(amount))
+            .ok_or_else(too_large)?;
+    }
+
+    Ok(Quote {
+        source_account: from.username().clone(),
+        source_asset_code: from.asset_code().to_string(),
+        source_asset_scale: from.asset_scale(),
+        source_amount,
+        destination_account: destination,
+        next_hop_account: to.username().clone(),
+        destination_asset_code: to.asset_code().to_string(),
+        destination_asset_scale: to.asset_scale(),
+        destination_amount,
+        max_packet_amount,
+        packets: full_packets + if remainder > 0 { 1 } else { 0 },
+        exchange_rate_age_ms,
+    })
+}
+
+/// Returns the host of a payment pointer
+fn payment_pointer_host(payment_pointer: &str) -> Option<String> {
+    let url = Url::parse(&format!("https://{}", payment_pointer.strip_prefix('$')?)).ok()?;
+    url.host_str().map(|host| host.to_lowercase())
+}
+
+/// Resolves the destination of a quote, which may be an ILP address or a payment pointer.
+/// Since resolving a payment pointer makes the node send a request to its host, only
+/// the admin may resolve SPSP URLs and payment pointers on hosts which are not allowed
+async fn resolve_destination(
+    destination: &str,
+    is_admin: bool,
+    allowed_hosts: &[String],
+) -> Result<Address, ApiError> {
+    if destination.starts_with('$') || destination.starts_with("http") {
+        let allowed = is_admin
+            || payment_pointer_host(destination)
+                .map(|host| {
+                    allowed_hosts
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+                })
+                .unwrap_or(false);
+        if !allowed {
+            return Err(ApiError::unauthorized().detail(format!(
+                "Only the admin can quote payments to {}",
+                destination
+            )));
+        }
+        let spsp = query(destination).await.map_err(|err| {
+            ApiError::bad_request().detail(format!("Error querying {}: {}", destination, err))
+        })?;
+        debug!("Resolved {} to {}", destination, spsp.destination_account());
+        Ok(spsp.destination_account().clone())
+    } else {
+        Address::from_str(destination).map_err(|_| {
+            ApiError::bad_request().detail(format!(
+                "Invalid destination (expected an ILP address or payment pointer): {}",
+                destination
+            ))
+        })
+    }
+}
+
+pub fn quote_api<S, A>(
+    admin_api_token: String,
+    exchange_rate_spread: Rate,
+    max_rate_age: Option<Duration>,
+    spsp_allowed_hosts: Vec<String>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AccountStore<Account = A>
+        + HttpStore<Account = A>
+        + RouterStore<Account = A>
+        + ExchangeRateStore
+        + FeeStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + MaxPacketAmountAccount + Send + Sync + 'static,
+{
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let with_store = warp::any().map(move || store.clone());
+    let with_spread = warp::any().map(move || exchange_rate_spread.clone());
+
+    // GET /quote?source_account=:username&destination=:address_or_payment_pointer&source_amount=:amount
+    // Only the admin and the source account can request quotes
+    warp::get()
+        .and(warp::path("quote"))
+        .and(warp::path::end())
+        .and(warp::header::<SecretString>("authorization"))
+        // Parse the query in the handler so that invalid parameters are reported
+        // as a bad request rather than rejected in favor of the other routes
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_store)
+        .and(with_spread)
+        .and_then(
+            move |auth_string: SecretString,
+                  params: HashMap<String, String>,
+                  store: S,
+                  spread: Rate| {
+                let is_admin = auth_string.expose_secret() == &admin_auth_header;
+                let spsp_allowed_hosts = spsp_allowed_hosts.clone();
+                async move {
+                    let query = QuoteQuery::from_params(&params)
+                        .ok_or_else(|| Rejection::from(ApiError::bad_request()))?;
+
+                    let from = if is_admin {
+                        let id = store
+                            .get_account_id_from_username(&query.source_account)
+                            .await?;
+                        store
+                            .get_accounts(vec![id])
+                            .await?
+                            .pop()
+                            .ok_or_else(ApiError::account_not_found)?
+                    } else if auth_string.expose_secret().len() >= BEARER_TOKEN_START {
+                        store
+                            .get_account_from_http_auth(
+                                &query.source_account,
+                                &auth_string.expose_secret()[BEARER_TOKEN_START..],
+                            )
+                            .await?
+                    } else {
+                        return Err(Rejection::from(ApiError::unauthorized()));
+                    };
+
+                    let destination =
+                        resolve_destination(&query.destination, is_admin, &spsp_allowed_hosts)
+                            .await?;
+                    let quote = quote(
+                        &store,
+                        &spread,
+                        max_rate_age,
+                        &from,
+                        destination,
+                        query.source_amount,
+                    )
+                    .await?;
+                    Ok::<_, Rejection>(warp::reply::json(&quote))
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::payment_pointer_host;
+    use crate::routes::test_helpers::{api_call, test_quote_api};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn quotes_payments() {
+        let api = test_quote_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/quote?source_account=alice&destination=example.bob&source_amount=250",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let quote: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(quote["destination_account"], "example.bob");
+        assert_eq!(quote["next_hop_account"], "alice");
+        // 1% spread charged on each of the 100, 100 and 50 packets
+        assert_eq!(quote["destination_amount"], 99 + 99 + 49);
+        assert_eq!(quote["packets"], 3);
+
+        // The source account can request quotes too
+        let resp = api_call(
+            &api,
+            "GET",
+            "/quote?source_account=alice&destination=example.bob&source_amount=250",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_quote_requests() {
+        let api = test_quote_api();
+        for (query, status) in &[
+            ("source_account=alice&destination=example.bob", 400),
+            (
+                "source_account=alice&destination=not%20an%20address&source_amount=1",
+                400,
+            ),
+            (
+                "source_account=alice&destination=test.bob&source_amount=1",
+                404,
+            ),
+        ] {
+            let resp = api_call(&api, "GET", &format!("/quote?{}", query), "admin", None).await;
+            assert_eq!(resp.status().as_u16(), *status, "{}", query);
+        }
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/quote?source_account=alice&destination=example.bob&source_amount=1",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_resolves_payment_pointers_on_other_hosts() {
+        let api = test_quote_api();
+        for destination in &[
+            "$internal.example/alice",
+            "http://localhost:7770/spsp/alice",
+        ] {
+            let resp = api_call(
+                &api,
+                "GET",
+                &format!(
+                    "/quote?source_account=alice&destination={}&source_amount=1",
+                    destination
+                ),
+                "password",
+                None,
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 401, "{}", destination);
+        }
+    }
+
+    #[test]
+    fn parses_payment_pointer_host() {
+        assert_eq!(
+            payment_pointer_host("$Wallet.Example/alice"),
+            Some("wallet.example".to_string())
+        );
+        assert_eq!(
+            payment_pointer_host("$wallet.example:8080"),
+            Some("wallet.example".to_string())
+        );
+        assert_eq!(payment_pointer_host("https://wallet.example/alice"), None);
+    }
+}
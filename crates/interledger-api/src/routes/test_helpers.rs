@@ -1,24 +1,40 @@
 use crate::{
-    routes::{accounts_api, node_settings_api},
+    routes::{accounts_api, node_settings_api, quote_api},
     AccountDetails, AccountSettings, NodeStore,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use futures::channel::mpsc::UnboundedSender;
 use http::Response;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
-use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
+use interledger_ccp::{
+    CcpRoutingAccount, PeerTableDetails, RouteDetails, RouteDetailsStore, RoutePolicy,
+    RoutePolicyStore, RouteSource, RoutingRelation, RoutingTableDetails,
+};
 use interledger_errors::*;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{
+    ExchangeRate, ExchangeRateStore, ExchangeRateSubscriptionStore, Rate, RateHistoryStore,
+};
 use interledger_router::RouterStore;
 use interledger_service::{
     incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
+use interledger_service_util::{
+    BalanceAlert, BalanceAlertStore, BalanceStore, FeeSchedule, FeeStore, MaxPacketAmountAccount,
+    RevenueEntry, RevenueStore,
+};
+use interledger_settlement::core::{
+    reconciliation::{ReconciliationState, SettlementJournalStore, SettlementTotals},
+    types::{
+        LeftoversStore, SettlementAccount, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
+};
 use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
@@ -59,6 +75,18 @@ pub fn test_node_settings_api(
     node_settings_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
 }
 
+pub fn test_quote_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    quote_api(
+        "admin".to_owned(),
+        Rate::from_str("0.01").unwrap(),
+        None,
+        vec!["wallet.example".to_string()],
+        TestStore,
+    )
+    .recover(default_rejection_handler)
+}
+
 pub fn test_accounts_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let incoming = incoming_service_fn(|_request| {
@@ -86,6 +114,7 @@ pub fn test_accounts_api(
         Bytes::from("admin"),
         "admin".to_owned(),
         None,
+        None,
         incoming,
         outgoing,
         btp,
@@ -142,6 +171,12 @@ impl Account for TestAccount {
     }
 }
 
+impl MaxPacketAmountAccount for TestAccount {
+    fn max_packet_amount(&self) -> u64 {
+        100
+    }
+}
+
 impl HttpAccount for TestAccount {
     fn get_http_auth_token(&self) -> Option<SecretString> {
         unimplemented!()
@@ -194,31 +229,137 @@ impl AccountStore for TestStore {
     }
 }
 
+#[async_trait]
 impl ExchangeRateStore for TestStore {
     fn get_exchange_rates(
         &self,
         _asset_codes: &[&str],
-    ) -> Result<Vec<f64>, ExchangeRateStoreError> {
-        Ok(vec![1.0, 2.0])
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
+        Ok(vec![
+            ExchangeRate::new(Rate::from(1), "test"),
+            ExchangeRate::new(Rate::from(2), "test"),
+        ])
     }
 
-    fn set_exchange_rates(
+    async fn set_exchange_rates(
         &self,
-        _rates: HashMap<String, f64>,
+        _rates: HashMap<String, ExchangeRate>,
     ) -> Result<(), ExchangeRateStoreError> {
         Ok(())
     }
 
-    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+    fn get_all_exchange_rates(
+        &self,
+    ) -> Result<HashMap<String, ExchangeRate>, ExchangeRateStoreError> {
         let mut ret = HashMap::new();
-        ret.insert("ABC".to_owned(), 1.0);
-        ret.insert("XYZ".to_owned(), 2.0);
+        ret.insert("ABC".to_owned(), ExchangeRate::new(Rate::from(1), "test"));
+        ret.insert(
+            "XYZ".to_owned(),
+            ExchangeRate::new(Rate::from_str("2.5").unwrap(), "test"),
+        );
         Ok(ret)
     }
 }
 
+pub static RATE_UPDATES: Lazy<broadcast::Sender<Arc<HashMap<String, ExchangeRate>>>> =
+    Lazy::new(|| broadcast::channel(16).0);
+
+impl ExchangeRateSubscriptionStore for TestStore {
+    fn subscribe_to_exchange_rates(
+        &self,
+    ) -> broadcast::Receiver<Arc<HashMap<String, ExchangeRate>>> {
+        RATE_UPDATES.subscribe()
+    }
+}
+
+#[async_trait]
+impl RoutePolicyStore for TestStore {
+    async fn get_route_policies(&self) -> Result<HashMap<Uuid, RoutePolicy>, CcpRoutingStoreError> {
+        Ok(HashMap::new())
+    }
+
+    async fn set_route_policy(
+        &self,
+        _account_id: Uuid,
+        _policy: RoutePolicy,
+    ) -> Result<(), CcpRoutingStoreError> {
+        Ok(())
+    }
+
+    fn subscribe_to_route_policies(&self) -> broadcast::Receiver<Uuid> {
+        broadcast::channel(1).1
+    }
+}
+
+#[async_trait]
+impl RouteDetailsStore for TestStore {
+    async fn get_route_details(&self) -> Result<RoutingTableDetails, CcpRoutingStoreError> {
+        let bob = Username::from_str("bob").unwrap();
+        let mut details = RoutingTableDetails {
+            routing_table_id: "01".repeat(16),
+            epoch: 4,
+            ..Default::default()
+        };
+        details.prefixes.insert(
+            "example.remote".to_string(),
+            vec![RouteDetails {
+                prefix: "example.remote".to_string(),
+                source: RouteSource::Learned,
+                account_id: Uuid::nil(),
+                username: bob.clone(),
+                path: vec!["example.bob".to_string()],
+                props: Vec::new(),
+                has_auth: false,
+                authenticated: false,
+            }],
+        );
+        details.peers.push(PeerTableDetails {
+            account_id: Uuid::nil(),
+            username: bob,
+            routing_table_id: "02".repeat(16),
+            epoch: 7,
+            routes: 1,
+        });
+        Ok(details)
+    }
+
+    async fn set_route_details(
+        &self,
+        _details: RoutingTableDetails,
+    ) -> Result<(), CcpRoutingStoreError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateHistoryStore for TestStore {
+    async fn get_rate_history(
+        &self,
+        asset_code: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
+        if asset_code != "XYZ" || since.is_some() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![
+            ExchangeRate::new(Rate::from(2), "test"),
+            ExchangeRate::new(Rate::from(3), "test"),
+        ])
+    }
+}
+
 impl RouterStore for TestStore {
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        let mut routes = HashMap::new();
+        routes.insert("example.bob".to_owned(), Uuid::nil());
+        Arc::new(routes)
+    }
+
+    fn alternate_routes(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+        Arc::new(HashMap::new())
+    }
+
+    fn multipath_routes(&self) -> Arc<HashMap<String, Vec<(Uuid, u32)>>> {
         Arc::new(HashMap::new())
     }
 }
@@ -273,13 +414,31 @@ impl NodeStore for TestStore {
         Ok(())
     }
 
+    async fn set_static_multipath_route(
+        &self,
+        _prefix: String,
+        _members: Vec<(Uuid, u32)>,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn set_static_routes_with_multipath(
+        &self,
+        _routes: Vec<(String, Uuid)>,
+        _multipath_routes: Vec<(String, Vec<(Uuid, u32)>)>,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
     async fn set_default_route(&self, _account_id: Uuid) -> Result<(), NodeStoreError> {
         unimplemented!()
     }
 
     async fn set_settlement_engines(
         &self,
-        _asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+        _asset_to_engine_map: impl IntoIterator<Item = (String, SettlementEngineDetails)>
+            + Send
+            + 'async_trait,
     ) -> Result<(), NodeStoreError> {
         Ok(())
     }
@@ -287,7 +446,7 @@ impl NodeStore for TestStore {
     async fn get_asset_settlement_engine(
         &self,
         _asset_code: &str,
-    ) -> Result<Option<Url>, NodeStoreError> {
+    ) -> Result<Option<SettlementEngineDetails>, NodeStoreError> {
         Ok(None)
     }
 }
@@ -360,6 +519,174 @@ impl BalanceStore for TestStore {
     }
 }
 
+#[async_trait]
+impl FeeStore for TestStore {
+    fn get_fee_schedule(&self) -> Arc<FeeSchedule> {
+        Arc::new(FeeSchedule::default())
+    }
+
+    async fn set_fee_schedule(&self, _schedule: FeeSchedule) -> Result<(), FeeStoreError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RevenueStore for TestStore {
+    async fn add_revenue(&self, _revenue: Vec<RevenueEntry>) -> Result<(), RevenueStoreError> {
+        unimplemented!()
+    }
+
+    async fn remove_revenue_before(&self, _before: DateTime<Utc>) -> Result<(), RevenueStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_revenue(
+        &self,
+        since: Option<DateTime<Utc>>,
+        _until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<RevenueEntry>, RevenueStoreError> {
+        if since.is_some() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![RevenueEntry {
+            bucket_start: Utc::now(),
+            from_account: USERNAME.clone(),
+            to_account: USERNAME.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            amount: 12,
+            reporting_asset: "USD".to_string(),
+            reporting_scale: 9,
+            reporting_amount: 24,
+            packets: 3,
+        }])
+    }
+}
+
+#[async_trait]
+impl BalanceAlertStore for TestStore {
+    async fn save_balance_alert(&self, _: BalanceAlert) -> Result<(), BalanceStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_balance_alerts(
+        &self,
+        _: Option<Uuid>,
+    ) -> Result<Vec<BalanceAlert>, BalanceStoreError> {
+        Ok(Vec::new())
+    }
+}
+
+#[async_trait]
+impl SettlementStore for TestStore {
+    type Account = TestAccount;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        _: Uuid,
+        _: u64,
+        _: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        unimplemented!()
+    }
+
+    async fn refund_settlement(&self, _: Uuid, _: u64) -> Result<(), SettlementStoreError> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for TestStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _: (BigUint, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _: u8,
+    ) -> Result<BigUint, LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn clear_uncredited_settlement_amount(&self, _: Uuid) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+    ) -> Result<(BigUint, u8), LeftoversStoreError> {
+        Ok((BigUint::from(0u32), 0))
+    }
+}
+
+#[async_trait]
+impl SettlementJournalStore for TestStore {
+    async fn get_settlement_totals(
+        &self,
+        _: Uuid,
+    ) -> Result<SettlementTotals, SettlementStoreError> {
+        Ok(SettlementTotals::default())
+    }
+
+    async fn get_reconciliation_state(
+        &self,
+        _: Uuid,
+    ) -> Result<Option<ReconciliationState>, SettlementStoreError> {
+        Ok(Some(ReconciliationState::default()))
+    }
+
+    async fn set_reconciliation_state(
+        &self,
+        _: Uuid,
+        _: ReconciliationState,
+    ) -> Result<(), SettlementStoreError> {
+        Ok(())
+    }
+
+    async fn refund_unsent_settlement(
+        &self,
+        _: Uuid,
+        _: u64,
+        _: String,
+    ) -> Result<(), SettlementStoreError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementQueueStore for TestStore {
+    async fn settlement_sent(&self, _: Uuid, _: u64) -> Result<(), SettlementStoreError> {
+        Ok(())
+    }
+
+    async fn queue_settlement(&self, _: Uuid, _: u64, _: &str) -> Result<(), SettlementStoreError> {
+        Ok(())
+    }
+
+    async fn claim_queued_settlement(&self, _: Uuid, _: &str) -> Result<u64, SettlementStoreError> {
+        Ok(0)
+    }
+
+    async fn get_queued_settlements(
+        &self,
+    ) -> Result<Vec<(Uuid, String, u64)>, SettlementStoreError> {
+        Ok(Vec::new())
+    }
+
+    async fn get_pending_settlement(&self, _: Uuid) -> Result<u64, SettlementStoreError> {
+        Ok(0)
+    }
+}
+
 #[async_trait]
 impl HttpStore for TestStore {
     type Account = TestAccount;
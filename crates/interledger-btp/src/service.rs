@@ -369,8 +369,9 @@ where
                                 "Sending request {} to account {} failed: {:?}",
                                 request_id, account_id, err
                             );
+                            // The connection was closed before the peer responded
                             Err(RejectBuilder {
-                                code: ErrorCode::T00_INTERNAL_ERROR,
+                                code: ErrorCode::T01_PEER_UNREACHABLE,
                                 message: &[],
                                 triggered_by: Some(&ilp_address),
                                 data: &[],
@@ -385,7 +386,7 @@ where
                         request_id, account_id, send_error
                     );
                     Err(RejectBuilder {
-                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        code: ErrorCode::T01_PEER_UNREACHABLE,
                         message: &[],
                         triggered_by: Some(&ilp_address),
                         data: &[],
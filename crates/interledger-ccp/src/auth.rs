@@ -0,0 +1,78 @@
+use crate::packet::Route;
+use ring::{
+    digest::{digest, SHA256},
+    hmac,
+};
+
+/// Length of the hash chain used to authenticate routes, i.e. the maximum number
+/// of hops an authenticated route can be advertised over
+pub const ROUTE_AUTH_CHAIN_LENGTH: usize = 64;
+
+pub(crate) fn hash(preimage: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0; 32];
+    out.copy_from_slice(digest(&SHA256, preimage).as_ref());
+    out
+}
+
+fn hash_times(preimage: &[u8; 32], times: usize) -> [u8; 32] {
+    (0..times).fold(*preimage, |value, _| hash(&value))
+}
+
+/// Returns the auth the owner of the prefix advertises its route with,
+/// which is the start of the prefix's hash chain
+pub(crate) fn route_auth(secret: &[u8; 32], prefix: &str) -> [u8; 32] {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &secret[..]);
+    let mut auth = [0; 32];
+    auth.copy_from_slice(hmac::sign(&key, prefix.as_bytes()).as_ref());
+    auth
+}
+
+/// Returns the anchor of the prefix's hash chain, which the nodes receiving
+/// routes for the prefix need to be configured with to authenticate them
+pub fn route_auth_anchor(secret: &[u8; 32], prefix: &str) -> [u8; 32] {
+    hash_times(&route_auth(secret, prefix), ROUTE_AUTH_CHAIN_LENGTH)
+}
+
+/// Returns true if the route's auth belongs to the hash chain ending with the anchor,
+/// at the position given by the length of its path. Since each node hashes the auth
+/// before forwarding the route, a node can make a route longer but not shorter
+pub(crate) fn verify_route_auth(route: &Route, anchor: &[u8; 32]) -> bool {
+    route.path.len() <= ROUTE_AUTH_CHAIN_LENGTH
+        && hash_times(&route.auth, ROUTE_AUTH_CHAIN_LENGTH - route.path.len()) == *anchor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_routes_along_the_hash_chain() {
+        let secret = [7; 32];
+        let anchor = route_auth_anchor(&secret, "example.a");
+        let mut route = Route {
+            prefix: "example.a".to_string(),
+            path: Vec::new(),
+            auth: route_auth(&secret, "example.a"),
+            props: Vec::new(),
+        };
+        assert!(verify_route_auth(&route, &anchor));
+
+        // Each hop adds itself to the path and hashes the auth
+        route.path.insert(0, "example.b".to_string());
+        route.auth = hash(&route.auth);
+        assert!(verify_route_auth(&route, &anchor));
+
+        // Shortening the path makes the auth invalid
+        route.path.clear();
+        assert!(!verify_route_auth(&route, &anchor));
+
+        // So does using the auth of another prefix
+        let other = Route {
+            prefix: "example.a".to_string(),
+            path: Vec::new(),
+            auth: route_auth(&secret, "example.c"),
+            props: Vec::new(),
+        };
+        assert!(!verify_route_auth(&other, &anchor));
+    }
+}
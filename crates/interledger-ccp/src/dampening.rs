@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Parameters of the route flap dampening, which suppresses the routes a peer keeps
+/// withdrawing and advertising again. Each withdrawal adds a penalty to the peer's route
+/// for the prefix, which decays exponentially. The route is suppressed once its penalty
+/// reaches the suppress threshold, until it decays below the reuse threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteDampening {
+    /// Penalty added each time the route is withdrawn
+    pub flap_penalty: f64,
+    /// Penalty at which the route is suppressed
+    pub suppress_threshold: f64,
+    /// Penalty below which a suppressed route is used again
+    pub reuse_threshold: f64,
+    /// Time, in milliseconds, it takes for the penalty to decay by half
+    pub half_life: u64,
+    /// Maximum time, in milliseconds, a route stays suppressed after its last withdrawal
+    pub max_suppress_time: u64,
+}
+
+impl Default for RouteDampening {
+    fn default() -> Self {
+        RouteDampening {
+            flap_penalty: 1000.0,
+            suppress_threshold: 2000.0,
+            reuse_threshold: 750.0,
+            half_life: 900_000,
+            max_suppress_time: 3_600_000,
+        }
+    }
+}
+
+impl RouteDampening {
+    fn decay(&self, penalty: f64, since: Instant, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(since).as_millis() as f64;
+        penalty * 0.5f64.powf(elapsed / self.half_life.max(1) as f64)
+    }
+
+    /// The penalty which decays to the reuse threshold in the maximum suppress time
+    fn max_penalty(&self) -> f64 {
+        self.reuse_threshold
+            * 2f64.powf(self.max_suppress_time as f64 / self.half_life.max(1) as f64)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FlapPenalty {
+    penalty: f64,
+    updated_at: Instant,
+    suppressed: bool,
+}
+
+/// Keeps track of the penalty of the routes received from each account
+#[derive(Clone, Debug)]
+pub(crate) struct FlapDampener {
+    config: RouteDampening,
+    penalties: HashMap<(Uuid, String), FlapPenalty>,
+}
+
+impl FlapDampener {
+    pub(crate) fn new(config: RouteDampening) -> Self {
+        FlapDampener {
+            config,
+            penalties: HashMap::new(),
+        }
+    }
+
+    /// Penalize the account's route for the prefix because it was withdrawn.
+    /// Returns true if the route just got suppressed
+    pub(crate) fn record_flap(&mut self, account_id: Uuid, prefix: &str, now: Instant) -> bool {
+        let config = self.config;
+        let flap = self
+            .penalties
+            .entry((account_id, prefix.to_string()))
+            .or_insert(FlapPenalty {
+                penalty: 0.0,
+                updated_at: now,
+                suppressed: false,
+            });
+        flap.penalty = (config.decay(flap.penalty, flap.updated_at, now) + config.flap_penalty)
+            .min(config.max_penalty());
+        flap.updated_at = now;
+        if !flap.suppressed && flap.penalty >= config.suppress_threshold {
+            flap.suppressed = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Decay the penalties and reuse the routes whose penalty dropped below the reuse
+    /// threshold. Returns the prefixes of the suppressed routes received from each account
+    pub(crate) fn suppressed_routes(&mut self, now: Instant) -> HashMap<Uuid, HashSet<String>> {
+        let config = self.config;
+        let mut suppressed: HashMap<Uuid, HashSet<String>> = HashMap::new();
+        self.penalties.retain(|(account_id, prefix), flap| {
+            flap.penalty = config.decay(flap.penalty, flap.updated_at, now);
+            flap.updated_at = now;
+            if flap.suppressed && flap.penalty < config.reuse_threshold {
+                flap.suppressed = false;
+            }
+            if flap.suppressed {
+                suppressed
+                    .entry(*account_id)
+                    .or_default()
+                    .insert(prefix.clone());
+            }
+            // Forget the penalties which are too low to get the route suppressed again soon
+            flap.suppressed || flap.penalty >= config.reuse_threshold / 2.0
+        });
+        suppressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn suppresses_flapping_routes_until_the_penalty_decays() {
+        let mut dampener = FlapDampener::new(RouteDampening {
+            half_life: 1000,
+            ..Default::default()
+        });
+        let account_id = Uuid::new_v4();
+        let start = Instant::now();
+
+        assert!(!dampener.record_flap(account_id, "example.a", start));
+        assert!(dampener.suppressed_routes(start).is_empty());
+        assert!(dampener.record_flap(account_id, "example.a", start));
+        assert!(dampener.suppressed_routes(start)[&account_id].contains("example.a"));
+
+        // 2000 decays to 1000 after one half life, which is still above the reuse threshold
+        let later = start + Duration::from_millis(1000);
+        assert!(dampener.suppressed_routes(later)[&account_id].contains("example.a"));
+
+        // and to 500 after two
+        let later = start + Duration::from_millis(2000);
+        assert!(dampener.suppressed_routes(later).is_empty());
+    }
+
+    #[test]
+    fn caps_the_penalty() {
+        let mut dampener = FlapDampener::new(RouteDampening {
+            half_life: 1000,
+            max_suppress_time: 2000,
+            ..Default::default()
+        });
+        let account_id = Uuid::new_v4();
+        let start = Instant::now();
+        for _ in 0..10 {
+            dampener.record_flap(account_id, "example.a", start);
+        }
+
+        // The route is reused at most 2 seconds after its last withdrawal
+        let later = start + Duration::from_millis(2001);
+        assert!(dampener.suppressed_routes(later).is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::packet::{Route, RouteProp};
+use interledger_service::Username;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Where a route comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteSource {
+    /// A static route configured by the node's operator
+    Configured,
+    /// The route to one of the node's own accounts
+    Local,
+    /// A route received from a peer over CCP
+    Learned,
+}
+
+/// A property advertised with a route
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutePropDetails {
+    pub id: u16,
+    pub is_optional: bool,
+    pub is_transitive: bool,
+    pub is_partial: bool,
+    pub is_utf8: bool,
+    /// The value of the property, hex-encoded unless it is UTF-8
+    pub value: String,
+}
+
+impl From<&RouteProp> for RoutePropDetails {
+    fn from(prop: &RouteProp) -> Self {
+        let value = if prop.is_utf8 {
+            String::from_utf8_lossy(&prop.value[..]).to_string()
+        } else {
+            hex::encode(&prop.value[..])
+        };
+        RoutePropDetails {
+            id: prop.id,
+            is_optional: prop.is_optional,
+            is_transitive: prop.is_transitive,
+            is_partial: prop.is_partial,
+            is_utf8: prop.is_utf8,
+            value,
+        }
+    }
+}
+
+/// One of the routes the node has for a prefix
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteDetails {
+    /// The prefix the route was configured or advertised for, which may be
+    /// shorter than the prefix it is used for
+    pub prefix: String,
+    pub source: RouteSource,
+    /// The account packets for the prefix are forwarded to with this route,
+    /// i.e. the peer a learned route was received from
+    pub account_id: Uuid,
+    pub username: Username,
+    /// The addresses of the nodes along the route, starting with the next hop
+    pub path: Vec<String>,
+    pub props: Vec<RoutePropDetails>,
+    /// Whether the route was advertised with an auth
+    pub has_auth: bool,
+    /// Whether the route's auth was verified against the anchor configured for its prefix.
+    /// Configured and local routes are always authenticated
+    pub authenticated: bool,
+}
+
+impl RouteDetails {
+    pub(crate) fn new(
+        prefix: String,
+        source: RouteSource,
+        account_id: Uuid,
+        username: Username,
+        route: &Route,
+        authenticated: bool,
+    ) -> Self {
+        RouteDetails {
+            prefix,
+            source,
+            account_id,
+            username,
+            path: route.path.clone(),
+            props: route.props.iter().map(RoutePropDetails::from).collect(),
+            has_auth: route.auth != [0; 32],
+            authenticated,
+        }
+    }
+}
+
+/// The routing table received from a peer, as far as we are in sync with it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerTableDetails {
+    pub account_id: Uuid,
+    pub username: Username,
+    /// The ID of the peer's routing table, hex-encoded
+    pub routing_table_id: String,
+    /// The epoch of the peer's routing table we have applied the updates up to
+    pub epoch: u32,
+    /// The number of routes in the peer's routing table
+    pub routes: usize,
+}
+
+/// Snapshot of the routing tables maintained by the `CcpRouteManager`, taken
+/// every time it recalculates all of its routes, which is used to inspect them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingTableDetails {
+    /// The ID of the routing table advertised to our peers, hex-encoded
+    pub routing_table_id: String,
+    /// The current epoch of the routing table advertised to our peers
+    pub epoch: u32,
+    /// All the routes we have for each prefix, ranked from best to worst.
+    /// Packets for the prefix are forwarded with the first one
+    pub prefixes: BTreeMap<String, Vec<RouteDetails>>,
+    /// The routing tables received from the peers, sorted by username
+    pub peers: Vec<PeerTableDetails>,
+}
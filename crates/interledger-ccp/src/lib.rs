@@ -12,19 +12,36 @@
 use async_trait::async_trait;
 use interledger_errors::CcpRoutingStoreError;
 use interledger_service::Account;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::{fmt, str::FromStr};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+mod auth;
+mod dampening;
+mod details;
 #[cfg(test)]
 mod fixtures;
 mod packet;
+mod parents;
+mod policy;
 mod routing_table;
+mod scoring;
 mod server;
 #[cfg(test)]
 mod test_helpers;
 
+pub use auth::{route_auth_anchor, ROUTE_AUTH_CHAIN_LENGTH};
+pub use dampening::RouteDampening;
+pub use details::{
+    PeerTableDetails, RouteDetails, RoutePropDetails, RouteSource, RoutingTableDetails,
+};
 pub use packet::{Mode, RouteControlRequest};
+pub use parents::{
+    get_address_from_parent, request_routes_from_parent, use_address_from_parent, ParentError,
+};
+pub use policy::{RoutePolicy, UnauthenticatedRoutes};
+pub use scoring::{RouteScoring, ROUTE_PROP_FEE, ROUTE_PROP_LATENCY, ROUTE_PROP_LIQUIDITY};
 pub use server::{CcpRouteManager, CcpRouteManagerBuilder};
 
 use serde::{Deserialize, Serialize};
@@ -95,6 +112,18 @@ pub trait CcpRoutingAccount: Account {
     }
 }
 
+/// The identity and history of the routing table a node advertises to its peers,
+/// saved so that the node can keep sending them incremental updates after it restarts
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ForwardingTableState {
+    /// The ID of the routing table
+    pub table_id: [u8; 16],
+    /// The update applied at each epoch, encoded as the new and withdrawn routes of a
+    /// Route Update Request. Once the updates are compacted, the first one combines all
+    /// the updates up to its epoch. The current epoch of the table is the one after the last update
+    pub updates: BTreeMap<u32, Vec<u8>>,
+}
+
 // key = Bytes, key should be Address -- TODO
 type Routes<T> = HashMap<String, T>;
 type LocalAndConfiguredRoutes<T> = (Routes<T>, Routes<T>);
@@ -127,4 +156,68 @@ pub trait CcpRoutingStore: Clone {
         &mut self,
         routes: impl IntoIterator<Item = (String, Self::Account)> + Send + 'async_trait,
     ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Sets the alternate next hops for each prefix, ranked from best to worst,
+    /// which packets are retried on if the next hop set with `set_routes` rejects them
+    async fn set_alternate_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Vec<Self::Account>)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Gets the ID and the updates of the routing table advertised to the node's peers,
+    /// as saved with `save_forwarding_table_update`
+    async fn get_forwarding_table(
+        &self,
+    ) -> Result<Option<ForwardingTableState>, CcpRoutingStoreError>;
+
+    /// Saves the update which moved the advertised routing table from the given epoch
+    /// to the next one. The updates saved for another table ID are discarded
+    async fn save_forwarding_table_update(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        update: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Replaces the updates saved up to the given epoch with a snapshot combining all of
+    /// them, so that the saved updates do not grow indefinitely. The snapshot is ignored
+    /// if the saved updates are for another table ID
+    async fn compact_forwarding_table(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        snapshot: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError>;
+}
+
+/// Store trait for the policies applied to the routes exchanged with each account
+#[async_trait]
+pub trait RoutePolicyStore {
+    /// Gets the route policies of the accounts which have one
+    async fn get_route_policies(&self) -> Result<HashMap<Uuid, RoutePolicy>, CcpRoutingStoreError>;
+
+    /// Sets the route policy of an account. Setting the default policy removes it
+    async fn set_route_policy(
+        &self,
+        account_id: Uuid,
+        policy: RoutePolicy,
+    ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Subscribes to the IDs of the accounts whose route policy is set from now on,
+    /// whether by this instance or another one sharing the store
+    fn subscribe_to_route_policies(&self) -> broadcast::Receiver<Uuid>;
+}
+
+/// Store trait for the snapshot of the routing tables taken by the `CcpRouteManager`
+#[async_trait]
+pub trait RouteDetailsStore {
+    /// Gets the last snapshot of the routing tables. It is taken on every route broadcast
+    /// interval, so it may be up to one interval behind the routes in use.
+    async fn get_route_details(&self) -> Result<RoutingTableDetails, CcpRoutingStoreError>;
+
+    /// Replaces the snapshot of the routing tables
+    async fn set_route_details(
+        &self,
+        details: RoutingTableDetails,
+    ) -> Result<(), CcpRoutingStoreError>;
 }
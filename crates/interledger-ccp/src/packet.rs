@@ -298,6 +298,36 @@ impl Route {
     }
 }
 
+/// Reads the new and withdrawn routes in the format used by Route Update Requests
+pub(crate) fn read_routes(data: &mut &[u8]) -> Result<(Vec<Route>, Vec<String>), ParseError> {
+    let new_routes_len = data.read_var_uint()? as usize;
+    let mut new_routes: Vec<Route> = Vec::with_capacity(new_routes_len);
+    for _i in 0..new_routes_len {
+        new_routes.push(Route::try_from(data as &mut &[u8])?);
+    }
+    let withdrawn_routes_len = data.read_var_uint()? as usize;
+    let mut withdrawn_routes = Vec::with_capacity(withdrawn_routes_len);
+    for _i in 0..withdrawn_routes_len {
+        withdrawn_routes.push(str::from_utf8(data.read_var_octet_string()?)?.to_string());
+    }
+    Ok((new_routes, withdrawn_routes))
+}
+
+/// Writes the new and withdrawn routes in the format used by Route Update Requests
+pub(crate) fn write_routes<B>(buf: &mut B, new_routes: &[Route], withdrawn_routes: &[String])
+where
+    B: BufMut,
+{
+    buf.put_var_uint(new_routes.len() as u64);
+    for route in new_routes.iter() {
+        route.write_to(buf);
+    }
+    buf.put_var_uint(withdrawn_routes.len() as u64);
+    for route in withdrawn_routes.iter() {
+        buf.put_var_octet_string(&route[..]);
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub struct RouteUpdateRequest {
     pub(crate) routing_table_id: [u8; 16],
@@ -361,16 +391,7 @@ impl RouteUpdateRequest {
         let to_epoch_index = data.read_u32::<BigEndian>()?;
         let hold_down_time = data.read_u32::<BigEndian>()?;
         let speaker = Address::try_from(data.read_var_octet_string()?)?;
-        let new_routes_len = data.read_var_uint()? as usize;
-        let mut new_routes: Vec<Route> = Vec::with_capacity(new_routes_len);
-        for _i in 0..new_routes_len {
-            new_routes.push(Route::try_from(&mut data)?);
-        }
-        let withdrawn_routes_len = data.read_var_uint()? as usize;
-        let mut withdrawn_routes = Vec::with_capacity(withdrawn_routes_len);
-        for _i in 0..withdrawn_routes_len {
-            withdrawn_routes.push(str::from_utf8(data.read_var_octet_string()?)?.to_string());
-        }
+        let (new_routes, withdrawn_routes) = read_routes(&mut data)?;
 
         Ok(RouteUpdateRequest {
             routing_table_id,
@@ -392,14 +413,7 @@ impl RouteUpdateRequest {
         data.put_u32_be(self.to_epoch_index);
         data.put_u32_be(self.hold_down_time);
         data.put_var_octet_string(&self.speaker[..]);
-        data.put_var_uint(self.new_routes.len() as u64);
-        for route in self.new_routes.iter() {
-            route.write_to(&mut data);
-        }
-        data.put_var_uint(self.withdrawn_routes.len() as u64);
-        for route in self.withdrawn_routes.iter() {
-            data.put_var_octet_string(&route[..]);
-        }
+        write_routes(&mut data, &self.new_routes, &self.withdrawn_routes);
 
         PrepareBuilder {
             destination: CCP_UPDATE_DESTINATION.clone(),
@@ -412,6 +426,60 @@ impl RouteUpdateRequest {
     }
 }
 
+impl RouteUpdateRequest {
+    /// Returns the size of the Prepare packet the request is sent in
+    pub(crate) fn packet_size(&self) -> usize {
+        self.to_prepare().as_ref().len()
+    }
+
+    /// Split the routes of the request into requests which fit in packets of the given size.
+    /// All but the last request end at the epoch the request starts from, so that the receiver
+    /// only moves on to the next epoch once it got all of its routes. A route which does not
+    /// fit in a packet on its own is sent in a request of its own anyway.
+    pub(crate) fn split_routes(self, max_packet_size: usize) -> Vec<RouteUpdateRequest> {
+        if self.packet_size() <= max_packet_size {
+            return vec![self];
+        }
+        let empty = RouteUpdateRequest {
+            new_routes: Vec::new(),
+            withdrawn_routes: Vec::new(),
+            ..self.clone()
+        };
+        // Leave room for the length prefixes, which grow with the number of routes
+        let max_routes_size = max_packet_size.saturating_sub(empty.packet_size() + 8);
+
+        let mut requests = vec![empty.clone()];
+        let mut routes_size = 0;
+        // Start a new request if the next route does not fit in the current one
+        let mut reserve = |size: usize, requests: &mut Vec<RouteUpdateRequest>| {
+            if routes_size + size > max_routes_size && routes_size > 0 {
+                requests.push(empty.clone());
+                routes_size = 0;
+            }
+            routes_size += size;
+        };
+
+        for prefix in self.withdrawn_routes {
+            let mut buf = Vec::new();
+            buf.put_var_octet_string(prefix.as_bytes());
+            reserve(buf.len(), &mut requests);
+            requests.last_mut().unwrap().withdrawn_routes.push(prefix);
+        }
+        for route in self.new_routes {
+            let mut buf = Vec::new();
+            route.write_to(&mut buf);
+            reserve(buf.len(), &mut requests);
+            requests.last_mut().unwrap().new_routes.push(route);
+        }
+
+        let last = requests.len() - 1;
+        for request in requests[..last].iter_mut() {
+            request.to_epoch_index = request.from_epoch_index;
+        }
+        requests
+    }
+}
+
 impl From<RouteUpdateRequest> for Prepare {
     fn from(request: RouteUpdateRequest) -> Self {
         request.to_prepare()
@@ -496,6 +564,43 @@ mod route_update_request {
         assert_eq!(prepare.data(), test_prepare.data());
     }
 
+    #[test]
+    fn splits_routes_into_packets_of_the_given_size() {
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.from_epoch_index = 4;
+        request.to_epoch_index = 5;
+        let max_packet_size = request.packet_size() - 1;
+        let requests = request.clone().split_routes(max_packet_size);
+
+        assert!(requests.len() > 1);
+        for (i, split) in requests.iter().enumerate() {
+            assert!(split.packet_size() <= max_packet_size);
+            assert_eq!(split.from_epoch_index, 4);
+            if i == requests.len() - 1 {
+                assert_eq!(split.to_epoch_index, 5);
+            } else {
+                assert_eq!(split.to_epoch_index, 4);
+            }
+        }
+        let new_routes: Vec<Route> = requests
+            .iter()
+            .flat_map(|split| split.new_routes.clone())
+            .collect();
+        let withdrawn_routes: Vec<String> = requests
+            .iter()
+            .flat_map(|split| split.withdrawn_routes.clone())
+            .collect();
+        assert_eq!(new_routes, request.new_routes);
+        assert_eq!(withdrawn_routes, request.withdrawn_routes);
+    }
+
+    #[test]
+    fn does_not_split_routes_which_fit() {
+        let request = UPDATE_REQUEST_COMPLEX.clone();
+        let requests = request.clone().split_routes(request.packet_size());
+        assert_eq!(requests, vec![request]);
+    }
+
     #[test]
     fn deserialize_complex() {
         let prepare =
@@ -0,0 +1,113 @@
+use crate::{CcpRoutingAccount, Mode, RouteControlRequest};
+use futures::TryFutureExt;
+use interledger_errors::AddressStoreError;
+use interledger_ildcp::{IldcpRequest, IldcpResponse};
+use interledger_packet::{Address, ParseError, Reject};
+use interledger_service::{AddressStore, OutgoingRequest, OutgoingService};
+use std::convert::TryFrom;
+use tracing::{debug, error};
+
+/// Errors which can happen when getting the node's address and routes from a parent
+#[derive(Debug, thiserror::Error)]
+pub enum ParentError {
+    #[error("Error getting ILDCP info: {0:?}")]
+    IldcpRequest(Reject),
+    #[error("Unable to parse ILDCP response from fulfill packet: {0:?}")]
+    InvalidIldcpResponse(ParseError),
+    #[error("Error setting the ILP address assigned by parent: {0}")]
+    SetAddress(#[from] AddressStoreError),
+    #[error("Error getting routes from parent: {0:?}")]
+    RouteControlRequest(Reject),
+}
+
+/// Sends an ILDCP request to the parent and returns the address it assigned to us
+pub async fn get_address_from_parent<O, A>(
+    service: &mut O,
+    parent: &A,
+) -> Result<Address, ParentError>
+where
+    O: OutgoingService<A>,
+    A: CcpRoutingAccount + Clone + Send + Sync + 'static,
+{
+    debug!(
+        "Getting ILP address from parent account: {} (id: {})",
+        parent.username(),
+        parent.id()
+    );
+    let prepare = IldcpRequest {}.to_prepare();
+    let fulfill = service
+        .send_request(OutgoingRequest {
+            from: parent.clone(), // Does not matter what we put here, they will get the account from the HTTP/BTP credentials
+            to: parent.clone(),
+            prepare,
+            original_amount: 0,
+        })
+        .map_err(|reject| {
+            let err = ParentError::IldcpRequest(reject);
+            error!("{}", err);
+            err
+        })
+        .await?;
+
+    let info = IldcpResponse::try_from(fulfill.into_data().freeze()).map_err(|parse_error| {
+        let err = ParentError::InvalidIldcpResponse(parse_error);
+        error!("{}", err);
+        err
+    })?;
+    debug!("Got ILDCP response from parent: {:?}", info);
+    Ok(info.ilp_address())
+}
+
+/// Sets the node's address (and with it, the addresses of its children) to the one the
+/// parent assigned to us and asks the parent for its routes. The parent should already be
+/// the node's default route
+pub async fn use_address_from_parent<O, A, S>(
+    service: O,
+    parent: &A,
+    ilp_address: Address,
+    store: &S,
+) -> Result<(), ParentError>
+where
+    O: OutgoingService<A>,
+    A: CcpRoutingAccount + Clone + Send + Sync + 'static,
+    S: AddressStore,
+{
+    debug!("ILP address is now: {}", ilp_address);
+    // Update our store's address
+    store.set_ilp_address(ilp_address).await?;
+
+    request_routes_from_parent(service, parent).await
+}
+
+/// Sends a RouteControl request asking the parent for all of its routes
+pub async fn request_routes_from_parent<O, A>(mut service: O, parent: &A) -> Result<(), ParentError>
+where
+    O: OutgoingService<A>,
+    A: CcpRoutingAccount + Clone + Send + Sync + 'static,
+{
+    let prepare = RouteControlRequest {
+        mode: Mode::Sync,
+        last_known_epoch: 0,
+        last_known_routing_table_id: [0; 16],
+        features: Vec::new(),
+    }
+    .to_prepare();
+
+    // Get the parent's routes for us
+    debug!("Asking for routes from {:?}", parent.clone());
+    service
+        .send_request(OutgoingRequest {
+            from: parent.clone(),
+            to: parent.clone(),
+            original_amount: prepare.amount(),
+            prepare: prepare.clone(),
+        })
+        .map_err(|reject| {
+            let err = ParentError::RouteControlRequest(reject);
+            error!("{}", err);
+            err
+        })
+        .await?;
+
+    Ok(())
+}
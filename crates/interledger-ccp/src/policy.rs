@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+/// Returns true if the prefix is the pattern or one of its sub-prefixes,
+/// e.g. "example.a" matches "example.a" and "example.a.b" but not "example.ab"
+fn matches_prefix(prefix: &str, pattern: &str) -> bool {
+    if pattern.ends_with('.') {
+        prefix.starts_with(pattern)
+    } else {
+        prefix.starts_with(pattern)
+            && (prefix.len() == pattern.len() || prefix[pattern.len()..].starts_with('.'))
+    }
+}
+
+fn matches_any(prefix: &str, patterns: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| matches_prefix(prefix, pattern))
+}
+
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// How the routes received from an account are handled if they cannot be authenticated,
+/// either because no anchor is configured for their prefix or because their auth is invalid
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnauthenticatedRoutes {
+    /// The routes are used like authenticated ones
+    #[default]
+    Accept,
+    /// The routes are only used if there is no authenticated route for the prefix
+    Deprioritize,
+    /// The routes are ignored
+    Reject,
+}
+
+/// Policy applied to the routes exchanged with an account over CCP, in addition to
+/// its routing relation. A prefix in one of the lists matches the routes for that
+/// prefix and for any longer prefix under it (e.g. `example.a` matches `example.a.b`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutePolicy {
+    /// Prefixes of the routes accepted from the account.
+    /// If the list is empty, routes for any prefix are accepted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub import_allow: Vec<String>,
+    /// Prefixes of the routes ignored from the account, even if they are allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub import_deny: Vec<String>,
+    /// Prefixes of the routes advertised to the account.
+    /// If the list is empty, routes for any prefix are advertised
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub export_allow: Vec<String>,
+    /// Prefixes of the routes not advertised to the account, even if they are allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub export_deny: Vec<String>,
+    /// Maximum number of routes accepted from the account. Routes for new prefixes
+    /// are ignored once the account's routing table holds that many
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_routes: Option<usize>,
+    /// Local preference of the routes received from the account. Among the routes
+    /// for a prefix, the ones from the accounts with the highest preference are used,
+    /// regardless of their routing relation or cost. Defaults to 0
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub preference: i32,
+    /// How the routes received from the account which cannot be authenticated are handled.
+    /// Defaults to accepting them
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub unauthenticated_routes: UnauthenticatedRoutes,
+}
+
+impl RoutePolicy {
+    /// Returns true if routes for the prefix are accepted from the account
+    pub fn imports(&self, prefix: &str) -> bool {
+        (self.import_allow.is_empty() || matches_any(prefix, &self.import_allow))
+            && !matches_any(prefix, &self.import_deny)
+    }
+
+    /// Returns true if routes for the prefix are advertised to the account
+    pub fn exports(&self, prefix: &str) -> bool {
+        (self.export_allow.is_empty() || matches_any(prefix, &self.export_allow))
+            && !matches_any(prefix, &self.export_deny)
+    }
+
+    /// Returns true if the policy does not restrict the routes advertised to the account
+    pub fn exports_all(&self) -> bool {
+        self.export_allow.is_empty() && self.export_deny.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_prefixes() {
+        let policy = RoutePolicy {
+            import_allow: vec!["example.a".to_string(), "example.b.".to_string()],
+            import_deny: vec!["example.a.private".to_string()],
+            export_deny: vec!["example.c".to_string()],
+            ..RoutePolicy::default()
+        };
+        assert!(policy.imports("example.a"));
+        assert!(policy.imports("example.a.b"));
+        assert!(!policy.imports("example.ab"));
+        assert!(policy.imports("example.b.c"));
+        assert!(!policy.imports("example.a.private"));
+        assert!(!policy.imports("example.a.private.c"));
+        assert!(policy.imports("example.a.privateer"));
+        assert!(!policy.imports("example.c"));
+
+        assert!(policy.exports("example.a"));
+        assert!(!policy.exports("example.c.d"));
+        assert!(!policy.exports_all());
+        assert!(RoutePolicy::default().exports_all());
+        assert!(RoutePolicy::default().imports("example.c"));
+    }
+}
@@ -71,6 +71,12 @@ where
         self.epoch = epoch;
     }
 
+    /// Resume the table with the ID and epoch it had before the node restarted
+    pub(crate) fn resume(&mut self, id: [u8; 16], epoch: u32) {
+        self.id = id;
+        self.epoch = epoch;
+    }
+
     pub(crate) fn id(&self) -> [u8; 16] {
         self.id
     }
@@ -96,10 +102,17 @@ where
         self.prefix_map.remove(prefix)
     }
 
-    /// Add the given route. Returns true if that routed did not already exist
+    /// Add the given route. Returns true if that route did not already exist or if its
+    /// path or properties changed
     pub(crate) fn add_route(&mut self, account: A, route: Route) -> bool {
+        let changed = match self.prefix_map.map.get(&route.prefix) {
+            Some((_account, current_route)) => *current_route != route,
+            None => true,
+        };
         self.prefix_map
-            .insert(route.prefix.clone(), (account, route))
+            .map
+            .insert(route.prefix.clone(), (account, route));
+        changed
     }
 
     /// Get the best route we have for the given prefix
@@ -107,6 +120,11 @@ where
         self.prefix_map.resolve(prefix)
     }
 
+    /// Get the prefixes this table has routes for
+    pub(crate) fn prefixes(&self) -> impl Iterator<Item = &str> {
+        self.prefix_map.map.keys().map(|prefix| prefix.as_str())
+    }
+
     pub(crate) fn get_simplified_table(&self) -> HashMap<String, A> {
         HashMap::from_iter(
             self.prefix_map
@@ -116,12 +134,14 @@ where
         )
     }
 
-    /// Handle a CCP Route Update Request from the peer this table represents
+    /// Handle a CCP Route Update Request from the peer this table represents.
+    /// If `max_routes` is set, routes for new prefixes are ignored once the table holds that many
     #[allow(clippy::cognitive_complexity)]
     pub(crate) fn handle_update_request(
         &mut self,
         account: A,
         request: RouteUpdateRequest,
+        max_routes: Option<usize>,
     ) -> Result<Vec<String>, String> {
         if self.id != request.routing_table_id {
             debug!(
@@ -174,6 +194,17 @@ where
 
         for route in request.new_routes.into_iter() {
             let prefix = route.prefix.clone();
+            if let Some(max_routes) = max_routes {
+                if self.prefix_map.map.len() >= max_routes
+                    && !self.prefix_map.map.contains_key(&prefix)
+                {
+                    debug!(
+                        "Ignoring route for {} because the routing table already has the maximum of {} routes",
+                        prefix, max_routes
+                    );
+                    continue;
+                }
+            }
             if self.add_route(account.clone(), route) {
                 changed_prefixes.push(prefix);
             }
@@ -246,7 +277,7 @@ mod table {
         let mut request = UPDATE_REQUEST_SIMPLE.clone();
         request.from_epoch_index = 0;
         table
-            .handle_update_request(ROUTING_ACCOUNT.clone(), request.clone())
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request.clone(), None)
             .unwrap();
         assert_eq!(table.id, request.routing_table_id);
     }
@@ -258,14 +289,14 @@ mod table {
         request.from_epoch_index = 0;
         request.to_epoch_index = 1;
         table
-            .handle_update_request(ROUTING_ACCOUNT.clone(), request.clone())
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request.clone(), None)
             .unwrap();
         assert_eq!(table.epoch, 1);
 
         request.from_epoch_index = 1;
         request.to_epoch_index = 3;
         table
-            .handle_update_request(ROUTING_ACCOUNT.clone(), request)
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request, None)
             .unwrap();
         assert_eq!(table.epoch, 3);
     }
@@ -275,7 +306,7 @@ mod table {
         let mut table = RoutingTable::new([0; 16]);
         let mut request = UPDATE_REQUEST_SIMPLE.clone();
         request.from_epoch_index = 1;
-        let result = table.handle_update_request(ROUTING_ACCOUNT.clone(), request);
+        let result = table.handle_update_request(ROUTING_ACCOUNT.clone(), request, None);
         assert_eq!(
             result.unwrap_err(),
             "Gap in routing table 21e55f8eabcd4e979ab9bf0ff00a224c. Expected epoch: 0, got from_epoch: 1"
@@ -290,7 +321,7 @@ mod table {
         request.from_epoch_index = 0;
         request.to_epoch_index = 1;
         let updated_routes = table
-            .handle_update_request(ROUTING_ACCOUNT.clone(), request)
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request, None)
             .unwrap();
         assert_eq!(updated_routes.len(), 0);
     }
@@ -302,11 +333,23 @@ mod table {
         request.from_epoch_index = 0;
         request.to_epoch_index = 1;
         let updated_routes = table
-            .handle_update_request(ROUTING_ACCOUNT.clone(), request)
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request, None)
             .unwrap();
         assert_eq!(updated_routes.len(), 0);
     }
 
+    #[test]
+    fn limits_number_of_routes() {
+        let mut table = RoutingTable::new(UPDATE_REQUEST_COMPLEX.routing_table_id);
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.from_epoch_index = 0;
+        let updated_routes = table
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request, Some(1))
+            .unwrap();
+        assert_eq!(updated_routes, vec!["example.prefix1".to_string()]);
+        assert!(table.get_route("example.prefix2").is_none());
+    }
+
     #[test]
     fn converts_to_a_simplified_table() {
         let mut table = RoutingTable::new([0; 16]);
@@ -0,0 +1,258 @@
+use crate::packet::{Route, RouteProp};
+use crate::{CcpRoutingAccount, RoutePolicy, UnauthenticatedRoutes};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// ID of the route property carrying the fee charged by the nodes along the route,
+/// as a fraction of the amount forwarded (e.g. "0.002"), encoded as a UTF-8 decimal
+pub const ROUTE_PROP_FEE: u16 = 0x0101;
+/// ID of the route property carrying the latency of the route, in milliseconds,
+/// encoded as a UTF-8 integer
+pub const ROUTE_PROP_LATENCY: u16 = 0x0102;
+/// ID of the route property carrying the amount the route can carry, in the asset
+/// of the node advertising it, encoded as a UTF-8 integer
+pub const ROUTE_PROP_LIQUIDITY: u16 = 0x0103;
+
+/// Weights used to calculate the cost of the routes received from peers with the same
+/// routing relation and local preference. The route with the lowest cost is picked.
+/// The default weights only take the length of the path into account.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteScoring {
+    /// Cost of each hop in the route's path
+    pub path_length: f64,
+    /// Cost of each percent of fee advertised for the route
+    pub fee: f64,
+    /// Cost of each millisecond of latency, i.e. the latency advertised for the route
+    /// plus the latency measured to the peer it was received from
+    pub latency: f64,
+    /// Cost saved for each order of magnitude of liquidity advertised for the route
+    pub liquidity: f64,
+    /// Fraction of the cost of the route in use which a route ranked the same otherwise
+    /// has to save to replace it, so that small changes in the latencies measured to
+    /// the peers do not keep switching between their routes
+    pub min_improvement: f64,
+}
+
+impl Default for RouteScoring {
+    fn default() -> Self {
+        RouteScoring {
+            path_length: 1.0,
+            fee: 0.0,
+            latency: 0.0,
+            liquidity: 0.0,
+            min_improvement: 0.1,
+        }
+    }
+}
+
+fn get_prop(route: &Route, id: u16) -> Option<&str> {
+    route
+        .props
+        .iter()
+        .find(|prop| prop.id == id)
+        .and_then(|prop| std::str::from_utf8(&prop.value[..]).ok())
+}
+
+fn set_prop(route: &mut Route, id: u16, value: String) {
+    route.props.retain(|prop| prop.id != id);
+    route.props.push(RouteProp {
+        // Nodes which do not know the property should pass it on unchanged
+        is_optional: true,
+        is_transitive: true,
+        is_partial: false,
+        id,
+        is_utf8: true,
+        value: Bytes::from(value),
+    });
+}
+
+/// Returns the fee advertised for the route, as a fraction
+pub(crate) fn route_fee(route: &Route) -> Option<f64> {
+    get_prop(route, ROUTE_PROP_FEE)
+        .and_then(|fee| fee.parse::<f64>().ok())
+        .filter(|fee| fee.is_finite() && *fee >= 0.0)
+}
+
+/// Returns the latency advertised for the route, in milliseconds
+pub(crate) fn route_latency(route: &Route) -> Option<u64> {
+    get_prop(route, ROUTE_PROP_LATENCY).and_then(|latency| latency.parse().ok())
+}
+
+/// Returns the liquidity advertised for the route
+pub(crate) fn route_liquidity(route: &Route) -> Option<u64> {
+    get_prop(route, ROUTE_PROP_LIQUIDITY).and_then(|liquidity| liquidity.parse().ok())
+}
+
+/// Adds our own fee and the latency to the next hop to the properties of a route
+/// before it is advertised to our peers. The liquidity is passed on as it is.
+pub(crate) fn add_hop_costs(route: &mut Route, fee: f64, latency: Option<u64>) {
+    let advertised_fee = route_fee(route);
+    if advertised_fee.is_some() || fee > 0.0 {
+        // Fees compound, so they are combined rather than added up
+        let total = 1.0 - (1.0 - advertised_fee.unwrap_or(0.0)) * (1.0 - fee);
+        set_prop(route, ROUTE_PROP_FEE, total.to_string());
+    }
+
+    let advertised_latency = route_latency(route);
+    if advertised_latency.is_some() || latency.is_some() {
+        let total = advertised_latency
+            .unwrap_or(0)
+            .saturating_add(latency.unwrap_or(0));
+        set_prop(route, ROUTE_PROP_LATENCY, total.to_string());
+    }
+}
+
+/// Ranks the routes received from peers according to the scoring weights,
+/// the accounts' local preferences, the latency measured to each peer
+/// and whether the routes could be authenticated
+pub(crate) struct RouteScorer<'a> {
+    pub(crate) scoring: &'a RouteScoring,
+    pub(crate) policies: &'a HashMap<Uuid, RoutePolicy>,
+    pub(crate) latencies: &'a HashMap<Uuid, u64>,
+    /// The prefixes of the unauthenticated routes received from each account
+    pub(crate) unauthenticated: &'a HashMap<Uuid, HashSet<String>>,
+    /// The prefixes of the routes received from each account which are suppressed
+    /// because they flapped
+    pub(crate) suppressed: &'a HashMap<Uuid, HashSet<String>>,
+}
+
+impl<'a> RouteScorer<'a> {
+    /// Returns the local preference of the routes received from the account
+    pub(crate) fn preference(&self, account_id: Uuid) -> i32 {
+        self.policies
+            .get(&account_id)
+            .map(|policy| policy.preference)
+            .unwrap_or(0)
+    }
+
+    /// Returns true if the route received from the account is unauthenticated and
+    /// the account's policy says such routes should only be used as a last resort
+    pub(crate) fn is_deprioritized(&self, account_id: Uuid, route: &Route) -> bool {
+        self.policies
+            .get(&account_id)
+            .map(|policy| policy.unauthenticated_routes)
+            == Some(UnauthenticatedRoutes::Deprioritize)
+            && self
+                .unauthenticated
+                .get(&account_id)
+                .map(|prefixes| prefixes.contains(&route.prefix))
+                .unwrap_or(false)
+    }
+
+    /// Returns true if the route received from the account is suppressed
+    pub(crate) fn is_suppressed(&self, account_id: Uuid, route: &Route) -> bool {
+        self.suppressed
+            .get(&account_id)
+            .map(|prefixes| prefixes.contains(&route.prefix))
+            .unwrap_or(false)
+    }
+
+    /// Returns true if the best route is ranked above the one currently used for something
+    /// else than its cost, or if its cost is more than the minimum improvement lower
+    pub(crate) fn is_significantly_better<A: CcpRoutingAccount>(
+        &self,
+        (best_account, best_route): &(A, Route),
+        (current_account, current_route): &(A, Route),
+    ) -> bool {
+        if self.is_deprioritized(best_account.id(), best_route)
+            != self.is_deprioritized(current_account.id(), current_route)
+            || self.preference(best_account.id()) != self.preference(current_account.id())
+            || best_account.routing_relation() != current_account.routing_relation()
+        {
+            return true;
+        }
+        let best_cost = self.cost(best_account.id(), best_route);
+        let current_cost = self.cost(current_account.id(), current_route);
+        current_cost - best_cost > self.scoring.min_improvement * current_cost.abs()
+    }
+
+    /// Returns the cost of sending through the account using the given route
+    pub(crate) fn cost(&self, account_id: Uuid, route: &Route) -> f64 {
+        let mut cost = self.scoring.path_length * route.path.len() as f64;
+        if self.scoring.fee != 0.0 {
+            cost += self.scoring.fee * route_fee(route).unwrap_or(0.0) * 100.0;
+        }
+        if self.scoring.latency != 0.0 {
+            let latency = route_latency(route)
+                .unwrap_or(0)
+                .saturating_add(self.latencies.get(&account_id).cloned().unwrap_or(0));
+            cost += self.scoring.latency * latency as f64;
+        }
+        if self.scoring.liquidity != 0.0 {
+            let liquidity = route_liquidity(route).unwrap_or(0) as f64;
+            cost -= self.scoring.liquidity * (1.0 + liquidity).log10();
+        }
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(props: &[(u16, &str)]) -> Route {
+        let mut route = Route {
+            prefix: "example.a".to_string(),
+            path: vec!["example.b".to_string(), "example.c".to_string()],
+            auth: [0; 32],
+            props: Vec::new(),
+        };
+        for (id, value) in props {
+            set_prop(&mut route, *id, value.to_string());
+        }
+        route
+    }
+
+    #[test]
+    fn adds_hop_costs() {
+        let mut without_props = route(&[]);
+        add_hop_costs(&mut without_props, 0.0, None);
+        assert!(without_props.props.is_empty());
+
+        let mut with_props = route(&[(ROUTE_PROP_FEE, "0.5"), (ROUTE_PROP_LIQUIDITY, "100")]);
+        add_hop_costs(&mut with_props, 0.5, Some(20));
+        assert_eq!(route_fee(&with_props), Some(0.75));
+        assert_eq!(route_latency(&with_props), Some(20));
+        assert_eq!(route_liquidity(&with_props), Some(100));
+        assert_eq!(with_props.props.len(), 3);
+    }
+
+    #[test]
+    fn calculates_route_costs() {
+        let account_id = Uuid::from_slice(&[1; 16]).unwrap();
+        let scoring = RouteScoring {
+            path_length: 1.0,
+            fee: 10.0,
+            latency: 0.1,
+            liquidity: 1.0,
+            min_improvement: 0.1,
+        };
+        let policies = HashMap::new();
+        let mut latencies = HashMap::new();
+        latencies.insert(account_id, 30);
+        let unauthenticated = HashMap::new();
+        let suppressed = HashMap::new();
+        let scorer = RouteScorer {
+            scoring: &scoring,
+            policies: &policies,
+            latencies: &latencies,
+            unauthenticated: &unauthenticated,
+            suppressed: &suppressed,
+        };
+
+        assert_eq!(scorer.cost(Uuid::new_v4(), &route(&[])), 2.0);
+        let cost = scorer.cost(
+            account_id,
+            &route(&[
+                (ROUTE_PROP_FEE, "0.01"),
+                (ROUTE_PROP_LATENCY, "70"),
+                (ROUTE_PROP_LIQUIDITY, "999"),
+            ]),
+        );
+        assert!((cost - (2.0 + 10.0 + 10.0 - 3.0)).abs() < 1e-9);
+        assert_eq!(scorer.preference(account_id), 0);
+    }
+}
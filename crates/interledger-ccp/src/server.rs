@@ -1,10 +1,16 @@
 use crate::{
+    auth::{hash, route_auth, route_auth_anchor, verify_route_auth},
+    dampening::{FlapDampener, RouteDampening},
+    details::{PeerTableDetails, RouteDetails, RouteSource, RoutingTableDetails},
     packet::{
-        Mode, Route, RouteControlRequest, RouteUpdateRequest, CCP_CONTROL_DESTINATION,
-        CCP_RESPONSE, CCP_UPDATE_DESTINATION,
+        read_routes, write_routes, Mode, Route, RouteControlRequest, RouteUpdateRequest,
+        CCP_CONTROL_DESTINATION, CCP_RESPONSE, CCP_UPDATE_DESTINATION,
     },
+    parents::{get_address_from_parent, use_address_from_parent},
     routing_table::RoutingTable,
-    CcpRoutingAccount, CcpRoutingStore, RoutingRelation,
+    scoring::{add_hop_costs, RouteScorer, RouteScoring},
+    CcpRoutingAccount, CcpRoutingStore, RouteDetailsStore, RoutePolicy, RoutePolicyStore,
+    RoutingRelation, UnauthenticatedRoutes,
 };
 use async_trait::async_trait;
 use futures::future::join_all;
@@ -14,10 +20,10 @@ use interledger_service::{
     Account, AddressStore, IlpResult, IncomingRequest, IncomingService, OutgoingRequest,
     OutgoingService,
 };
+use metrics::{labels, recorder, Key};
 use parking_lot::{Mutex, RwLock};
-use ring::digest::{digest, SHA256};
 use std::cmp::Ordering as StdOrdering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::{
     cmp::min,
     convert::TryFrom,
@@ -26,9 +32,10 @@ use std::{
         atomic::{AtomicU32, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tracing::{debug, error, trace, warn};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
 #[cfg(test)]
@@ -45,12 +52,10 @@ use once_cell::sync::Lazy;
 const DEFAULT_ROUTE_EXPIRY_TIME: u32 = 30000;
 const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
 const DUMMY_ROUTING_TABLE_ID: [u8; 16] = [0; 16];
-
-fn hash(preimage: &[u8; 32]) -> [u8; 32] {
-    let mut out = [0; 32];
-    out.copy_from_slice(digest(&SHA256, preimage).as_ref());
-    out
-}
+/// Leaves room for the BTP and HTTP framing under the 40000 bytes they accept
+const DEFAULT_MAX_PACKET_SIZE: usize = 32767;
+/// Number of forwarding table updates saved after its last snapshot before taking a new one
+const MAX_FORWARDING_LOG_UPDATES: u32 = 1000;
 
 type NewAndWithdrawnRoutes = (Vec<Route>, Vec<String>);
 
@@ -68,13 +73,27 @@ pub struct CcpRouteManagerBuilder<I, O, S> {
     store: S,
     ilp_address: Address,
     broadcast_interval: u64,
+    route_scoring: RouteScoring,
+    route_fee: f64,
+    route_auth_secret: Option<[u8; 32]>,
+    route_auth_anchors: HashMap<String, [u8; 32]>,
+    route_dampening: Option<RouteDampening>,
+    min_update_interval: Option<Duration>,
+    max_packet_size: usize,
 }
 
 impl<I, O, S, A> CcpRouteManagerBuilder<I, O, S>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
-    S: AddressStore + CcpRoutingStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + CcpRoutingStore<Account = A>
+        + RoutePolicyStore
+        + RouteDetailsStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     A: CcpRoutingAccount + Send + Sync + 'static,
 {
     pub fn new(ilp_address: Address, store: S, outgoing: O, next_incoming: I) -> Self {
@@ -84,6 +103,13 @@ where
             outgoing,
             store,
             broadcast_interval: DEFAULT_BROADCAST_INTERVAL,
+            route_scoring: RouteScoring::default(),
+            route_fee: 0.0,
+            route_auth_secret: None,
+            route_auth_anchors: HashMap::new(),
+            route_dampening: None,
+            min_update_interval: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
         }
     }
 
@@ -98,6 +124,54 @@ where
         self
     }
 
+    /// Set the weights used to pick the best of the routes received from peers
+    pub fn route_scoring(&mut self, scoring: RouteScoring) -> &mut Self {
+        self.route_scoring = scoring;
+        self
+    }
+
+    /// Set the fee, as a fraction of the amount forwarded, which is added to
+    /// the fee advertised for the routes we broadcast
+    pub fn route_fee(&mut self, fee: f64) -> &mut Self {
+        self.route_fee = fee;
+        self
+    }
+
+    /// Set the secret the route for our own address is authenticated with
+    pub fn route_auth_secret(&mut self, secret: [u8; 32]) -> &mut Self {
+        self.route_auth_secret = Some(secret);
+        self
+    }
+
+    /// Set the anchors of the hash chains the routes received for each prefix
+    /// are authenticated against
+    pub fn route_auth_anchors(&mut self, anchors: HashMap<String, [u8; 32]>) -> &mut Self {
+        self.route_auth_anchors = anchors;
+        self
+    }
+
+    /// Enable the dampening of the routes which flap
+    pub fn route_dampening(&mut self, dampening: RouteDampening) -> &mut Self {
+        self.route_dampening = Some(dampening);
+        self
+    }
+
+    /// Set the minimum interval (in milliseconds) between the route updates broadcast
+    /// to each account. The updates are combined until the interval has passed.
+    /// Updates are only broadcast on the broadcast interval, so this only has an effect
+    /// if it is larger than that
+    pub fn min_update_interval(&mut self, ms: u64) -> &mut Self {
+        self.min_update_interval = Some(Duration::from_millis(ms));
+        self
+    }
+
+    /// Set the maximum size (in bytes) of the packets route updates are sent in.
+    /// Larger updates are split into several ones
+    pub fn max_packet_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_packet_size = bytes;
+        self
+    }
+
     pub fn to_service(&self) -> CcpRouteManager<I, O, S, A> {
         #[allow(clippy::let_and_return)]
         let service = CcpRouteManager {
@@ -108,10 +182,29 @@ where
             forwarding_table: Arc::new(RwLock::new(RoutingTable::default())),
             forwarding_table_updates: Arc::new(RwLock::new(Vec::new())),
             last_epoch_updates_sent_for: Arc::new(AtomicU32::new(0)),
+            forwarding_log_start: Arc::new(AtomicU32::new(0)),
             local_table: Arc::new(RwLock::new(RoutingTable::default())),
             incoming_tables: Arc::new(RwLock::new(HashMap::new())),
             unavailable_accounts: Arc::new(Mutex::new(HashMap::new())),
+            route_scoring: self.route_scoring,
+            route_fee: self.route_fee,
+            peer_latencies: Arc::new(RwLock::new(HashMap::new())),
+            alternate_table: Arc::new(RwLock::new(HashMap::new())),
+            idle_accounts: Arc::new(Mutex::new(HashSet::new())),
+            restored_routes: Arc::new(Mutex::new(None)),
+            route_auth_secret: self.route_auth_secret,
+            route_auth_anchors: Arc::new(self.route_auth_anchors.clone()),
+            unauthenticated_routes: Arc::new(RwLock::new(HashMap::new())),
+            flap_dampener: self
+                .route_dampening
+                .map(|dampening| Arc::new(Mutex::new(FlapDampener::new(dampening)))),
+            min_update_interval: self.min_update_interval,
+            last_updates_sent: Arc::new(Mutex::new(HashMap::new())),
+            deferred_updates: Arc::new(Mutex::new(HashMap::new())),
+            max_packet_size: self.max_packet_size,
+            parent_addresses: Arc::new(Mutex::new(HashMap::new())),
         };
+        service.log_route_auth_anchor(&self.ilp_address);
 
         #[cfg(not(test))]
         {
@@ -160,6 +253,8 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     last_epoch_updates_sent_for: Arc<AtomicU32>,
     /// These updates are stored such that index 0 is the transition from epoch 0 to epoch 1
     forwarding_table_updates: Arc<RwLock<Vec<NewAndWithdrawnRoutes>>>,
+    /// Epoch of the first forwarding table update saved to the store, i.e. of its last snapshot
+    forwarding_log_start: Arc<AtomicU32>,
     /// This is the routing table we have compile from configuration and
     /// broadcasts we have received from our peers. It is saved to the Store so that
     /// the Router services forwards packets according to what it says.
@@ -175,19 +270,64 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// This maps the account ID to the number of route brodcast intervals
     /// we should wait before trying again
     unavailable_accounts: Arc<Mutex<HashMap<Uuid, BackoffParams>>>,
+    /// Weights used to pick the best of the routes received from peers
+    route_scoring: RouteScoring,
+    /// Our fee, which is added to the fee advertised for the routes we broadcast
+    route_fee: f64,
+    /// Round trip time, in milliseconds, of the CCP messages sent to each account
+    peer_latencies: Arc<RwLock<HashMap<Uuid, u64>>>,
+    /// The other next hops we have for each prefix of the local_table, ranked from
+    /// best to worst. They are saved to the Store so that the Router can retry
+    /// packets rejected with temporary errors on them.
+    alternate_table: Arc<RwLock<HashMap<String, Vec<A>>>>,
+    /// Accounts which sent us a Route Control Request in Idle mode,
+    /// which we do not broadcast routes to until they request them again
+    idle_accounts: Arc<Mutex<HashSet<Uuid>>>,
+    /// The routes of the forwarding table saved before the node restarted, which our
+    /// peers already have. They are only advertised again if they changed, and the ones
+    /// we no longer have are withdrawn once all the routes have been checked
+    restored_routes: Arc<Mutex<Option<HashMap<String, Route>>>>,
+    /// Secret the route for our own address is authenticated with
+    route_auth_secret: Option<[u8; 32]>,
+    /// Anchors of the hash chains the received routes are authenticated against, by prefix
+    route_auth_anchors: Arc<HashMap<String, [u8; 32]>>,
+    /// The prefixes of the routes received from each account which could not be authenticated
+    unauthenticated_routes: Arc<RwLock<HashMap<Uuid, HashSet<String>>>>,
+    /// The flap penalties of the routes received from each account, if dampening is enabled
+    flap_dampener: Option<Arc<Mutex<FlapDampener>>>,
+    /// Minimum interval between the route updates broadcast to each account
+    min_update_interval: Option<Duration>,
+    /// When the last route update was sent to each account
+    last_updates_sent: Arc<Mutex<HashMap<Uuid, Instant>>>,
+    /// The epoch from which the accounts whose broadcasts were deferred because of the
+    /// minimum update interval need to be sent the updates
+    deferred_updates: Arc<Mutex<HashMap<Uuid, u32>>>,
+    /// Maximum size of the packets route updates are sent in
+    max_packet_size: usize,
+    /// The address each parent last advertised itself with in its route updates
+    parent_addresses: Arc<Mutex<HashMap<Uuid, Address>>>,
 }
 
 impl<I, O, S, A> CcpRouteManager<I, O, S, A>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
-    S: AddressStore + CcpRoutingStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + CcpRoutingStore<Account = A>
+        + RoutePolicyStore
+        + RouteDetailsStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     A: CcpRoutingAccount + Send + Sync + 'static,
 {
     /// Returns a future that will trigger this service to update its routes and broadcast
     /// updates to peers on the given interval. `interval` is in milliseconds
     pub async fn start_broadcast_interval(&self, interval: u64) {
+        self.load_forwarding_table().await;
         self.request_all_routes().await;
+        tokio::spawn(self.clone().resync_on_policy_changes());
         let mut interval = tokio::time::interval(Duration::from_millis(interval));
         loop {
             interval.tick().await;
@@ -198,6 +338,52 @@ where
         }
     }
 
+    /// Exchange all the routes again with the accounts whose route policy is changed,
+    /// so that the new policy also applies to the routes received and sent before
+    async fn resync_on_policy_changes(self) {
+        let mut policy_changes = self.store.subscribe_to_route_policies();
+        loop {
+            match policy_changes.recv().await {
+                Ok(account_id) => self.resync_account(account_id).await,
+                Err(broadcast::RecvError::Lagged(skipped)) => {
+                    warn!("Missed {} route policy changes", skipped);
+                }
+                Err(broadcast::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Drop the routes received from the account and request all of them again,
+    /// then send it all the routes we advertise
+    async fn resync_account(&self, account_id: Uuid) {
+        debug!("Resyncing routes with account {}", account_id);
+        match self.store.get_accounts_to_receive_routes_from().await {
+            Ok(accounts) => {
+                if let Some(account) = accounts.into_iter().find(|a| a.id() == account_id) {
+                    self.incoming_tables.write().remove(&account_id);
+                    self.unauthenticated_routes.write().remove(&account_id);
+                    self.send_route_control_request(account, Mode::Sync, DUMMY_ROUTING_TABLE_ID, 0)
+                        .await;
+                }
+            }
+            Err(err) => error!("Error getting accounts to receive routes from: {}", err),
+        }
+
+        if let Err(err) = self.update_best_routes(None).await {
+            error!("Error updating best routes: {}", err);
+        }
+
+        match self.store.get_accounts_to_send_routes_to(Vec::new()).await {
+            Ok(accounts) => {
+                if let Some(account) = accounts.into_iter().find(|a| a.id() == account_id) {
+                    let to_epoch_index = self.forwarding_table.read().epoch();
+                    self.send_route_update(account, 0, to_epoch_index).await;
+                }
+            }
+            Err(err) => error!("Error getting accounts to send routes to: {}", err),
+        }
+    }
+
     fn update_ilp_address(&self) {
         let current_ilp_address = self.ilp_address.read();
         let ilp_address = self.store.get_ilp_address();
@@ -208,25 +394,284 @@ where
             );
             // release the read lock
             drop(current_ilp_address);
+            self.log_route_auth_anchor(&ilp_address);
             *self.ilp_address.write() = ilp_address;
         }
     }
 
+    /// Log the anchor the nodes receiving the route for our address need to authenticate it
+    fn log_route_auth_anchor(&self, ilp_address: &Address) {
+        if let Some(ref secret) = self.route_auth_secret {
+            info!(
+                "Route authentication anchor for {}: {}",
+                ilp_address,
+                hex::encode(route_auth_anchor(secret, ilp_address as &str))
+            );
+        }
+    }
+
+    /// Update the latency measured to the account with the round trip time
+    /// of a CCP message sent to it, smoothing out the individual measurements
+    fn record_latency(&self, account_id: Uuid, sent_at: Instant) {
+        let sample = sent_at.elapsed().as_millis() as u64;
+        let mut peer_latencies = self.peer_latencies.write();
+        let latency = peer_latencies.entry(account_id).or_insert(sample);
+        *latency = (*latency * 3 + sample) / 4;
+    }
+
+    /// Penalize the routes the account withdrew, suppressing the ones which flap too often
+    fn record_flaps(&self, account: &A, withdrawn_prefixes: &[String]) {
+        if let Some(ref dampener) = self.flap_dampener {
+            let now = Instant::now();
+            let mut dampener = dampener.lock();
+            for prefix in withdrawn_prefixes {
+                if dampener.record_flap(account.id(), prefix, now) {
+                    info!(
+                        "Suppressing the route for {} received from account {} (id: {}) because it is flapping",
+                        prefix,
+                        account.username(),
+                        account.id()
+                    );
+                    recorder().increment_counter(
+                        Key::from_name_and_labels(
+                            "ccp.route_suppressions",
+                            labels!("from" => account.username().to_string()),
+                        ),
+                        1,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the prefixes of the suppressed routes received from each account
+    fn suppressed_routes(&self) -> HashMap<Uuid, HashSet<String>> {
+        match self.flap_dampener {
+            Some(ref dampener) => {
+                let suppressed = dampener.lock().suppressed_routes(Instant::now());
+                recorder().update_gauge(
+                    Key::from_name("ccp.suppressed_routes"),
+                    suppressed
+                        .values()
+                        .map(|prefixes| prefixes.len() as i64)
+                        .sum(),
+                );
+                suppressed
+            }
+            None => HashMap::new(),
+        }
+    }
+
+    /// Resume the forwarding table with the ID, epoch and updates saved before the node
+    /// restarted, so that peers which are in sync with it only get the updates since then
+    async fn load_forwarding_table(&self) {
+        let state = match self.store.get_forwarding_table().await {
+            Ok(Some(state)) => state,
+            Ok(None) => return,
+            Err(err) => {
+                error!("Error loading the forwarding routing table: {}", err);
+                return;
+            }
+        };
+
+        // The peers may have gotten the updates which could not be saved,
+        // so the table cannot be resumed if any of them are missing
+        let first_epoch = state.updates.keys().next().cloned().unwrap_or(0);
+        let has_gaps = state
+            .updates
+            .keys()
+            .enumerate()
+            .any(|(i, epoch)| *epoch != first_epoch + i as u32);
+        if has_gaps {
+            warn!(
+                "Starting a new forwarding routing table because updates of the saved one {} are missing",
+                hex::encode(&state.table_id[..])
+            );
+            return;
+        }
+
+        // The updates up to the first saved one are combined in it
+        let mut updates: Vec<NewAndWithdrawnRoutes> =
+            vec![(Vec::new(), Vec::new()); first_epoch as usize];
+        for update in state.updates.values() {
+            match read_routes(&mut &update[..]) {
+                Ok(update) => updates.push(update),
+                Err(err) => {
+                    error!(
+                        "Ignoring the saved forwarding routing table because one of its updates is invalid: {:?}",
+                        err
+                    );
+                    return;
+                }
+            }
+        }
+
+        // Replay the updates to get the routes our peers already have
+        let mut routes: HashMap<String, Route> = HashMap::new();
+        for (new_routes, withdrawn_routes) in updates.iter() {
+            for route in new_routes {
+                routes.insert(route.prefix.clone(), route.clone());
+            }
+            for prefix in withdrawn_routes {
+                routes.remove(prefix);
+            }
+        }
+
+        let epoch = updates.len() as u32;
+        debug!(
+            "Resuming forwarding routing table {} at epoch {} with {} routes",
+            hex::encode(&state.table_id[..]),
+            epoch,
+            routes.len()
+        );
+        self.forwarding_table.write().resume(state.table_id, epoch);
+        *self.forwarding_table_updates.write() = updates;
+        self.forwarding_log_start
+            .store(first_epoch, Ordering::SeqCst);
+        self.last_epoch_updates_sent_for
+            .store(epoch, Ordering::SeqCst);
+        *self.restored_routes.lock() = Some(routes);
+    }
+
     pub async fn broadcast_routes(&self) -> Result<(), CcpRoutingStoreError> {
+        self.remove_deleted_accounts().await;
         self.update_best_routes(None).await?;
+        if let Err(err) = self.save_route_details().await {
+            warn!("Error saving the details of the routing tables: {}", err);
+        }
         self.send_route_updates().await
     }
 
+    /// Drop the routing tables and the unauthenticated routes of the accounts
+    /// we no longer receive routes from, for example because they were deleted
+    async fn remove_deleted_accounts(&self) {
+        let accounts = match self.store.get_accounts_to_receive_routes_from().await {
+            Ok(accounts) => accounts,
+            Err(err) => {
+                error!("Error getting accounts to receive routes from: {}", err);
+                return;
+            }
+        };
+        let account_ids: HashSet<Uuid> = accounts.iter().map(|account| account.id()).collect();
+        self.incoming_tables
+            .write()
+            .retain(|account_id, _| account_ids.contains(account_id));
+        self.unauthenticated_routes
+            .write()
+            .retain(|account_id, _| account_ids.contains(account_id));
+    }
+
+    /// Save a snapshot of the routing tables to the store, with all the routes we have
+    /// for each prefix and the state of the tables received from our peers
+    async fn save_route_details(&self) -> Result<(), CcpRoutingStoreError> {
+        let (local_routes, configured_routes) =
+            self.store.get_local_and_configured_routes().await?;
+        let policies = self.store.get_route_policies().await?;
+        let mut peers = self.store.get_accounts_to_receive_routes_from().await?;
+        peers.sort_unstable_by(|a, b| a.username().cmp(b.username()));
+
+        let suppressed_routes = self.suppressed_routes();
+        let details = {
+            let local_table = self.local_table.read();
+            let forwarding_table = self.forwarding_table.read();
+            let incoming_tables = self.incoming_tables.read();
+            let peer_latencies = self.peer_latencies.read();
+            let unauthenticated_routes = self.unauthenticated_routes.read();
+            let scorer = RouteScorer {
+                scoring: &self.route_scoring,
+                policies: &policies,
+                latencies: &peer_latencies,
+                unauthenticated: &unauthenticated_routes,
+                suppressed: &suppressed_routes,
+            };
+
+            let prefixes: BTreeSet<&str> = configured_routes
+                .keys()
+                .chain(local_routes.keys())
+                .map(|prefix| prefix.as_str())
+                .chain(incoming_tables.values().flat_map(|table| table.prefixes()))
+                .collect();
+            let prefixes = prefixes
+                .into_iter()
+                .map(|prefix| {
+                    // The configured and local routes come first, in that order
+                    let configured = get_configured_route(&configured_routes, prefix)
+                        .map(|(prefix, _account)| (prefix.to_string(), RouteSource::Configured));
+                    let local = if local_routes.contains_key(prefix) {
+                        Some((prefix.to_string(), RouteSource::Local))
+                    } else {
+                        None
+                    };
+                    let mut routes = get_routes_for_prefix(
+                        &local_routes,
+                        &configured_routes,
+                        &incoming_tables,
+                        prefix,
+                        &scorer,
+                    );
+                    if configured.is_none() && local.is_none() {
+                        keep_current_route(&mut routes, local_table.get_route(prefix), &scorer);
+                    }
+                    let mut sources = configured.into_iter().chain(local);
+                    let routes = routes
+                        .into_iter()
+                        .map(|(account, route)| {
+                            let (route_prefix, source) = sources
+                                .next()
+                                .unwrap_or_else(|| (route.prefix.clone(), RouteSource::Learned));
+                            let authenticated = source != RouteSource::Learned
+                                || !unauthenticated_routes
+                                    .get(&account.id())
+                                    .map(|prefixes| prefixes.contains(&route.prefix))
+                                    .unwrap_or(false);
+                            RouteDetails::new(
+                                route_prefix,
+                                source,
+                                account.id(),
+                                account.username().clone(),
+                                &route,
+                                authenticated,
+                            )
+                        })
+                        .collect();
+                    (prefix.to_string(), routes)
+                })
+                .collect();
+
+            let peers = peers
+                .iter()
+                .filter_map(|account| {
+                    incoming_tables
+                        .get(&account.id())
+                        .map(|table| PeerTableDetails {
+                            account_id: account.id(),
+                            username: account.username().clone(),
+                            routing_table_id: hex::encode(&table.id()[..]),
+                            epoch: table.epoch(),
+                            routes: table.prefixes().count(),
+                        })
+                })
+                .collect();
+
+            RoutingTableDetails {
+                routing_table_id: hex::encode(&forwarding_table.id()[..]),
+                epoch: forwarding_table.epoch(),
+                prefixes,
+                peers,
+            }
+        };
+
+        self.store.set_route_details(details).await
+    }
+
     /// Request routes from all the peers we are willing to receive routes from.
     /// This is mostly intended for when the CCP server starts up and doesn't have any routes from peers.
     async fn request_all_routes(&self) {
         let result = self.store.get_accounts_to_receive_routes_from().await;
         let accounts = result.unwrap_or_else(|_| Vec::new());
-        join_all(
-            accounts
-                .into_iter()
-                .map(|account| self.send_route_control_request(account, DUMMY_ROUTING_TABLE_ID, 0)),
-        )
+        join_all(accounts.into_iter().map(|account| {
+            self.send_route_control_request(account, Mode::Sync, DUMMY_ROUTING_TABLE_ID, 0)
+        }))
         .await;
     }
 
@@ -261,8 +706,26 @@ where
             control
         );
 
-        // TODO stop sending updates if they are in Idle mode
+        if control.mode == Mode::Idle {
+            if self.idle_accounts.lock().insert(request.from.id()) {
+                debug!(
+                    "Account {} (id: {}) is idle, will stop broadcasting routes to it",
+                    request.from.username(),
+                    request.from.id()
+                );
+            }
+            return Ok(CCP_RESPONSE.clone());
+        }
+
         if control.mode == Mode::Sync {
+            if self.idle_accounts.lock().remove(&request.from.id()) {
+                debug!(
+                    "Account {} (id: {}) is no longer idle, will resume broadcasting routes to it",
+                    request.from.username(),
+                    request.from.id()
+                );
+            }
+
             // Don't skip them in the route update broadcasts anymore since this
             // tells us that they are online
             // TODO what happens if they can send to us but we can't send to them?
@@ -311,9 +774,14 @@ where
         Ok(CCP_RESPONSE.clone())
     }
 
-    /// Remove invalid routes before processing the Route Update Request
+    /// Remove invalid routes and routes the account's policy does not accept
+    /// before processing the Route Update Request
     #[allow(clippy::cognitive_complexity)]
-    fn filter_routes(&self, mut update: RouteUpdateRequest) -> RouteUpdateRequest {
+    fn filter_routes(
+        &self,
+        mut update: RouteUpdateRequest,
+        policy: &RoutePolicy,
+    ) -> RouteUpdateRequest {
         update.new_routes = update
             .new_routes
             .into_iter()
@@ -336,6 +804,12 @@ where
                         route
                     );
                     false
+                } else if !policy.imports(&route.prefix) {
+                    debug!(
+                        "Ignoring route broadcast for a prefix the account's policy does not accept: {:?}",
+                        route
+                    );
+                    false
                 } else {
                     true
                 }
@@ -344,13 +818,77 @@ where
         update
     }
 
+    /// Authenticate the new routes against the anchors configured for their prefixes,
+    /// and remove the unauthenticated ones if the account's policy rejects them.
+    /// The prefixes of the other unauthenticated routes are kept so that they can be ranked
+    /// according to the policy.
+    fn authenticate_routes(
+        &self,
+        account_id: Uuid,
+        mut update: RouteUpdateRequest,
+        policy: &RoutePolicy,
+    ) -> RouteUpdateRequest {
+        let mut unauthenticated_routes = self.unauthenticated_routes.write();
+        let unauthenticated = unauthenticated_routes.entry(account_id).or_default();
+        for prefix in update.withdrawn_routes.iter() {
+            unauthenticated.remove(prefix);
+        }
+
+        let anchors = &self.route_auth_anchors;
+        update.new_routes.retain(|route| {
+            let authenticated = anchors
+                .get(&route.prefix)
+                .map(|anchor| verify_route_auth(route, anchor))
+                .unwrap_or(false);
+            if authenticated {
+                unauthenticated.remove(&route.prefix);
+                true
+            } else if policy.unauthenticated_routes == UnauthenticatedRoutes::Reject {
+                debug!(
+                    "Ignoring route broadcast which could not be authenticated: {:?}",
+                    route
+                );
+                unauthenticated.remove(&route.prefix);
+                false
+            } else {
+                if anchors.contains_key(&route.prefix) {
+                    warn!("Got route broadcast with an invalid auth: {:?}", route);
+                }
+                unauthenticated.insert(route.prefix.clone());
+                true
+            }
+        });
+        update
+    }
+
     /// Check if this Route Update Request is valid and, if so, apply any updates it contains.
     /// If updates are applied to the Incoming Routing Table for this peer, we will
     /// then check whether those routes are better than the current best ones we have in the
     /// Local Routing Table.
     async fn handle_route_update_request(&self, request: IncomingRequest<A>) -> IlpResult {
-        // Ignore the request if we don't accept routes from them
+        // Ignore the request if we don't accept routes from them,
+        // and ask them to stop sending us updates
         if !request.from.should_receive_routes() {
+            #[cfg(not(test))]
+            tokio::spawn({
+                let self_clone = self.clone();
+                let account = request.from.clone();
+                async move {
+                    self_clone
+                        .send_route_control_request(account, Mode::Idle, DUMMY_ROUTING_TABLE_ID, 0)
+                        .await
+                }
+            });
+
+            #[cfg(test)]
+            self.send_route_control_request(
+                request.from.clone(),
+                Mode::Idle,
+                DUMMY_ROUTING_TABLE_ID,
+                0,
+            )
+            .await;
+
             return Err(RejectBuilder {
                 code: ErrorCode::F00_BAD_REQUEST,
                 message: b"Your route broadcasts are not accepted here",
@@ -377,8 +915,41 @@ where
             update
         );
 
+        // Our address has to change along with the address of the parent it was derived from
+        if self.parent_address_changed(&request.from, &update.speaker) {
+            #[cfg(not(test))]
+            tokio::spawn({
+                let self_clone = self.clone();
+                let parent = request.from.clone();
+                async move { self_clone.update_address_from_parent(parent).await }
+            });
+
+            #[cfg(test)]
+            self.update_address_from_parent(request.from.clone()).await;
+        }
+
+        let policy = match self.store.get_route_policies().await {
+            Ok(mut policies) => policies.remove(&request.from.id()).unwrap_or_default(),
+            Err(err) => {
+                error!(
+                    "Error loading the route policy of account {}: {}",
+                    request.from.id(),
+                    err
+                );
+                return Err(RejectBuilder {
+                    code: ErrorCode::T00_INTERNAL_ERROR,
+                    message: b"Error loading route policy",
+                    triggered_by: Some(&self.ilp_address.read()),
+                    data: &[],
+                }
+                .build());
+            }
+        };
+
         // Filter out routes that don't make sense or that we won't accept
-        let update = self.filter_routes(update);
+        let update = self.filter_routes(update, &policy);
+        let update = self.authenticate_routes(request.from.id(), update, &policy);
+        let withdrawn_prefixes = update.withdrawn_routes.clone();
 
         // Ensure the mutex gets dropped before the async block
         let result = {
@@ -392,7 +963,7 @@ where
             incoming_tables
                 .get_mut(&request.from.id())
                 .expect("Should have inserted a routing table for this account")
-                .handle_update_request(request.from.clone(), update)
+                .handle_update_request(request.from.clone(), update, policy.max_routes)
         };
 
         // Update the routing table we maintain for the account we got this from.
@@ -400,6 +971,14 @@ where
         // that were included in this route update.
         match result {
             Ok(prefixes_updated) => {
+                // Only the routes which were actually removed count as flaps
+                let flapped: Vec<String> = prefixes_updated
+                    .iter()
+                    .filter(|prefix| withdrawn_prefixes.contains(prefix))
+                    .cloned()
+                    .collect();
+                self.record_flaps(&request.from, &flapped);
+
                 if prefixes_updated.is_empty() {
                     trace!("Route update request did not contain any prefixes we need to update our routes for");
                     return Ok(CCP_RESPONSE.clone());
@@ -455,6 +1034,7 @@ where
                         self_clone
                             .send_route_control_request(
                                 request.from.clone(),
+                                Mode::Sync,
                                 table.id(),
                                 table.epoch(),
                             )
@@ -463,34 +1043,95 @@ where
                 });
 
                 #[cfg(test)]
-                self.send_route_control_request(request.from.clone(), table.id(), table.epoch())
-                    .await;
+                self.send_route_control_request(
+                    request.from.clone(),
+                    Mode::Sync,
+                    table.id(),
+                    table.epoch(),
+                )
+                .await;
                 Err(reject)
             }
         }
     }
 
+    /// Records the address the account advertised itself with, if it is a parent. Returns
+    /// true if the parent's address changed and ours was derived from its old address
+    fn parent_address_changed(&self, account: &A, speaker: &Address) -> bool {
+        if account.routing_relation() != RoutingRelation::Parent {
+            return false;
+        }
+        let previous = self
+            .parent_addresses
+            .lock()
+            .insert(account.id(), speaker.clone());
+        match previous {
+            Some(previous) if previous != *speaker => {
+                // Our address is the one of the parent which assigned it with our name appended
+                let ilp_address = self.ilp_address.read();
+                let mut segments: Vec<&str> = ilp_address.segments().collect();
+                segments.pop();
+                segments.join(".") == *previous
+            }
+            _ => false,
+        }
+    }
+
+    /// Get the address the parent assigns to us with ILDCP and use it the same way as when
+    /// the parent is added, which also updates the addresses of our children. They in turn
+    /// find out about it from the next route updates we send them
+    async fn update_address_from_parent(&self, parent: A) {
+        let mut outgoing = self.outgoing.clone();
+        let ilp_address = match get_address_from_parent(&mut outgoing, &parent).await {
+            Ok(ilp_address) => ilp_address,
+            Err(err) => {
+                error!(
+                    "Error getting our new address from parent {}: {}",
+                    parent.id(),
+                    err
+                );
+                return;
+            }
+        };
+
+        info!(
+            "Parent {} changed its address, changing ours to {}",
+            parent.id(),
+            ilp_address
+        );
+        if let Err(err) = use_address_from_parent(outgoing, &parent, ilp_address, &self.store).await
+        {
+            error!("Error using our new address: {}", err);
+            return;
+        }
+        self.update_ilp_address();
+    }
+
     /// Request a Route Update from the specified peer. This is sent when we get
     /// a Route Update Request from them with a gap in the epochs since the last one we saw.
+    /// In Idle mode, this asks the peer to stop sending us Route Updates instead.
     async fn send_route_control_request(
         &self,
         account: A,
+        mode: Mode,
         last_known_routing_table_id: [u8; 16],
         last_known_epoch: u32,
     ) {
         let account_id = account.id();
         let control = RouteControlRequest {
-            mode: Mode::Sync,
+            mode,
             last_known_routing_table_id,
             last_known_epoch,
             features: Vec::new(),
         };
-        debug!("Sending Route Control Request to account: {} (id: {}), last known table id: {}, last known epoch: {}",
+        debug!("Sending Route Control Request in {:?} mode to account: {} (id: {}), last known table id: {}, last known epoch: {}",
+            mode,
             account.username(),
             account_id,
             hex::encode(&last_known_routing_table_id[..]),
             last_known_epoch);
         let prepare = control.to_prepare();
+        let sent_at = Instant::now();
         let result = self
             .clone()
             .outgoing
@@ -505,11 +1146,12 @@ where
             })
             .await;
 
-        if let Err(err) = result {
-            warn!(
+        match result {
+            Ok(_) => self.record_latency(account_id, sent_at),
+            Err(err) => warn!(
                 "Error sending Route Control Request to account {}: {:?}",
                 account_id, err
-            )
+            ),
         }
     }
 
@@ -531,20 +1173,60 @@ where
 
         let (local_routes, configured_routes) =
             self.store.get_local_and_configured_routes().await?;
+        let policies = self.store.get_route_policies().await?;
+        let received_prefixes: Vec<String> = if prefixes.is_none() {
+            // The routes we currently use are checked too, in case the table they were
+            // received in was removed
+            let local_table = local_table.read();
+            let incoming_tables = incoming_tables.read();
+            let mut received_prefixes: Vec<String> = incoming_tables
+                .values()
+                .flat_map(|table| table.prefixes())
+                .chain(local_table.prefixes())
+                .filter(|prefix| {
+                    !local_routes.contains_key(*prefix) && !configured_routes.contains_key(*prefix)
+                })
+                .map(|prefix| prefix.to_string())
+                .collect();
+            received_prefixes.sort_unstable();
+            received_prefixes.dedup();
+            received_prefixes
+        } else {
+            Vec::new()
+        };
+
+        // After a restart, the routes we no longer have are withdrawn once all of them are checked
+        let withdraw_restored_routes = prefixes.is_none() && self.restored_routes.lock().is_some();
 
+        let suppressed_routes = self.suppressed_routes();
+        let mut alternates_changed = false;
         // TODO: Should we extract this to a function and #[inline] it?
         let (better_routes, withdrawn_routes) = {
             // Note we only use a read lock here and later get a write lock if we need to update the table
             let local_table = local_table.read();
             let incoming_tables = incoming_tables.read();
+            let peer_latencies = self.peer_latencies.read();
+            let unauthenticated_routes = self.unauthenticated_routes.read();
+            let scorer = RouteScorer {
+                scoring: &self.route_scoring,
+                policies: &policies,
+                latencies: &peer_latencies,
+                unauthenticated: &unauthenticated_routes,
+                suppressed: &suppressed_routes,
+            };
 
-            // Either check the given prefixes or check all of our local and configured routes
+            // Either check the given prefixes or check all of our local and configured routes,
+            // as well as the received ones since their cost may have changed
             let prefixes_to_check: Box<dyn Iterator<Item = &str>> =
                 if let Some(ref prefixes) = prefixes {
                     Box::new(prefixes.iter().map(|prefix| prefix.as_str()))
                 } else {
                     let routes = configured_routes.iter().chain(local_routes.iter());
-                    Box::new(routes.map(|(prefix, _account)| prefix.as_str()))
+                    Box::new(
+                        routes
+                            .map(|(prefix, _account)| prefix.as_str())
+                            .chain(received_prefixes.iter().map(|prefix| prefix.as_str())),
+                    )
                 };
 
             // Check all the prefixes to see which ones we have different routes for
@@ -552,16 +1234,50 @@ where
             let mut better_routes: Vec<(&str, A, Route)> =
                 Vec::with_capacity(prefixes_to_check.size_hint().0);
             let mut withdrawn_routes: Vec<&str> = Vec::new();
+            let mut alternate_table = self.alternate_table.write();
             for prefix in prefixes_to_check {
-                // See which prefixes there is now a better route for
-                if let Some((best_next_account, best_route)) = get_best_route_for_prefix(
+                let mut routes = get_routes_for_prefix(
                     &local_routes,
                     &configured_routes,
                     &incoming_tables,
                     prefix,
-                ) {
-                    if let Some((ref next_account, ref _route)) = local_table.get_route(prefix) {
-                        if next_account.id() == best_next_account.id() {
+                    &scorer,
+                );
+                if get_configured_route(&configured_routes, prefix).is_none()
+                    && !local_routes.contains_key(prefix)
+                {
+                    keep_current_route(&mut routes, local_table.get_route(prefix), &scorer);
+                }
+                let mut routes = routes.into_iter();
+                // See which prefixes there is now a better route for
+                if let Some((best_next_account, best_route)) = routes.next() {
+                    // Keep the other next hops the Router can fail over to
+                    let mut alternates: Vec<A> = Vec::new();
+                    for (account, _route) in routes {
+                        if account.id() != best_next_account.id()
+                            && alternates.iter().all(|a| a.id() != account.id())
+                        {
+                            alternates.push(account);
+                        }
+                    }
+                    let alternate_ids: Vec<Uuid> = alternates.iter().map(|a| a.id()).collect();
+                    let current_ids: Vec<Uuid> = alternate_table
+                        .get(prefix)
+                        .map(|current| current.iter().map(|a| a.id()).collect())
+                        .unwrap_or_default();
+                    if alternate_ids != current_ids {
+                        alternates_changed = true;
+                        if alternates.is_empty() {
+                            alternate_table.remove(prefix);
+                        } else {
+                            alternate_table.insert(prefix.to_string(), alternates);
+                        }
+                    }
+
+                    // The route also changed if the next hop now advertises a different path or
+                    // different costs, which the peers we forward it to need to hear about
+                    if let Some((ref next_account, ref route)) = local_table.get_route(prefix) {
+                        if next_account.id() == best_next_account.id() && *route == best_route {
                             continue;
                         } else {
                             better_routes.push((
@@ -576,17 +1292,35 @@ where
                 } else {
                     // No longer have a route to this prefix
                     withdrawn_routes.push(prefix);
+                    alternates_changed |= alternate_table.remove(prefix).is_some();
                 }
             }
             (better_routes, withdrawn_routes)
         };
 
+        // Failing to save the alternates only affects retries, so the best routes
+        // are still updated. The alternates are forgotten so that all of them are
+        // saved again the next time the routes are checked
+        if alternates_changed {
+            let alternate_table = self.alternate_table.read().clone();
+            if let Err(err) = self
+                .store
+                .clone()
+                .set_alternate_routes(alternate_table)
+                .await
+            {
+                error!("Error setting alternate routes: {}", err);
+                self.alternate_table.write().clear();
+            }
+        }
+
         // Update the local and forwarding tables
-        if !better_routes.is_empty() || !withdrawn_routes.is_empty() {
-            let update_routes = {
+        if !better_routes.is_empty() || !withdrawn_routes.is_empty() || withdraw_restored_routes {
+            let (routes, saved_update) = {
                 let mut local_table = local_table.write();
                 let mut forwarding_table = forwarding_table.write();
                 let mut forwarding_table_updates = forwarding_table_updates.write();
+                let mut restored_routes = self.restored_routes.lock();
 
                 let mut new_routes: Vec<Route> = Vec::with_capacity(better_routes.len());
 
@@ -618,17 +1352,31 @@ where
                     let not_withdrawn_route = !withdrawn_routes.contains(&prefix);
 
                     if correct_address_scheme && not_local_route && not_withdrawn_route {
-                        let old_route = forwarding_table.get_route(prefix);
-                        if old_route.is_none() || old_route.unwrap().0.id() != account.id() {
-                            route.path.insert(0, ilp_address.to_string());
-                            // Each hop hashes the auth before forwarding
-                            route.auth = hash(&route.auth);
+                        route.path.insert(0, ilp_address.to_string());
+                        // Each hop hashes the auth before forwarding
+                        route.auth = hash(&route.auth);
+                        // and adds its own costs to the route's properties
+                        let latency = self.peer_latencies.read().get(&account.id()).cloned();
+                        add_hop_costs(&mut route, self.route_fee, latency);
+                        let unchanged = forwarding_table
+                            .get_route(prefix)
+                            .map(|(old_account, old_route)| {
+                                old_account.id() == account.id() && *old_route == route
+                            })
+                            .unwrap_or(false);
+                        if !unchanged {
                             forwarding_table.set_route(
                                 prefix.to_string(),
                                 account.clone(),
                                 route.clone(),
                             );
-                            new_routes.push(route);
+                            // Our peers already have the route if we advertised it before restarting
+                            let advertised = restored_routes
+                                .as_mut()
+                                .and_then(|routes| routes.remove(prefix));
+                            if advertised.as_ref() != Some(&route) {
+                                new_routes.push(route);
+                            }
                         }
                     }
                 }
@@ -639,26 +1387,104 @@ where
                     forwarding_table.delete_route(prefix);
                 }
 
+                let mut withdrawn_routes: Vec<String> = withdrawn_routes
+                    .into_iter()
+                    .map(|s| s.to_string())
+                    .collect();
+                if withdraw_restored_routes {
+                    if let Some(routes) = restored_routes.take() {
+                        for (prefix, _route) in routes {
+                            if !withdrawn_routes.contains(&prefix) {
+                                debug!(
+                                    "Withdrawing route advertised before restarting for prefix: {}",
+                                    prefix
+                                );
+                                withdrawn_routes.push(prefix);
+                            }
+                        }
+                    }
+                }
+
+                let mut update = Vec::new();
+                write_routes(&mut update, &new_routes, &withdrawn_routes);
                 let epoch = forwarding_table.increment_epoch();
-                forwarding_table_updates.push((
-                    new_routes,
-                    withdrawn_routes
-                        .into_iter()
-                        .map(|s| s.to_string())
-                        .collect(),
-                ));
+                forwarding_table_updates.push((new_routes, withdrawn_routes));
                 debug_assert_eq!(epoch as usize + 1, forwarding_table_updates.len());
 
-                store.set_routes(local_table.get_simplified_table())
+                (
+                    local_table.get_simplified_table(),
+                    (forwarding_table.id(), epoch, update),
+                )
             };
 
-            update_routes.await
+            // The update is saved even if the routes cannot be, since it is sent to the peers
+            let set_routes_result = store.set_routes(routes).await;
+            let (table_id, epoch, update) = saved_update;
+            let save_update_result = store
+                .save_forwarding_table_update(table_id, epoch, update)
+                .await;
+            if save_update_result.is_ok()
+                && epoch - self.forwarding_log_start.load(Ordering::SeqCst)
+                    >= MAX_FORWARDING_LOG_UPDATES
+            {
+                self.compact_forwarding_log(table_id, epoch).await;
+            }
+            set_routes_result.and(save_update_result)
         } else {
             // The routing table hasn't changed
             Ok(())
         }
     }
 
+    /// Replace the saved updates of the forwarding table up to the given epoch
+    /// with a snapshot of the routes it had at that epoch
+    async fn compact_forwarding_log(&self, table_id: [u8; 16], epoch: u32) {
+        let snapshot = {
+            let forwarding_table_updates = self.forwarding_table_updates.read();
+            let mut routes: BTreeMap<&str, &Route> = BTreeMap::new();
+            let mut withdrawn_routes: BTreeSet<&str> = BTreeSet::new();
+            for (new, withdrawn) in forwarding_table_updates.iter().take(epoch as usize + 1) {
+                for route in new {
+                    withdrawn_routes.remove(route.prefix.as_str());
+                    routes.insert(route.prefix.as_str(), route);
+                }
+                for prefix in withdrawn {
+                    routes.remove(prefix.as_str());
+                    withdrawn_routes.insert(prefix.as_str());
+                }
+            }
+            // The prefixes withdrawn before are withdrawn again, for the peers which
+            // still have them
+            let new_routes: Vec<Route> = routes.values().map(|route| (*route).clone()).collect();
+            let withdrawn_routes: Vec<String> = withdrawn_routes
+                .into_iter()
+                .map(|prefix| prefix.to_string())
+                .collect();
+            let mut snapshot = Vec::new();
+            write_routes(&mut snapshot, &new_routes, &withdrawn_routes);
+            snapshot
+        };
+
+        match self
+            .store
+            .clone()
+            .compact_forwarding_table(table_id, epoch, snapshot)
+            .await
+        {
+            Ok(()) => {
+                debug!(
+                    "Compacted the forwarding routing table updates up to epoch {}",
+                    epoch
+                );
+                self.forwarding_log_start.store(epoch, Ordering::SeqCst);
+            }
+            Err(err) => error!(
+                "Error compacting the forwarding routing table updates: {}",
+                err
+            ),
+        }
+    }
+
     /// Send RouteUpdateRequests to all peers that we send routing messages to
     async fn send_route_updates(&self) -> Result<(), CcpRoutingStoreError> {
         let self_clone = self.clone();
@@ -667,7 +1493,7 @@ where
         let accounts_to_skip: Vec<Uuid> = {
             trace!("Checking accounts to skip");
             let mut unavailable_accounts = self.unavailable_accounts.lock();
-            let mut skip = Vec::new();
+            let mut skip: Vec<Uuid> = self.idle_accounts.lock().iter().cloned().collect();
             for (id, mut backoff) in unavailable_accounts.iter_mut() {
                 if backoff.skip_intervals > 0 {
                     skip.push(*id);
@@ -688,19 +1514,53 @@ where
             .last_epoch_updates_sent_for
             .swap(to_epoch_index, Ordering::SeqCst);
 
-        let route_update_request = self_clone.create_route_update(from_epoch_index, to_epoch_index);
+        let route_updates = self_clone.create_route_updates(from_epoch_index, to_epoch_index);
 
-        let prepare = route_update_request.to_prepare();
+        let policies = match self.store.get_route_policies().await {
+            Ok(policies) => policies,
+            Err(err) => {
+                error!(
+                    "Error loading route policies, sending updates with the default ones: {}",
+                    err
+                );
+                HashMap::new()
+            }
+        };
         accounts.sort_unstable_by_key(|a| a.id().to_string());
         accounts.dedup_by_key(|a| a.id());
 
+        // Defer the updates to the accounts we sent one to too recently,
+        // remembering the first epoch they have not been sent
+        if let Some(min_update_interval) = self.min_update_interval {
+            let now = Instant::now();
+            let last_updates_sent = self.last_updates_sent.lock();
+            let mut deferred_updates = self.deferred_updates.lock();
+            accounts.retain(|account| {
+                let too_soon = last_updates_sent
+                    .get(&account.id())
+                    .map(|sent_at| now.duration_since(*sent_at) < min_update_interval)
+                    .unwrap_or(false);
+                if too_soon {
+                    trace!(
+                        "Deferring route update to account {} (id: {})",
+                        account.username(),
+                        account.id()
+                    );
+                    deferred_updates
+                        .entry(account.id())
+                        .or_insert(from_epoch_index);
+                }
+                !too_soon
+            });
+        }
+
         let broadcasting = !accounts.is_empty();
         if broadcasting {
             trace!(
                 "Sending route update for epochs {} - {} to accounts: {:?} {}",
                 from_epoch_index,
                 to_epoch_index,
-                route_update_request,
+                route_updates,
                 {
                     let account_list: Vec<String> = accounts
                         .iter()
@@ -720,16 +1580,27 @@ where
             // TODO: How can this be converted to a join_all expression?
             // futures 0.1 version worked by doing `join_all(accounts.into_iter().map(...)).and_then(...)`
             // It is odd that the same but with `.await` instead does not work.
-            let mut outgoing = self_clone.outgoing.clone();
             let mut results = Vec::new();
             for account in accounts.into_iter() {
-                let res = outgoing
-                    .send_request(OutgoingRequest {
-                        from: account.clone(),
-                        to: account.clone(),
-                        original_amount: prepare.amount(),
-                        prepare: prepare.clone(),
-                    })
+                // Accounts whose updates were deferred get all the epochs they missed
+                let deferred_updates =
+                    self.deferred_updates
+                        .lock()
+                        .remove(&account.id())
+                        .map(|from_epoch_index| {
+                            self.create_route_updates(from_epoch_index, to_epoch_index)
+                        });
+                if self.min_update_interval.is_some() {
+                    self.last_updates_sent
+                        .lock()
+                        .insert(account.id(), Instant::now());
+                }
+                let res = self
+                    .send_updates(
+                        &account,
+                        deferred_updates.as_ref().unwrap_or(&route_updates),
+                        policies.get(&account.id()),
+                    )
                     .await;
                 results.push((account, res));
             }
@@ -781,22 +1652,83 @@ where
         }
     }
 
-    /// Create a RouteUpdateRequest representing the given range of Forwarding Routing Table epochs.
-    /// If the epoch range is not specified, it will create an update for the last epoch only.
-    fn create_route_update(
+    /// Create the updates for the given epochs, split into several updates if they do not fit
+    /// in a packet of the maximum size. The epochs are split in halves until the update
+    /// of a single epoch is left, whose routes are then split
+    fn create_route_updates(
         &self,
         from_epoch_index: u32,
         to_epoch_index: u32,
-    ) -> RouteUpdateRequest {
-        let (start, end) = (from_epoch_index as usize, to_epoch_index as usize);
-        let (routing_table_id, current_epoch_index) = {
-            let table = self.forwarding_table.read();
-            (table.id(), table.epoch())
-        };
-        let forwarding_table_updates = self.forwarding_table_updates.read();
-        let epochs_to_take = end.saturating_sub(start);
-
-        // Merge the new routes and withdrawn routes from all of the given epochs
+    ) -> Vec<RouteUpdateRequest> {
+        let update = self.create_route_update(from_epoch_index, to_epoch_index);
+        if update.packet_size() <= self.max_packet_size {
+            vec![update]
+        } else if to_epoch_index > from_epoch_index + 1 {
+            let middle_epoch_index = from_epoch_index + (to_epoch_index - from_epoch_index) / 2;
+            let mut updates = self.create_route_updates(from_epoch_index, middle_epoch_index);
+            updates.extend(self.create_route_updates(middle_epoch_index, to_epoch_index));
+            updates
+        } else {
+            debug!(
+                "Splitting the route update for epochs {} - {} into packets of at most {} bytes",
+                from_epoch_index, to_epoch_index, self.max_packet_size
+            );
+            update.split_routes(self.max_packet_size)
+        }
+    }
+
+    /// Send the updates to the account one after the other, applying its export policy.
+    /// Stops at the first update which is rejected, since the account cannot apply the next
+    /// ones without it
+    async fn send_updates(
+        &self,
+        account: &A,
+        updates: &[RouteUpdateRequest],
+        policy: Option<&RoutePolicy>,
+    ) -> IlpResult {
+        let mut outgoing = self.outgoing.clone();
+        let mut result = Ok(CCP_RESPONSE.clone());
+        for update in updates {
+            // Accounts with export policies get their own version of the update
+            let prepare = match policy {
+                Some(policy) if !policy.exports_all() => self
+                    .filter_exported_routes(update.clone(), policy)
+                    .to_prepare(),
+                _ => update.to_prepare(),
+            };
+            let sent_at = Instant::now();
+            result = outgoing
+                .send_request(OutgoingRequest {
+                    from: account.clone(),
+                    to: account.clone(),
+                    original_amount: prepare.amount(),
+                    prepare,
+                })
+                .await;
+            if result.is_err() {
+                break;
+            }
+            self.record_latency(account.id(), sent_at);
+        }
+        result
+    }
+
+    /// Create a RouteUpdateRequest representing the given range of Forwarding Routing Table epochs.
+    /// If the epoch range is not specified, it will create an update for the last epoch only.
+    fn create_route_update(
+        &self,
+        from_epoch_index: u32,
+        to_epoch_index: u32,
+    ) -> RouteUpdateRequest {
+        let (start, end) = (from_epoch_index as usize, to_epoch_index as usize);
+        let (routing_table_id, current_epoch_index) = {
+            let table = self.forwarding_table.read();
+            (table.id(), table.epoch())
+        };
+        let forwarding_table_updates = self.forwarding_table_updates.read();
+        let epochs_to_take = end.saturating_sub(start);
+
+        // Merge the new routes and withdrawn routes from all of the given epochs
         let mut new_routes: Vec<Route> = Vec::with_capacity(epochs_to_take);
         let mut withdrawn_routes: Vec<String> = Vec::new();
 
@@ -806,13 +1738,19 @@ where
         // or the updates is that there isn't necessarily an Account that
         // corresponds to this ILP address)
         if start == 0 {
-            new_routes.push(Route {
-                prefix: self.ilp_address.read().to_string(),
+            let prefix = self.ilp_address.read().to_string();
+            let auth = self
+                .route_auth_secret
+                .map(|secret| route_auth(&secret, &prefix))
+                .unwrap_or([0; 32]);
+            let mut route = Route {
+                prefix,
                 path: Vec::new(),
-                // TODO what should we include here?
-                auth: [0; 32],
+                auth,
                 props: Vec::new(),
-            });
+            };
+            add_hop_costs(&mut route, self.route_fee, None);
+            new_routes.push(route);
         }
 
         // Iterate through each of the given epochs
@@ -859,66 +1797,101 @@ where
         }
     }
 
+    /// Withdraw the new routes which the account's policy does not allow advertising to it,
+    /// in case they were advertised before the policy changed. Our own address is always
+    /// advertised, and withdrawing a route the account never got is harmless.
+    fn filter_exported_routes(
+        &self,
+        mut update: RouteUpdateRequest,
+        policy: &RoutePolicy,
+    ) -> RouteUpdateRequest {
+        let ilp_address = self.ilp_address.read();
+        let (new_routes, filtered_routes): (Vec<Route>, Vec<Route>) =
+            update.new_routes.into_iter().partition(|route| {
+                route.prefix == &*ilp_address as &str || policy.exports(&route.prefix)
+            });
+        update.new_routes = new_routes;
+        update
+            .withdrawn_routes
+            .extend(filtered_routes.into_iter().map(|route| route.prefix));
+        update
+    }
+
     /// Send a Route Update Request to a specific account for the given epoch range.
     /// This is used when the peer has fallen behind and has requested a specific range of updates.
     async fn send_route_update(&self, account: A, from_epoch_index: u32, to_epoch_index: u32) {
-        let prepare = self
-            .create_route_update(from_epoch_index, to_epoch_index)
-            .to_prepare();
         let account_id = account.id();
+        let policy = match self.store.get_route_policies().await {
+            Ok(mut policies) => policies.remove(&account_id).unwrap_or_default(),
+            Err(err) => {
+                error!(
+                    "Error loading the route policy of account {}: {}",
+                    account_id, err
+                );
+                return;
+            }
+        };
+        let updates = self.create_route_updates(from_epoch_index, to_epoch_index);
         debug!(
             "Sending individual route update to account: {} for epochs from: {} to: {}",
             account_id, from_epoch_index, to_epoch_index
         );
-        let result = self
-            .outgoing
-            .clone()
-            .send_request(OutgoingRequest {
-                from: account.clone(),
-                to: account,
-                original_amount: prepare.amount(),
-                prepare,
-            })
-            .await;
+        // This update includes the epochs the account's broadcasts were deferred for
+        self.deferred_updates.lock().remove(&account_id);
+        let result = self.send_updates(&account, &updates, Some(&policy)).await;
 
         if let Err(err) = result {
             error!(
                 "Error sending route update to account {}: {:?}",
                 account_id, err
-            )
+            );
         }
     }
 }
 
-fn get_best_route_for_prefix<A: CcpRoutingAccount>(
-    local_routes: &HashMap<String, A>,
-    configured_routes: &HashMap<String, A>,
-    incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+/// Returns the configured route for that specific prefix or the longest shorter prefix
+/// ("example.a.b.c" will match "example.a.b" and "example.a"), along with its prefix
+fn get_configured_route<'a, A>(
+    configured_routes: &'a HashMap<String, A>,
     prefix: &str,
-) -> Option<(A, Route)> {
-    // Check if we have a configured route for that specific prefix
-    // or any shorter prefix ("example.a.b.c" will match "example.a.b" and "example.a")
+) -> Option<(&'a str, &'a A)> {
     // Note that this logic is duplicated from the Address type. We are not using
     // Addresses here because the prefixes may not be valid ILP addresses ("example." is
     // a valid prefix but not a valid address)
     let segments: Vec<&str> = prefix.split(|c| c == '.').collect();
-    for i in 0..segments.len() {
-        let prefix = &segments[0..segments.len() - i].join(".");
-        if let Some(account) = configured_routes.get(prefix) {
-            return Some((
-                account.clone(),
-                Route {
-                    prefix: account.ilp_address().to_string(),
-                    auth: [0; 32],
-                    path: Vec::new(),
-                    props: Vec::new(),
-                },
-            ));
-        }
+    (0..segments.len()).find_map(|i| {
+        configured_routes
+            .get_key_value(&segments[0..segments.len() - i].join("."))
+            .map(|(prefix, account)| (prefix.as_str(), account))
+    })
+}
+
+/// Returns all the routes we have for the prefix, ranked from best to worst:
+/// the configured route comes first, then the local route and then the routes
+/// received from peers which are not suppressed
+fn get_routes_for_prefix<A: CcpRoutingAccount>(
+    local_routes: &HashMap<String, A>,
+    configured_routes: &HashMap<String, A>,
+    incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
+    prefix: &str,
+    scorer: &RouteScorer,
+) -> Vec<(A, Route)> {
+    let mut routes = Vec::new();
+
+    if let Some((_prefix, account)) = get_configured_route(configured_routes, prefix) {
+        routes.push((
+            account.clone(),
+            Route {
+                prefix: account.ilp_address().to_string(),
+                auth: [0; 32],
+                path: Vec::new(),
+                props: Vec::new(),
+            },
+        ));
     }
 
     if let Some(account) = local_routes.get(prefix) {
-        return Some((
+        routes.push((
             account.clone(),
             Route {
                 prefix: account.ilp_address().to_string(),
@@ -929,41 +1902,58 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
         ));
     }
 
-    let mut candidate_routes = incoming_tables
+    let mut candidate_routes: Vec<&(A, Route)> = incoming_tables
         .values()
-        .filter_map(|incoming_table| incoming_table.get_route(prefix));
-    if let Some((account, route)) = candidate_routes.next() {
-        let (best_account, best_route) = candidate_routes.fold(
-            (account, route),
-            |(best_account, best_route), (account, route)| {
-                // Prioritize child > peer > parent
-                match best_account
+        .filter_map(|incoming_table| incoming_table.get_route(prefix))
+        .filter(|(account, route)| !scorer.is_suppressed(account.id(), route))
+        .collect();
+    candidate_routes.sort_by(|(account_a, route_a), (account_b, route_b)| {
+        // Put the unauthenticated routes the accounts' policies deprioritize last,
+        // then prioritize the accounts' local preference, then child > peer > parent,
+        // then the lowest cost (by default, the shortest path)
+        // and finally base it on account ID
+        scorer
+            .is_deprioritized(account_a.id(), route_a)
+            .cmp(&scorer.is_deprioritized(account_b.id(), route_b))
+            .then_with(|| {
+                scorer
+                    .preference(account_b.id())
+                    .cmp(&scorer.preference(account_a.id()))
+            })
+            .then_with(|| {
+                account_b
                     .routing_relation()
-                    .cmp(&account.routing_relation())
-                {
-                    StdOrdering::Greater => (best_account, best_route),
-                    StdOrdering::Less => (account, route),
-                    _ => {
-                        // Prioritize shortest path
-                        match best_route.path.len().cmp(&route.path.len()) {
-                            StdOrdering::Less => (best_account, best_route),
-                            StdOrdering::Greater => (account, route),
-                            _ => {
-                                // Finally base it on account ID
-                                if best_account.id().to_string() < account.id().to_string() {
-                                    (best_account, best_route)
-                                } else {
-                                    (account, route)
-                                }
-                            }
-                        }
-                    }
-                }
-            },
-        );
-        Some((best_account.clone(), best_route.clone()))
-    } else {
-        None
+                    .cmp(&account_a.routing_relation())
+            })
+            .then_with(|| {
+                let cost_a = scorer.cost(account_a.id(), route_a);
+                let cost_b = scorer.cost(account_b.id(), route_b);
+                cost_a.partial_cmp(&cost_b).unwrap_or(StdOrdering::Equal)
+            })
+            .then_with(|| account_a.id().to_string().cmp(&account_b.id().to_string()))
+    });
+    routes.extend(candidate_routes.into_iter().cloned());
+    routes
+}
+
+/// Puts the route through the account currently used for the prefix back first if the
+/// best route received from another account is not significantly better, so that small
+/// changes in the costs of the routes do not keep switching between them
+fn keep_current_route<A: CcpRoutingAccount>(
+    routes: &mut Vec<(A, Route)>,
+    current: Option<&(A, Route)>,
+    scorer: &RouteScorer,
+) {
+    if let Some((current_account, _route)) = current {
+        let index = routes
+            .iter()
+            .position(|(account, _route)| account.id() == current_account.id());
+        if let Some(index) = index {
+            if index > 0 && !scorer.is_significantly_better(&routes[0], &routes[index]) {
+                let current = routes.remove(index);
+                routes.insert(0, current);
+            }
+        }
     }
 }
 
@@ -972,7 +1962,14 @@ impl<I, O, S, A> IncomingService<A> for CcpRouteManager<I, O, S, A>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
-    S: AddressStore + CcpRoutingStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + CcpRoutingStore<Account = A>
+        + RoutePolicyStore
+        + RouteDetailsStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     A: CcpRoutingAccount + Send + Sync + 'static,
 {
     /// Handle the IncomingRequest if it is a CCP protocol message or
@@ -1085,56 +2082,190 @@ mod ranking_routes {
         ])
     });
 
+    static SCORING: Lazy<RouteScoring> = Lazy::new(RouteScoring::default);
+    static POLICIES: Lazy<HashMap<Uuid, RoutePolicy>> = Lazy::new(HashMap::new);
+    static LATENCIES: Lazy<HashMap<Uuid, u64>> = Lazy::new(HashMap::new);
+    static UNAUTHENTICATED: Lazy<HashMap<Uuid, HashSet<String>>> = Lazy::new(HashMap::new);
+    static SUPPRESSED: Lazy<HashMap<Uuid, HashSet<String>>> = Lazy::new(HashMap::new);
+
+    fn scorer() -> RouteScorer<'static> {
+        RouteScorer {
+            scoring: &SCORING,
+            policies: &POLICIES,
+            latencies: &LATENCIES,
+            unauthenticated: &UNAUTHENTICATED,
+            suppressed: &SUPPRESSED,
+        }
+    }
+
     #[test]
     fn prioritizes_configured_routes() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a");
-        assert_eq!(
-            best_route.unwrap().0.id(),
-            Uuid::from_slice(&[4; 16]).unwrap()
-        );
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a", &scorer());
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[4; 16]).unwrap());
     }
 
     #[test]
     fn prioritizes_shorter_configured_routes() {
-        let best_route =
-            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a.sub-prefix");
-        assert_eq!(
-            best_route.unwrap().0.id(),
-            Uuid::from_slice(&[4; 16]).unwrap()
+        let routes = get_routes_for_prefix(
+            &LOCAL,
+            &CONFIGURED,
+            &INCOMING,
+            "example.a.sub-prefix",
+            &scorer(),
         );
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[4; 16]).unwrap());
     }
 
     #[test]
     fn prioritizes_local_routes_over_broadcasted_ones() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.c");
-        assert_eq!(
-            best_route.unwrap().0.id(),
-            Uuid::from_slice(&[3; 16]).unwrap()
-        );
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.c", &scorer());
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[3; 16]).unwrap());
     }
 
     #[test]
     fn prioritizes_children_over_peers() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d");
-        assert_eq!(
-            best_route.unwrap().0.id(),
-            Uuid::from_slice(&[6; 16]).unwrap()
-        );
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d", &scorer());
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[6; 16]).unwrap());
     }
 
     #[test]
     fn prioritizes_shorter_paths() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e");
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e", &scorer());
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[7; 16]).unwrap());
+    }
+
+    #[test]
+    fn prioritizes_preferred_accounts() {
+        let mut policies = HashMap::new();
+        policies.insert(
+            Uuid::from_slice(&[7; 16]).unwrap(),
+            RoutePolicy {
+                preference: 1,
+                ..RoutePolicy::default()
+            },
+        );
+        let scorer = RouteScorer {
+            policies: &policies,
+            ..scorer()
+        };
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d", &scorer);
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[7; 16]).unwrap());
+    }
+
+    #[test]
+    fn deprioritizes_unauthenticated_routes() {
+        let child_id = Uuid::from_slice(&[6; 16]).unwrap();
+        let mut policies = HashMap::new();
+        policies.insert(
+            child_id,
+            RoutePolicy {
+                unauthenticated_routes: UnauthenticatedRoutes::Deprioritize,
+                ..RoutePolicy::default()
+            },
+        );
+        let mut unauthenticated = HashMap::new();
+        unauthenticated.insert(child_id, HashSet::from_iter(vec!["example.d".to_string()]));
+        let scorer = RouteScorer {
+            policies: &policies,
+            unauthenticated: &unauthenticated,
+            ..scorer()
+        };
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d", &scorer);
+        let ids: Vec<Uuid> = routes.iter().map(|(account, _)| account.id()).collect();
+        assert_eq!(ids, vec![Uuid::from_slice(&[7; 16]).unwrap(), child_id]);
+    }
+
+    #[test]
+    fn ignores_suppressed_routes() {
+        let child_id = Uuid::from_slice(&[6; 16]).unwrap();
+        let mut suppressed = HashMap::new();
+        suppressed.insert(child_id, HashSet::from_iter(vec!["example.d".to_string()]));
+        let scorer = RouteScorer {
+            suppressed: &suppressed,
+            ..scorer()
+        };
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d", &scorer);
+        let ids: Vec<Uuid> = routes.iter().map(|(account, _)| account.id()).collect();
+        assert_eq!(ids, vec![Uuid::from_slice(&[7; 16]).unwrap()]);
+    }
+
+    #[test]
+    fn prioritizes_cheaper_routes() {
+        let scoring = RouteScoring {
+            latency: 1.0,
+            ..RouteScoring::default()
+        };
+        let mut latencies = HashMap::new();
+        latencies.insert(Uuid::from_slice(&[7; 16]).unwrap(), 10);
+        let scorer = RouteScorer {
+            scoring: &scoring,
+            latencies: &latencies,
+            ..scorer()
+        };
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e", &scorer);
+        assert_eq!(routes[0].0.id(), Uuid::from_slice(&[8; 16]).unwrap());
+    }
+
+    #[test]
+    fn keeps_current_route_unless_significantly_better() {
+        let id7 = Uuid::from_slice(&[7; 16]).unwrap();
+        let id8 = Uuid::from_slice(&[8; 16]).unwrap();
+        let scoring = RouteScoring {
+            path_length: 0.0,
+            latency: 1.0,
+            ..RouteScoring::default()
+        };
+        let current = INCOMING[&id7].get_route("example.e").cloned();
+
+        for &(latency, kept) in &[(100, true), (120, false)] {
+            let latencies = HashMap::from_iter(vec![(id7, latency), (id8, 95)]);
+            let scorer = RouteScorer {
+                scoring: &scoring,
+                latencies: &latencies,
+                ..scorer()
+            };
+            let mut routes =
+                get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e", &scorer);
+            assert_eq!(routes[0].0.id(), id8);
+            keep_current_route(&mut routes, current.as_ref(), &scorer);
+            assert_eq!(routes[0].0.id(), if kept { id7 } else { id8 });
+            assert_eq!(routes.len(), 2);
+        }
+    }
+
+    #[test]
+    fn ranks_alternate_routes() {
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e", &scorer());
+        let ids: Vec<Uuid> = routes.iter().map(|(account, _)| account.id()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                Uuid::from_slice(&[7; 16]).unwrap(),
+                Uuid::from_slice(&[8; 16]).unwrap()
+            ]
+        );
+
+        let routes = get_routes_for_prefix(
+            &LOCAL,
+            &CONFIGURED,
+            &INCOMING,
+            "example.a.sub-prefix",
+            &scorer(),
+        );
+        let ids: Vec<Uuid> = routes.iter().map(|(account, _)| account.id()).collect();
         assert_eq!(
-            best_route.unwrap().0.id(),
-            Uuid::from_slice(&[7; 16]).unwrap()
+            ids,
+            vec![
+                Uuid::from_slice(&[4; 16]).unwrap(),
+                Uuid::from_slice(&[7; 16]).unwrap()
+            ]
         );
     }
 
     #[test]
     fn returns_none_for_no_route() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z");
-        assert!(best_route.is_none());
+        let routes = get_routes_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z", &scorer());
+        assert!(routes.is_empty());
     }
 }
 
@@ -1250,6 +2381,44 @@ mod handle_route_control_request {
         assert_eq!(update.current_epoch_index, 1);
         assert_eq!(update.new_routes.len(), 3);
     }
+
+    #[tokio::test]
+    async fn stops_broadcasting_to_idle_accounts() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        let account = TestAccount::new(Uuid::from_slice(&[1; 16]).unwrap(), "example.local.1");
+        let control_request = |mode: Mode| IncomingRequest {
+            from: account.clone(),
+            prepare: RouteControlRequest {
+                mode,
+                ..CONTROL_REQUEST.clone()
+            }
+            .to_prepare(),
+        };
+
+        service
+            .handle_request(control_request(Mode::Idle))
+            .await
+            .unwrap();
+        assert!(outgoing_requests.lock().is_empty());
+        service.send_route_updates().await.unwrap();
+        let accounts: Vec<Uuid> = outgoing_requests
+            .lock()
+            .iter()
+            .map(|request| request.to.id())
+            .collect();
+        assert_eq!(accounts, vec![Uuid::from_slice(&[2; 16]).unwrap()]);
+
+        // Switching back to Sync mode sends them an update right away
+        // and includes them in the broadcasts again
+        *outgoing_requests.lock() = Vec::new();
+        service
+            .handle_request(control_request(Mode::Sync))
+            .await
+            .unwrap();
+        assert_eq!(outgoing_requests.lock().len(), 1);
+        service.send_route_updates().await.unwrap();
+        assert_eq!(outgoing_requests.lock().len(), 3);
+    }
 }
 
 #[cfg(test)]
@@ -1257,9 +2426,13 @@ mod handle_route_update_request {
     use super::*;
     use crate::fixtures::*;
     use crate::test_helpers::*;
+    use crate::ROUTE_AUTH_CHAIN_LENGTH;
+    use interledger_ildcp::{is_ildcp_request, IldcpResponseBuilder};
     use interledger_packet::PrepareBuilder;
+    use interledger_service::{incoming_service_fn, outgoing_service_fn};
     use std::{
         iter::FromIterator,
+        str::FromStr,
         time::{Duration, SystemTime},
     };
 
@@ -1294,6 +2467,22 @@ mod handle_route_update_request {
         );
     }
 
+    #[tokio::test]
+    async fn asks_accounts_we_do_not_receive_routes_from_to_go_idle() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        let result = service
+            .handle_request(IncomingRequest {
+                prepare: UPDATE_REQUEST_SIMPLE.to_prepare(),
+                from: CHILD_ACCOUNT.clone(),
+            })
+            .await;
+        assert!(result.is_err());
+        let request: &OutgoingRequest<TestAccount> = &outgoing_requests.lock()[0];
+        assert_eq!(request.to.id(), CHILD_ACCOUNT.id());
+        let control = RouteControlRequest::try_from(&request.prepare).unwrap();
+        assert_eq!(control.mode, Mode::Idle);
+    }
+
     #[tokio::test]
     async fn rejects_from_non_routing_account() {
         let result = test_service()
@@ -1364,7 +2553,7 @@ mod handle_route_update_request {
             auth: [0; 32],
             props: Vec::new(),
         });
-        let request = service.filter_routes(request);
+        let request = service.filter_routes(request, &RoutePolicy::default());
         assert_eq!(request.new_routes.len(), 1);
         assert_eq!(request.new_routes[0].prefix, "example.valid".to_string());
     }
@@ -1385,7 +2574,7 @@ mod handle_route_update_request {
             auth: [0; 32],
             props: Vec::new(),
         });
-        let request = service.filter_routes(request);
+        let request = service.filter_routes(request, &RoutePolicy::default());
         assert_eq!(request.new_routes.len(), 1);
         assert_eq!(request.new_routes[0].prefix, "example.valid".to_string());
     }
@@ -1410,7 +2599,7 @@ mod handle_route_update_request {
             auth: [0; 32],
             props: Vec::new(),
         });
-        let request = service.filter_routes(request);
+        let request = service.filter_routes(request, &RoutePolicy::default());
         assert_eq!(request.new_routes.len(), 1);
         assert_eq!(request.new_routes[0].prefix, "example.valid".to_string());
     }
@@ -1431,7 +2620,7 @@ mod handle_route_update_request {
             auth: [0; 32],
             props: Vec::new(),
         });
-        let request = service.filter_routes(request);
+        let request = service.filter_routes(request, &RoutePolicy::default());
         assert_eq!(request.new_routes.len(), 1);
         assert_eq!(request.new_routes[0].prefix, "example.valid".to_string());
     }
@@ -1467,6 +2656,109 @@ mod handle_route_update_request {
         );
     }
 
+    #[tokio::test]
+    async fn applies_import_policy() {
+        let mut service = test_service();
+        service
+            .store
+            .set_route_policy(
+                ROUTING_ACCOUNT.id(),
+                RoutePolicy {
+                    import_deny: vec!["example.prefix2".to_string()],
+                    ..RoutePolicy::default()
+                },
+            )
+            .await
+            .unwrap();
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        let local_table = service.local_table.read();
+        assert!(local_table.get_route("example.prefix1").is_some());
+        assert!(local_table.get_route("example.prefix2").is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unauthenticated_routes_if_policy_says_so() {
+        let mut service = test_service();
+        service
+            .store
+            .set_route_policy(
+                ROUTING_ACCOUNT.id(),
+                RoutePolicy {
+                    unauthenticated_routes: UnauthenticatedRoutes::Reject,
+                    ..RoutePolicy::default()
+                },
+            )
+            .await
+            .unwrap();
+        // The route for example.prefix1 went through one hop, which hashed its auth
+        let route = &UPDATE_REQUEST_COMPLEX.new_routes[0];
+        let anchor = (1..ROUTE_AUTH_CHAIN_LENGTH).fold(route.auth, |auth, _| hash(&auth));
+        service.route_auth_anchors = Arc::new(HashMap::from_iter(vec![(
+            "example.prefix1".to_string(),
+            anchor,
+        )]));
+
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        let local_table = service.local_table.read();
+        assert!(local_table.get_route("example.prefix1").is_some());
+        assert!(local_table.get_route("example.prefix2").is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_track_of_unauthenticated_routes() {
+        let mut service = test_service();
+        service.route_auth_anchors = Arc::new(HashMap::from_iter(vec![(
+            "example.prefix1".to_string(),
+            [0; 32],
+        )]));
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        // Unauthenticated routes are accepted by default
+        assert!(service
+            .local_table
+            .read()
+            .get_route("example.prefix1")
+            .is_some());
+        {
+            let unauthenticated = service.unauthenticated_routes.read();
+            let prefixes = &unauthenticated[&ROUTING_ACCOUNT.id()];
+            assert!(prefixes.contains("example.prefix1"));
+            assert!(prefixes.contains("example.prefix2"));
+        }
+
+        // Once the account is deleted, its routes are no longer kept track of
+        service.store.routes.lock().clear();
+        service.remove_deleted_accounts().await;
+        assert!(service.incoming_tables.read().is_empty());
+        assert!(service.unauthenticated_routes.read().is_empty());
+    }
+
     #[tokio::test]
     async fn writes_local_routing_table_to_store() {
         let mut service = test_service();
@@ -1502,6 +2794,65 @@ mod handle_route_update_request {
         );
     }
 
+    #[tokio::test]
+    async fn writes_alternate_routes_to_store() {
+        let mut service = test_service();
+        let other_peer = TestAccount::new(Uuid::new_v4(), "example.other-peer");
+        for account in &[ROUTING_ACCOUNT.clone(), other_peer.clone()] {
+            let mut request = UPDATE_REQUEST_COMPLEX.clone();
+            request.to_epoch_index = 1;
+            request.from_epoch_index = 0;
+            service
+                .handle_request(IncomingRequest {
+                    from: account.clone(),
+                    prepare: request.to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let best_id = service.store.routes.lock()["example.prefix1"].id();
+        let alternate_ids: Vec<Uuid> = service.store.alternate_routes.lock()["example.prefix1"]
+            .iter()
+            .map(|account| account.id())
+            .collect();
+        let other_id = if best_id == other_peer.id() {
+            ROUTING_ACCOUNT.id()
+        } else {
+            other_peer.id()
+        };
+        assert_eq!(alternate_ids, vec![other_id]);
+    }
+
+    #[tokio::test]
+    async fn updates_routes_when_alternate_routes_cannot_be_saved() {
+        let mut service = test_service();
+        *service.store.fail_alternate_routes.lock() = true;
+        let other_peer = TestAccount::new(Uuid::new_v4(), "example.other-peer");
+        for account in &[ROUTING_ACCOUNT.clone(), other_peer] {
+            let mut request = UPDATE_REQUEST_COMPLEX.clone();
+            request.to_epoch_index = 1;
+            request.from_epoch_index = 0;
+            service
+                .handle_request(IncomingRequest {
+                    from: account.clone(),
+                    prepare: request.to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+        assert!(service.store.routes.lock().contains_key("example.prefix1"));
+        assert!(service.store.alternate_routes.lock().is_empty());
+
+        // The alternates are saved once the store is available again
+        *service.store.fail_alternate_routes.lock() = false;
+        service.update_best_routes(None).await.unwrap();
+        assert_eq!(
+            service.store.alternate_routes.lock()["example.prefix1"].len(),
+            1
+        );
+    }
+
     #[tokio::test]
     async fn doesnt_overwrite_configured_or_local_routes() {
         let mut service = test_service();
@@ -1592,12 +2943,239 @@ mod handle_route_update_request {
     }
 
     #[tokio::test]
-    async fn sends_control_request_if_routing_table_id_changed() {
-        let (mut service, outgoing_requests) = test_service_with_routes();
-        // First request is valid
-        let mut request1 = UPDATE_REQUEST_COMPLEX.clone();
-        request1.to_epoch_index = 3;
-        request1.from_epoch_index = 0;
+    async fn forwards_routes_whose_path_changed() {
+        let mut service = test_service();
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        let epoch = service.forwarding_table.read().epoch();
+
+        // The same next hop now reaches the prefix through another connector
+        let mut route = UPDATE_REQUEST_COMPLEX.new_routes[1].clone();
+        route.path[0] = "example.connector2".to_string();
+        let mut update = RouteUpdateRequest {
+            routing_table_id: UPDATE_REQUEST_COMPLEX.routing_table_id,
+            from_epoch_index: 1,
+            to_epoch_index: 2,
+            current_epoch_index: 2,
+            hold_down_time: 45000,
+            speaker: UPDATE_REQUEST_COMPLEX.speaker.clone(),
+            new_routes: vec![route.clone()],
+            withdrawn_routes: Vec::new(),
+        };
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: update.to_prepare(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(service.forwarding_table.read().epoch(), epoch + 1);
+        let forwarded = service
+            .forwarding_table
+            .read()
+            .get_route("example.prefix2")
+            .unwrap()
+            .1
+            .clone();
+        assert_eq!(forwarded.path[1..], route.path[..]);
+        let (new_routes, _withdrawn) = service
+            .forwarding_table_updates
+            .read()
+            .last()
+            .cloned()
+            .unwrap();
+        assert_eq!(new_routes, vec![forwarded]);
+
+        // Advertising the same route again does not change anything
+        update.from_epoch_index = 2;
+        update.to_epoch_index = 3;
+        update.current_epoch_index = 3;
+        service
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: update.to_prepare(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(service.forwarding_table.read().epoch(), epoch + 1);
+    }
+
+    #[tokio::test]
+    async fn applies_update_split_into_several_requests() {
+        let mut service = test_service();
+        let mut first = UPDATE_REQUEST_COMPLEX.clone();
+        first.from_epoch_index = 0;
+        first.to_epoch_index = 0;
+        first.withdrawn_routes = Vec::new();
+        let second_route = first.new_routes.split_off(1);
+        let mut second = first.clone();
+        second.to_epoch_index = 1;
+        second.new_routes = second_route;
+
+        for request in [first, second].iter() {
+            service
+                .handle_request(IncomingRequest {
+                    from: ROUTING_ACCOUNT.clone(),
+                    prepare: request.to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+
+        assert!((*service.local_table.read())
+            .get_route("example.prefix1")
+            .is_some());
+        assert!((*service.local_table.read())
+            .get_route("example.prefix2")
+            .is_some());
+        assert_eq!(
+            service.incoming_tables.read()[&ROUTING_ACCOUNT.id()].epoch(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn suppresses_flapping_routes() {
+        let mut service = test_service();
+        service.flap_dampener = Some(Arc::new(Mutex::new(FlapDampener::new(RouteDampening {
+            suppress_threshold: 1500.0,
+            ..RouteDampening::default()
+        }))));
+
+        // The route is advertised at even epochs and withdrawn at odd ones
+        for epoch in 0..5 {
+            let mut update = UPDATE_REQUEST_COMPLEX.clone();
+            update.from_epoch_index = epoch;
+            update.to_epoch_index = epoch + 1;
+            if epoch % 2 == 1 {
+                update.new_routes.clear();
+                update.withdrawn_routes = vec!["example.prefix2".to_string()];
+            }
+            service
+                .handle_request(IncomingRequest {
+                    from: ROUTING_ACCOUNT.clone(),
+                    prepare: update.to_prepare(),
+                })
+                .await
+                .unwrap();
+
+            let route = (*service.local_table.read())
+                .get_route("example.prefix2")
+                .map(|(account, _route)| account.id());
+            // The first withdrawal does not suppress the route but the second one does
+            if epoch == 0 || epoch == 2 {
+                assert_eq!(route, Some(ROUTING_ACCOUNT.id()));
+            } else {
+                assert_eq!(route, None);
+            }
+        }
+        assert!(service
+            .suppressed_routes()
+            .get(&ROUTING_ACCOUNT.id())
+            .unwrap()
+            .contains("example.prefix2"));
+    }
+
+    #[tokio::test]
+    async fn gets_new_address_when_parent_address_changes() {
+        let parent = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: Address::from_str("example.parent").unwrap(),
+            relation: RoutingRelation::Parent,
+        };
+        let store = TestStore::new();
+        let outgoing_requests: Arc<Mutex<Vec<OutgoingRequest<TestAccount>>>> =
+            Arc::new(Mutex::new(Vec::new()));
+        let outgoing_requests_clone = outgoing_requests.clone();
+        let service = CcpRouteManagerBuilder::new(
+            Address::from_str("example.parent.connector").unwrap(),
+            store.clone(),
+            outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+                let is_ildcp = is_ildcp_request(&request.prepare);
+                (*outgoing_requests_clone.lock()).push(request);
+                if is_ildcp {
+                    Ok(IldcpResponseBuilder {
+                        ilp_address: &Address::from_str("example.new-parent.connector").unwrap(),
+                        asset_scale: 9,
+                        asset_code: "XYZ",
+                    }
+                    .build()
+                    .into())
+                } else {
+                    Ok(CCP_RESPONSE.clone())
+                }
+            }),
+            incoming_service_fn(|_request| -> IlpResult { unreachable!() }),
+        )
+        .to_service();
+        let heartbeat = |speaker: &str| RouteUpdateRequest {
+            routing_table_id: [1; 16],
+            current_epoch_index: 0,
+            from_epoch_index: 0,
+            to_epoch_index: 0,
+            hold_down_time: 30000,
+            speaker: Address::from_str(speaker).unwrap(),
+            new_routes: Vec::new(),
+            withdrawn_routes: Vec::new(),
+        };
+
+        for speaker in ["example.parent", "example.parent"].iter() {
+            service
+                .handle_route_update_request(IncomingRequest {
+                    from: parent.clone(),
+                    prepare: heartbeat(speaker).to_prepare(),
+                })
+                .await
+                .unwrap();
+        }
+        assert!(outgoing_requests.lock().is_empty());
+
+        service
+            .handle_route_update_request(IncomingRequest {
+                from: parent.clone(),
+                prepare: heartbeat("example.new-parent").to_prepare(),
+            })
+            .await
+            .unwrap();
+        {
+            // The parent is asked for our new address and for its routes again
+            let requests = outgoing_requests.lock();
+            assert_eq!(requests.len(), 2);
+            assert!(requests
+                .iter()
+                .all(|request| request.to.id() == parent.id()));
+            assert!(is_ildcp_request(&requests[0].prepare));
+            let control = RouteControlRequest::try_from(&requests[1].prepare).unwrap();
+            assert_eq!(control.mode, Mode::Sync);
+        }
+        let new_address = Address::from_str("example.new-parent.connector").unwrap();
+        assert_eq!(store.get_ilp_address(), new_address);
+        assert_eq!(*service.ilp_address.read(), new_address);
+
+        // Our address is only updated if it was assigned under the parent's old address,
+        // not if it merely starts with it
+        *service.ilp_address.write() =
+            Address::from_str("example.new-parent.child.connector").unwrap();
+        let speaker = Address::from_str("example.newer-parent").unwrap();
+        assert!(!service.parent_address_changed(&parent, &speaker));
+    }
+
+    #[tokio::test]
+    async fn sends_control_request_if_routing_table_id_changed() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        // First request is valid
+        let mut request1 = UPDATE_REQUEST_COMPLEX.clone();
+        request1.to_epoch_index = 3;
+        request1.from_epoch_index = 0;
         service
             .handle_request(IncomingRequest {
                 from: ROUTING_ACCOUNT.clone(),
@@ -1664,6 +3242,155 @@ mod handle_route_update_request {
     }
 }
 
+#[cfg(test)]
+mod resume_forwarding_table {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::ForwardingTableState;
+    use std::iter::FromIterator;
+
+    fn route(prefix: &str) -> Route {
+        Route {
+            prefix: prefix.to_string(),
+            path: vec!["example.connector".to_string()],
+            auth: [0; 32],
+            props: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_forwarding_table_updates() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        service.update_best_routes(None).await.unwrap();
+
+        let state = service.store.forwarding_table.lock().clone().unwrap();
+        assert_eq!(state.table_id, service.forwarding_table.read().id());
+        assert_eq!(state.updates.len(), 1);
+        let (new_routes, withdrawn_routes) = read_routes(&mut &state.updates[&0][..]).unwrap();
+        assert_eq!(new_routes, service.forwarding_table_updates.read()[0].0);
+        assert_eq!(new_routes.len(), 2);
+        assert!(withdrawn_routes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resumes_saved_forwarding_table() {
+        let (previous, _outgoing_requests) = test_service_with_routes();
+        previous.update_best_routes(None).await.unwrap();
+        let mut state = previous.store.forwarding_table.lock().clone().unwrap();
+        // A route the node advertised before restarting but no longer has
+        let mut update = Vec::new();
+        write_routes(&mut update, &[route("example.gone")], &[]);
+        state.updates.insert(1, update);
+
+        let (service, _outgoing_requests) = test_service_with_routes();
+        *service.store.forwarding_table.lock() = Some(state.clone());
+        service.load_forwarding_table().await;
+        assert_eq!(service.forwarding_table.read().id(), state.table_id);
+        assert_eq!(service.forwarding_table.read().epoch(), 2);
+        assert_eq!(
+            service.last_epoch_updates_sent_for.load(Ordering::SeqCst),
+            2
+        );
+
+        // Only the route which is gone is included in the next update
+        service.update_best_routes(None).await.unwrap();
+        let update = service.create_route_update(2, 3);
+        assert_eq!(update.routing_table_id, state.table_id);
+        assert!(update.new_routes.is_empty());
+        assert_eq!(update.withdrawn_routes, vec!["example.gone".to_string()]);
+        assert_eq!(
+            service
+                .store
+                .forwarding_table
+                .lock()
+                .as_ref()
+                .unwrap()
+                .updates
+                .len(),
+            3
+        );
+        assert!(service.restored_routes.lock().is_none());
+    }
+
+    #[tokio::test]
+    async fn ignores_invalid_saved_table() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        let table_id = service.forwarding_table.read().id();
+        *service.store.forwarding_table.lock() = Some(ForwardingTableState {
+            table_id: [9; 16],
+            updates: BTreeMap::from_iter(vec![(0, vec![1, 2, 3])]),
+        });
+        service.load_forwarding_table().await;
+        assert_eq!(service.forwarding_table.read().id(), table_id);
+        assert_eq!(service.forwarding_table.read().epoch(), 0);
+    }
+
+    #[tokio::test]
+    async fn starts_new_table_if_saved_updates_are_missing() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        let table_id = service.forwarding_table.read().id();
+        let mut update = Vec::new();
+        write_routes(&mut update, &[route("example.a")], &[]);
+        *service.store.forwarding_table.lock() = Some(ForwardingTableState {
+            table_id: [9; 16],
+            updates: BTreeMap::from_iter(vec![(0, update.clone()), (2, update)]),
+        });
+        service.load_forwarding_table().await;
+        assert_eq!(service.forwarding_table.read().id(), table_id);
+        assert_eq!(service.forwarding_table.read().epoch(), 0);
+    }
+
+    #[tokio::test]
+    async fn compacts_saved_forwarding_table() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        *service.forwarding_table_updates.write() = vec![
+            (vec![route("example.a"), route("example.b")], Vec::new()),
+            (vec![route("example.c")], vec!["example.a".to_string()]),
+            (Vec::new(), vec!["example.c".to_string()]),
+        ];
+        let table_id = service.forwarding_table.read().id();
+        let mut store = service.store.clone();
+        let updates = service.forwarding_table_updates.read().clone();
+        for (epoch, (new_routes, withdrawn_routes)) in updates.iter().enumerate() {
+            let mut update = Vec::new();
+            write_routes(&mut update, new_routes, withdrawn_routes);
+            store
+                .save_forwarding_table_update(table_id, epoch as u32, update)
+                .await
+                .unwrap();
+        }
+
+        service.compact_forwarding_log(table_id, 1).await;
+        assert_eq!(service.forwarding_log_start.load(Ordering::SeqCst), 1);
+        let state = service.store.forwarding_table.lock().clone().unwrap();
+        assert_eq!(
+            state.updates.keys().cloned().collect::<Vec<u32>>(),
+            vec![1, 2]
+        );
+        let (new_routes, withdrawn_routes) = read_routes(&mut &state.updates[&1][..]).unwrap();
+        assert_eq!(new_routes, vec![route("example.b"), route("example.c")]);
+        assert_eq!(withdrawn_routes, vec!["example.a".to_string()]);
+
+        // The snapshot stands in for the updates it combines when the table is resumed
+        let (resumed, _outgoing_requests) = test_service_with_routes();
+        *resumed.store.forwarding_table.lock() = Some(state);
+        resumed.load_forwarding_table().await;
+        assert_eq!(resumed.forwarding_table.read().id(), table_id);
+        assert_eq!(resumed.forwarding_table.read().epoch(), 3);
+        assert_eq!(resumed.forwarding_log_start.load(Ordering::SeqCst), 1);
+        let update = resumed.create_route_update(0, 2);
+        let prefixes: Vec<&str> = update
+            .new_routes
+            .iter()
+            .map(|route| route.prefix.as_str())
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec!["example.connector", "example.b", "example.c"]
+        );
+    }
+}
+
 #[cfg(test)]
 mod create_route_update {
     use super::*;
@@ -1682,6 +3409,15 @@ mod create_route_update {
         assert!(update.withdrawn_routes.is_empty());
     }
 
+    #[tokio::test]
+    async fn authenticates_own_route() {
+        let mut service = test_service();
+        service.route_auth_secret = Some([1; 32]);
+        let update = service.create_route_update(0, 0);
+        let anchor = route_auth_anchor(&[1; 32], "example.connector");
+        assert!(verify_route_auth(&update.new_routes[0], &anchor));
+    }
+
     #[tokio::test]
     async fn includes_the_given_range_of_epochs() {
         let service = test_service();
@@ -1769,6 +3505,56 @@ mod send_route_updates {
         assert_eq!(accounts, expected);
     }
 
+    #[tokio::test]
+    async fn defers_updates_to_accounts_updated_recently() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        service.min_update_interval = Some(Duration::from_secs(60));
+        service.send_route_updates().await.unwrap();
+        assert_eq!(outgoing_requests.lock().len(), 2);
+
+        service.update_best_routes(None).await.unwrap();
+        service.send_route_updates().await.unwrap();
+        assert_eq!(outgoing_requests.lock().len(), 2);
+        assert_eq!(service.deferred_updates.lock().len(), 2);
+
+        // Once the interval has passed, the accounts get all the epochs they missed
+        service.min_update_interval = Some(Duration::from_millis(0));
+        service.update_best_routes(None).await.unwrap();
+        service.send_route_updates().await.unwrap();
+        let update = RouteUpdateRequest::try_from(&outgoing_requests.lock()[2].prepare).unwrap();
+        assert_eq!(update.from_epoch_index, 0);
+        assert_eq!(update.to_epoch_index, 1);
+        assert_eq!(update.new_routes.len(), 3);
+        assert!(service.deferred_updates.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn splits_updates_larger_than_the_max_packet_size() {
+        let (mut service, outgoing_requests) = test_service_with_routes();
+        service.update_best_routes(None).await.unwrap();
+        service.max_packet_size = service.create_route_update(0, 1).packet_size() - 1;
+
+        service.send_route_updates().await.unwrap();
+        let account_id = Uuid::from_slice(&[1; 16]).unwrap();
+        let updates: Vec<RouteUpdateRequest> = outgoing_requests
+            .lock()
+            .iter()
+            .filter(|request| request.to.id() == account_id)
+            .map(|request| RouteUpdateRequest::try_from(&request.prepare).unwrap())
+            .collect();
+        assert!(updates.len() > 1);
+        for update in updates.iter() {
+            assert!(update.packet_size() <= service.max_packet_size);
+            assert_eq!(update.from_epoch_index, 0);
+        }
+        assert!(updates[..updates.len() - 1]
+            .iter()
+            .all(|update| update.to_epoch_index == 0));
+        assert_eq!(updates.last().unwrap().to_epoch_index, 1);
+        let routes: usize = updates.iter().map(|update| update.new_routes.len()).sum();
+        assert_eq!(routes, 3);
+    }
+
     #[tokio::test]
     async fn broadcasts_configured_and_local_routes() {
         let (service, outgoing_requests) = test_service_with_routes();
@@ -1788,6 +3574,123 @@ mod send_route_updates {
         assert!(prefixes.contains(&"example.configured.1"));
     }
 
+    #[tokio::test]
+    async fn applies_export_policies() {
+        let (service, outgoing_requests) = test_service_with_routes();
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        service
+            .store
+            .set_route_policy(
+                id1,
+                RoutePolicy {
+                    export_allow: vec!["example.local".to_string()],
+                    ..RoutePolicy::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // This is normally spawned as a task when the service is created
+        service.update_best_routes(None).await.unwrap();
+
+        service.send_route_updates().await.unwrap();
+        for request in outgoing_requests.lock().iter() {
+            let update = RouteUpdateRequest::try_from(&request.prepare).unwrap();
+            let prefixes: Vec<&str> = update
+                .new_routes
+                .iter()
+                .map(|route| route.prefix.as_str())
+                .collect();
+            assert!(prefixes.contains(&"example.connector"));
+            assert!(prefixes.contains(&"example.local.1"));
+            assert_eq!(
+                prefixes.contains(&"example.configured.1"),
+                request.to.id() != id1
+            );
+            assert_eq!(
+                update
+                    .withdrawn_routes
+                    .contains(&"example.configured.1".to_string()),
+                request.to.id() == id1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn resyncs_account_when_route_policy_changes() {
+        let (service, outgoing_requests) = test_service_with_routes();
+        let peer = TestAccount::new(Uuid::from_slice(&[2; 16]).unwrap(), "example.configured.1");
+
+        // This is normally spawned as a task when the service is created
+        service.update_best_routes(None).await.unwrap();
+
+        service
+            .handle_route_update_request(IncomingRequest {
+                from: peer.clone(),
+                prepare: RouteUpdateRequest {
+                    routing_table_id: [0; 16],
+                    current_epoch_index: 1,
+                    from_epoch_index: 0,
+                    to_epoch_index: 1,
+                    hold_down_time: 30000,
+                    speaker: Address::from_str("example.remote").unwrap(),
+                    new_routes: vec![Route {
+                        prefix: "example.remote".to_string(),
+                        path: vec!["example.configured.1".to_string()],
+                        auth: [0; 32],
+                        props: Vec::new(),
+                    }],
+                    withdrawn_routes: Vec::new(),
+                }
+                .to_prepare(),
+            })
+            .await
+            .unwrap();
+        assert!(service
+            .local_table
+            .read()
+            .get_route("example.remote")
+            .is_some());
+        outgoing_requests.lock().clear();
+
+        service
+            .store
+            .set_route_policy(
+                peer.id(),
+                RoutePolicy {
+                    import_deny: vec!["example.remote".to_string()],
+                    ..RoutePolicy::default()
+                },
+            )
+            .await
+            .unwrap();
+        service.resync_account(peer.id()).await;
+
+        // The routes received under the old policy are dropped until the peer sends them again
+        assert!(service
+            .local_table
+            .read()
+            .get_route("example.remote")
+            .is_none());
+        assert!(!service.incoming_tables.read().contains_key(&peer.id()));
+        assert!(!service
+            .unauthenticated_routes
+            .read()
+            .contains_key(&peer.id()));
+        let requests = outgoing_requests.lock();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.to.id() == peer.id()));
+        let control = RouteControlRequest::try_from(&requests[0].prepare).unwrap();
+        assert_eq!(control.mode, Mode::Sync);
+        assert_eq!(control.last_known_epoch, 0);
+        let update = RouteUpdateRequest::try_from(&requests[1].prepare).unwrap();
+        assert_eq!(update.from_epoch_index, 0);
+        assert_eq!(
+            update.to_epoch_index,
+            service.forwarding_table.read().epoch()
+        );
+    }
+
     #[tokio::test]
     async fn broadcasts_received_routes() {
         let (service, outgoing_requests) = test_service_with_routes();
@@ -2075,3 +3978,115 @@ mod send_route_updates {
         assert_eq!(outgoing_requests.lock().len(), 2);
     }
 }
+
+#[cfg(test)]
+mod save_route_details {
+    use super::*;
+    use crate::packet::RouteProp;
+    use crate::test_helpers::*;
+    use bytes::Bytes;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn saves_all_routes_for_each_prefix() {
+        let (service, _outgoing_requests) = test_service_with_routes();
+        service.update_best_routes(None).await.unwrap();
+        service
+            .handle_route_update_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: RouteUpdateRequest {
+                    routing_table_id: [1; 16],
+                    current_epoch_index: 1,
+                    from_epoch_index: 0,
+                    to_epoch_index: 1,
+                    hold_down_time: 30000,
+                    speaker: Address::from_str("example.peer").unwrap(),
+                    new_routes: vec![
+                        Route {
+                            prefix: "example.configured.1".to_string(),
+                            path: vec!["example.peer".to_string()],
+                            auth: [0; 32],
+                            props: Vec::new(),
+                        },
+                        Route {
+                            prefix: "example.remote".to_string(),
+                            path: vec!["example.peer".to_string()],
+                            auth: [1; 32],
+                            props: vec![RouteProp {
+                                is_optional: true,
+                                is_transitive: true,
+                                is_partial: false,
+                                id: 0x0102,
+                                is_utf8: true,
+                                value: Bytes::from("20"),
+                            }],
+                        },
+                    ],
+                    withdrawn_routes: Vec::new(),
+                }
+                .to_prepare(),
+            })
+            .await
+            .unwrap();
+
+        service.broadcast_routes().await.unwrap();
+        let details = service.store.route_details.lock().clone();
+
+        {
+            let forwarding_table = service.forwarding_table.read();
+            assert_eq!(
+                details.routing_table_id,
+                hex::encode(&forwarding_table.id()[..])
+            );
+            assert_eq!(details.epoch, forwarding_table.epoch());
+        }
+        assert_eq!(
+            details.prefixes.keys().collect::<Vec<_>>(),
+            vec![
+                "example.configured.1",
+                "example.connector.other-local",
+                "example.local.1",
+                "example.remote"
+            ]
+        );
+
+        let configured = &details.prefixes["example.configured.1"];
+        assert_eq!(configured.len(), 2);
+        assert_eq!(configured[0].source, RouteSource::Configured);
+        assert_eq!(
+            configured[0].account_id,
+            Uuid::from_slice(&[2; 16]).unwrap()
+        );
+        assert!(configured[0].authenticated);
+        assert_eq!(configured[1].source, RouteSource::Learned);
+        assert_eq!(configured[1].account_id, ROUTING_ACCOUNT.id());
+        assert_eq!(configured[1].path, vec!["example.peer".to_string()]);
+        assert!(!configured[1].has_auth);
+        // No anchor is configured for the prefix
+        assert!(!configured[1].authenticated);
+
+        assert_eq!(
+            details.prefixes["example.local.1"][0].source,
+            RouteSource::Local
+        );
+
+        let remote = &details.prefixes["example.remote"];
+        assert_eq!(remote.len(), 1);
+        assert_eq!(remote[0].source, RouteSource::Learned);
+        assert!(remote[0].has_auth);
+        assert_eq!(remote[0].props.len(), 1);
+        assert_eq!(remote[0].props[0].id, 0x0102);
+        assert_eq!(remote[0].props[0].value, "20");
+
+        assert_eq!(
+            details.peers,
+            vec![PeerTableDetails {
+                account_id: ROUTING_ACCOUNT.id(),
+                username: ROUTING_ACCOUNT.username().clone(),
+                routing_table_id: hex::encode([1; 16]),
+                epoch: 1,
+                routes: 2,
+            }]
+        );
+    }
+}
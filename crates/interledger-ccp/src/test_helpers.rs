@@ -13,6 +13,7 @@ use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::{iter::FromIterator, sync::Arc};
+use tokio::sync::broadcast;
 
 pub static ROUTING_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
@@ -83,6 +84,14 @@ pub struct TestStore {
     pub local: HashMap<String, TestAccount>,
     pub configured: HashMap<String, TestAccount>,
     pub routes: Arc<Mutex<HashMap<String, TestAccount>>>,
+    pub alternate_routes: Arc<Mutex<HashMap<String, Vec<TestAccount>>>>,
+    /// Makes setting the alternate routes fail, as if the store was unavailable
+    pub fail_alternate_routes: Arc<Mutex<bool>>,
+    pub forwarding_table: Arc<Mutex<Option<ForwardingTableState>>>,
+    pub policies: Arc<Mutex<HashMap<Uuid, RoutePolicy>>>,
+    pub policy_publisher: broadcast::Sender<Uuid>,
+    pub route_details: Arc<Mutex<RoutingTableDetails>>,
+    pub ilp_address: Arc<Mutex<Address>>,
 }
 
 impl TestStore {
@@ -91,6 +100,13 @@ impl TestStore {
             local: HashMap::new(),
             configured: HashMap::new(),
             routes: Arc::new(Mutex::new(HashMap::new())),
+            alternate_routes: Arc::new(Mutex::new(HashMap::new())),
+            fail_alternate_routes: Arc::new(Mutex::new(false)),
+            forwarding_table: Arc::new(Mutex::new(None)),
+            policies: Arc::new(Mutex::new(HashMap::new())),
+            policy_publisher: broadcast::channel(16).0,
+            route_details: Arc::new(Mutex::new(RoutingTableDetails::default())),
+            ilp_address: Arc::new(Mutex::new(EXAMPLE_CONNECTOR.clone())),
         }
     }
 
@@ -102,6 +118,13 @@ impl TestStore {
             local,
             configured,
             routes: Arc::new(Mutex::new(HashMap::new())),
+            alternate_routes: Arc::new(Mutex::new(HashMap::new())),
+            fail_alternate_routes: Arc::new(Mutex::new(false)),
+            forwarding_table: Arc::new(Mutex::new(None)),
+            policies: Arc::new(Mutex::new(HashMap::new())),
+            policy_publisher: broadcast::channel(16).0,
+            route_details: Arc::new(Mutex::new(RoutingTableDetails::default())),
+            ilp_address: Arc::new(Mutex::new(EXAMPLE_CONNECTOR.clone())),
         }
     }
 }
@@ -111,8 +134,9 @@ type RoutingTable<A> = HashMap<String, A>;
 #[async_trait]
 impl AddressStore for TestStore {
     /// Saves the ILP Address in the store's memory and database
-    async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
-        unimplemented!()
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        *self.ilp_address.lock() = ilp_address;
+        Ok(())
     }
 
     async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
@@ -121,7 +145,7 @@ impl AddressStore for TestStore {
 
     /// Get's the store's ilp address from memory
     fn get_ilp_address(&self) -> Address {
-        Address::from_str("example.connector").unwrap()
+        self.ilp_address.lock().clone()
     }
 }
 
@@ -175,6 +199,94 @@ impl CcpRoutingStore for TestStore {
         *self.routes.lock() = HashMap::from_iter(routes.into_iter());
         Ok(())
     }
+
+    async fn set_alternate_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Vec<TestAccount>)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        if *self.fail_alternate_routes.lock() {
+            return Err(CcpRoutingStoreError::Other(Box::new(
+                std::io::Error::other("store unavailable"),
+            )));
+        }
+        *self.alternate_routes.lock() = HashMap::from_iter(routes);
+        Ok(())
+    }
+
+    async fn get_forwarding_table(
+        &self,
+    ) -> Result<Option<ForwardingTableState>, CcpRoutingStoreError> {
+        Ok(self.forwarding_table.lock().clone())
+    }
+
+    async fn save_forwarding_table_update(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        update: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let mut forwarding_table = self.forwarding_table.lock();
+        let state = forwarding_table.get_or_insert_with(ForwardingTableState::default);
+        if state.table_id != table_id {
+            state.table_id = table_id;
+            state.updates.clear();
+        }
+        state.updates.split_off(&epoch);
+        state.updates.insert(epoch, update);
+        Ok(())
+    }
+
+    async fn compact_forwarding_table(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        snapshot: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let mut forwarding_table = self.forwarding_table.lock();
+        if let Some(state) = forwarding_table.as_mut() {
+            if state.table_id == table_id {
+                state.updates = state.updates.split_off(&epoch);
+                state.updates.insert(epoch, snapshot);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RoutePolicyStore for TestStore {
+    async fn get_route_policies(&self) -> Result<HashMap<Uuid, RoutePolicy>, CcpRoutingStoreError> {
+        Ok(self.policies.lock().clone())
+    }
+
+    async fn set_route_policy(
+        &self,
+        account_id: Uuid,
+        policy: RoutePolicy,
+    ) -> Result<(), CcpRoutingStoreError> {
+        self.policies.lock().insert(account_id, policy);
+        let _ = self.policy_publisher.send(account_id);
+        Ok(())
+    }
+
+    fn subscribe_to_route_policies(&self) -> broadcast::Receiver<Uuid> {
+        self.policy_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl RouteDetailsStore for TestStore {
+    async fn get_route_details(&self) -> Result<RoutingTableDetails, CcpRoutingStoreError> {
+        Ok(self.route_details.lock().clone())
+    }
+
+    async fn set_route_details(
+        &self,
+        details: RoutingTableDetails,
+    ) -> Result<(), CcpRoutingStoreError> {
+        *self.route_details.lock() = details;
+        Ok(())
+    }
 }
 
 pub fn test_service() -> CcpRouteManager<
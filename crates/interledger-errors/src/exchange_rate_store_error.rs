@@ -24,3 +24,13 @@ impl From<ExchangeRateStoreError> for warp::Rejection {
         ApiError::from(src).into()
     }
 }
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for ExchangeRateStoreError {
+    fn from(src: RedisError) -> ExchangeRateStoreError {
+        ExchangeRateStoreError::Other(Box::new(src))
+    }
+}
@@ -0,0 +1,34 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the FeeStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum FeeStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+}
+
+impl From<FeeStoreError> for ApiError {
+    fn from(src: FeeStoreError) -> Self {
+        ApiError::internal_server_error().detail(src.to_string())
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<FeeStoreError> for warp::Rejection {
+    fn from(src: FeeStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for FeeStoreError {
+    fn from(src: RedisError) -> FeeStoreError {
+        FeeStoreError::Other(Box::new(src))
+    }
+}
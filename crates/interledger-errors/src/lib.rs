@@ -26,6 +26,15 @@ pub use node_store_error::NodeStoreError;
 mod exchange_rate_store_error;
 pub use exchange_rate_store_error::ExchangeRateStoreError;
 
+mod fee_store_error;
+pub use fee_store_error::FeeStoreError;
+
+mod revenue_store_error;
+pub use revenue_store_error::RevenueStoreError;
+
+mod remainder_store_error;
+pub use remainder_store_error::RemainderStoreError;
+
 mod settlement_errors;
 pub use settlement_errors::{IdempotentStoreError, LeftoversStoreError, SettlementStoreError};
 
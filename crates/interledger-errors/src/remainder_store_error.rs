@@ -0,0 +1,34 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the RemainderStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum RemainderStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+}
+
+impl From<RemainderStoreError> for ApiError {
+    fn from(src: RemainderStoreError) -> Self {
+        ApiError::internal_server_error().detail(src.to_string())
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<RemainderStoreError> for warp::Rejection {
+    fn from(src: RemainderStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for RemainderStoreError {
+    fn from(src: RedisError) -> RemainderStoreError {
+        RemainderStoreError::Other(Box::new(src))
+    }
+}
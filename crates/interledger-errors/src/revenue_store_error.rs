@@ -0,0 +1,34 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the RevenueStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum RevenueStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+}
+
+impl From<RevenueStoreError> for ApiError {
+    fn from(src: RevenueStoreError) -> Self {
+        ApiError::internal_server_error().detail(src.to_string())
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<RevenueStoreError> for warp::Rejection {
+    fn from(src: RevenueStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for RevenueStoreError {
+    fn from(src: RedisError) -> RevenueStoreError {
+        RevenueStoreError::Other(Box::new(src))
+    }
+}
@@ -0,0 +1,330 @@
+use crate::Rate;
+use chrono::{DateTime, Utc};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use tracing::{trace, warn};
+
+fn default_min_sources() -> usize {
+    1
+}
+
+/// How the prices reported by several sources for the same asset are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum AggregationMethod {
+    /// Use the median of the prices, ignoring the sources' weights
+    #[default]
+    #[serde(alias = "median")]
+    Median,
+    /// Use the average of the prices, weighted by each source's weight
+    #[serde(alias = "weighted_average")]
+    WeightedAverage,
+}
+
+/// Configuration of how the prices of several sources are combined
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregationConfig {
+    /// Method used to combine the prices of an asset
+    #[serde(default)]
+    pub method: AggregationMethod,
+    /// Maximum deviation from the median, as a fraction of it, of the prices
+    /// which are taken into account. Prices further away are dropped as outliers
+    #[serde(default)]
+    pub max_deviation: Option<Rate>,
+    /// Minimum number of sources which must report a (non-outlier) price for an
+    /// asset. Assets with fewer prices are left out of the aggregated rates
+    #[serde(default = "default_min_sources")]
+    pub min_sources: usize,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        AggregationConfig {
+            method: AggregationMethod::default(),
+            max_deviation: None,
+            min_sources: default_min_sources(),
+        }
+    }
+}
+
+/// Deserializes the weight of a source, rejecting a weight of 0 which would
+/// leave the source out of weighted averages (or make them undefined)
+pub(crate) fn deserialize_weight<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match u32::deserialize(deserializer)? {
+        0 => Err(de::Error::custom("the weight of a source must be positive")),
+        weight => Ok(weight),
+    }
+}
+
+/// Prices reported by a single source, relative to a common base asset
+#[derive(Debug, Clone)]
+pub struct SourcePrices {
+    /// Name of the source
+    pub source: String,
+    /// Weight of the source's prices in a weighted average
+    pub weight: u32,
+    /// Price of each asset
+    pub prices: HashMap<String, Rate>,
+    /// When the prices were fetched
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The rate of an asset combined from the prices of several sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregatedRate {
+    /// The combined rate
+    pub rate: Rate,
+    /// Names of the sources whose prices were used to compute the rate
+    pub sources: Vec<String>,
+    /// When the oldest of the prices used to compute the rate was fetched
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Expresses the prices relative to another asset. Returns None if that asset's price is unknown
+pub fn rebase(prices: &HashMap<String, Rate>, base: &str) -> Option<HashMap<String, Rate>> {
+    let base_price = prices.get(base).filter(|price| price.is_positive())?;
+    Some(
+        prices
+            .iter()
+            .filter_map(|(code, price)| Some((code.clone(), price.checked_div(base_price)?)))
+            .collect(),
+    )
+}
+
+fn median(sorted: &[&Rate]) -> Rate {
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[middle].clone()
+    } else {
+        (sorted[middle - 1].clone() + sorted[middle].clone()) / Rate::from(2)
+    }
+}
+
+fn weighted_average(prices: &[(&str, u32, &Rate)]) -> Option<Rate> {
+    let total_weight: u64 = prices.iter().map(|(_, weight, _)| u64::from(*weight)).sum();
+    let sum = prices.iter().fold(Rate::zero(), |sum, (_, weight, price)| {
+        sum + &Rate::from(u64::from(*weight)) * price
+    });
+    sum.checked_div(&Rate::from(total_weight))
+}
+
+/// Combines the prices of every source into a single rate per asset
+pub fn aggregate(
+    sources: &[SourcePrices],
+    config: &AggregationConfig,
+) -> HashMap<String, AggregatedRate> {
+    let mut prices_by_asset: HashMap<&str, Vec<(&str, u32, &Rate)>> = HashMap::new();
+    let updated_at: HashMap<&str, DateTime<Utc>> = sources
+        .iter()
+        .map(|source| (source.source.as_str(), source.updated_at))
+        .collect();
+    for source in sources {
+        for (code, price) in source.prices.iter() {
+            if price.is_positive() {
+                prices_by_asset.entry(code.as_str()).or_default().push((
+                    source.source.as_str(),
+                    source.weight,
+                    price,
+                ));
+            }
+        }
+    }
+
+    prices_by_asset
+        .into_iter()
+        .filter_map(|(code, mut prices)| {
+            prices.sort_by(|a, b| a.2.cmp(b.2));
+            let sorted: Vec<&Rate> = prices.iter().map(|(_, _, price)| *price).collect();
+            let median_price = median(&sorted);
+
+            // The median of fewer than 3 prices lies between them, so rejecting
+            // outliers would drop either both prices or none
+            let max_deviation = config.max_deviation.as_ref().filter(|_| prices.len() >= 3);
+            if let Some(max_deviation) = max_deviation {
+                prices.retain(|(source, _, price)| {
+                    let deviation = if *price > &median_price {
+                        *price - &median_price
+                    } else {
+                        &median_price - *price
+                    };
+                    let within = deviation <= max_deviation * &median_price;
+                    if !within {
+                        warn!(
+                            "Dropping {} price {} from {} which deviates more than {} from the median {}",
+                            code, price, source, max_deviation, median_price
+                        );
+                    }
+                    within
+                });
+            }
+
+            if prices.is_empty() || prices.len() < config.min_sources {
+                warn!(
+                    "Not enough sources reported a price for {} ({} of the required {})",
+                    code,
+                    prices.len(),
+                    config.min_sources
+                );
+                return None;
+            }
+
+            let rate = match config.method {
+                AggregationMethod::Median => {
+                    let sorted: Vec<&Rate> = prices.iter().map(|(_, _, price)| *price).collect();
+                    median(&sorted)
+                }
+                AggregationMethod::WeightedAverage => weighted_average(&prices)?,
+            };
+            let sources: Vec<String> = prices
+                .iter()
+                .map(|(source, _, _)| source.to_string())
+                .collect();
+            let oldest = prices
+                .iter()
+                .filter_map(|(source, _, _)| updated_at.get(source))
+                .min()
+                .cloned()
+                .unwrap_or_else(Utc::now);
+            trace!("Aggregated {} rate {} from {:?}", code, rate, sources);
+            Some((
+                code.to_string(),
+                AggregatedRate {
+                    rate,
+                    sources,
+                    updated_at: oldest,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn rate(s: &str) -> Rate {
+        Rate::from_str(s).unwrap()
+    }
+
+    fn source(name: &str, weight: u32, prices: &[(&str, &str)]) -> SourcePrices {
+        SourcePrices {
+            source: name.to_string(),
+            weight,
+            prices: prices
+                .iter()
+                .map(|(code, price)| (code.to_string(), rate(price)))
+                .collect(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn sources() -> Vec<SourcePrices> {
+        vec![
+            source("a", 1, &[("BTC", "100"), ("ETH", "10")]),
+            source("b", 3, &[("BTC", "104"), ("ETH", "0")]),
+            source("c", 1, &[("BTC", "200"), ("XRP", "0.2")]),
+        ]
+    }
+
+    #[test]
+    fn computes_median() {
+        let rates = aggregate(&sources(), &AggregationConfig::default());
+        assert_eq!(rates["BTC"].rate, rate("104"));
+        assert_eq!(rates["BTC"].sources.len(), 3);
+        // Non-positive prices are ignored
+        assert_eq!(rates["ETH"].rate, rate("10"));
+        assert_eq!(rates["ETH"].sources, vec!["a".to_string()]);
+
+        let even = vec![
+            source("a", 1, &[("BTC", "100")]),
+            source("b", 1, &[("BTC", "101")]),
+        ];
+        let rates = aggregate(&even, &AggregationConfig::default());
+        assert_eq!(rates["BTC"].rate, rate("100.5"));
+    }
+
+    #[test]
+    fn computes_weighted_average() {
+        let config = AggregationConfig {
+            method: AggregationMethod::WeightedAverage,
+            ..AggregationConfig::default()
+        };
+        let rates = aggregate(&sources(), &config);
+        // (100 * 1 + 104 * 3 + 200 * 1) / 5
+        assert_eq!(rates["BTC"].rate, rate("122.4"));
+    }
+
+    #[test]
+    fn drops_outliers() {
+        let config = AggregationConfig {
+            method: AggregationMethod::WeightedAverage,
+            max_deviation: Some(rate("0.05")),
+            min_sources: 2,
+        };
+        let rates = aggregate(&sources(), &config);
+        // (100 * 1 + 104 * 3) / 4
+        assert_eq!(rates["BTC"].rate, rate("103"));
+        assert_eq!(rates["BTC"].sources, vec!["a".to_string(), "b".to_string()]);
+        // Only reported by a single source
+        assert!(!rates.contains_key("ETH"));
+        assert!(!rates.contains_key("XRP"));
+    }
+
+    #[test]
+    fn keeps_two_sources_without_outlier_rejection() {
+        let config = AggregationConfig {
+            method: AggregationMethod::Median,
+            max_deviation: Some(rate("0.05")),
+            min_sources: 2,
+        };
+        let two = vec![
+            source("a", 1, &[("BTC", "100")]),
+            source("b", 1, &[("BTC", "120")]),
+        ];
+        let rates = aggregate(&two, &config);
+        assert_eq!(rates["BTC"].rate, rate("110"));
+        assert_eq!(rates["BTC"].sources.len(), 2);
+    }
+
+    #[derive(Deserialize)]
+    struct Weighted {
+        #[serde(deserialize_with = "deserialize_weight")]
+        weight: u32,
+    }
+
+    #[test]
+    fn rejects_zero_weight() {
+        let weighted: Weighted = serde_json::from_str(r#"{"weight": 2}"#).unwrap();
+        assert_eq!(weighted.weight, 2);
+        assert!(serde_json::from_str::<Weighted>(r#"{"weight": 0}"#).is_err());
+    }
+
+    #[test]
+    fn keeps_oldest_timestamp() {
+        let mut sources = sources();
+        let old = Utc::now() - chrono::Duration::seconds(30);
+        sources[0].updated_at = old;
+        let rates = aggregate(&sources, &AggregationConfig::default());
+        assert_eq!(rates["BTC"].updated_at, old);
+        assert!(rates["XRP"].updated_at > old);
+    }
+
+    #[test]
+    fn rebases_prices() {
+        let prices: HashMap<String, Rate> = vec![
+            ("EUR".to_string(), Rate::one()),
+            ("USD".to_string(), rate("0.8")),
+            ("BTC".to_string(), rate("8000")),
+        ]
+        .into_iter()
+        .collect();
+        let rebased = rebase(&prices, "USD").unwrap();
+        assert_eq!(rebased["USD"], Rate::one());
+        assert_eq!(rebased["EUR"], rate("1.25"));
+        assert_eq!(rebased["BTC"], rate("10000"));
+        assert!(rebase(&prices, "JPY").is_none());
+    }
+}
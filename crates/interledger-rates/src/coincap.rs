@@ -1,3 +1,4 @@
+use crate::Rate;
 use futures::TryFutureExt;
 use once_cell::sync::Lazy;
 use reqwest::{Client, Url};
@@ -14,7 +15,7 @@ static COINCAP_RATES_URL: Lazy<Url> =
     Lazy::new(|| Url::parse("https://api.coincap.io/v2/rates").unwrap());
 
 #[derive(Deserialize, Debug)]
-struct Rate {
+struct CoinCapRate {
     symbol: String,
     #[serde(alias = "rateUsd", alias = "priceUsd")]
     rate_usd: String,
@@ -22,25 +23,25 @@ struct Rate {
 
 #[derive(Deserialize, Debug)]
 struct RateResponse {
-    data: Vec<Rate>,
+    data: Vec<CoinCapRate>,
 }
 
-pub async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
+pub async fn query_coincap(client: &Client) -> Result<HashMap<String, Rate>, ()> {
     let (assets, rates) = futures::future::join(
         query_coincap_endpoint(client, COINCAP_ASSETS_URL.clone()),
         query_coincap_endpoint(client, COINCAP_RATES_URL.clone()),
     )
     .await;
 
-    let all_rates: HashMap<String, f64> = assets?
+    let all_rates: HashMap<String, Rate> = assets?
         .data
         .into_iter()
         .chain(rates?.data.into_iter())
-        .filter_map(|record| match f64::from_str(record.rate_usd.as_str()) {
+        .filter_map(|record| match Rate::from_str(record.rate_usd.as_str()) {
             Ok(rate) => Some((record.symbol.to_uppercase(), rate)),
             Err(err) => {
                 warn!(
-                    "Unable to parse {} rate as an f64: {} {:?}",
+                    "Unable to parse {} rate: {} {:?}",
                     record.symbol, record.rate_usd, err
                 );
                 None
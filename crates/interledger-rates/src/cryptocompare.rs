@@ -1,3 +1,4 @@
+use crate::Rate;
 use futures::TryFutureExt;
 use once_cell::sync::Lazy;
 use reqwest::{Client, Url};
@@ -16,7 +17,7 @@ static CRYPTOCOMPARE_URL: Lazy<Url> = Lazy::new(|| {
 #[derive(Deserialize, Debug)]
 struct Price {
     #[serde(rename = "PRICE")]
-    price: f64,
+    price: Rate,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,7 +49,7 @@ struct Response {
 pub async fn query_cryptocompare(
     client: &Client,
     api_key: &SecretString,
-) -> Result<HashMap<String, f64>, ()> {
+) -> Result<HashMap<String, Rate>, ()> {
     // ref: https://github.com/rust-lang/rust/pull/64856
     let header = format!("Apikey {}", api_key.expose_secret());
     let res = client
@@ -91,6 +92,6 @@ pub async fn query_cryptocompare(
                 None
             }
         })
-        .chain(once(("USD".to_string(), 1.0)));
+        .chain(once(("USD".to_string(), Rate::one())));
     Ok(HashMap::from_iter(rates))
 }
@@ -0,0 +1,385 @@
+use crate::Rate;
+use futures::TryFutureExt;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, str::FromStr};
+use thiserror::Error;
+use tracing::{error, warn};
+use url::Url;
+
+fn default_auth_header_name() -> String {
+    "Authorization".to_string()
+}
+
+fn default_base_currency() -> String {
+    "USD".to_string()
+}
+
+/// Configuration of an arbitrary HTTP endpoint returning prices as JSON.
+///
+/// For example, a feed returning
+/// `{"data": [{"symbol": "BTC", "price": "9000.12"}, {"symbol": "ETH", "price": 200}]}`
+/// would be configured with `records: "$.data[*]"`, `asset_code: "$.symbol"` and `price: "$.price"`,
+/// while a feed returning `{"rates": {"EUR": 0.92, "JPY": 108.5}}` (quoting how many units
+/// of each asset one unit of the base currency buys) would be configured with
+/// `records: "$.rates.*"`, `price: "$"` and `inverse: true`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomProvider {
+    /// URL the prices are fetched from with a GET request
+    pub url: Url,
+    /// Value of the header used to authenticate to the feed, if it requires it
+    #[serde(default)]
+    pub auth_header: Option<SecretString>,
+    /// Name of the header `auth_header` is sent in
+    #[serde(default = "default_auth_header_name")]
+    pub auth_header_name: String,
+    /// Selects each record of the response
+    pub records: JsonSelector,
+    /// Selects the asset code within each record. If it is not set, the records
+    /// must be the values of an object and their keys are used as the asset codes
+    #[serde(default)]
+    pub asset_code: Option<JsonSelector>,
+    /// Selects the price within each record. Prices may be JSON numbers or strings
+    pub price: JsonSelector,
+    /// Asset the prices are denominated in
+    #[serde(default = "default_base_currency")]
+    pub base_currency: String,
+    /// Set if the prices are the number of units of each asset one unit of the
+    /// base currency is worth, rather than the value of one unit of each asset
+    #[serde(default)]
+    pub inverse: bool,
+}
+
+impl CustomProvider {
+    /// Name identifying the provider, made of the host and path of its URL
+    pub fn name(&self) -> String {
+        match self.url.host_str() {
+            Some(host) => format!("{}{}", host, self.url.path().trim_end_matches('/')),
+            None => self.url.as_str().to_string(),
+        }
+    }
+}
+
+/// Error returned when a [`JsonSelector`](./struct.JsonSelector.html) cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid JSON selector: {0}")]
+pub struct ParseSelectorError(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// A small subset of JSONPath used to find values in a JSON document.
+///
+/// Selectors start with an optional `$` (the root) followed by any number of
+/// `.field`, `["field"]`, `[index]` or wildcard (`.*` or `[*]`) segments.
+/// Wildcards select every element of an array or every value of an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonSelector(Vec<Segment>);
+
+impl JsonSelector {
+    /// Returns the values matched by the selector, along with the key
+    /// under which each was found if it was selected by a wildcard over an object
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<(Option<&'a str>, &'a Value)> {
+        let mut selected = vec![(None, value)];
+        for segment in &self.0 {
+            selected = selected
+                .into_iter()
+                .flat_map(|(key, value)| -> Vec<(Option<&'a str>, &'a Value)> {
+                    match (segment, value) {
+                        (Segment::Field(name), Value::Object(map)) => map
+                            .get(name)
+                            .map(|value| (key, value))
+                            .into_iter()
+                            .collect(),
+                        (Segment::Index(index), Value::Array(values)) => values
+                            .get(*index)
+                            .map(|value| (key, value))
+                            .into_iter()
+                            .collect(),
+                        (Segment::Wildcard, Value::Array(values)) => {
+                            values.iter().map(|value| (key, value)).collect()
+                        }
+                        (Segment::Wildcard, Value::Object(map)) => map
+                            .iter()
+                            .map(|(key, value)| (Some(key.as_str()), value))
+                            .collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        selected
+    }
+}
+
+impl FromStr for JsonSelector {
+    type Err = ParseSelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseSelectorError(s.to_string());
+        let path = s.trim();
+        let path = match path.strip_prefix('$') {
+            Some(path) => path.to_string(),
+            None if path.is_empty() || path.starts_with('[') || path.starts_with('.') => {
+                path.to_string()
+            }
+            // Allow omitting the root, e.g. `data[*].price`
+            None => format!(".{}", path),
+        };
+
+        let mut segments = Vec::new();
+        let mut i = 0;
+        while i < path.len() {
+            let rest = &path[i..];
+            if let Some(field) = rest.strip_prefix('.') {
+                let end = field.find(['.', '[']).unwrap_or(field.len());
+                segments.push(match &field[..end] {
+                    "" => return Err(error()),
+                    "*" => Segment::Wildcard,
+                    name => Segment::Field(name.to_string()),
+                });
+                i += end + 1;
+            } else if rest.starts_with('[') {
+                let end = rest.find(']').ok_or_else(error)?;
+                let inner = rest[1..end].trim();
+                let quoted = inner.len() >= 2
+                    && ((inner.starts_with('"') && inner.ends_with('"'))
+                        || (inner.starts_with('\'') && inner.ends_with('\'')));
+                segments.push(if inner == "*" {
+                    Segment::Wildcard
+                } else if quoted {
+                    Segment::Field(inner[1..inner.len() - 1].to_string())
+                } else {
+                    Segment::Index(usize::from_str(inner).map_err(|_| error())?)
+                });
+                i += end + 1;
+            } else {
+                return Err(error());
+            }
+        }
+        Ok(JsonSelector(segments))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonSelector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let selector = String::deserialize(deserializer)?;
+        JsonSelector::from_str(&selector).map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for JsonSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "$")?;
+        for segment in &self.0 {
+            match segment {
+                Segment::Field(name) => write!(f, "[\"{}\"]", name)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+                Segment::Wildcard => write!(f, "[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the prices from the feed's response, keyed by upper-cased asset code
+fn parse_prices(config: &CustomProvider, body: &Value) -> HashMap<String, Rate> {
+    config
+        .records
+        .select(body)
+        .into_iter()
+        .filter_map(|(key, record)| {
+            let code = match config.asset_code {
+                Some(ref selector) => selector
+                    .select(record)
+                    .first()
+                    .and_then(|(_, code)| code.as_str()),
+                None => key,
+            };
+            let code = match code {
+                Some(code) => code.to_uppercase(),
+                None => {
+                    warn!("Skipping price record without an asset code: {}", record);
+                    return None;
+                }
+            };
+
+            let price = config
+                .price
+                .select(record)
+                .first()
+                .and_then(|(_, price)| Rate::deserialize(*price).ok());
+            let price = match price {
+                Some(ref price) if price.is_positive() => price.clone(),
+                _ => {
+                    warn!("Unable to parse a positive {} price: {}", code, record);
+                    return None;
+                }
+            };
+
+            let rate = if config.inverse {
+                Rate::one().checked_div(&price)?
+            } else {
+                price
+            };
+            Some((code, rate))
+        })
+        .collect()
+}
+
+pub async fn query_custom(
+    client: &Client,
+    config: &CustomProvider,
+) -> Result<HashMap<String, Rate>, ()> {
+    let mut request = client.get(config.url.clone());
+    if let Some(ref auth_header) = config.auth_header {
+        request = request.header(
+            config.auth_header_name.as_str(),
+            auth_header.expose_secret().as_str(),
+        );
+    }
+
+    let res = request
+        .send()
+        .map_err(|err| {
+            error!(
+                "Error fetching exchange rates from {}: {:?}",
+                config.url, err
+            );
+        })
+        .await?;
+
+    let res = res.error_for_status().map_err(|err| {
+        error!(
+            "HTTP error getting exchange rates from {}: {:?}",
+            config.url, err
+        );
+    })?;
+
+    let body: Value = res
+        .json()
+        .map_err(|err| {
+            error!(
+                "Error getting exchange rate response body from {}, invalid JSON: {:?}",
+                config.url, err
+            );
+        })
+        .await?;
+
+    Ok(parse_prices(config, &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn selector(s: &str) -> JsonSelector {
+        JsonSelector::from_str(s).unwrap()
+    }
+
+    fn provider(records: &str, asset_code: Option<&str>, price: &str) -> CustomProvider {
+        CustomProvider {
+            url: Url::parse(&mockito::server_url())
+                .unwrap()
+                .join("/prices")
+                .unwrap(),
+            auth_header: None,
+            auth_header_name: default_auth_header_name(),
+            records: selector(records),
+            asset_code: asset_code.map(selector),
+            price: selector(price),
+            base_currency: default_base_currency(),
+            inverse: false,
+        }
+    }
+
+    #[test]
+    fn names_providers_by_host_and_path() {
+        let mut provider = provider("$", None, "$");
+        provider.url = Url::parse("https://rates.example.com/v1/prices/").unwrap();
+        assert_eq!(provider.name(), "rates.example.com/v1/prices");
+        provider.url = Url::parse("https://rates.example.com/v2/prices").unwrap();
+        assert_eq!(provider.name(), "rates.example.com/v2/prices");
+    }
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(selector("$").to_string(), "$");
+        assert_eq!(
+            selector("$.data[*].price").to_string(),
+            "$[\"data\"][*][\"price\"]"
+        );
+        assert_eq!(selector("data[2]"), selector("$['data'][2]"));
+        assert_eq!(selector("$.rates.*"), selector("$[\"rates\"][*]"));
+        for invalid in &["$..a", "$[", "$[abc]", "$a", "$.a[1"] {
+            assert!(JsonSelector::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn selects_values() {
+        let body = json!({"data": [{"a": 1}, {"a": 2}, {"b": 3}], "map": {"x": 4}});
+        let values: Vec<_> = selector("$.data[*].a")
+            .select(&body)
+            .into_iter()
+            .map(|(_, value)| value.clone())
+            .collect();
+        assert_eq!(values, vec![json!(1), json!(2)]);
+        assert_eq!(selector("$.data[1].a").select(&body)[0].1, &json!(2));
+        assert_eq!(
+            selector("$.map.*").select(&body),
+            vec![(Some("x"), &json!(4))]
+        );
+        assert!(selector("$.missing[*]").select(&body).is_empty());
+    }
+
+    #[test]
+    fn parses_prices() {
+        let body = json!({"data": [
+            {"symbol": "btc", "price": "9000.12"},
+            {"symbol": "ETH", "price": 200},
+            {"symbol": "BAD", "price": "abc"},
+            {"symbol": "NEG", "price": -1},
+            {"price": 1},
+        ]});
+        let prices = parse_prices(&provider("$.data[*]", Some("$.symbol"), "$.price"), &body);
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices["BTC"], Rate::from_str("9000.12").unwrap());
+        assert_eq!(prices["ETH"], Rate::from(200));
+
+        let body = json!({"rates": {"EUR": 0.5, "JPY": "100"}});
+        let mut config = provider("$.rates.*", None, "$");
+        config.inverse = true;
+        let prices = parse_prices(&config, &body);
+        assert_eq!(prices["EUR"], Rate::from(2));
+        assert_eq!(prices["JPY"], Rate::from_str("0.01").unwrap());
+    }
+
+    #[tokio::test]
+    async fn queries_feed() {
+        let m = mockito::mock("GET", "/prices")
+            .match_header("X-Api-Key", "secret")
+            .with_status(200)
+            .with_body(r#"[{"code": "abc", "usd": "1.5"}, {"code": "XYZ", "usd": 3}]"#)
+            .create();
+        let mut config = provider("$[*]", Some("$.code"), "$.usd");
+        config.auth_header = Some(SecretString::new("secret".to_string()));
+        config.auth_header_name = "X-Api-Key".to_string();
+
+        let prices = query_custom(&Client::new(), &config).await.unwrap();
+
+        m.assert();
+        assert_eq!(prices["ABC"], Rate::from_str("1.5").unwrap());
+        assert_eq!(prices["XYZ"], Rate::from(3));
+    }
+}
@@ -1,25 +1,82 @@
-use futures::TryFutureExt;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
 use interledger_errors::ExchangeRateStoreError;
 use reqwest::Client;
 use secrecy::SecretString;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast;
 use tracing::{debug, error, trace, warn};
 
+mod aggregation;
+pub use aggregation::{
+    aggregate, rebase, AggregatedRate, AggregationConfig, AggregationMethod, SourcePrices,
+};
+
 mod cryptocompare;
 
 mod coincap;
 
+mod custom;
+pub use custom::{CustomProvider, JsonSelector, ParseSelectorError};
+
+mod rate;
+pub use rate::{ParseRateError, Rate, Rounding};
+
+/// Source of the exchange rates set via the HTTP API
+pub const API_RATE_SOURCE: &str = "api";
+
+/// An exchange rate along with where and when it was obtained
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    /// The asset's rate relative to the common base asset
+    pub rate: Rate,
+    /// Name of the source(s) the rate was obtained from
+    pub source: String,
+    /// When the rate was obtained from its source
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ExchangeRate {
+    /// Creates a rate which was just obtained from the source
+    pub fn new(rate: Rate, source: impl Into<String>) -> Self {
+        ExchangeRate {
+            rate,
+            source: source.into(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Time elapsed since the rate was obtained
+    pub fn age(&self) -> Duration {
+        Utc::now()
+            .signed_duration_since(self.updated_at)
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+/// Store of the exchange rates of every asset, expressed as exact
+/// [`Rate`](./struct.Rate.html)s relative to a common base asset
+#[async_trait]
 pub trait ExchangeRateStore: Clone {
-    // TODO we may want to make this async if/when we use pubsub to broadcast
-    // rate changes to different instances of a horizontally-scalable node
-    fn set_exchange_rates(&self, rates: HashMap<String, f64>)
-        -> Result<(), ExchangeRateStoreError>;
+    /// Replaces all the rates. The new rates must be persisted (and shared with
+    /// the other instances of a horizontally-scaled node) and returned by the
+    /// getters once this resolves
+    async fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, ExchangeRate>,
+    ) -> Result<(), ExchangeRateStoreError>;
 
-    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError>;
+    fn get_exchange_rates(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError>;
 
     // TODO should this be on the API instead? That's where it's actually used
     // TODO should we combine this method with get_exchange_rates?
@@ -27,7 +84,30 @@ pub trait ExchangeRateStore: Clone {
     // (so that we don't accidentally lock up the RwLock on the store's exchange_rates)
     // but in the normal case of getting the rate between two assets, we don't want to
     // copy all the rate data
-    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
+    fn get_all_exchange_rates(
+        &self,
+    ) -> Result<HashMap<String, ExchangeRate>, ExchangeRateStoreError>;
+}
+
+/// Store which notifies its subscribers whenever the exchange rates are set,
+/// whether by this instance or another one sharing the store
+pub trait ExchangeRateSubscriptionStore {
+    /// Subscribes to the rates set from now on. Every update contains all the rates
+    fn subscribe_to_exchange_rates(
+        &self,
+    ) -> broadcast::Receiver<Arc<HashMap<String, ExchangeRate>>>;
+}
+
+/// Store which keeps a bounded history of the rates of every asset
+#[async_trait]
+pub trait RateHistoryStore {
+    /// Loads the rates of an asset obtained since the given time, oldest first.
+    /// Only a limited number of rates is kept per asset
+    async fn get_rate_history(
+        &self,
+        asset_code: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError>;
 }
 
 /// This determines which external API service to poll for exchange rates.
@@ -51,14 +131,116 @@ pub enum ExchangeRateProvider {
     /// [CryptoCompare]: https://cryptocompare.com
     #[serde(alias = "cryptocompare")]
     CryptoCompare(SecretString),
+    /// Use any HTTP endpoint which returns prices as JSON, such as an
+    /// internal pricing service or a fiat FX feed.
+    ///
+    /// Note that when configured with YAML, this MUST be specified as
+    /// "Custom", not "custom".
+    #[serde(alias = "custom")]
+    Custom(Box<CustomProvider>),
+}
+
+impl ExchangeRateProvider {
+    /// The asset the provider's prices are denominated in
+    pub fn base_currency(&self) -> &str {
+        match self {
+            ExchangeRateProvider::Custom(ref config) => &config.base_currency,
+            _ => "USD",
+        }
+    }
+
+    /// Name identifying the provider in logs
+    pub fn name(&self) -> String {
+        match self {
+            ExchangeRateProvider::CoinCap => "CoinCap".to_string(),
+            ExchangeRateProvider::CryptoCompare(_) => "CryptoCompare".to_string(),
+            ExchangeRateProvider::Custom(ref config) => config.name(),
+        }
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_poll_failure_tolerance() -> u32 {
+    5
+}
+
+/// An exchange rate provider polled along with others, whose
+/// prices are combined according to an [`AggregationConfig`](./struct.AggregationConfig.html)
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateSource {
+    /// API to poll for exchange rates
+    pub provider: ExchangeRateProvider,
+    /// Name identifying the source in logs, which must be unique among the sources
+    /// (defaults to the provider's name)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Weight of the source's prices when they are combined with a weighted average.
+    /// It must be positive
+    #[serde(
+        default = "default_weight",
+        deserialize_with = "aggregation::deserialize_weight"
+    )]
+    pub weight: u32,
+    /// The number of consecutive failed polls of this source that will be tolerated
+    /// before its last known prices stop being used
+    #[serde(default = "default_poll_failure_tolerance")]
+    pub poll_failure_tolerance: u32,
+}
+
+impl RateSource {
+    /// Creates a source with the default weight
+    pub fn new(provider: ExchangeRateProvider, poll_failure_tolerance: u32) -> Self {
+        RateSource {
+            provider,
+            name: None,
+            weight: default_weight(),
+            poll_failure_tolerance,
+        }
+    }
+
+    /// Name identifying the source
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.provider.name())
+    }
+}
+
+/// Error returned when several exchange rate sources have the same name
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("several exchange rate sources are named {0}, set a different name for each of them")]
+pub struct DuplicateSourceName(pub String);
+
+fn check_source_names(sources: &[RateSource]) -> Result<(), DuplicateSourceName> {
+    let mut names = HashSet::new();
+    for source in sources {
+        let name = source.name();
+        if !names.insert(name.clone()) {
+            return Err(DuplicateSourceName(name));
+        }
+    }
+    Ok(())
+}
+
+/// Prices of a source along with the time they were fetched
+type TimestampedPrices = (HashMap<String, Rate>, DateTime<Utc>);
+
+/// Polling state of a source
+struct SourceState {
+    source: RateSource,
+    name: String,
+    consecutive_failed_polls: AtomicU32,
+    /// Prices from the last successful poll, in the source's base currency,
+    /// along with the time of that poll
+    last_prices: Mutex<Option<TimestampedPrices>>,
 }
 
 /// Poll exchange rate providers for the current exchange rates
 #[derive(Clone)]
 pub struct ExchangeRateFetcher<S> {
-    provider: ExchangeRateProvider,
-    consecutive_failed_polls: Arc<AtomicU32>,
-    failed_polls_before_invalidation: u32,
+    sources: Arc<Vec<SourceState>>,
+    aggregation: AggregationConfig,
     store: S,
     client: Client,
 }
@@ -67,16 +249,45 @@ impl<S> ExchangeRateFetcher<S>
 where
     S: ExchangeRateStore + Send + Sync + 'static,
 {
-    /// Simple constructor
+    /// Simple constructor polling a single provider
     pub fn new(
         provider: ExchangeRateProvider,
         failed_polls_before_invalidation: u32,
         store: S,
     ) -> Self {
+        Self::from_sources(
+            vec![RateSource::new(provider, failed_polls_before_invalidation)],
+            AggregationConfig::default(),
+            store,
+        )
+    }
+
+    /// Constructor polling several sources concurrently and combining their prices.
+    /// The prices are expressed relative to the base currency of the first source.
+    /// Since the prices of the sources are told apart by their names, every source
+    /// must have a different name.
+    pub fn with_sources(
+        sources: Vec<RateSource>,
+        aggregation: AggregationConfig,
+        store: S,
+    ) -> Result<Self, DuplicateSourceName> {
+        check_source_names(&sources)?;
+        Ok(Self::from_sources(sources, aggregation, store))
+    }
+
+    fn from_sources(sources: Vec<RateSource>, aggregation: AggregationConfig, store: S) -> Self {
+        let sources = sources
+            .into_iter()
+            .map(|source| SourceState {
+                name: source.name(),
+                source,
+                consecutive_failed_polls: AtomicU32::new(0),
+                last_prices: Mutex::new(None),
+            })
+            .collect();
         ExchangeRateFetcher {
-            provider,
-            consecutive_failed_polls: Arc::new(AtomicU32::new(0)),
-            failed_polls_before_invalidation,
+            sources: Arc::new(sources),
+            aggregation,
             store,
             client: Client::new(),
         }
@@ -85,8 +296,11 @@ where
     /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`
     pub fn spawn_interval(self, interval: Duration) {
         debug!(
-            "Starting interval to poll exchange rate provider: {:?} for rates",
-            self.provider
+            "Starting interval to poll exchange rate sources: {:?} for rates",
+            self.sources
+                .iter()
+                .map(|state| state.name.as_str())
+                .collect::<Vec<_>>()
         );
         let interval = async move {
             let mut interval = tokio::time::interval(interval);
@@ -100,48 +314,126 @@ where
     }
 
     /// Calls the proper exchange rate provider
-    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
-        match self.provider {
+    async fn fetch_rates(
+        &self,
+        provider: &ExchangeRateProvider,
+    ) -> Result<HashMap<String, Rate>, ()> {
+        let mut rates = match provider {
             ExchangeRateProvider::CryptoCompare(ref api_key) => {
                 cryptocompare::query_cryptocompare(&self.client, api_key).await
             }
             ExchangeRateProvider::CoinCap => coincap::query_coincap(&self.client).await,
+            ExchangeRateProvider::Custom(ref config) => {
+                custom::query_custom(&self.client, config).await
+            }
+        }?;
+        rates.insert(provider.base_currency().to_uppercase(), Rate::one());
+        Ok(rates)
+    }
+
+    /// Records the outcome of polling a source. Returns the source's prices to use, if any
+    fn record_poll(
+        &self,
+        state: &SourceState,
+        result: Result<HashMap<String, Rate>, ()>,
+    ) -> Option<(HashMap<String, Rate>, DateTime<Utc>)> {
+        let mut last_prices = state.last_prices.lock().unwrap();
+        match result {
+            Ok(prices) => {
+                trace!("Fetched exchange rates from {}: {:?}", state.name, prices);
+                // Reset our invalidation counter
+                state.consecutive_failed_polls.store(0, Ordering::Relaxed);
+                *last_prices = Some((prices, Utc::now()));
+            }
+            Err(()) => {
+                let failed_polls = state
+                    .consecutive_failed_polls
+                    .fetch_add(1, Ordering::Relaxed);
+                if failed_polls < state.source.poll_failure_tolerance {
+                    warn!(
+                        "Failed to update exchange rates from {} (previous consecutive failed attempts: {})",
+                        state.name, failed_polls
+                    );
+                } else if last_prices.take().is_some() {
+                    error!(
+                        "Failed to update exchange rates from {} (previous consecutive failed attempts: {}), no longer using its old rates for safety",
+                        state.name, failed_polls
+                    );
+                }
+            }
         }
+        last_prices.clone()
     }
 
-    /// Gets the exchange rates and proceeds to update the store with the newly polled values
+    /// Polls every source, combines their prices and updates the store with the result
     async fn update_rates(&self) -> Result<(), ()> {
-        let consecutive_failed_polls = self.consecutive_failed_polls.clone();
-        let consecutive_failed_polls_zeroer = consecutive_failed_polls.clone();
-        let failed_polls_before_invalidation = self.failed_polls_before_invalidation;
-        let store = self.store.clone();
-        let store_clone = self.store.clone();
-        let provider = self.provider.clone();
-        #[allow(clippy::cognitive_complexity)]
-        let mut rates = self.fetch_rates()
-            .map_err(move |_| {
-                // Note that a race between the read on this line and the check on the line after
-                // is quite unlikely as long as the interval between polls is reasonable.
-                let failed_polls = consecutive_failed_polls.fetch_add(1, Ordering::Relaxed);
-                if failed_polls < failed_polls_before_invalidation {
-                    warn!("Failed to update exchange rates (previous consecutive failed attempts: {})", failed_polls);
-                } else {
-                    error!("Failed to update exchange rates (previous consecutive failed attempts: {}), removing old rates for safety", failed_polls);
-                    // Clear out all of the old rates
-                    if store.set_exchange_rates(HashMap::new()).is_err() {
-                        error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
-                        panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
+        let results = join_all(
+            self.sources
+                .iter()
+                .map(|state| self.fetch_rates(&state.source.provider)),
+        )
+        .await;
+
+        let base = match self.sources.first() {
+            Some(state) => state.source.provider.base_currency().to_uppercase(),
+            None => return Ok(()),
+        };
+        let prices: Vec<SourcePrices> = self
+            .sources
+            .iter()
+            .zip(results)
+            .filter_map(|(state, result)| {
+                let (prices, updated_at) = self.record_poll(state, result)?;
+                let prices = match rebase(&prices, &base) {
+                    Some(prices) => prices,
+                    None => {
+                        warn!(
+                            "Ignoring exchange rates from {} because they do not include {}",
+                            state.name, base
+                        );
+                        return None;
                     }
-                }
-            }).await?;
+                };
+                Some(SourcePrices {
+                    source: state.name.clone(),
+                    weight: state.source.weight,
+                    prices,
+                    updated_at,
+                })
+            })
+            .collect();
 
-        trace!("Fetched exchange rates: {:?}", rates);
+        if prices.is_empty() {
+            error!("No exchange rate source has valid rates, removing old rates for safety");
+            // Clear out all of the old rates
+            if self.store.set_exchange_rates(HashMap::new()).await.is_err() {
+                error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
+                panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
+            }
+            return Err(());
+        }
+
+        // Record which sources contributed to each rate, and when
+        // the oldest of the prices it was computed from was fetched
+        let rates: HashMap<String, ExchangeRate> = aggregate(&prices, &self.aggregation)
+            .into_iter()
+            .map(|(code, aggregated)| {
+                let rate = ExchangeRate {
+                    rate: aggregated.rate,
+                    source: aggregated.sources.join(","),
+                    updated_at: aggregated.updated_at,
+                };
+                (code, rate)
+            })
+            .collect();
         let num_rates = rates.len();
-        rates.insert("USD".to_string(), 1.0);
-        if store_clone.set_exchange_rates(rates).is_ok() {
-            // Reset our invalidation counter
-            consecutive_failed_polls_zeroer.store(0, Ordering::Relaxed);
-            debug!("Updated {} exchange rates from {:?}", num_rates, provider);
+
+        if self.store.set_exchange_rates(rates).await.is_ok() {
+            debug!(
+                "Updated {} exchange rates from {} sources",
+                num_rates,
+                prices.len()
+            );
             Ok(())
         } else {
             error!("Error setting exchange rates in store");
@@ -149,3 +441,20 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_duplicate_source_names() {
+        let coincap = RateSource::new(ExchangeRateProvider::CoinCap, 5);
+        let mut other = coincap.clone();
+        assert_eq!(
+            check_source_names(&[coincap.clone(), other.clone()]),
+            Err(DuplicateSourceName("CoinCap".to_string()))
+        );
+        other.name = Some("CoinCap mirror".to_string());
+        assert!(check_source_names(&[coincap, other]).is_ok());
+    }
+}
@@ -0,0 +1,465 @@
+use num::{
+    bigint::{BigInt, Sign},
+    pow,
+    rational::BigRational,
+    traits::{One, Signed, ToPrimitive, Zero},
+    Integer,
+};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Sub},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Largest decimal exponent accepted when parsing a rate, to keep
+/// malicious inputs such as `1e999999999` from allocating huge integers
+const MAX_EXPONENT: u32 = 1000;
+
+/// Error returned when a string cannot be parsed into a [`Rate`](./struct.Rate.html)
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid exchange rate: {0}")]
+pub struct ParseRateError(String);
+
+/// Direction in which an amount converted with a rate is rounded to an integer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards zero, e.g. when forwarding a packet so that the connector
+    /// never sends out more than what the rate allows
+    Down,
+    /// Round away from zero, e.g. when computing a minimum acceptable amount
+    Up,
+}
+
+/// An exact exchange rate (or any other ratio (which may be negative), such as a spread).
+///
+/// Rates are stored as arbitrary precision rationals so that converting amounts does not
+/// suffer from floating point errors. They are (de)serialized as decimal strings such as
+/// `"0.0123"` or, if they cannot be represented as a finite decimal, as fractions such as
+/// `"1/3"`. JSON numbers are accepted as well and are interpreted as the shortest decimal
+/// which represents the same float, so `0.1` is exactly one tenth.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate(BigRational);
+
+impl Rate {
+    /// The rate between an asset and itself
+    pub fn one() -> Self {
+        Rate(BigRational::one())
+    }
+
+    /// A zero rate
+    pub fn zero() -> Self {
+        Rate(BigRational::zero())
+    }
+
+    /// Creates a rate from a float, using the shortest decimal representation of
+    /// the float rather than its exact binary value (so `0.1` becomes `1/10`).
+    /// Returns None if the float is not finite.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // Display for floats prints the shortest representation which round-trips
+        // and never uses scientific notation, so this always parses
+        Rate::from_str(&value.to_string()).ok()
+    }
+
+    /// Returns the closest float to the rate. This is lossy and should only be used
+    /// for display purposes or metrics, never to convert amounts.
+    pub fn to_f64(&self) -> f64 {
+        match (self.0.numer().to_f64(), self.0.denom().to_f64()) {
+            (Some(numer), Some(denom)) => numer / denom,
+            _ => f64::NAN,
+        }
+    }
+
+    /// Returns the underlying rational
+    pub fn as_ratio(&self) -> &BigRational {
+        &self.0
+    }
+
+    /// Consumes the rate and returns the underlying rational
+    pub fn into_ratio(self) -> BigRational {
+        self.0
+    }
+
+    /// Returns true if the rate is zero
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// Returns true if the rate is strictly greater than zero
+    pub fn is_positive(&self) -> bool {
+        self.0.is_positive()
+    }
+
+    /// Divides by the other rate, returning None if it is zero
+    pub fn checked_div(&self, other: &Rate) -> Option<Rate> {
+        if other.is_zero() {
+            None
+        } else {
+            Some(Rate(&self.0 / &other.0))
+        }
+    }
+
+    /// Adjusts the rate so that it converts amounts denominated in `from_scale`
+    /// into amounts denominated in `to_scale`
+    pub fn scaled(&self, from_scale: u8, to_scale: u8) -> Rate {
+        let ten = BigRational::from_integer(BigInt::from(10u8));
+        if to_scale >= from_scale {
+            Rate(&self.0 * pow(ten, usize::from(to_scale - from_scale)))
+        } else {
+            Rate(&self.0 / pow(ten, usize::from(from_scale - to_scale)))
+        }
+    }
+
+    /// Multiplies the amount by the rate and rounds the result in the given direction.
+    /// Returns None if the result is negative or does not fit in a u64.
+    pub fn convert(&self, amount: u64, rounding: Rounding) -> Option<u64> {
+        let amount = BigRational::from_integer(BigInt::from(amount)) * &self.0;
+        let amount = match rounding {
+            Rounding::Down => amount.floor(),
+            Rounding::Up => amount.ceil(),
+        };
+        amount.to_integer().to_u64()
+    }
+
+    /// Multiplies the amount by the rate and rounds the result down, also returning the
+    /// fraction which was truncated. Returns None if the result is negative or does not fit in a u64.
+    pub fn convert_with_remainder(&self, amount: u64) -> Option<(u64, Rate)> {
+        let amount = BigRational::from_integer(BigInt::from(amount)) * &self.0;
+        let converted = amount.floor();
+        let remainder = Rate(&amount - &converted);
+        Some((converted.to_integer().to_u64()?, remainder))
+    }
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Rate::zero()
+    }
+}
+
+impl From<BigRational> for Rate {
+    fn from(ratio: BigRational) -> Self {
+        Rate(ratio)
+    }
+}
+
+impl From<u64> for Rate {
+    fn from(value: u64) -> Self {
+        Rate(BigRational::from_integer(BigInt::from(value)))
+    }
+}
+
+impl Add for Rate {
+    type Output = Rate;
+
+    fn add(self, other: Rate) -> Rate {
+        Rate(self.0 + other.0)
+    }
+}
+
+impl<'a> Add<&'a Rate> for &'a Rate {
+    type Output = Rate;
+
+    fn add(self, other: &Rate) -> Rate {
+        Rate(&self.0 + &other.0)
+    }
+}
+
+impl Mul for Rate {
+    type Output = Rate;
+
+    fn mul(self, other: Rate) -> Rate {
+        Rate(self.0 * other.0)
+    }
+}
+
+impl<'a> Mul<&'a Rate> for &'a Rate {
+    type Output = Rate;
+
+    fn mul(self, other: &Rate) -> Rate {
+        Rate(&self.0 * &other.0)
+    }
+}
+
+impl Sub for Rate {
+    type Output = Rate;
+
+    fn sub(self, other: Rate) -> Rate {
+        Rate(self.0 - other.0)
+    }
+}
+
+impl<'a> Sub<&'a Rate> for &'a Rate {
+    type Output = Rate;
+
+    fn sub(self, other: &Rate) -> Rate {
+        Rate(&self.0 - &other.0)
+    }
+}
+
+impl Div for Rate {
+    type Output = Rate;
+
+    /// Panics if the divisor is zero, use
+    /// [`checked_div`](#method.checked_div) when it may be
+    fn div(self, other: Rate) -> Rate {
+        Rate(self.0 / other.0)
+    }
+}
+
+fn parse_integer(s: &str, original: &str) -> Result<BigInt, ParseRateError> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseRateError(original.to_string()));
+    }
+    BigInt::from_str(s).map_err(|_| ParseRateError(original.to_string()))
+}
+
+fn parse_decimal(s: &str) -> Result<BigRational, ParseRateError> {
+    let error = || ParseRateError(s.to_string());
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(index) => {
+            let exponent = i64::from_str(&unsigned[index + 1..]).map_err(|_| error())?;
+            if exponent.abs() > i64::from(MAX_EXPONENT) {
+                return Err(error());
+            }
+            (&unsigned[..index], exponent)
+        }
+        None => (unsigned, 0),
+    };
+
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(error());
+    }
+    let digits = format!("{}{}", integer, fraction);
+    let numer = parse_integer(&digits, s)?;
+    let numer = if negative { -numer } else { numer };
+
+    // The value is numer * 10^(exponent - fraction digits)
+    let exponent = exponent - fraction.len() as i64;
+    let ten = BigInt::from(10u8);
+    let ratio = if exponent >= 0 {
+        BigRational::from_integer(numer * pow(ten, exponent as usize))
+    } else {
+        BigRational::new(numer, pow(ten, (-exponent) as usize))
+    };
+    Ok(ratio)
+}
+
+impl FromStr for Rate {
+    type Err = ParseRateError;
+
+    /// Parses decimals (optionally with an exponent, e.g. `1.5e-3`)
+    /// or fractions of integers (e.g. `1/3`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        match trimmed.find('/') {
+            Some(index) => {
+                let numer = parse_decimal(&trimmed[..index])?;
+                let denom = parse_decimal(&trimmed[index + 1..])?;
+                if denom.is_zero() {
+                    return Err(ParseRateError(s.to_string()));
+                }
+                Ok(Rate(numer / denom))
+            }
+            None => parse_decimal(trimmed).map(Rate),
+        }
+    }
+}
+
+/// Returns the number of times the factor divides the value
+fn multiplicity(value: &BigInt, factor: u8) -> (usize, BigInt) {
+    let factor = BigInt::from(factor);
+    let mut value = value.clone();
+    let mut count = 0;
+    loop {
+        let (quotient, remainder) = value.div_rem(&factor);
+        if !remainder.is_zero() {
+            return (count, value);
+        }
+        value = quotient;
+        count += 1;
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let numer = self.0.numer();
+        let denom = self.0.denom();
+        if denom.is_one() {
+            return write!(f, "{}", numer);
+        }
+
+        // The rate can be written as a finite decimal only if the
+        // denominator has no prime factors other than 2 and 5
+        let (twos, rest) = multiplicity(denom, 2);
+        let (fives, rest) = multiplicity(&rest, 5);
+        if !rest.is_one() {
+            return write!(f, "{}/{}", numer, denom);
+        }
+
+        let decimals = twos.max(fives);
+        let digits = numer * pow(BigInt::from(10u8), decimals) / denom;
+        let sign = if digits.sign() == Sign::Minus {
+            "-"
+        } else {
+            ""
+        };
+        let digits = digits.abs().to_string();
+        let digits = format!("{:0>width$}", digits, width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+impl fmt::Debug for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RateVisitor;
+
+        impl<'de> Visitor<'de> for RateVisitor {
+            type Value = Rate;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number or a string containing a decimal or a fraction")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Rate, E> {
+                Rate::from_str(value).map_err(E::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Rate, E> {
+                Rate::from_f64(value)
+                    .ok_or_else(|| E::custom(format!("invalid exchange rate: {}", value)))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Rate, E> {
+                Ok(Rate::from(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Rate, E> {
+                Ok(Rate(BigRational::from_integer(BigInt::from(value))))
+            }
+        }
+
+        deserializer.deserialize_any(RateVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(s: &str) -> Rate {
+        Rate::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parses_decimals_and_fractions() {
+        assert_eq!(rate("1"), Rate::one());
+        assert_eq!(rate("0.1").to_string(), "0.1");
+        assert_eq!(rate("-0.01").to_string(), "-0.01");
+        assert_eq!(rate("1.5e-3").to_string(), "0.0015");
+        assert_eq!(rate("2E2").to_string(), "200");
+        assert_eq!(rate(".5"), rate("1/2"));
+        assert_eq!(rate("2/6").to_string(), "1/3");
+        assert_eq!(rate("1/8").to_string(), "0.125");
+        assert_eq!(rate("0.5/0.25"), Rate::from(2));
+
+        for invalid in &[
+            "", ".", "abc", "1/0", "1.2.3", "1e", "--1", "1e99999", "0x10",
+        ] {
+            assert!(Rate::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn converts_floats_exactly() {
+        assert_eq!(Rate::from_f64(0.1).unwrap(), rate("1/10"));
+        assert_eq!(Rate::from_f64(0.000_000_5).unwrap(), rate("5e-7"));
+        assert_eq!(Rate::from_f64(1e21).unwrap(), rate("1e21"));
+        assert!(Rate::from_f64(f64::NAN).is_none());
+        assert!(Rate::from_f64(f64::INFINITY).is_none());
+        assert_eq!(rate("0.25").to_f64(), 0.25);
+    }
+
+    #[test]
+    fn converts_amounts() {
+        let third = rate("1/3");
+        assert_eq!(third.convert(100, Rounding::Down), Some(33));
+        assert_eq!(third.convert(100, Rounding::Up), Some(34));
+        assert_eq!(Rate::from(2).convert(u64::MAX, Rounding::Down), None);
+        assert_eq!(rate("-1").convert(1, Rounding::Down), None);
+
+        assert_eq!(
+            Rate::one().scaled(2, 6).convert(33, Rounding::Down),
+            Some(330_000)
+        );
+        assert_eq!(
+            Rate::one()
+                .scaled(9, 4)
+                .convert(123_456_789, Rounding::Down),
+            Some(1234)
+        );
+        assert_eq!(Rate::one().scaled(255, 255), Rate::one());
+
+        assert_eq!(third.convert_with_remainder(100), Some((33, rate("1/3"))));
+        assert_eq!(
+            Rate::one().scaled(9, 4).convert_with_remainder(123_456_789),
+            Some((1234, rate("0.56789")))
+        );
+        assert_eq!(rate("-1").convert_with_remainder(1), None);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let value: Rate = serde_json::from_str("0.1").unwrap();
+        assert_eq!(value, rate("0.1"));
+        let value: Rate = serde_json::from_str("\"1/3\"").unwrap();
+        assert_eq!(value, rate("1/3"));
+        let value: Rate = serde_json::from_str("3").unwrap();
+        assert_eq!(value, Rate::from(3));
+        let value: Rate = serde_json::from_str("-3").unwrap();
+        assert_eq!(value, rate("-3"));
+        assert!(serde_json::from_str::<Rate>("\"abc\"").is_err());
+
+        assert_eq!(
+            serde_json::to_string(&rate("0.0123")).unwrap(),
+            "\"0.0123\""
+        );
+        assert_eq!(serde_json::to_string(&rate("2/3")).unwrap(), "\"2/3\"");
+    }
+}
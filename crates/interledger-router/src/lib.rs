@@ -18,7 +18,7 @@ use uuid::Uuid;
 
 mod router;
 
-pub use self::router::Router;
+pub use self::router::{find_next_hop, Router};
 
 /// A trait for Store implmentations that have ILP routing tables.
 pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
@@ -28,4 +28,14 @@ pub trait RouterStore: AccountStore + Clone + Send + Sync + 'static {
     /// This ensures that individual packets can be routed without hitting the underlying store.
     /// An Arc is returned to avoid copying the underlying data while processing each packet.
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>>;
+
+    /// **Synchronously** return the alternate next hops for each prefix of the routing table,
+    /// ranked from best to worst and not including the next hop in the routing table.
+    /// These are used to retry packets rejected with temporary errors.
+    fn alternate_routes(&self) -> Arc<HashMap<String, Vec<Uuid>>>;
+
+    /// **Synchronously** return the static routes which spread the packets for a prefix
+    /// across several next hops, as the ID and weight of each member of the route.
+    /// For these prefixes, the routing table lists the member with the highest weight.
+    fn multipath_routes(&self) -> Arc<HashMap<String, Vec<(Uuid, u32)>>>;
 }
@@ -1,9 +1,22 @@
 use super::RouterStore;
 use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_packet::{Address, ErrorClass, ErrorCode, Reject, RejectBuilder};
 use interledger_service::*;
-use std::str;
-use tracing::{error, trace};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{debug, error, trace, warn};
+use uuid::Uuid;
+
+/// How long a member of a multipath route is left out after its link failed
+const MEMBER_DOWN_DURATION: Duration = Duration::from_secs(30);
 
 /// # Interledger Router
 ///
@@ -21,11 +34,28 @@ use tracing::{error, trace};
 ///   - reduce the Prepare packet's expiry
 ///
 /// That is done by OutgoingServices.
+///
+/// If it is configured to retry packets, the router forwards packets rejected
+/// with temporary (T-class) errors to the alternate next hops the store has for the
+/// route's prefix, in order, for as long as the packet has not expired.
+/// The temporary errors this node triggers before the packet leaves it are not retried,
+/// but link failures (`T01 Peer Unreachable`) are, even though this node triggers them.
+///
+/// The packets for the prefix of a multipath route are spread across its members
+/// in proportion to their weights. Members are left out for a while when their link
+/// fails, i.e. a packet sent to them is rejected with `T01 Peer Unreachable` or
+/// with another temporary error the member triggered itself.
 
 #[derive(Clone)]
 pub struct Router<S, O> {
     store: S,
     next: O,
+    max_retries: u32,
+    prefix_max_retries: HashMap<String, u32>,
+    /// Number of packets sent using multipath routes, used to pick their members
+    multipath_packets: Arc<AtomicUsize>,
+    /// The members of multipath routes whose link is down, and until when
+    down_members: Arc<Mutex<HashMap<Uuid, Instant>>>,
 }
 
 impl<S, O> Router<S, O>
@@ -34,8 +64,133 @@ where
     O: OutgoingService<S::Account>,
 {
     pub fn new(store: S, next: O) -> Self {
-        Router { store, next }
+        Router {
+            store,
+            next,
+            max_retries: 0,
+            prefix_max_retries: HashMap::new(),
+            multipath_packets: Arc::new(AtomicUsize::new(0)),
+            down_members: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set the number of alternate next hops a rejected packet is retried on.
+    /// Defaults to 0, i.e. packets are only sent to the best next hop
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
     }
+
+    /// Set the number of alternate next hops the rejected packets for the given
+    /// destination prefix are retried on, instead of the default one
+    pub fn with_prefix_max_retries(mut self, prefix: String, max_retries: u32) -> Self {
+        self.prefix_max_retries.insert(prefix, max_retries);
+        self
+    }
+
+    /// Returns the retry limit of the longest prefix matching the destination
+    fn max_retries_for(&self, destination: &str) -> u32 {
+        self.prefix_max_retries
+            .iter()
+            .filter(|(prefix, _)| destination.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, max_retries)| *max_retries)
+            .unwrap_or(self.max_retries)
+    }
+
+    /// Orders the members of a multipath route: the first one is picked in proportion
+    /// to the members' weights and is followed by the others, as alternates.
+    /// The members whose link is down are left out, unless all of them are down
+    fn order_members(&self, members: &[(Uuid, u32)]) -> Vec<Uuid> {
+        let now = Instant::now();
+        let live_members: Vec<(Uuid, u32)> = {
+            let mut down_members = self.down_members.lock();
+            down_members.retain(|_, until| *until > now);
+            members
+                .iter()
+                .filter(|(id, _)| !down_members.contains_key(id))
+                .cloned()
+                .collect()
+        };
+        let mut members = if live_members.is_empty() {
+            members.to_vec()
+        } else {
+            live_members
+        };
+
+        let total_weight: usize = members.iter().map(|(_, weight)| *weight as usize).sum();
+        if total_weight > 0 {
+            let mut point = self.multipath_packets.fetch_add(1, Ordering::Relaxed) % total_weight;
+            let picked = members
+                .iter()
+                .position(|(_, weight)| {
+                    if point < *weight as usize {
+                        true
+                    } else {
+                        point -= *weight as usize;
+                        false
+                    }
+                })
+                .unwrap_or(0);
+            let picked = members.remove(picked);
+            members.insert(0, picked);
+        }
+        members.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Leaves out the member of a multipath route for a while if the result
+    /// of sending it a packet shows that its link is down. The other temporary errors
+    /// this node triggers, e.g. when the member's balance is too low, are not link failures
+    fn check_member_link(&self, account_id: Uuid, member_address: &Address, result: &IlpResult) {
+        if let Err(reject) = result {
+            let link_down = reject.code() == ErrorCode::T01_PEER_UNREACHABLE
+                || (reject.code().class() == ErrorClass::Temporary
+                    && reject.triggered_by().as_ref() == Some(member_address));
+            if link_down {
+                warn!(
+                    "Link to account {} is down, leaving it out of multipath routes for {:?}",
+                    account_id, MEMBER_DOWN_DURATION
+                );
+                self.down_members
+                    .lock()
+                    .insert(account_id, Instant::now() + MEMBER_DOWN_DURATION);
+            }
+        }
+    }
+}
+
+/// Returns true if a packet rejected with the given error may be sent to another next hop.
+/// Link failures are reported by this node with its own address, unlike the other temporary
+/// errors it triggers before the packet leaves it, which would be the same on any next hop
+fn is_retryable(reject: &Reject, ilp_address: &Address) -> bool {
+    reject.code() == ErrorCode::T01_PEER_UNREACHABLE
+        || (reject.code().class() == ErrorClass::Temporary
+            && reject.triggered_by().as_ref() != Some(ilp_address))
+}
+
+/// Finds the route for the given destination address: the exact route for the
+/// address if there is one, or else the one with the longest matching prefix
+/// (the empty prefix being a catch-all route).
+/// Returns the matching prefix along with the ID of the next hop account.
+pub fn find_next_hop<'a>(
+    routing_table: &'a HashMap<String, Uuid>,
+    destination: &str,
+) -> Option<(&'a str, Uuid)> {
+    if let Some((prefix, account_id)) = routing_table.get_key_value(destination) {
+        return Some((prefix.as_str(), *account_id));
+    }
+    let mut next_hop = None;
+    let mut matching_prefix = "";
+    for (prefix, account) in routing_table.iter() {
+        // Check if the route prefix matches or is empty (meaning it's a catch-all address)
+        if (prefix.is_empty() || destination.starts_with(prefix.as_str()))
+            && prefix.len() >= matching_prefix.len()
+        {
+            next_hop.replace(*account);
+            matching_prefix = prefix.as_str();
+        }
+    }
+    next_hop.map(|account_id| (matching_prefix, account_id))
 }
 
 #[async_trait]
@@ -51,62 +206,114 @@ where
     /// the prepare packet's destination or if it's a catch-all address (i.e. empty prefix)
     async fn handle_request(&mut self, request: IncomingRequest<S::Account>) -> IlpResult {
         let destination = request.prepare.destination();
-        let mut next_hop = None;
         let routing_table = self.store.routing_table();
         let ilp_address = self.store.get_ilp_address();
 
         // Check if we have a direct path for that account or if we need to scan
         // through the routing table
-        let dest: &str = &destination;
-        if let Some(account_id) = routing_table.get(dest) {
-            trace!(
-                "Found direct route for address: \"{}\". Account: {}",
-                destination,
-                account_id
-            );
-            next_hop = Some(*account_id);
-        } else if !routing_table.is_empty() {
-            let mut matching_prefix = "";
-            let routing_table = self.store.routing_table();
-            for (ref prefix, account) in (*routing_table).iter() {
-                // Check if the route prefix matches or is empty (meaning it's a catch-all address)
-                if (prefix.is_empty() || dest.starts_with(prefix.as_str()))
-                    && prefix.len() >= matching_prefix.len()
-                {
-                    next_hop.replace(*account);
-                    matching_prefix = prefix.as_str();
-                }
-            }
-            if let Some(account_id) = next_hop {
+        let next_hop = if routing_table.is_empty() {
+            error!("Unable to route request because routing table is empty");
+            None
+        } else {
+            find_next_hop(&routing_table, &destination).map(|(prefix, account_id)| {
                 trace!(
                     "Found matching route for address: \"{}\". Prefix: \"{}\", account: {}",
                     destination,
-                    matching_prefix,
+                    prefix,
                     account_id,
                 );
+                (prefix, account_id)
+            })
+        };
+
+        if let Some((prefix, account_id)) = next_hop {
+            // Multipath routes pick a member for each packet, the others are alternates
+            let multipath_routes = self.store.multipath_routes();
+            let members = multipath_routes
+                .get(prefix)
+                .filter(|members| !members.is_empty())
+                .map(|members| self.order_members(members))
+                .unwrap_or_default();
+            let (account_id, mut alternates) = if members.is_empty() {
+                (account_id, Vec::new())
+            } else {
+                (members[0], members[1..].to_vec())
+            };
+
+            // The alternate next hops the packet may be retried on
+            let max_retries = self.max_retries_for(&destination) as usize;
+            if max_retries > 0 {
+                if let Some(route_alternates) = self.store.alternate_routes().get(prefix) {
+                    alternates.extend(route_alternates.iter().cloned());
+                }
+                let mut seen = vec![account_id, request.from.id()];
+                alternates.retain(|id| {
+                    if seen.contains(id) {
+                        false
+                    } else {
+                        seen.push(*id);
+                        true
+                    }
+                });
+                alternates.truncate(max_retries);
+            } else {
+                alternates.clear();
             }
-        } else {
-            error!("Unable to route request because routing table is empty");
-        }
 
-        if let Some(account_id) = next_hop {
             let mut next = self.next.clone();
-            match self.store.get_accounts(vec![account_id]).await {
-                Ok(mut accounts) => {
-                    let request = request.into_outgoing(accounts.remove(0));
-                    next.send_request(request).await
-                }
+            let account = match self.store.get_accounts(vec![account_id]).await {
+                Ok(mut accounts) => accounts.remove(0),
                 Err(_) => {
                     error!("No record found for account: {}", account_id);
-                    Err(RejectBuilder {
+                    return Err(RejectBuilder {
                         code: ErrorCode::F02_UNREACHABLE,
                         message: &[],
                         triggered_by: Some(&ilp_address),
                         data: &[],
                     }
-                    .build())
+                    .build());
+                }
+            };
+            if alternates.is_empty() && members.is_empty() {
+                return next.send_request(request.into_outgoing(account)).await;
+            }
+
+            let account_address = account.ilp_address().clone();
+            let mut result = next
+                .send_request(request.clone().into_outgoing(account))
+                .await;
+            if members.contains(&account_id) {
+                self.check_member_link(account_id, &account_address, &result);
+            }
+            for account_id in alternates {
+                match result {
+                    Err(ref reject) if is_retryable(reject, &ilp_address) => {}
+                    _ => break,
+                }
+                if request.prepare.expires_at() <= SystemTime::now() {
+                    debug!("Not retrying request since it has expired: {:?}", request);
+                    break;
+                }
+                let account = match self.store.get_accounts(vec![account_id]).await {
+                    Ok(mut accounts) => accounts.remove(0),
+                    Err(_) => {
+                        error!("No record found for alternate account: {}", account_id);
+                        continue;
+                    }
+                };
+                debug!(
+                    "Retrying request rejected with a temporary error on alternate account: {}",
+                    account_id
+                );
+                let account_address = account.ilp_address().clone();
+                result = next
+                    .send_request(request.clone().into_outgoing(account))
+                    .await;
+                if members.contains(&account_id) {
+                    self.check_member_link(account_id, &account_address, &result);
                 }
             }
+            result
         } else {
             error!(
                 "No route found for request {}: {:?}",
@@ -141,6 +348,7 @@ where
 mod tests {
     use super::*;
     use interledger_errors::*;
+    use interledger_packet::ErrorCode;
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
     use interledger_service::outgoing_service_fn;
     use once_cell::sync::Lazy;
@@ -149,7 +357,7 @@ mod tests {
     use std::iter::FromIterator;
     use std::str::FromStr;
     use std::sync::Arc;
-    use std::time::UNIX_EPOCH;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
     use uuid::Uuid;
 
     #[derive(Debug, Clone)]
@@ -184,6 +392,8 @@ mod tests {
     #[derive(Clone)]
     struct TestStore {
         routes: HashMap<String, Uuid>,
+        alternates: HashMap<String, Vec<Uuid>>,
+        multipath: HashMap<String, Vec<(Uuid, u32)>>,
     }
 
     #[async_trait]
@@ -227,6 +437,14 @@ mod tests {
         fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
             Arc::new(self.routes.clone())
         }
+
+        fn alternate_routes(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+            Arc::new(self.alternates.clone())
+        }
+
+        fn multipath_routes(&self) -> Arc<HashMap<String, Vec<(Uuid, u32)>>> {
+            Arc::new(self.multipath.clone())
+        }
     }
 
     #[tokio::test]
@@ -234,6 +452,8 @@ mod tests {
         let mut router = Router::new(
             TestStore {
                 routes: HashMap::new(),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -267,6 +487,8 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.other".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -300,6 +522,8 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.destination".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -331,6 +555,8 @@ mod tests {
         let mut router = Router::new(
             TestStore {
                 routes: HashMap::from_iter(vec![(String::new(), Uuid::new_v4())].into_iter()),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -364,6 +590,8 @@ mod tests {
                 routes: HashMap::from_iter(
                     vec![("example.".to_string(), Uuid::new_v4())].into_iter(),
                 ),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(|_| {
                 Ok(FulfillBuilder {
@@ -407,6 +635,8 @@ mod tests {
                     ]
                     .into_iter(),
                 ),
+                alternates: HashMap::new(),
+                multipath: HashMap::new(),
             },
             outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
                 *to_clone.lock() = Some(request.to);
@@ -435,4 +665,252 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(to.lock().take().unwrap().0, id2);
     }
+
+    fn failover_store(first: Uuid, alternates: Vec<Uuid>) -> TestStore {
+        TestStore {
+            routes: HashMap::from_iter(vec![("example.destination".to_string(), first)]),
+            alternates: HashMap::from_iter(vec![("example.destination".to_string(), alternates)]),
+            multipath: HashMap::new(),
+        }
+    }
+
+    /// Rejects packets sent to the first account with the given code and fulfills the others,
+    /// recording the accounts they were sent to
+    fn failover_next(
+        first: Uuid,
+        code: ErrorCode,
+        sent_to: Arc<Mutex<Vec<Uuid>>>,
+    ) -> impl OutgoingService<TestAccount> + Clone {
+        failover_next_triggered_by(first, code, None, sent_to)
+    }
+
+    fn failover_next_triggered_by(
+        first: Uuid,
+        code: ErrorCode,
+        triggered_by: Option<Address>,
+        sent_to: Arc<Mutex<Vec<Uuid>>>,
+    ) -> impl OutgoingService<TestAccount> + Clone {
+        outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            sent_to.lock().push(request.to.0);
+            if request.to.0 == first {
+                Err(RejectBuilder {
+                    code,
+                    message: &[],
+                    triggered_by: triggered_by.as_ref(),
+                    data: &[],
+                }
+                .build())
+            } else {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }
+        })
+    }
+
+    fn failover_request() -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount(Uuid::from_slice(&[0; 16]).unwrap()),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                execution_condition: &[1; 32],
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_temporary_errors_on_alternates() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            failover_store(id1, vec![id2]),
+            failover_next(id1, ErrorCode::T04_INSUFFICIENT_LIQUIDITY, sent_to.clone()),
+        )
+        .with_max_retries(1);
+
+        let result = router.handle_request(failover_request()).await;
+        assert!(result.is_ok());
+        assert_eq!(*sent_to.lock(), vec![id1, id2]);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_final_errors() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            failover_store(id1, vec![id2]),
+            failover_next(id1, ErrorCode::F02_UNREACHABLE, sent_to.clone()),
+        )
+        .with_max_retries(1);
+
+        let result = router.handle_request(failover_request()).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::F02_UNREACHABLE);
+        assert_eq!(*sent_to.lock(), vec![id1]);
+    }
+
+    #[tokio::test]
+    async fn retries_link_failures_reported_by_this_node() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        // The HTTP and BTP clients report that the next hop is unreachable with our address
+        let mut router = Router::new(
+            failover_store(id1, vec![id2]),
+            failover_next_triggered_by(
+                id1,
+                ErrorCode::T01_PEER_UNREACHABLE,
+                Some(Address::from_str("example.connector").unwrap()),
+                sent_to.clone(),
+            ),
+        )
+        .with_max_retries(1);
+
+        let result = router.handle_request(failover_request()).await;
+        assert!(result.is_ok());
+        assert_eq!(*sent_to.lock(), vec![id1, id2]);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors_triggered_by_this_node() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            failover_store(id1, vec![id2]),
+            failover_next_triggered_by(
+                id1,
+                ErrorCode::T00_INTERNAL_ERROR,
+                Some(Address::from_str("example.connector").unwrap()),
+                sent_to.clone(),
+            ),
+        )
+        .with_max_retries(1);
+
+        let result = router.handle_request(failover_request()).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert_eq!(*sent_to.lock(), vec![id1]);
+    }
+
+    #[tokio::test]
+    async fn applies_prefix_retry_limits() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            failover_store(id1, vec![id2]),
+            failover_next(id1, ErrorCode::T01_PEER_UNREACHABLE, sent_to.clone()),
+        )
+        .with_max_retries(1)
+        .with_prefix_max_retries("example.dest".to_string(), 0);
+
+        let result = router.handle_request(failover_request()).await;
+        assert_eq!(result.unwrap_err().code(), ErrorCode::T01_PEER_UNREACHABLE);
+        assert_eq!(*sent_to.lock(), vec![id1]);
+    }
+
+    fn multipath_store(members: Vec<(Uuid, u32)>) -> TestStore {
+        TestStore {
+            routes: HashMap::from_iter(vec![("example.destination".to_string(), members[0].0)]),
+            alternates: HashMap::new(),
+            multipath: HashMap::from_iter(vec![("example.destination".to_string(), members)]),
+        }
+    }
+
+    #[tokio::test]
+    async fn spreads_packets_across_multipath_members() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            multipath_store(vec![(id1, 1), (id2, 3)]),
+            failover_next(
+                Uuid::nil(),
+                ErrorCode::T01_PEER_UNREACHABLE,
+                sent_to.clone(),
+            ),
+        );
+
+        for _ in 0..8 {
+            let result = router.handle_request(failover_request()).await;
+            assert!(result.is_ok());
+        }
+        let sent_to = sent_to.lock();
+        assert_eq!(sent_to.iter().filter(|id| **id == id1).count(), 2);
+        assert_eq!(sent_to.iter().filter(|id| **id == id2).count(), 6);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_multipath_members_whose_link_is_down() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            multipath_store(vec![(id1, 1), (id2, 1)]),
+            failover_next(id1, ErrorCode::T01_PEER_UNREACHABLE, sent_to.clone()),
+        );
+
+        let mut rejected = 0;
+        for _ in 0..4 {
+            if router.handle_request(failover_request()).await.is_err() {
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 1);
+        assert_eq!(sent_to.lock().iter().filter(|id| **id == id1).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_multipath_packets_on_the_other_members_after_a_link_failure() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            multipath_store(vec![(id1, 1), (id2, 1)]),
+            failover_next_triggered_by(
+                id1,
+                ErrorCode::T01_PEER_UNREACHABLE,
+                Some(Address::from_str("example.connector").unwrap()),
+                sent_to.clone(),
+            ),
+        )
+        .with_max_retries(1);
+
+        let result = router.handle_request(failover_request()).await;
+        assert!(result.is_ok());
+        assert_eq!(*sent_to.lock(), vec![id1, id2]);
+        assert!(router.down_members.lock().contains_key(&id1));
+    }
+
+    #[tokio::test]
+    async fn keeps_multipath_members_rejected_by_this_node() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let sent_to = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new(
+            multipath_store(vec![(id1, 1), (id2, 1)]),
+            failover_next_triggered_by(
+                id1,
+                ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                Some(Address::from_str("example.connector").unwrap()),
+                sent_to.clone(),
+            ),
+        );
+
+        let mut rejected = 0;
+        for _ in 0..4 {
+            if router.handle_request(failover_request()).await.is_err() {
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 2);
+        assert_eq!(sent_to.lock().iter().filter(|id| **id == id1).count(), 2);
+    }
 }
@@ -0,0 +1,621 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::TryFutureExt;
+use interledger_errors::{AccountStoreError, BalanceStoreError};
+use interledger_service::{Account, AccountStore, Username};
+use metrics::{labels, recorder, Key};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the
+/// balance limits and the thresholds after which alerts should be raised for them
+pub trait BalanceAlertAccount: Account {
+    /// The minimum balance (credit limit) of the account
+    fn min_balance(&self) -> Option<i64> {
+        None
+    }
+
+    /// The balance above which the account is expected to be settled
+    fn settle_threshold(&self) -> Option<i64> {
+        None
+    }
+
+    /// Percentage (0-100) of the credit limit the account may use before an alert is raised
+    fn credit_limit_alert_percent(&self) -> Option<u8> {
+        None
+    }
+
+    /// Time (in milliseconds) the balance may stay above the settle threshold before an alert is raised
+    fn settle_threshold_alert_delay(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// The condition which caused a [`BalanceAlert`](./struct.BalanceAlert.html) to be raised
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceAlertKind {
+    /// The account used more than the configured percentage of its credit limit
+    CreditLimit,
+    /// The balance stayed above the settle threshold for longer than the configured delay
+    SettlementOverdue,
+}
+
+impl BalanceAlertKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            BalanceAlertKind::CreditLimit => "credit_limit",
+            BalanceAlertKind::SettlementOverdue => "settlement_overdue",
+        }
+    }
+}
+
+/// An alert raised when an account's balance crossed one of its alert thresholds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceAlert {
+    /// The account whose balance crossed the threshold
+    pub account_id: Uuid,
+    /// The username of that account
+    pub username: Username,
+    /// The condition which raised the alert
+    pub kind: BalanceAlertKind,
+    /// The balance of the account when the alert was raised
+    pub balance: i64,
+    /// The balance which triggers the alert
+    pub threshold: i64,
+    /// The time the alert was raised in RFC3339 format
+    pub timestamp: String,
+}
+
+/// Store trait which persists the alerts raised for accounts so that they can be queried later
+#[async_trait]
+pub trait BalanceAlertStore {
+    /// Saves an alert which was raised for an account
+    async fn save_balance_alert(&self, alert: BalanceAlert) -> Result<(), BalanceStoreError>;
+
+    /// Loads the most recent alerts, either of the given account or of every account, newest first
+    async fn get_balance_alerts(
+        &self,
+        account_id: Option<Uuid>,
+    ) -> Result<Vec<BalanceAlert>, BalanceStoreError>;
+}
+
+/// Last known balance of an account along with its alert thresholds
+#[derive(Clone, Debug)]
+struct AlertState {
+    username: Username,
+    balance: i64,
+    credit_limit_threshold: Option<i64>,
+    settle_threshold: Option<i64>,
+    settle_threshold_alert_delay: Option<Duration>,
+    // Alerts are raised once when a threshold is crossed and are re-armed only
+    // after the balance moves back, so that every packet doesn't raise a new one
+    credit_limit_alerted: bool,
+    above_settle_threshold_since: Option<Instant>,
+    settlement_overdue_alerted: bool,
+}
+
+impl AlertState {
+    fn new<A: BalanceAlertAccount>(account: &A, balance: i64) -> Self {
+        let mut state = AlertState {
+            username: account.username().clone(),
+            balance,
+            credit_limit_threshold: None,
+            settle_threshold: None,
+            settle_threshold_alert_delay: None,
+            credit_limit_alerted: false,
+            above_settle_threshold_since: None,
+            settlement_overdue_alerted: false,
+        };
+        state.configure(account);
+        state
+    }
+
+    /// Refreshes the thresholds, in case the account's settings were changed
+    fn configure<A: BalanceAlertAccount>(&mut self, account: &A) {
+        self.credit_limit_threshold =
+            match (account.min_balance(), account.credit_limit_alert_percent()) {
+                // Only accounts which are extended credit (negative min balance) have a credit limit
+                // The product is computed in i128 so that large credit limits do not overflow
+                (Some(min_balance), Some(percent)) if min_balance < 0 => {
+                    Some((i128::from(min_balance) * i128::from(percent.min(100)) / 100) as i64)
+                }
+                _ => None,
+            };
+        self.settle_threshold_alert_delay = account
+            .settle_threshold_alert_delay()
+            .map(Duration::from_millis);
+        self.settle_threshold = self
+            .settle_threshold_alert_delay
+            .and(account.settle_threshold());
+    }
+
+    /// Applies a new balance and returns the kinds of alerts which should be raised
+    fn update_balance(&mut self, balance: i64, now: Instant) -> Vec<(BalanceAlertKind, i64)> {
+        self.balance = balance;
+
+        let mut alerts = Vec::new();
+        if let Some(threshold) = self.credit_limit_threshold {
+            if balance <= threshold {
+                if !self.credit_limit_alerted {
+                    self.credit_limit_alerted = true;
+                    alerts.push((BalanceAlertKind::CreditLimit, threshold));
+                }
+            } else {
+                self.credit_limit_alerted = false;
+            }
+        }
+
+        if let Some(threshold) = self.settle_threshold {
+            if balance > threshold {
+                if self.above_settle_threshold_since.is_none() {
+                    self.above_settle_threshold_since = Some(now);
+                }
+            } else {
+                self.above_settle_threshold_since = None;
+                self.settlement_overdue_alerted = false;
+            }
+        }
+        alerts.extend(self.check_overdue(now));
+        alerts
+    }
+
+    /// Returns the settlement overdue alert if the balance has stayed above the
+    /// settle threshold for longer than the configured delay
+    fn check_overdue(&mut self, now: Instant) -> Option<(BalanceAlertKind, i64)> {
+        match (
+            self.settle_threshold,
+            self.settle_threshold_alert_delay,
+            self.above_settle_threshold_since,
+        ) {
+            (Some(threshold), Some(delay), Some(since))
+                if !self.settlement_overdue_alerted && now.duration_since(since) >= delay =>
+            {
+                self.settlement_overdue_alerted = true;
+                Some((BalanceAlertKind::SettlementOverdue, threshold))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Evaluates account balances against their alert thresholds.
+///
+/// Raised alerts are logged, counted in the `balance.alerts` metric,
+/// saved to the store and, if a webhook is configured, POSTed to it as JSON.
+#[derive(Clone)]
+pub struct BalanceAlerts<S> {
+    store: S,
+    webhook_url: Option<Url>,
+    http_client: reqwest::Client,
+    accounts: Arc<Mutex<HashMap<Uuid, AlertState>>>,
+}
+
+impl<S> BalanceAlerts<S>
+where
+    S: BalanceAlertStore + Clone + Send + Sync + 'static,
+{
+    /// Simple constructor
+    pub fn new(store: S, webhook_url: Option<Url>) -> Self {
+        BalanceAlerts {
+            store,
+            webhook_url,
+            http_client: reqwest::Client::new(),
+            accounts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns true if any alert threshold is configured for the account
+    pub fn is_monitored<A: BalanceAlertAccount>(account: &A) -> bool {
+        account.credit_limit_alert_percent().is_some()
+            || account.settle_threshold_alert_delay().is_some()
+    }
+
+    /// Evaluates the account's new balance against its alert thresholds
+    pub fn update_balance<A: BalanceAlertAccount>(&self, account: &A, balance: i64) {
+        if !Self::is_monitored(account) {
+            // Its alerts may have been turned off since its balance was last updated
+            self.accounts.lock().remove(&account.id());
+            return;
+        }
+
+        let now = Instant::now();
+        let (username, alerts) = {
+            let mut accounts = self.accounts.lock();
+            let state = accounts
+                .entry(account.id())
+                .or_insert_with(|| AlertState::new(account, balance));
+            state.configure(account);
+            (state.username.clone(), state.update_balance(balance, now))
+        };
+        for (kind, threshold) in alerts {
+            self.raise(account.id(), username.clone(), kind, balance, threshold);
+        }
+    }
+
+    /// Raises the settlement overdue alerts of the accounts whose balance
+    /// has been above the settle threshold for too long
+    pub fn check_overdue(&self) {
+        let now = Instant::now();
+        let alerts: Vec<_> = self
+            .accounts
+            .lock()
+            .iter_mut()
+            .filter_map(|(id, state)| {
+                state.check_overdue(now).map(|(kind, threshold)| {
+                    (*id, state.username.clone(), state.balance, kind, threshold)
+                })
+            })
+            .collect();
+        for (account_id, username, balance, kind, threshold) in alerts {
+            self.raise(account_id, username, kind, balance, threshold);
+        }
+    }
+
+    /// Reloads the accounts whose balance is tracked to pick up changes to their alert
+    /// thresholds, and forgets the ones which were deleted or no longer have any alert
+    /// configured, so that the tracked state does not grow without bound
+    pub async fn refresh_accounts<A>(&self)
+    where
+        S: AccountStore<Account = A>,
+        A: BalanceAlertAccount,
+    {
+        let account_ids: Vec<Uuid> = self.accounts.lock().keys().cloned().collect();
+        for account_id in account_ids {
+            match self.store.get_accounts(vec![account_id]).await {
+                Ok(accounts) => {
+                    let mut tracked = self.accounts.lock();
+                    match accounts.first() {
+                        Some(account) if Self::is_monitored(account) => {
+                            if let Some(state) = tracked.get_mut(&account_id) {
+                                state.configure(account);
+                            }
+                        }
+                        _ => {
+                            tracked.remove(&account_id);
+                        }
+                    }
+                }
+                Err(AccountStoreError::AccountNotFound(_))
+                | Err(AccountStoreError::WrongLength { .. }) => {
+                    debug!(
+                        "Account {} was deleted, no longer tracking its balance alerts",
+                        account_id
+                    );
+                    self.accounts.lock().remove(&account_id);
+                }
+                Err(err) => error!(
+                    "Error loading account {} to refresh its balance alerts: {}",
+                    account_id, err
+                ),
+            }
+        }
+    }
+
+    /// Spawns a task which periodically checks for overdue settlements.
+    /// This is needed because an account's balance may stay above the
+    /// settle threshold without any packets being routed through it.
+    /// The task also forgets the accounts which no longer need to be tracked.
+    pub fn spawn_overdue_checker<A>(&self, interval: Duration)
+    where
+        S: AccountStore<Account = A>,
+        A: BalanceAlertAccount,
+    {
+        let alerts = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                alerts.refresh_accounts().await;
+                alerts.check_overdue();
+            }
+        });
+    }
+
+    fn raise(
+        &self,
+        account_id: Uuid,
+        username: Username,
+        kind: BalanceAlertKind,
+        balance: i64,
+        threshold: i64,
+    ) {
+        warn!(
+            "Balance alert ({}) for account {}: balance is {}, threshold is {}",
+            kind.as_str(),
+            username,
+            balance,
+            threshold
+        );
+        recorder().increment_counter(
+            Key::from_name_and_labels(
+                "balance.alerts",
+                labels!("kind" => kind.as_str(), "account" => username.to_string()),
+            ),
+            1,
+        );
+
+        let alert = BalanceAlert {
+            account_id,
+            username,
+            kind,
+            balance,
+            threshold,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        let store = self.store.clone();
+        let webhook = self
+            .webhook_url
+            .clone()
+            .map(|url| (url, self.http_client.clone()));
+        tokio::spawn(async move {
+            if let Some((url, client)) = webhook {
+                let _ = client
+                    .post(url.as_ref())
+                    .json(&alert)
+                    .send()
+                    .map_err(|err| error!("Error sending balance alert to webhook: {}", err))
+                    .and_then(|response| async move {
+                        if response.status().is_success() {
+                            debug!("Sent balance alert to webhook");
+                            Ok(())
+                        } else {
+                            error!(
+                                "Webhook responded to balance alert with status {}",
+                                response.status()
+                            );
+                            Err(())
+                        }
+                    })
+                    .await;
+            }
+            store
+                .save_balance_alert(alert)
+                .map_err(|err| error!("Error saving balance alert: {}", err))
+                .await
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::Address;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        min_balance: Option<i64>,
+        settle_threshold: Option<i64>,
+        credit_limit_alert_percent: Option<u8>,
+        settle_threshold_alert_delay: Option<u64>,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl BalanceAlertAccount for TestAccount {
+        fn min_balance(&self) -> Option<i64> {
+            self.min_balance
+        }
+
+        fn settle_threshold(&self) -> Option<i64> {
+            self.settle_threshold
+        }
+
+        fn credit_limit_alert_percent(&self) -> Option<u8> {
+            self.credit_limit_alert_percent
+        }
+
+        fn settle_threshold_alert_delay(&self) -> Option<u64> {
+            self.settle_threshold_alert_delay
+        }
+    }
+
+    #[test]
+    fn credit_limit_alert_fires_once_until_rearmed() {
+        let account = TestAccount {
+            min_balance: Some(-1000),
+            settle_threshold: None,
+            credit_limit_alert_percent: Some(80),
+            settle_threshold_alert_delay: None,
+        };
+        let now = Instant::now();
+        let mut state = AlertState::new(&account, 0);
+        assert_eq!(state.credit_limit_threshold, Some(-800));
+
+        assert!(state.update_balance(-700, now).is_empty());
+        assert_eq!(
+            state.update_balance(-800, now),
+            vec![(BalanceAlertKind::CreditLimit, -800)]
+        );
+        // Still above the threshold, the alert is not raised again
+        assert!(state.update_balance(-900, now).is_empty());
+        // The balance recovers and crosses the threshold once more
+        assert!(state.update_balance(-500, now).is_empty());
+        assert_eq!(
+            state.update_balance(-850, now),
+            vec![(BalanceAlertKind::CreditLimit, -800)]
+        );
+    }
+
+    #[test]
+    fn credit_limit_threshold_does_not_overflow() {
+        let account = TestAccount {
+            min_balance: Some(i64::MIN),
+            settle_threshold: None,
+            credit_limit_alert_percent: Some(100),
+            settle_threshold_alert_delay: None,
+        };
+        assert_eq!(
+            AlertState::new(&account, 0).credit_limit_threshold,
+            Some(i64::MIN)
+        );
+
+        let account = TestAccount {
+            credit_limit_alert_percent: Some(50),
+            ..account
+        };
+        assert_eq!(
+            AlertState::new(&account, 0).credit_limit_threshold,
+            Some(i64::MIN / 2)
+        );
+    }
+
+    #[test]
+    fn no_credit_limit_alert_without_credit() {
+        let account = TestAccount {
+            min_balance: Some(0),
+            settle_threshold: None,
+            credit_limit_alert_percent: Some(80),
+            settle_threshold_alert_delay: None,
+        };
+        let mut state = AlertState::new(&account, 0);
+        assert!(state.update_balance(0, Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn settlement_overdue_alert_after_delay() {
+        let account = TestAccount {
+            min_balance: None,
+            settle_threshold: Some(100),
+            credit_limit_alert_percent: None,
+            settle_threshold_alert_delay: Some(1000),
+        };
+        let start = Instant::now();
+        let mut state = AlertState::new(&account, 0);
+
+        assert!(state.update_balance(150, start).is_empty());
+        assert!(state
+            .check_overdue(start + Duration::from_millis(999))
+            .is_none());
+        assert_eq!(
+            state.check_overdue(start + Duration::from_millis(1000)),
+            Some((BalanceAlertKind::SettlementOverdue, 100))
+        );
+        assert!(state
+            .check_overdue(start + Duration::from_secs(5))
+            .is_none());
+
+        // Settling re-arms the alert
+        assert!(state
+            .update_balance(0, start + Duration::from_secs(6))
+            .is_empty());
+        assert!(state
+            .update_balance(200, start + Duration::from_secs(7))
+            .is_empty());
+        assert_eq!(
+            state.update_balance(200, start + Duration::from_secs(8)),
+            vec![(BalanceAlertKind::SettlementOverdue, 100)]
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        account: Arc<Mutex<Option<TestAccount>>>,
+    }
+
+    #[async_trait]
+    impl BalanceAlertStore for TestStore {
+        async fn save_balance_alert(&self, _: BalanceAlert) -> Result<(), BalanceStoreError> {
+            Ok(())
+        }
+
+        async fn get_balance_alerts(
+            &self,
+            _: Option<Uuid>,
+        ) -> Result<Vec<BalanceAlert>, BalanceStoreError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            match self.account.lock().clone() {
+                Some(account) => Ok(vec![account]),
+                None => Err(AccountStoreError::AccountNotFound(
+                    account_ids[0].to_string(),
+                )),
+            }
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            _: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            Ok(Uuid::nil())
+        }
+    }
+
+    #[tokio::test]
+    async fn forgets_accounts_which_are_no_longer_monitored() {
+        let account = TestAccount {
+            min_balance: Some(-1000),
+            settle_threshold: None,
+            credit_limit_alert_percent: Some(80),
+            settle_threshold_alert_delay: None,
+        };
+        let unmonitored = TestAccount {
+            credit_limit_alert_percent: None,
+            ..account.clone()
+        };
+        let store = TestStore::default();
+        let alerts = BalanceAlerts::new(store.clone(), None);
+
+        // The account's alerts are turned off while it routes packets
+        alerts.update_balance(&account, 0);
+        assert_eq!(alerts.accounts.lock().len(), 1);
+        alerts.update_balance(&unmonitored, 0);
+        assert!(alerts.accounts.lock().is_empty());
+
+        // The account's alerts are turned off while it is idle
+        alerts.update_balance(&account, 0);
+        *store.account.lock() = Some(unmonitored);
+        alerts.refresh_accounts().await;
+        assert!(alerts.accounts.lock().is_empty());
+
+        // The account is deleted
+        alerts.update_balance(&account, 0);
+        *store.account.lock() = None;
+        alerts.refresh_accounts().await;
+        assert!(alerts.accounts.lock().is_empty());
+    }
+}
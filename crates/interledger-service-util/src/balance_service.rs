@@ -1,10 +1,11 @@
+use super::balance_alerts::{BalanceAlertAccount, BalanceAlertStore, BalanceAlerts};
 use async_trait::async_trait;
 use futures::TryFutureExt;
 use interledger_errors::BalanceStoreError;
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_service::*;
 use interledger_settlement::core::{
-    types::{SettlementAccount, SettlementStore},
+    types::{SettlementAccount, SettlementQueueStore, SettlementStore},
     SettlementClient,
 };
 use std::marker::PhantomData;
@@ -51,6 +52,7 @@ pub struct BalanceService<S, O, A> {
     store: S,
     next: O,
     settlement_client: SettlementClient,
+    alerts: Option<BalanceAlerts<S>>,
     account_type: PhantomData<A>,
 }
 
@@ -65,17 +67,49 @@ where
             store,
             next,
             settlement_client: SettlementClient::default(),
+            alerts: None,
             account_type: PhantomData,
         }
     }
+
+    /// Evaluates the balances of the accounts against their alert thresholds
+    /// as packets are processed
+    pub fn with_alerts(mut self, alerts: BalanceAlerts<S>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Resumes retrying the settlements which were queued when the node stopped
+    pub fn resume_queued_settlements(&self)
+    where
+        S: SettlementQueueStore + AccountStore<Account = A> + Clone + Send + Sync + 'static,
+        A: Send + Sync + 'static,
+    {
+        let store = self.store.clone();
+        let settlement_client = self.settlement_client.clone();
+        tokio::spawn(async move {
+            settlement_client
+                .resume_queued_settlements(store)
+                .map_err(|err| error!("Error resuming queued settlements: {}", err))
+                .await
+        });
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for BalanceService<S, O, A>
 where
-    S: AddressStore + BalanceStore + SettlementStore<Account = A> + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + BalanceStore
+        + BalanceAlertStore
+        + SettlementStore<Account = A>
+        + SettlementQueueStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     O: OutgoingService<A> + Send + Clone + 'static,
-    A: SettlementAccount + Send + Sync + 'static,
+    A: SettlementAccount + BalanceAlertAccount + Send + Sync + 'static,
 {
     /// On send message:
     /// 1. Calls `store.update_balances_for_prepare` with the prepare.
@@ -106,6 +140,7 @@ where
         let outgoing_amount = request.prepare.amount();
         let ilp_address = self.store.get_ilp_address();
         let settlement_client = self.settlement_client.clone();
+        let alerts = self.alerts.clone();
 
         // Update the balance _before_ sending the settlement so that we don't accidentally send
         // multiple settlements for the same balance. While there will be a small moment of time (the delta
@@ -131,6 +166,29 @@ where
             })
             .await?;
 
+        if let Some(ref alerts) = alerts {
+            // The balance is only fetched for the accounts which have alerts configured
+            if BalanceAlerts::<S>::is_monitored(&from) {
+                let alerts = alerts.clone();
+                let store = self.store.clone();
+                let from = from.clone();
+                tokio::spawn(async move {
+                    let balance = store
+                        .get_balance(from.id())
+                        .map_err(|err| {
+                            error!(
+                                "Error getting balance of account {} to evaluate its alerts: {}",
+                                from.id(),
+                                err
+                            )
+                        })
+                        .await?;
+                    alerts.update_balance(&from, balance);
+                    Ok::<(), ()>(())
+                });
+            }
+        }
+
         match next.send_request(request).await {
             Ok(fulfill) => {
                 if outgoing_amount > 0 {
@@ -151,31 +209,63 @@ where
                             "Account balance after fulfill: {}. Amount that needs to be settled: {}",
                             balance, amount_to_settle
                         );
+                        if let Some(alerts) = alerts {
+                            alerts.update_balance(&to, balance);
+                        }
                         if amount_to_settle > 0 {
                             if let Some(engine_details) = to.settlement_engine_details() {
-                                let engine_url = engine_details.url;
-                                // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL script)
-                                // and the send_settlement fails but the program isn't alive to hear that, the balance will be incorrect.
-                                // No other instance will know that it was trying to send an outgoing settlement. We could
-                                // make this more robust by saving something to the DB about the outgoing settlement when we change the balance
-                                // but then we would also need to prevent a situation where every connector instance is polling the
-                                // settlement engine for the status of each
-                                // outgoing settlement and putting unnecessary
-                                // load on the settlement engine.
-                                if settlement_client
+                                // The settlement is marked as in flight in the store along with the balance change
+                                // (in the PROCESS_FULFILL script) until the engine accepts it. Note that if this program
+                                // crashes before hearing back from the engine, the settlement stays in flight: no other
+                                // instance knows whether the engine received it, so it is left to the reconciliation
+                                // with the engine instead of being sent twice.
+                                let idempotency_key = Uuid::new_v4().to_hyphenated().to_string();
+                                match settlement_client
                                     .send_settlement(
                                         to.id(),
-                                        engine_url,
+                                        engine_details.clone(),
                                         amount_to_settle,
                                         to.asset_scale(),
+                                        &idempotency_key,
                                     )
                                     .await
-                                    .is_err()
                                 {
-                                    store
-                                        .refund_settlement(to_id, amount_to_settle)
-                                        .map_err(|_| ())
-                                        .await?;
+                                    Ok(_) => {
+                                        store
+                                            .settlement_sent(to_id, amount_to_settle)
+                                            .map_err(|err| error!("Error marking settlement for account {} as sent: {}", to_id, err))
+                                            .await?;
+                                    }
+                                    // The engine refused the settlement, retrying it would not help
+                                    Err(err) if err.is_rejected_by_engine() => {
+                                        error!("Settlement engine rejected settlement for account {}, refunding it: {}", to_id, err);
+                                        store
+                                            .refund_settlement(to_id, amount_to_settle)
+                                            .map_err(|_| ())
+                                            .await?;
+                                    }
+                                    // The engine is unreachable, keep the settlement until it recovers.
+                                    // It will be refunded if the engine stays down. The engine may have
+                                    // handled a request which timed out, so it keeps its idempotency key
+                                    Err(err) => {
+                                        debug!("Could not reach settlement engine, queueing settlement for account {}: {}", to_id, err);
+                                        settlement_client
+                                            .queue_settlement(
+                                                store,
+                                                to_id,
+                                                engine_details,
+                                                amount_to_settle,
+                                                to.asset_scale(),
+                                                idempotency_key,
+                                            )
+                                            .map_err(|err| {
+                                                error!(
+                                                    "Error queueing settlement for account {}: {}",
+                                                    to_id, err
+                                                )
+                                            })
+                                            .await?;
+                                    }
                                 }
                             }
                         }
@@ -214,6 +304,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::BalanceAlert;
     use interledger_errors::{AddressStoreError, SettlementStoreError};
     use interledger_packet::{Address, FulfillBuilder, PrepareBuilder, RejectBuilder};
     use interledger_settlement::core::types::SettlementEngineDetails;
@@ -350,11 +441,11 @@ mod tests {
         }
     }
 
+    impl BalanceAlertAccount for TestAccount {}
+
     impl SettlementAccount for TestAccount {
         fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
-            Some(SettlementEngineDetails {
-                url: self.engine_url.clone(),
-            })
+            Some(SettlementEngineDetails::new(self.engine_url.clone()))
         }
     }
 
@@ -422,6 +513,20 @@ mod tests {
         }
     }
 
+    #[async_trait]
+    impl BalanceAlertStore for TestStore {
+        async fn save_balance_alert(&self, _: BalanceAlert) -> Result<(), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn get_balance_alerts(
+            &self,
+            _: Option<Uuid>,
+        ) -> Result<Vec<BalanceAlert>, BalanceStoreError> {
+            unimplemented!()
+        }
+    }
+
     #[async_trait]
     impl SettlementStore for TestStore {
         type Account = TestAccount;
@@ -441,6 +546,40 @@ mod tests {
         }
     }
 
+    #[async_trait]
+    impl SettlementQueueStore for TestStore {
+        async fn settlement_sent(&self, _: Uuid, _: u64) -> Result<(), SettlementStoreError> {
+            Ok(())
+        }
+
+        async fn queue_settlement(
+            &self,
+            _: Uuid,
+            _: u64,
+            _: &str,
+        ) -> Result<(), SettlementStoreError> {
+            Ok(())
+        }
+
+        async fn claim_queued_settlement(
+            &self,
+            _: Uuid,
+            _: &str,
+        ) -> Result<u64, SettlementStoreError> {
+            Ok(0)
+        }
+
+        async fn get_queued_settlements(
+            &self,
+        ) -> Result<Vec<(Uuid, String, u64)>, SettlementStoreError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_pending_settlement(&self, _: Uuid) -> Result<u64, SettlementStoreError> {
+            Ok(0)
+        }
+    }
+
     static TEST_REQUEST: Lazy<OutgoingRequest<TestAccount>> = Lazy::new(|| {
         let url = mockito::server_url();
         OutgoingRequest {
@@ -1,18 +1,39 @@
+use super::fees::{FeePolicy, FeeStore};
+use super::remainders::{RemainderStore, REMAINDER_PRECISION};
+use super::revenue::{RevenueStore, RevenueTracker};
 use async_trait::async_trait;
 use interledger_packet::{ErrorCode, RejectBuilder};
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, Rate, Rounding};
 use interledger_service::*;
-use interledger_settlement::core::types::{Convert, ConvertDetails};
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Duration};
 use tracing::{error, trace, warn};
 
 /// # Exchange Rates Service
 ///
 /// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`).
-/// Requires a `ExchangeRateStore`
+/// Requires a `ExchangeRateStore` and a `FeeStore`
+///
+/// Amounts are converted with exact rational arithmetic and the outgoing amount
+/// is rounded down, so any rounding is always in the connector's favor.
+///
+/// The fees charged are those of the most specific rule of the store's
+/// [`FeeSchedule`](./struct.FeeSchedule.html) which applies to the packet, or
+/// only the global spread if there is none.
+///
+/// If a maximum rate age is set, cross-currency packets are rejected when
+/// either asset's rate was last updated longer ago than that.
+///
+/// If revenue tracking is enabled, the fees captured on each fulfilled packet are recorded.
+///
+/// If remainder tracking is enabled, the fractions of a unit truncated when converting
+/// each packet are accumulated per pair of accounts in the store. Whenever they add up
+/// to a whole unit, it is added to the next packet forwarded between those accounts.
 #[derive(Clone)]
 pub struct ExchangeRateService<S, O, A> {
-    spread: f64,
+    spread: Rate,
+    max_rate_age: Option<Duration>,
+    track_remainders: bool,
+    revenue: Option<RevenueTracker<S>>,
     store: S,
     next: O,
     account_type: PhantomData<A>,
@@ -20,25 +41,127 @@ pub struct ExchangeRateService<S, O, A> {
 
 impl<S, O, A> ExchangeRateService<S, O, A>
 where
-    S: AddressStore + ExchangeRateStore,
+    S: AddressStore + ExchangeRateStore + FeeStore + RevenueStore + RemainderStore,
     O: OutgoingService<A>,
     A: Account,
 {
-    pub fn new(spread: f64, store: S, next: O) -> Self {
+    pub fn new(spread: Rate, store: S, next: O) -> Self {
         ExchangeRateService {
             spread,
+            max_rate_age: None,
+            track_remainders: false,
+            revenue: None,
             store,
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Rejects packets whose conversion would use a rate older than `max_rate_age`
+    pub fn with_max_rate_age(mut self, max_rate_age: Duration) -> Self {
+        self.max_rate_age = Some(max_rate_age);
+        self
+    }
+
+    /// Accumulates the remainders truncated when converting packets and pays them out
+    /// to the outgoing accounts once they add up to whole units
+    pub fn with_remainder_tracking(mut self) -> Self {
+        self.track_remainders = true;
+        self
+    }
+
+    /// Records the fees captured on the fulfilled packets
+    pub fn with_revenue(mut self, revenue: RevenueTracker<S>) -> Self {
+        self.revenue = Some(revenue);
+        self
+    }
+}
+
+/// The result of [`convert_amount`](./fn.convert_amount.html)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conversion {
+    /// The converted amount, or None if it does not fit in a u64
+    pub amount: Option<u64>,
+    /// The exchange rate after the spread, adjusted for the assets' scales
+    pub rate: Rate,
+    /// Whether fixed or minimum fees were deducted
+    pub flat_fees: bool,
+    /// Fraction of a unit of the outgoing asset truncated when rounding the converted amount down
+    pub remainder: Rate,
+}
+
+/// Converts a packet's amount the way the [`ExchangeRateService`](./struct.ExchangeRateService.html) does,
+/// given the exchange rate between the assets before any fee. The spread and flat fees of the
+/// fee policy are deducted, falling back to the global `spread` if the policy does not set one.
+pub fn convert_amount(
+    amount: u64,
+    rate: &Rate,
+    from_scale: u8,
+    to_scale: u8,
+    spread: &Rate,
+    fee_policy: Option<&FeePolicy>,
+) -> Conversion {
+    // Apply spread. Same-currency packets are charged the global spread too,
+    // unless a fee rule (e.g. one with `same_asset` set) overrides it
+    let spread = fee_policy
+        .and_then(|policy| policy.spread.as_ref())
+        .unwrap_or(spread);
+    let rate_after_spread = rate * &(Rate::one() - spread.clone());
+    let rate_after_spread = if rate_after_spread.is_positive() {
+        rate_after_spread
+    } else {
+        warn!(
+            "Exchange rate would have been {} based on rate and spread, using 0 instead",
+            rate_after_spread
+        );
+        Rate::zero()
+    };
+
+    // The conversion is exact, the result is only rounded down once at the end
+    // so that the connector never forwards more than what the rate allows
+    let scaled_rate = rate_after_spread.scaled(from_scale, to_scale);
+    let converted = scaled_rate.convert_with_remainder(amount);
+
+    // Fixed and minimum fees are denominated in the outgoing asset
+    let flat_fees = fee_policy.filter(|policy| policy.has_flat_fees());
+    let converted = match (converted, flat_fees) {
+        (Some((converted, remainder)), Some(policy)) => {
+            let (amount_before_fees, remainder_before_fees) = rate
+                .scaled(from_scale, to_scale)
+                .convert_with_remainder(amount)
+                .unwrap_or((u64::MAX, Rate::zero()));
+            let after_fixed_fee = converted.saturating_sub(policy.fixed_fee);
+            let after_min_fee = amount_before_fees.saturating_sub(policy.min_fee);
+            if after_min_fee < after_fixed_fee {
+                Some((after_min_fee, remainder_before_fees))
+            } else {
+                Some((after_fixed_fee, remainder))
+            }
+        }
+        (converted, _) => converted,
+    };
+
+    Conversion {
+        amount: converted.as_ref().map(|(amount, _)| *amount),
+        rate: scaled_rate,
+        flat_fees: flat_fees.is_some(),
+        remainder: converted.map_or_else(Rate::zero, |(_, remainder)| remainder),
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for ExchangeRateService<S, O, A>
 where
     // TODO can we make these non-'static?
-    S: AddressStore + ExchangeRateStore + Clone + Send + Sync + 'static,
+    S: AddressStore
+        + ExchangeRateStore
+        + FeeStore
+        + RevenueStore
+        + RemainderStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
     A: Account + Send + Sync + 'static,
 {
@@ -47,133 +170,219 @@ where
     /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
     ///     - return reject if the call to the store fails
     /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
+    /// 1. Deducts the fees of the applicable fee policy
+    /// 1. Accumulates the truncated remainder and adds any whole units owed to the outgoing account, if enabled
+    /// 1. Rejects the packet if its amount is still rounded down to 0
     /// 1. Updates the amount in the prepare packet and forwards it
+    /// 1. Records the fees captured if the packet is fulfilled, or reverts the remainder if it is rejected
     async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
         let ilp_address = self.store.get_ilp_address();
+        let mut revenue = None;
+        let mut remainder = None;
         if request.prepare.amount() > 0 {
-            let rate: f64 = if request.from.asset_code() == request.to.asset_code() {
-                1f64
+            let rate = if request.from.asset_code() == request.to.asset_code() {
+                Some(Rate::one())
             } else if let Ok(rates) = self
                 .store
                 .get_exchange_rates(&[&request.from.asset_code(), &request.to.asset_code()])
             {
+                if let Some(max_rate_age) = self.max_rate_age {
+                    if let Some(stale) = rates.iter().position(|rate| rate.age() > max_rate_age) {
+                        let asset_code = if stale == 0 {
+                            request.from.asset_code()
+                        } else {
+                            request.to.asset_code()
+                        };
+                        let age = rates[stale].age();
+                        error!(
+                            "Exchange rate for {} from {} is stale: last updated {:?} ago (max age {:?})",
+                            asset_code, rates[stale].source, age, max_rate_age
+                        );
+                        return Err(RejectBuilder {
+                            code: ErrorCode::T00_INTERNAL_ERROR,
+                            message: format!(
+                                "Exchange rate for {} is stale: last updated {}s ago (max age {}s)",
+                                asset_code,
+                                age.as_secs(),
+                                max_rate_age.as_secs()
+                            )
+                            .as_bytes(),
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                }
                 // Exchange rates are expressed as `base asset / asset`. To calculate the outgoing amount,
                 // we multiply by the incoming asset's rate and divide by the outgoing asset's rate. For example,
                 // if an incoming packet is denominated in an asset worth 1 USD and the outgoing asset is worth
                 // 10 USD, the outgoing amount will be 1/10th of the source amount.
-                rates[0] / rates[1]
+                rates[0].rate.checked_div(&rates[1].rate)
             } else {
-                error!(
-                    "No exchange rates available for assets: {}, {}",
-                    request.from.asset_code(),
-                    request.to.asset_code()
-                );
-                return Err(RejectBuilder {
-                    code: ErrorCode::T00_INTERNAL_ERROR,
-                    message: format!(
-                        "No exchange rate available from asset: {} to: {}",
+                None
+            };
+            let rate = match rate {
+                Some(rate) => rate,
+                None => {
+                    error!(
+                        "No exchange rates available for assets: {}, {}",
                         request.from.asset_code(),
                         request.to.asset_code()
+                    );
+                    return Err(RejectBuilder {
+                        code: ErrorCode::T00_INTERNAL_ERROR,
+                        message: format!(
+                            "No exchange rate available from asset: {} to: {}",
+                            request.from.asset_code(),
+                            request.to.asset_code()
+                        )
+                        .as_bytes(),
+                        triggered_by: Some(&ilp_address),
+                        data: &[],
+                    }
+                    .build());
+                }
+            };
+
+            let fee_schedule = self.store.get_fee_schedule();
+            let fee_policy = fee_schedule.policy_for(&request.from, &request.to);
+            let rate_before_fees = rate;
+            let Conversion {
+                amount: outgoing_amount,
+                rate,
+                flat_fees,
+                remainder: truncated,
+            } = convert_amount(
+                request.prepare.amount(),
+                &rate_before_fees,
+                request.from.asset_scale(),
+                request.to.asset_scale(),
+                &self.spread,
+                fee_policy,
+            );
+
+            let reject = |code, reason: &str| {
+                RejectBuilder {
+                    code,
+                    message: format!(
+                        "Could not convert {} {} (scale {}) to {} (scale {}), {}",
+                        request.prepare.amount(),
+                        request.from.asset_code(),
+                        request.from.asset_scale(),
+                        request.to.asset_code(),
+                        request.to.asset_scale(),
+                        reason,
                     )
                     .as_bytes(),
                     triggered_by: Some(&ilp_address),
                     data: &[],
                 }
-                .build());
+                .build()
             };
-
-            // Apply spread
-            // TODO should this be applied differently for "local" or same-currency packets?
-            let rate = rate * (1.0 - self.spread);
-            let rate = if rate.is_finite() && rate.is_sign_positive() {
-                rate
-            } else {
-                warn!(
-                    "Exchange rate would have been {} based on rate and spread, using 0.0 instead",
-                    rate
-                );
-                0.0
+            let converted_amount = match outgoing_amount {
+                // amount that arrived was too large for us to forward
+                None => return Err(reject(ErrorCode::F08_AMOUNT_TOO_LARGE, "amount too large")),
+                // the fees took up the whole amount
+                Some(0) if flat_fees => {
+                    return Err(reject(
+                        ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                        "amount too small to cover the fees",
+                    ))
+                }
+                Some(amount) => amount,
             };
 
-            // Can we overflow here?
-            let outgoing_amount = (request.prepare.amount() as f64) * rate;
-            let outgoing_amount = outgoing_amount.normalize_scale(ConvertDetails {
-                from: request.from.asset_scale(),
-                to: request.to.asset_scale(),
-            });
-
-            match outgoing_amount {
-                Ok(outgoing_amount) => {
-                    // The conversion succeeded, but the produced f64
-                    // is larger than the maximum value for a u64.
-                    // When it gets cast to a u64, it will end up being 0.
-                    if outgoing_amount != 0.0 && outgoing_amount as u64 == 0 {
-                        let (code, message) = if outgoing_amount < 1.0 {
-                            // user wanted to send a positive value but it got rounded down to 0
-                            (
-                                ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
-                                format!(
-                                    "Could not cast to f64, amount too small: {}",
-                                    outgoing_amount
-                                ),
-                            )
-                        } else {
-                            // amount that arrived was too large for us to forward
-                            (
-                                ErrorCode::F08_AMOUNT_TOO_LARGE,
-                                format!(
-                                    "Could not cast to f64, amount too large: {}",
-                                    outgoing_amount
-                                ),
-                            )
-                        };
-
-                        return Err(RejectBuilder {
-                            code,
-                            message: message.as_bytes(),
-                            triggered_by: Some(&ilp_address),
-                            data: &[],
+            let mut outgoing_amount = converted_amount;
+            if self.track_remainders {
+                let truncated = truncated
+                    .convert(REMAINDER_PRECISION, Rounding::Down)
+                    .unwrap_or(0);
+                if truncated > 0 {
+                    let (from_id, to_id) = (request.from.id(), request.to.id());
+                    match self.store.add_remainder(from_id, to_id, truncated).await {
+                        Ok(units) => {
+                            if let Some(amount) = outgoing_amount.checked_add(units) {
+                                outgoing_amount = amount;
+                                remainder = Some((truncated, units));
+                            } else if let Err(err) = self
+                                .store
+                                .revert_remainder(from_id, to_id, truncated, units)
+                                .await
+                            {
+                                error!("Error reverting remainder: {}", err);
+                            }
                         }
-                        .build());
+                        Err(err) => error!("Error saving remainder: {}", err),
                     }
-                    request.prepare.set_amount(outgoing_amount as u64);
-                    trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
-                        request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
-                        outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
                 }
-                Err(_) => {
-                    // This branch gets executed when the `Convert` trait
-                    // returns an error. Happens due to float
-                    // multiplication overflow .
-                    // (float overflow in Rust produces +inf)
-                    return Err(RejectBuilder {
-                        code: ErrorCode::F08_AMOUNT_TOO_LARGE,
-                        message: format!(
-                            "Could not convert exchange rate from {}:{} to: {}:{}. Got incoming amount: {}",
-                            request.from.asset_code(),
-                            request.from.asset_scale(),
-                            request.to.asset_code(),
-                            request.to.asset_scale(),
-                            request.prepare.amount(),
-                        )
-                        .as_bytes(),
-                        triggered_by: Some(&ilp_address),
-                        data: &[],
+            }
+
+            // user wanted to send a positive value but it got rounded down to 0,
+            // and the remainders accumulated so far do not add up to a unit either
+            if outgoing_amount == 0 && !rate.is_zero() {
+                if let Some((truncated, units)) = remainder {
+                    if let Err(err) = self
+                        .store
+                        .revert_remainder(request.from.id(), request.to.id(), truncated, units)
+                        .await
+                    {
+                        error!("Error reverting remainder: {}", err);
                     }
-                    .build());
                 }
+                return Err(reject(
+                    ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                    "amount too small",
+                ));
+            }
+            if let Some(ref tracker) = self.revenue {
+                revenue = Some((
+                    tracker.clone(),
+                    request.from.clone(),
+                    request.to.clone(),
+                    request.prepare.amount(),
+                    // The units paid out of the remainders were earned on previous packets
+                    converted_amount,
+                    rate_before_fees.scaled(request.from.asset_scale(), request.to.asset_scale()),
+                ));
             }
+            request.prepare.set_amount(outgoing_amount);
+            trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
+                request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
+                outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
         }
 
-        self.next.send_request(request).await
+        let (from_id, to_id) = (request.from.id(), request.to.id());
+        let result = self.next.send_request(request).await;
+        if let (Err(_), Some((truncated, units))) = (&result, remainder) {
+            if let Err(err) = self
+                .store
+                .revert_remainder(from_id, to_id, truncated, units)
+                .await
+            {
+                error!("Error reverting remainder: {}", err);
+            }
+        }
+        if let (Ok(_), Some((tracker, from, to, incoming_amount, outgoing_amount, rate))) =
+            (&result, revenue)
+        {
+            tracker.record(&from, &to, incoming_amount, outgoing_amount, &rate);
+        }
+        result
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
+    use crate::fees::{FeePolicy, FeeRule, FeeSchedule};
+    use crate::revenue::RevenueEntry;
+    use chrono::{DateTime, Utc};
+    use interledger_errors::{
+        AddressStoreError, ExchangeRateStoreError, FeeStoreError, RemainderStoreError,
+        RevenueStoreError,
+    };
     use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder, Reject};
+    use interledger_rates::ExchangeRate;
     use interledger_service::{outgoing_service_fn, Account};
     use once_cell::sync::Lazy;
     use std::collections::HashMap;
@@ -190,70 +399,327 @@ mod tests {
     async fn exchange_rate_ok() {
         // if `to` is worth $2, and `from` is worth 1, then they receive half
         // the amount of units
-        let ret = exchange_rate(200, 1, 1.0, 1, 2.0, 0.0).await;
+        let ret = exchange_rate(200, 1, "1", 1, "2", "0").await;
         assert_eq!(ret.1[0].prepare.amount(), 100);
 
-        let ret = exchange_rate(1_000_000, 1, 3.0, 1, 2.0, 0.0).await;
+        let ret = exchange_rate(1_000_000, 1, "3", 1, "2", "0").await;
         assert_eq!(ret.1[0].prepare.amount(), 1_500_000);
+
+        // rates which cannot be represented exactly as floats
+        let ret = exchange_rate(300, 2, "0.1", 2, "0.3", "0").await;
+        assert_eq!(ret.1[0].prepare.amount(), 100);
+
+        let ret = exchange_rate(1, 0, "1", 9, "1/3", "0").await;
+        assert_eq!(ret.1[0].prepare.amount(), 3_000_000_000);
     }
 
     #[tokio::test]
     async fn exchange_conversion_error() {
-        // rejects f64 that does not fit in u64
-        let ret = exchange_rate(std::u64::MAX, 1, 2.0, 1, 1.0, 0.0).await;
+        // rejects amounts which do not fit in u64
+        let ret = exchange_rate(u64::MAX, 1, "2", 1, "1", "0").await;
         let reject = ret.0.unwrap_err();
         assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
-        assert!(reject
-            .message()
-            .starts_with(b"Could not cast to f64, amount too large"));
+        assert!(reject.message().starts_with(b"Could not convert"));
+        assert!(reject.message().ends_with(b"amount too large"));
 
-        // rejects f64 which gets rounded down to 0
-        let ret = exchange_rate(1, 2, 1.0, 1, 1.0, 0.0).await;
+        // rejects amounts which get rounded down to 0
+        let ret = exchange_rate(1, 2, "1", 1, "1", "0").await;
         let reject = ret.0.unwrap_err();
         assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
-        assert!(reject
-            .message()
-            .starts_with(b"Could not cast to f64, amount too small"));
+        assert!(reject.message().ends_with(b"amount too small"));
 
-        // `Convert` errored
-        let ret = exchange_rate(std::u64::MAX, 1, std::f64::MAX, 255, 1.0, 0.0).await;
+        // huge rates and scales
+        let ret = exchange_rate(u64::MAX, 1, "1e300", 255, "1", "0").await;
         let reject = ret.0.unwrap_err();
         assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
-        assert!(reject.message().starts_with(b"Could not convert"));
+
+        // rejects when the destination asset's rate is zero
+        let ret = exchange_rate(100, 1, "1", 1, "0", "0").await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_rates() {
+        let outgoing = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut store = test_store("1", "2");
+        let old = Utc::now() - chrono::Duration::seconds(120);
+        store
+            .rates
+            .values_mut()
+            .for_each(|rates| rates.1.updated_at = old);
+        let mut service = ExchangeRateService::new(Rate::zero(), store.clone(), outgoing.clone())
+            .with_max_rate_age(Duration::from_secs(60));
+        let reject = service.send_request(request(100)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert!(reject
+            .message()
+            .starts_with(b"Exchange rate for XYZ is stale"));
+
+        // Fresh enough
+        let mut service = ExchangeRateService::new(Rate::zero(), store.clone(), outgoing.clone())
+            .with_max_rate_age(Duration::from_secs(180));
+        assert!(service.send_request(request(100)).await.is_ok());
+
+        // No max age
+        let mut service = ExchangeRateService::new(Rate::zero(), store, outgoing);
+        assert!(service.send_request(request(100)).await.is_ok());
     }
 
     #[tokio::test]
     async fn applies_spread() {
-        let ret = exchange_rate(100, 1, 1.0, 1, 2.0, 0.01).await;
+        let ret = exchange_rate(100, 1, "1", 1, "2", "0.01").await;
         assert_eq!(ret.1[0].prepare.amount(), 49);
 
         // Negative spread is unusual but possible
-        let ret = exchange_rate(200, 1, 1.0, 1, 2.0, -0.01).await;
+        let ret = exchange_rate(200, 1, "1", 1, "2", "-0.01").await;
         assert_eq!(ret.1[0].prepare.amount(), 101);
 
         // Rounds down
-        let ret = exchange_rate(4, 1, 1.0, 1, 2.0, 0.01).await;
-        // this would've been 2, but it becomes 1.99 and gets rounded down to 1
+        let ret = exchange_rate(4, 1, "1", 1, "2", "0.01").await;
+        // this would've been 2, but it becomes 1.98 and gets rounded down to 1
         assert_eq!(ret.1[0].prepare.amount(), 1);
 
+        // No floating point errors: 0.7 * 100 is exactly 70
+        let ret = exchange_rate(100, 1, "1", 1, "1", "0.3").await;
+        assert_eq!(ret.1[0].prepare.amount(), 70);
+
         // Spread >= 1 means the node takes everything
-        let ret = exchange_rate(10_000_000_000, 1, 1.0, 1, 2.0, 1.0).await;
+        let ret = exchange_rate(10_000_000_000, 1, "1", 1, "2", "1").await;
         assert_eq!(ret.1[0].prepare.amount(), 0);
 
         // Need to catch when spread > 1
-        let ret = exchange_rate(10_000_000_000, 1, 1.0, 1, 2.0, 2.0).await;
+        let ret = exchange_rate(10_000_000_000, 1, "1", 1, "2", "2").await;
         assert_eq!(ret.1[0].prepare.amount(), 0);
     }
 
+    #[tokio::test]
+    async fn applies_fee_rules() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            requests_clone
+                .lock()
+                .unwrap()
+                .push(request.prepare.amount());
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut store = test_store("1", "2");
+        store.fees = Arc::new(FeeSchedule {
+            rules: vec![
+                FeeRule {
+                    to_asset: Some("XYZ".to_string()),
+                    policy: FeePolicy {
+                        spread: Some(Rate::from_str("0.1").unwrap()),
+                        fixed_fee: 2,
+                        min_fee: 20,
+                    },
+                    ..FeeRule::default()
+                },
+                FeeRule {
+                    same_asset: Some(true),
+                    policy: FeePolicy {
+                        spread: Some(Rate::zero()),
+                        ..FeePolicy::default()
+                    },
+                    ..FeeRule::default()
+                },
+            ],
+        });
+        let mut service =
+            ExchangeRateService::new(Rate::from_str("0.5").unwrap(), store, outgoing.clone());
+
+        // 1000 ABC converts to 500 XYZ, minus 10% and 2 units
+        service.send_request(request(1000)).await.unwrap();
+        // 100 ABC converts to 50 XYZ, the minimum fee of 20 applies instead of 5 + 2
+        service.send_request(request(100)).await.unwrap();
+        assert_eq!(*requests.lock().unwrap(), vec![448, 30]);
+
+        // Rejects packets which do not cover the fees
+        let reject = service.send_request(request(40)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert!(reject
+            .message()
+            .ends_with(b"amount too small to cover the fees"));
+
+        // Same-currency packets are exempt from the global spread
+        let mut same_asset = request(1000);
+        same_asset.to.asset_code = "ABC".to_string();
+        service.send_request(same_asset).await.unwrap();
+        assert_eq!(requests.lock().unwrap()[2], 1000);
+    }
+
+    #[tokio::test]
+    async fn records_revenue() {
+        let store = test_store("1", "2");
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            if request.prepare.amount() > 100 {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            } else {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"hello!",
+                }
+                .build())
+            }
+        });
+        let tracker =
+            RevenueTracker::new(store.clone(), "XYZ".to_string(), 2, Duration::from_secs(60))
+                .with_flush_interval(Duration::from_millis(5));
+        let mut service =
+            ExchangeRateService::new(Rate::from_str("0.01").unwrap(), store.clone(), outgoing)
+                .with_revenue(tracker);
+
+        // 200 ABC are worth 100 XYZ, 1% of which are kept
+        service.send_request(request(200)).await.unwrap();
+        service.send_request(request(200)).await.unwrap();
+        // Rejected packets earn nothing
+        service.send_request(request(2000)).await.unwrap_err();
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+
+        // The fees of both packets are written at once
+        let revenue = store.revenue.lock().unwrap();
+        assert_eq!(revenue.len(), 1);
+        assert_eq!(revenue[0].asset_code, "ABC");
+        assert_eq!(revenue[0].amount, 4);
+        // 0.2 ABC are worth 0.1 XYZ
+        assert_eq!(revenue[0].reporting_asset, "XYZ");
+        assert_eq!(revenue[0].reporting_amount, 20);
+        assert_eq!(revenue[0].packets, 2);
+        assert_eq!(revenue[0].bucket_start.timestamp() % 60, 0);
+    }
+
+    #[tokio::test]
+    async fn pays_out_accumulated_remainders() {
+        let amounts = Arc::new(Mutex::new(Vec::new()));
+        let amounts_clone = amounts.clone();
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            let mut amounts = amounts_clone.lock().unwrap();
+            amounts.push(request.prepare.amount());
+            // Reject the first packet which includes a paid out remainder
+            if amounts.iter().filter(|amount| **amount == 2).count() == 1
+                && request.prepare.amount() == 2
+            {
+                Err(RejectBuilder {
+                    code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            } else {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let store = test_store("1", "2.5");
+        let mut service = ExchangeRateService::new(Rate::zero(), store.clone(), outgoing)
+            .with_remainder_tracking();
+
+        // Each packet of 3 ABC is worth 1.2 XYZ
+        for _ in 0..4 {
+            service.send_request(request(3)).await.unwrap();
+        }
+        assert_eq!(*store.remainders.lock().unwrap(), 800_000_000);
+        // The rejected packet's remainder is reverted and its unit paid out again
+        service.send_request(request(3)).await.unwrap_err();
+        assert_eq!(*store.remainders.lock().unwrap(), 800_000_000);
+        service.send_request(request(3)).await.unwrap();
+        assert_eq!(*store.remainders.lock().unwrap(), 0);
+        assert_eq!(*amounts.lock().unwrap(), vec![1, 1, 1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn forwards_small_packets_paid_out_of_remainders() {
+        let amounts = Arc::new(Mutex::new(Vec::new()));
+        let amounts_clone = amounts.clone();
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            amounts_clone.lock().unwrap().push(request.prepare.amount());
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let store = test_store("1", "2.5");
+        let mut service = ExchangeRateService::new(Rate::zero(), store.clone(), outgoing)
+            .with_remainder_tracking();
+
+        // 1 ABC is worth 0.4 XYZ, which is not enough on its own
+        let reject = service.send_request(request(1)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert_eq!(*store.remainders.lock().unwrap(), 0);
+
+        // Each packet of 3 ABC is worth 1.2 XYZ
+        for _ in 0..4 {
+            service.send_request(request(3)).await.unwrap();
+        }
+        assert_eq!(*store.remainders.lock().unwrap(), 800_000_000);
+        // 0.8 + 0.4 XYZ add up to a unit
+        service.send_request(request(1)).await.unwrap();
+        assert_eq!(*store.remainders.lock().unwrap(), 200_000_000);
+        assert_eq!(*amounts.lock().unwrap(), vec![1, 1, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn records_revenue_without_paid_out_remainders() {
+        let store = test_store("1", "2.5");
+        let outgoing = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let tracker =
+            RevenueTracker::new(store.clone(), "XYZ".to_string(), 1, Duration::from_secs(60))
+                .with_flush_interval(Duration::from_millis(5));
+        let mut service =
+            ExchangeRateService::new(Rate::from_str("0.01").unwrap(), store.clone(), outgoing)
+                .with_revenue(tracker)
+                .with_remainder_tracking();
+
+        // 100 ABC are worth 40 XYZ, 39.6 of which are forwarded. The second packet
+        // also pays out the unit the truncated remainders add up to
+        service.send_request(request(100)).await.unwrap();
+        service.send_request(request(100)).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+
+        // Each packet earned 100 - 39 / 0.4 ABC (rounded down)
+        let revenue = store.revenue.lock().unwrap();
+        assert_eq!(revenue.len(), 1);
+        assert_eq!(revenue[0].amount, 4);
+        assert_eq!(revenue[0].packets, 2);
+    }
+
     // Instantiates an exchange rate service and returns the fulfill/reject
     // packet and the outgoing request after performing an asset conversion
     async fn exchange_rate(
         amount: u64,
         scale1: u8,
-        rate1: f64,
+        rate1: &str,
         scale2: u8,
-        rate2: f64,
-        spread: f64,
+        rate2: &str,
+        spread: &str,
     ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
         let requests = Arc::new(Mutex::new(Vec::new()));
         let requests_clone = requests.clone();
@@ -266,26 +732,31 @@ mod tests {
             .build())
         });
         let mut service = test_service(rate1, rate2, spread, outgoing);
-        let result = service
-            .send_request(OutgoingRequest {
-                from: TestAccount::new("ABC".to_owned(), scale1),
-                to: TestAccount::new("XYZ".to_owned(), scale2),
-                original_amount: amount,
-                prepare: PrepareBuilder {
-                    destination: Address::from_str("example.destination").unwrap(),
-                    amount,
-                    expires_at: SystemTime::now(),
-                    execution_condition: &[1; 32],
-                    data: b"hello",
-                }
-                .build(),
-            })
-            .await;
+        let mut request = request(amount);
+        request.from.asset_scale = scale1;
+        request.to.asset_scale = scale2;
+        let result = service.send_request(request).await;
 
         let reqs = requests.lock().unwrap();
         (result, reqs.clone())
     }
 
+    fn request(amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount::new("ABC".to_owned(), 1),
+            to: TestAccount::new("XYZ".to_owned(), 1),
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now(),
+                execution_condition: &[1; 32],
+                data: b"hello",
+            }
+            .build(),
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         ilp_address: Address,
@@ -343,20 +814,87 @@ mod tests {
 
     #[derive(Debug, Clone)]
     struct TestStore {
-        rates: HashMap<Vec<String>, (f64, f64)>,
+        rates: HashMap<Vec<String>, (ExchangeRate, ExchangeRate)>,
+        fees: Arc<FeeSchedule>,
+        revenue: Arc<Mutex<Vec<RevenueEntry>>>,
+        remainders: Arc<Mutex<i64>>,
     }
 
+    // The test accounts' IDs are random, so the remainders of every pair of accounts are added up
+    #[async_trait]
+    impl RemainderStore for TestStore {
+        async fn add_remainder(
+            &self,
+            _from_account: Uuid,
+            _to_account: Uuid,
+            remainder: u64,
+        ) -> Result<u64, RemainderStoreError> {
+            let mut remainders = self.remainders.lock().unwrap();
+            *remainders += remainder as i64;
+            let units = *remainders / REMAINDER_PRECISION as i64;
+            *remainders -= units * REMAINDER_PRECISION as i64;
+            Ok(units as u64)
+        }
+
+        async fn revert_remainder(
+            &self,
+            _from_account: Uuid,
+            _to_account: Uuid,
+            remainder: u64,
+            units: u64,
+        ) -> Result<(), RemainderStoreError> {
+            *self.remainders.lock().unwrap() +=
+                (units * REMAINDER_PRECISION) as i64 - remainder as i64;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl RevenueStore for TestStore {
+        async fn add_revenue(&self, revenue: Vec<RevenueEntry>) -> Result<(), RevenueStoreError> {
+            self.revenue.lock().unwrap().extend(revenue);
+            Ok(())
+        }
+
+        async fn remove_revenue_before(
+            &self,
+            _before: DateTime<Utc>,
+        ) -> Result<(), RevenueStoreError> {
+            Ok(())
+        }
+
+        async fn get_revenue(
+            &self,
+            _since: Option<DateTime<Utc>>,
+            _until: Option<DateTime<Utc>>,
+        ) -> Result<Vec<RevenueEntry>, RevenueStoreError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
+    impl FeeStore for TestStore {
+        fn get_fee_schedule(&self) -> Arc<FeeSchedule> {
+            self.fees.clone()
+        }
+
+        async fn set_fee_schedule(&self, _schedule: FeeSchedule) -> Result<(), FeeStoreError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
     impl ExchangeRateStore for TestStore {
         fn get_exchange_rates(
             &self,
             asset_codes: &[&str],
-        ) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
             let mut ret = Vec::new();
             let key = vec![asset_codes[0].to_owned(), asset_codes[1].to_owned()];
             let v = self.rates.get(&key);
             if let Some(v) = v {
-                ret.push(v.0);
-                ret.push(v.1);
+                ret.push(v.0.clone());
+                ret.push(v.1.clone());
             } else {
                 return Err(ExchangeRateStoreError::PairNotFound {
                     from: key[0].clone(),
@@ -366,28 +904,41 @@ mod tests {
             Ok(ret)
         }
 
-        fn set_exchange_rates(
+        async fn set_exchange_rates(
             &self,
-            _rates: HashMap<String, f64>,
+            _rates: HashMap<String, ExchangeRate>,
         ) -> Result<(), ExchangeRateStoreError> {
             unimplemented!()
         }
 
-        fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        fn get_all_exchange_rates(
+            &self,
+        ) -> Result<HashMap<String, ExchangeRate>, ExchangeRateStoreError> {
             unimplemented!()
         }
     }
 
-    fn test_store(rate1: f64, rate2: f64) -> TestStore {
+    fn test_store(rate1: &str, rate2: &str) -> TestStore {
         let mut rates = HashMap::new();
-        rates.insert(vec!["ABC".to_owned(), "XYZ".to_owned()], (rate1, rate2));
-        TestStore { rates }
+        rates.insert(
+            vec!["ABC".to_owned(), "XYZ".to_owned()],
+            (
+                ExchangeRate::new(Rate::from_str(rate1).unwrap(), "test"),
+                ExchangeRate::new(Rate::from_str(rate2).unwrap(), "test"),
+            ),
+        );
+        TestStore {
+            rates,
+            fees: Arc::new(FeeSchedule::default()),
+            revenue: Arc::new(Mutex::new(Vec::new())),
+            remainders: Arc::new(Mutex::new(0)),
+        }
     }
 
     fn test_service(
-        rate1: f64,
-        rate2: f64,
-        spread: f64,
+        rate1: &str,
+        rate2: &str,
+        spread: &str,
         handler: impl OutgoingService<TestAccount> + Clone + Send + Sync,
     ) -> ExchangeRateService<
         TestStore,
@@ -395,6 +946,6 @@ mod tests {
         TestAccount,
     > {
         let store = test_store(rate1, rate2);
-        ExchangeRateService::new(spread, store, handler)
+        ExchangeRateService::new(Rate::from_str(spread).unwrap(), store, handler)
     }
 }
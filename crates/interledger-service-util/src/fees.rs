@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use interledger_errors::FeeStoreError;
+use interledger_rates::Rate;
+use interledger_service::{Account, Username};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{str::FromStr, sync::Arc};
+
+// Usernames only deserialize from borrowed strings, which is not always possible
+// when the rule is deserialized along with its flattened policy
+fn deserialize_username<'de, D>(deserializer: D) -> Result<Option<Username>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|username| Username::from_str(&username).map_err(de::Error::custom))
+        .transpose()
+}
+
+/// Fees charged on the packets matching a [`FeeRule`](./struct.FeeRule.html)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// Spread, as a fraction of the converted amount, charged instead of the node's global spread
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<Rate>,
+    /// Fee charged on every packet on top of the spread, in units of the outgoing account's asset
+    #[serde(default)]
+    pub fixed_fee: u64,
+    /// Minimum total fee charged on every packet, in units of the outgoing account's asset
+    #[serde(default)]
+    pub min_fee: u64,
+}
+
+impl FeePolicy {
+    /// Returns true if the policy charges fixed or minimum fees
+    pub fn has_flat_fees(&self) -> bool {
+        self.fixed_fee > 0 || self.min_fee > 0
+    }
+}
+
+/// A fee policy along with the packets it applies to.
+/// A rule applies to a packet if every one of its criteria which is set matches it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRule {
+    /// Username of the account the packet is received from
+    #[serde(
+        default,
+        deserialize_with = "deserialize_username",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub from_account: Option<Username>,
+    /// Username of the account the packet is forwarded to
+    #[serde(
+        default,
+        deserialize_with = "deserialize_username",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_account: Option<Username>,
+    /// Asset code of the account the packet is received from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_asset: Option<String>,
+    /// Asset code of the account the packet is forwarded to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_asset: Option<String>,
+    /// Whether the packet is received and forwarded in the same asset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_asset: Option<bool>,
+    #[serde(flatten)]
+    pub policy: FeePolicy,
+}
+
+impl FeeRule {
+    fn matches<A: Account>(&self, from: &A, to: &A) -> bool {
+        let same_asset = from.asset_code() == to.asset_code();
+        self.from_account
+            .iter()
+            .all(|username| username == from.username())
+            && self
+                .to_account
+                .iter()
+                .all(|username| username == to.username())
+            && self.from_asset.iter().all(|code| code == from.asset_code())
+            && self.to_asset.iter().all(|code| code == to.asset_code())
+            && self.same_asset.iter().all(|same| *same == same_asset)
+    }
+
+    /// Number of criteria set on the rule
+    fn specificity(&self) -> usize {
+        [
+            self.from_account.is_some(),
+            self.to_account.is_some(),
+            self.from_asset.is_some(),
+            self.to_asset.is_some(),
+            self.same_asset.is_some(),
+        ]
+        .iter()
+        .filter(|set| **set)
+        .count()
+    }
+}
+
+/// The fee rules of the node. Packets which no rule applies to are only charged the global spread
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FeeSchedule {
+    pub rules: Vec<FeeRule>,
+}
+
+impl FeeSchedule {
+    /// Returns the policy of the most specific rule which applies to a packet
+    /// received from and forwarded to the given accounts.
+    /// If several rules are equally specific, the first one listed wins.
+    pub fn policy_for<A: Account>(&self, from: &A, to: &A) -> Option<&FeePolicy> {
+        // max_by_key returns the last of the maximum elements
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(from, to))
+            .max_by_key(|rule| rule.specificity())
+            .map(|rule| &rule.policy)
+    }
+}
+
+/// Store of the fee schedule applied to the packets forwarded by the node
+#[async_trait]
+pub trait FeeStore {
+    /// Returns the fee schedule currently in effect. It is kept in memory
+    /// so that it can be used synchronously while packets are processed
+    fn get_fee_schedule(&self) -> Arc<FeeSchedule>;
+
+    /// Saves a new fee schedule, replacing the previous one
+    async fn set_fee_schedule(&self, schedule: FeeSchedule) -> Result<(), FeeStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::Address;
+    use once_cell::sync::Lazy;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    static ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Username, &'static str);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &self.0
+        }
+
+        fn asset_code(&self) -> &str {
+            self.1
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &ADDRESS
+        }
+    }
+
+    fn account(username: &str, asset_code: &'static str) -> TestAccount {
+        TestAccount(Username::from_str(username).unwrap(), asset_code)
+    }
+
+    #[test]
+    fn picks_most_specific_rule() {
+        let schedule: FeeSchedule = serde_json::from_value(json!([
+            {"same_asset": true, "spread": "0"},
+            {"from_asset": "XRP", "to_asset": "USD", "spread": "0.02", "min_fee": 10},
+            {"to_asset": "USD", "spread": "0.01"},
+            {"to_asset": "USD", "spread": "0.03"},
+            {"from_account": "alice", "to_asset": "USD", "fixed_fee": 1},
+        ]))
+        .unwrap();
+        let alice = account("alice", "XRP");
+        let bob = account("bob", "XRP");
+        let charlie = account("charlie", "USD");
+        let dave = account("dave", "EUR");
+
+        assert_eq!(
+            schedule.policy_for(&bob, &alice).unwrap().spread,
+            Some(Rate::zero())
+        );
+        assert_eq!(schedule.policy_for(&bob, &charlie).unwrap().min_fee, 10);
+        // Equally specific rules are evaluated in order
+        assert_eq!(
+            schedule.policy_for(&dave, &charlie).unwrap().spread,
+            Some(Rate::from_str("0.01").unwrap())
+        );
+        // The rule matching the assets is listed before the one matching the account
+        assert_eq!(schedule.policy_for(&alice, &charlie).unwrap().min_fee, 10);
+        assert!(schedule.policy_for(&charlie, &dave).is_none());
+    }
+}
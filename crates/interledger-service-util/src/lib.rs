@@ -2,6 +2,8 @@
 //!
 //! Miscellaneous, small Interledger Services.
 
+/// Alerts raised when account balances cross their configured thresholds
+mod balance_alerts;
 /// Balance tracking service
 mod balance_service;
 /// Service which implements the echo protocol
@@ -11,22 +13,34 @@ mod exchange_rates_service;
 /// Service responsible for shortening the expiry time of packets,
 /// to take into account for network latency
 mod expiry_shortener_service;
+/// Fees charged on the packets forwarded by the node
+mod fees;
 /// Service responsible for capping the amount an account can send in a packet
 mod max_packet_amount_service;
 /// Service responsible for capping the amount of packets and amount in packets an account can send
 mod rate_limit_service;
+/// Accumulation of the remainders truncated when converting packets
+mod remainders;
+/// Accounting of the fees earned by the node
+mod revenue;
 /// Service responsible for checking that packets are not expired and that prepare packets' fulfillment conditions
 /// match the fulfillment inside the incoming fulfills
 mod validator_service;
 
+pub use self::balance_alerts::{
+    BalanceAlert, BalanceAlertAccount, BalanceAlertKind, BalanceAlertStore, BalanceAlerts,
+};
 pub use self::balance_service::{BalanceService, BalanceStore};
 pub use self::echo_service::EchoService;
-pub use self::exchange_rates_service::ExchangeRateService;
+pub use self::exchange_rates_service::{convert_amount, Conversion, ExchangeRateService};
 pub use self::expiry_shortener_service::{
     ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
 };
+pub use self::fees::{FeePolicy, FeeRule, FeeSchedule, FeeStore};
 pub use self::max_packet_amount_service::{MaxPacketAmountAccount, MaxPacketAmountService};
 pub use self::rate_limit_service::{
     RateLimitAccount, RateLimitError, RateLimitService, RateLimitStore,
 };
+pub use self::remainders::{RemainderStore, REMAINDER_PRECISION};
+pub use self::revenue::{captured_fee, RevenueEntry, RevenueStore, RevenueTracker};
 pub use self::validator_service::ValidatorService;
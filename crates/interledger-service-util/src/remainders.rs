@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+use interledger_errors::RemainderStoreError;
+use uuid::Uuid;
+
+/// Number of parts each unit of an asset is divided into when accumulating remainders,
+/// i.e. remainders are kept with a precision of a billionth of a unit
+pub const REMAINDER_PRECISION: u64 = 1_000_000_000;
+
+/// Store trait which accumulates the fractions of a unit truncated when converting
+/// the packets forwarded from one account to another, so that they can be paid out
+/// once they add up to whole units
+#[async_trait]
+pub trait RemainderStore {
+    /// Adds the remainder left over by converting a packet from `from_account` to
+    /// `to_account`, in [`REMAINDER_PRECISION`](./constant.REMAINDER_PRECISION.html)ths
+    /// of a unit of the outgoing account's asset. Then takes out and returns the whole
+    /// units accumulated for that pair of accounts, which are owed to the outgoing account
+    async fn add_remainder(
+        &self,
+        from_account: Uuid,
+        to_account: Uuid,
+        remainder: u64,
+    ) -> Result<u64, RemainderStoreError>;
+
+    /// Reverts a call to `add_remainder`, given the remainder which was added and the units
+    /// which were taken out, e.g. because the packet they were added to was rejected
+    async fn revert_remainder(
+        &self,
+        from_account: Uuid,
+        to_account: Uuid,
+        remainder: u64,
+        units: u64,
+    ) -> Result<(), RemainderStoreError>;
+}
@@ -0,0 +1,290 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use interledger_errors::RevenueStoreError;
+use interledger_rates::{ExchangeRateStore, Rate, Rounding};
+use interledger_service::{Account, Username};
+use metrics::{labels, recorder, Key};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, mem, sync::Arc, time::Duration};
+use tracing::{debug, error, trace};
+
+/// How long the fees are accumulated in memory before they are written to the store
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fees earned by the node on the packets forwarded between two accounts during a period of time
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevenueEntry {
+    /// Start of the period the fees were earned in
+    pub bucket_start: DateTime<Utc>,
+    /// Username of the account the packets were received from
+    pub from_account: Username,
+    /// Username of the account the packets were forwarded to
+    pub to_account: Username,
+    /// Asset of the incoming packets, which the fees are denominated in
+    pub asset_code: String,
+    /// Scale of `amount`
+    pub asset_scale: u8,
+    /// Fees earned, in units of the incoming asset
+    pub amount: u64,
+    /// Asset the fees are reported in
+    pub reporting_asset: String,
+    /// Scale of `reporting_amount`
+    pub reporting_scale: u8,
+    /// Fees earned, converted to the reporting asset at the rates in effect
+    /// when each packet was forwarded. Fees which could not be converted are left out
+    pub reporting_amount: u64,
+    /// Number of fulfilled packets the fees were earned on
+    pub packets: u64,
+}
+
+/// Store trait which accumulates the fees earned by the node so that they can be reported
+#[async_trait]
+pub trait RevenueStore {
+    /// Adds the fees of each entry to the totals of its period, accounts and assets
+    async fn add_revenue(&self, revenue: Vec<RevenueEntry>) -> Result<(), RevenueStoreError>;
+
+    /// Removes the totals of the periods starting before the given time
+    async fn remove_revenue_before(&self, before: DateTime<Utc>) -> Result<(), RevenueStoreError>;
+
+    /// Loads the totals of the periods starting within the given range, oldest first
+    async fn get_revenue(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<RevenueEntry>, RevenueStoreError>;
+}
+
+/// Returns the fee, in units of the incoming asset, captured by forwarding `outgoing_amount`
+/// in exchange for `incoming_amount`, given the exchange rate (adjusted for the assets' scales)
+/// before any fee was applied. The fee is rounded down.
+pub fn captured_fee(incoming_amount: u64, outgoing_amount: u64, rate: &Rate) -> Option<u64> {
+    let cost = Rate::one()
+        .checked_div(rate)?
+        .convert(outgoing_amount, Rounding::Up)?;
+    Some(incoming_amount.saturating_sub(cost))
+}
+
+/// Identifies the totals a packet's fee is added to: the start of the period (in ms),
+/// the usernames of the accounts and the incoming asset's code and scale
+type RevenueKey = (i64, String, String, String, u8);
+
+/// Accumulates the fees captured on the fulfilled packets.
+///
+/// Fees are counted in the `revenue.amount` and `revenue.reporting_amount`
+/// metrics and added to the store's totals of the period they were earned in.
+/// The totals are accumulated in memory and written to the store in batches,
+/// at most once per flush interval.
+#[derive(Clone)]
+pub struct RevenueTracker<S> {
+    store: S,
+    reporting_asset: String,
+    reporting_scale: u8,
+    bucket_interval: Duration,
+    flush_interval: Duration,
+    retention: Option<Duration>,
+    pending: Arc<Mutex<HashMap<RevenueKey, RevenueEntry>>>,
+}
+
+impl<S> RevenueTracker<S>
+where
+    S: RevenueStore + ExchangeRateStore + Clone + Send + Sync + 'static,
+{
+    /// Simple constructor. Fees are reported in `reporting_asset` and
+    /// accumulated in periods of `bucket_interval`
+    pub fn new(
+        store: S,
+        reporting_asset: String,
+        reporting_scale: u8,
+        bucket_interval: Duration,
+    ) -> Self {
+        RevenueTracker {
+            store,
+            reporting_asset,
+            reporting_scale,
+            bucket_interval,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            retention: None,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sets how long the fees are accumulated in memory before they are written to the store
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Removes the totals of the periods which started longer than `retention` ago
+    /// whenever new fees are written to the store
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Records the fee captured on a fulfilled packet, given the exchange rate
+    /// (adjusted for the assets' scales) before any fee was applied
+    pub fn record<A: Account>(
+        &self,
+        from: &A,
+        to: &A,
+        incoming_amount: u64,
+        outgoing_amount: u64,
+        rate: &Rate,
+    ) {
+        let amount = match captured_fee(incoming_amount, outgoing_amount, rate) {
+            Some(amount) if amount > 0 => amount,
+            _ => return,
+        };
+        let reporting_amount = self.to_reporting_asset(from, amount);
+        trace!(
+            "Earned {} {} (scale {}) on packet from {} to {}",
+            amount,
+            from.asset_code(),
+            from.asset_scale(),
+            from.username(),
+            to.username()
+        );
+
+        recorder().increment_counter(
+            Key::from_name_and_labels(
+                "revenue.amount",
+                labels!(
+                    "from" => from.username().to_string(),
+                    "to" => to.username().to_string(),
+                    "asset" => from.asset_code().to_string()
+                ),
+            ),
+            amount,
+        );
+        if let Some(reporting_amount) = reporting_amount {
+            recorder().increment_counter(
+                Key::from_name_and_labels(
+                    "revenue.reporting_amount",
+                    labels!(
+                        "from" => from.username().to_string(),
+                        "to" => to.username().to_string(),
+                        "asset" => self.reporting_asset.clone()
+                    ),
+                ),
+                reporting_amount,
+            );
+        }
+
+        let bucket_start = self.bucket_start(Utc::now());
+        let key = (
+            bucket_start.timestamp_millis(),
+            from.username().to_string(),
+            to.username().to_string(),
+            from.asset_code().to_string(),
+            from.asset_scale(),
+        );
+        let mut pending = self.pending.lock();
+        // The first fee since the last write schedules the next one
+        if pending.is_empty() {
+            let tracker = self.clone();
+            tokio::spawn(async move {
+                tokio::time::delay_for(tracker.flush_interval).await;
+                tracker.flush().await;
+            });
+        }
+        let entry = pending.entry(key).or_insert_with(|| RevenueEntry {
+            bucket_start,
+            from_account: from.username().clone(),
+            to_account: to.username().clone(),
+            asset_code: from.asset_code().to_string(),
+            asset_scale: from.asset_scale(),
+            amount: 0,
+            reporting_asset: self.reporting_asset.clone(),
+            reporting_scale: self.reporting_scale,
+            reporting_amount: 0,
+            packets: 0,
+        });
+        entry.amount = entry.amount.saturating_add(amount);
+        entry.reporting_amount = entry
+            .reporting_amount
+            .saturating_add(reporting_amount.unwrap_or(0));
+        entry.packets += 1;
+    }
+
+    /// Writes the accumulated fees to the store and removes the expired periods
+    async fn flush(&self) {
+        let entries: Vec<RevenueEntry> =
+            mem::take(&mut *self.pending.lock()).into_values().collect();
+        if !entries.is_empty() {
+            let num_entries = entries.len();
+            match self.store.add_revenue(entries).await {
+                Ok(_) => trace!("Saved {} revenue entries", num_entries),
+                Err(err) => error!("Error saving revenue: {}", err),
+            }
+        }
+        if let Some(retention) = self.retention {
+            let expired = match chrono::Duration::from_std(retention)
+                .ok()
+                .and_then(|retention| Utc::now().checked_sub_signed(retention))
+            {
+                Some(expired) => self.bucket_start(expired),
+                None => return,
+            };
+            if let Err(err) = self.store.remove_revenue_before(expired).await {
+                error!("Error removing expired revenue: {}", err);
+            }
+        }
+    }
+
+    fn to_reporting_asset<A: Account>(&self, from: &A, amount: u64) -> Option<u64> {
+        let rate = if from.asset_code() == self.reporting_asset {
+            Rate::one()
+        } else {
+            let rates = self
+                .store
+                .get_exchange_rates(&[from.asset_code(), &self.reporting_asset])
+                .ok();
+            match rates.and_then(|rates| rates[0].rate.checked_div(&rates[1].rate)) {
+                Some(rate) => rate,
+                None => {
+                    debug!(
+                        "No exchange rate available to report revenue in {} in {}",
+                        from.asset_code(),
+                        self.reporting_asset
+                    );
+                    return None;
+                }
+            }
+        };
+        rate.scaled(from.asset_scale(), self.reporting_scale)
+            .convert(amount, Rounding::Down)
+    }
+
+    fn bucket_start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let interval = self.bucket_interval.as_millis().max(1) as i64;
+        let millis = time.timestamp_millis();
+        Utc.timestamp_millis_opt(millis - millis.rem_euclid(interval))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn computes_captured_fee() {
+        // 1:2 rate with 1% spread
+        assert_eq!(captured_fee(100, 198, &Rate::from(2)), Some(1));
+        // Rounds the fee down
+        assert_eq!(
+            captured_fee(100, 33, &Rate::from_str("1/3").unwrap()),
+            Some(1)
+        );
+        assert_eq!(
+            captured_fee(100, 32, &Rate::from_str("1/3").unwrap()),
+            Some(4)
+        );
+        assert_eq!(captured_fee(100, 100, &Rate::one()), Some(0));
+        // Negative spreads are a loss, not counted as revenue
+        assert_eq!(captured_fee(100, 101, &Rate::one()), Some(0));
+        assert_eq!(captured_fee(100, 0, &Rate::zero()), None);
+    }
+}
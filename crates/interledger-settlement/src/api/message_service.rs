@@ -54,7 +54,7 @@ where
                     .client
                     .send_message(
                         request.from.id(),
-                        settlement_engine_details.url,
+                        settlement_engine_details,
                         request.prepare.data().to_vec(),
                     )
                     .map_err(move |error| {
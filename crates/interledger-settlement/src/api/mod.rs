@@ -10,4 +10,4 @@ mod fixtures;
 mod test_helpers;
 
 pub use message_service::SettlementMessageService;
-pub use node_api::create_settlements_filter;
+pub use node_api::{create_authenticated_settlements_filter, create_settlements_filter};
@@ -16,11 +16,13 @@ use interledger_packet::PrepareBuilder;
 use interledger_service::{Account, AccountStore, OutgoingRequest, OutgoingService};
 use num_bigint::BigUint;
 use num_traits::cast::ToPrimitive;
+use ring::constant_time::verify_slices_are_equal;
+use secrecy::{ExposeSecret, SecretString};
 use std::{
     str::{self, FromStr},
     time::{Duration, SystemTime},
 };
-use tracing::error;
+use tracing::{error, warn};
 use uuid::Uuid;
 use warp::{self, reject::Rejection, Filter};
 
@@ -30,10 +32,73 @@ static PEER_PROTOCOL_CONDITION: [u8; 32] = [
     110, 226, 51, 179, 144, 42, 89, 29, 13, 95, 41, 37,
 ];
 
+/// Rejects the request if it does not present the authorization token of the account's
+/// settlement engine, or the node-wide token if the engine has none, as its bearer token.
+/// The check is opt-in: if neither token is configured, the request is accepted.
+/// This is checked before the idempotency cache so unauthorized callers cannot
+/// read the responses of previous calls
+async fn authorize_engine<S, A>(
+    store: &S,
+    account_id: &str,
+    authorization: Option<String>,
+    default_auth_token: Option<&SecretString>,
+) -> Result<(), ApiError>
+where
+    S: AccountStore<Account = A>,
+    A: SettlementAccount,
+{
+    // Invalid or unknown accounts are rejected by the handlers themselves
+    let account_id = match Uuid::from_str(account_id) {
+        Ok(account_id) => account_id,
+        Err(_) => return Ok(()),
+    };
+    let account = match store.get_accounts(vec![account_id]).await {
+        Ok(mut accounts) => accounts.remove(0),
+        Err(AccountStoreError::AccountNotFound(_)) | Err(AccountStoreError::WrongLength { .. }) => {
+            return Ok(())
+        }
+        // Without the account we cannot tell whether the engine must authenticate
+        Err(err) => {
+            let err = ApiError::internal_server_error().detail(format!(
+                "Error loading account {} to check the settlement engine authorization: {}",
+                account_id, err
+            ));
+            error!("{}", err);
+            return Err(err);
+        }
+    };
+    let auth_token = match account
+        .settlement_engine_details()
+        .and_then(|details| details.auth_token)
+        .or_else(|| default_auth_token.cloned())
+    {
+        Some(auth_token) => auth_token,
+        None => return Ok(()),
+    };
+    let expected = format!("Bearer {}", auth_token.expose_secret());
+    let authorized = authorization
+        .map(|authorization| {
+            verify_slices_are_equal(authorization.as_bytes(), expected.as_bytes()).is_ok()
+        })
+        .unwrap_or(false);
+    if authorized {
+        Ok(())
+    } else {
+        let err = ApiError::unauthorized().detail(format!(
+            "Invalid settlement engine authorization for account {}",
+            account_id
+        ));
+        error!("{}", err);
+        Err(err)
+    }
+}
+
 /// Makes an idempotent call to [`do_receive_settlement`](./fn.do_receive_settlement.html)
 /// Returns Status Code 201
 async fn receive_settlement<S, A>(
     account_id: String,
+    authorization: Option<String>,
+    auth_token: Option<SecretString>,
     idempotency_key: Option<String>,
     quantity: Quantity,
     store: S,
@@ -49,6 +114,7 @@ where
         + 'static,
     A: SettlementAccount + Account + Send + Sync + 'static,
 {
+    authorize_engine(&store, &account_id, authorization, auth_token.as_ref()).await?;
     let input = format!("{}{:?}", account_id, quantity);
     let input_hash = get_hash_of(input.as_ref());
 
@@ -73,6 +139,8 @@ where
 /// Returns Status Code 201
 async fn send_message<S, A, O>(
     account_id: String,
+    authorization: Option<String>,
+    auth_token: Option<SecretString>,
     idempotency_key: Option<String>,
     message: Bytes,
     store: S,
@@ -90,6 +158,7 @@ where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: SettlementAccount + Account + Send + Sync + 'static,
 {
+    authorize_engine(&store, &account_id, authorization, auth_token.as_ref()).await?;
     let input = format!("{}{:?}", account_id, message);
     let input_hash = get_hash_of(input.as_ref());
 
@@ -114,6 +183,9 @@ where
 /// 1. receives messages about incoming settlements from the engine
 /// 1. sends messages from the connector's engine to the peer's
 ///    message service which are sent to the peer's engine
+///
+/// Only the engines configured with an authorization token must authenticate,
+/// see [`create_authenticated_settlements_filter`](./fn.create_authenticated_settlements_filter.html)
 pub fn create_settlements_filter<S, O, A>(
     store: S,
     outgoing_handler: O,
@@ -130,7 +202,35 @@ where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: SettlementAccount + Account + Send + Sync + 'static,
 {
+    create_authenticated_settlements_filter(store, outgoing_handler, None)
+}
+
+/// Same as [`create_settlements_filter`](./fn.create_settlements_filter.html), but the
+/// engines which are not configured with an authorization token of their own must
+/// present `auth_token` as their bearer token. If it is `None`, they are not authenticated.
+pub fn create_authenticated_settlements_filter<S, O, A>(
+    store: S,
+    outgoing_handler: O,
+    auth_token: Option<SecretString>,
+) -> warp::filters::BoxedFilter<(impl warp::Reply,)>
+where
+    S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>
+        + SettlementStore<Account = A>
+        + IdempotentStore
+        + AccountStore<Account = A>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: SettlementAccount + Account + Send + Sync + 'static,
+{
+    if auth_token.is_none() {
+        warn!("Settlement engines without an authorization token can call the settlement API without authenticating");
+    }
     let with_store = warp::any().map(move || store.clone());
+    let authorization = warp::header::optional::<String>("authorization");
+    let with_auth_token = warp::any().map(move || auth_token.clone());
     let idempotency = warp::header::optional::<String>("idempotency-key");
     let account_id_filter = warp::path("accounts").and(warp::path::param::<String>()); // account_id
 
@@ -140,6 +240,8 @@ where
     let settlements = warp::post()
         .and(settlement_endpoint)
         .and(warp::path::end())
+        .and(authorization)
+        .and(with_auth_token.clone())
         .and(idempotency)
         .and(warp::body::json())
         .and(with_store.clone())
@@ -152,6 +254,8 @@ where
     let messages = warp::post()
         .and(messages_endpoint)
         .and(warp::path::end())
+        .and(authorization)
+        .and(with_auth_token.clone())
         .and(idempotency)
         .and(warp::body::bytes())
         .and(with_store)
@@ -663,6 +767,77 @@ mod tests {
                 &bytes::Bytes::from("Account 00000000-0000-0000-0000-000000000000 was not found")
             );
         }
+
+        #[tokio::test]
+        async fn requires_engine_authorization() {
+            let mut account = TEST_ACCOUNT_0.clone();
+            account.auth_token = Some("secret".to_string());
+            let id = account.id.to_string();
+            let store = TestStore::new(vec![account], false);
+            let api = test_api(store.clone(), false);
+
+            let response = settlement_call(&api, &id, 100, 9, None).await;
+            check_error_status_and_message(
+                response,
+                401,
+                "Invalid settlement engine authorization for account 00000000-0000-0000-0000-000000000000",
+            );
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 0);
+
+            let response = warp::test::request()
+                .method("POST")
+                .path(&format!("/accounts/{}/settlements", id))
+                .header("Authorization", "Bearer secret")
+                .body(json!(Quantity::new("100", 9)).to_string())
+                .reply(&api)
+                .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 100);
+        }
+
+        #[tokio::test]
+        async fn requires_node_authorization_without_engine_token() {
+            let id = TEST_ACCOUNT_0.id.to_string();
+            let store = test_store(false, true);
+            let api = test_api_with_auth_token(store.clone(), false, Some("node-secret"));
+
+            let response = settlement_call(&api, &id, 100, 9, None).await;
+            assert_eq!(response.status().as_u16(), 401);
+            let response = warp::test::request()
+                .method("POST")
+                .path(&format!("/accounts/{}/settlements", id))
+                .header("Authorization", "Bearer node-secre")
+                .body(json!(Quantity::new("100", 9)).to_string())
+                .reply(&api)
+                .await;
+            assert_eq!(response.status().as_u16(), 401);
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 0);
+
+            let response = warp::test::request()
+                .method("POST")
+                .path(&format!("/accounts/{}/settlements", id))
+                .header("Authorization", "Bearer node-secret")
+                .body(json!(Quantity::new("100", 9)).to_string())
+                .reply(&api)
+                .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 100);
+        }
+
+        #[tokio::test]
+        async fn engine_authorization_fails_closed() {
+            let mut account = TEST_ACCOUNT_0.clone();
+            account.auth_token = Some("secret".to_string());
+            let id = account.id.to_string();
+            let mut store = TestStore::new(vec![account], false);
+            store.account_lookups_fail = true;
+            let api = test_api(store.clone(), false);
+
+            let response = settlement_call(&api, &id, 100, 9, Some(IDEMPOTENCY)).await;
+            assert_eq!(response.status().as_u16(), 500);
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 0);
+            assert!(store.cache.read().is_empty());
+        }
     }
 
     mod message_tests {
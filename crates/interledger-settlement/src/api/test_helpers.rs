@@ -22,6 +22,7 @@ use async_trait::async_trait;
 use interledger_errors::*;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+use secrecy::SecretString;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -35,6 +36,7 @@ pub struct TestAccount {
     pub ilp_address: Address,
     pub no_details: bool,
     pub balance: i64,
+    pub auth_token: Option<String>,
 }
 
 pub static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
@@ -68,6 +70,7 @@ impl SettlementAccount for TestAccount {
         }
         Some(SettlementEngineDetails {
             url: self.url.clone(),
+            auth_token: self.auth_token.clone().map(SecretString::new),
         })
     }
 }
@@ -77,6 +80,7 @@ impl SettlementAccount for TestAccount {
 pub struct TestStore {
     pub accounts: Arc<RwLock<Vec<TestAccount>>>,
     pub should_fail: bool,
+    pub account_lookups_fail: bool,
     pub cache: Arc<RwLock<HashMap<String, IdempotentData>>>,
     pub cache_hits: Arc<RwLock<u64>>,
     pub uncredited_settlement_amount: Arc<RwLock<HashMap<Uuid, (BigUint, u8)>>>,
@@ -158,6 +162,11 @@ impl AccountStore for TestStore {
         &self,
         account_ids: Vec<Uuid>,
     ) -> Result<Vec<Self::Account>, AccountStoreError> {
+        if self.account_lookups_fail {
+            return Err(AccountStoreError::Other(Box::new(std::io::Error::other(
+                "store unavailable",
+            ))));
+        }
         let accounts: Vec<TestAccount> = self
             .accounts
             .read()
@@ -286,6 +295,7 @@ impl TestStore {
         TestStore {
             accounts: Arc::new(RwLock::new(accs)),
             should_fail,
+            account_lookups_fail: false,
             cache: Arc::new(RwLock::new(HashMap::new())),
             cache_hits: Arc::new(RwLock::new(0)),
             uncredited_settlement_amount: Arc::new(RwLock::new(HashMap::new())),
@@ -313,6 +323,7 @@ impl TestAccount {
             ilp_address: Address::from_str(ilp_address).unwrap(),
             no_details: false,
             balance: 0,
+            auth_token: None,
         }
     }
 }
@@ -348,6 +359,14 @@ pub fn test_store(store_fails: bool, account_has_engine: bool) -> TestStore {
 pub fn test_api(
     test_store: TestStore,
     should_fulfill: bool,
+) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+    test_api_with_auth_token(test_store, should_fulfill, None)
+}
+
+pub fn test_api_with_auth_token(
+    test_store: TestStore,
+    should_fulfill: bool,
+    auth_token: Option<&str>,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
     let outgoing = outgoing_service_fn(move |_| {
         if should_fulfill {
@@ -366,5 +385,9 @@ pub fn test_api(
             .build())
         }
     });
-    create_settlements_filter(test_store, outgoing)
+    create_authenticated_settlements_filter(
+        test_store,
+        outgoing,
+        auth_token.map(|auth_token| SecretString::new(auth_token.to_string())),
+    )
 }
@@ -0,0 +1,370 @@
+use metrics::{labels, recorder, Key};
+use parking_lot::RwLock;
+use std::{
+    cmp::min,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use url::Url;
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_BASE_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Configuration of the circuit breaker which guards requests to each settlement engine
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed requests after which the circuit is opened
+    /// and requests to the engine fail fast
+    pub failure_threshold: u32,
+    /// Time the circuit stays open the first time it trips. It doubles every
+    /// time the probe request sent after the backoff fails
+    pub base_backoff: Duration,
+    /// Upper bound of the time the circuit stays open
+    pub max_backoff: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            base_backoff: DEFAULT_BASE_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+/// State of the circuit breaker of a settlement engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The engine is healthy and requests are sent normally
+    Closed,
+    /// The engine is considered down, requests fail without being sent
+    Open,
+    /// The backoff expired and a single probe request is in flight
+    HalfOpen,
+}
+
+/// Health information tracked for a single settlement engine URL
+#[derive(Debug, Clone)]
+pub struct EngineHealth {
+    /// Current state of the engine's circuit breaker
+    pub state: CircuitState,
+    /// Number of failed requests since the last successful one
+    pub consecutive_failures: u32,
+    /// Number of times the circuit was (re)opened since the engine was last available
+    pub trips: u32,
+    /// Instant after which a probe request will be let through, if the circuit is open
+    pub retry_at: Option<Instant>,
+    /// Instant of the last successful request to the engine
+    pub last_success: Option<Instant>,
+    /// Instant of the last failed request to the engine
+    pub last_failure: Option<Instant>,
+}
+
+impl Default for EngineHealth {
+    fn default() -> Self {
+        EngineHealth {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            trips: 0,
+            retry_at: None,
+            last_success: None,
+            last_failure: None,
+        }
+    }
+}
+
+/// Tracks the availability of every settlement engine a
+/// [`SettlementClient`](../struct.SettlementClient.html) talks to.
+///
+/// After `failure_threshold` consecutive failures the engine's circuit opens and
+/// requests to it are refused until an exponentially growing backoff elapses.
+/// Then a single probe request is allowed: if it succeeds the circuit closes,
+/// otherwise it opens again with a doubled backoff.
+#[derive(Clone)]
+pub struct EngineHealthTracker {
+    config: CircuitBreakerConfig,
+    engines: Arc<RwLock<HashMap<Url, EngineHealth>>>,
+}
+
+impl EngineHealthTracker {
+    /// Simple constructor
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        EngineHealthTracker {
+            config,
+            engines: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Checks whether a request may be sent to the engine. If the circuit is open,
+    /// returns the time left until the next probe request will be allowed.
+    /// The outcome of the request must be recorded on the returned permit: a probe
+    /// which is dropped before that counts as failed, so that the circuit cannot
+    /// stay half-open forever.
+    pub fn try_acquire(&self, engine_url: &Url) -> Result<EnginePermit, Duration> {
+        let mut engines = self.engines.write();
+        let health = engines.entry(engine_url.clone()).or_default();
+        let probe = match health.state {
+            CircuitState::Closed => false,
+            // Only one probe at a time, everyone else waits for its outcome
+            CircuitState::HalfOpen => return Err(self.config.base_backoff),
+            CircuitState::Open => {
+                let now = Instant::now();
+                match health.retry_at {
+                    Some(retry_at) if retry_at > now => return Err(retry_at - now),
+                    _ => {
+                        health.state = CircuitState::HalfOpen;
+                        true
+                    }
+                }
+            }
+        };
+        Ok(EnginePermit {
+            tracker: self.clone(),
+            engine_url: engine_url.clone(),
+            probe,
+            completed: false,
+        })
+    }
+
+    /// Returns the time left until a request to the engine will be allowed
+    pub fn retry_in(&self, engine_url: &Url) -> Duration {
+        let engines = self.engines.read();
+        match engines.get(engine_url) {
+            Some(health) if health.state == CircuitState::Open => health
+                .retry_at
+                .map(|retry_at| retry_at.saturating_duration_since(Instant::now()))
+                .unwrap_or_default(),
+            Some(health) if health.state == CircuitState::HalfOpen => self.config.base_backoff,
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// Marks a request to the engine as successful, closing its circuit
+    pub fn record_success(&self, engine_url: &Url) {
+        let mut engines = self.engines.write();
+        let health = engines.entry(engine_url.clone()).or_default();
+        if health.state != CircuitState::Closed {
+            info!(
+                "Settlement engine {} is available again after {} failed requests",
+                engine_url, health.consecutive_failures
+            );
+        }
+        health.state = CircuitState::Closed;
+        health.consecutive_failures = 0;
+        health.trips = 0;
+        health.retry_at = None;
+        health.last_success = Some(Instant::now());
+
+        let labels = labels!("engine_url" => engine_url.to_string());
+        recorder().increment_counter(
+            Key::from_name_and_labels("settlement_engine.requests.success", labels.clone()),
+            1,
+        );
+        recorder().update_gauge(
+            Key::from_name_and_labels("settlement_engine.available", labels),
+            1,
+        );
+    }
+
+    /// Marks a request to the engine as failed, opening its circuit if
+    /// the failure threshold was reached or if the request was a probe
+    pub fn record_failure(&self, engine_url: &Url) {
+        let mut engines = self.engines.write();
+        let health = engines.entry(engine_url.clone()).or_default();
+        let now = Instant::now();
+        health.consecutive_failures += 1;
+        health.last_failure = Some(now);
+
+        let labels = labels!("engine_url" => engine_url.to_string());
+        recorder().increment_counter(
+            Key::from_name_and_labels("settlement_engine.requests.failure", labels.clone()),
+            1,
+        );
+
+        let should_open = match health.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => health.consecutive_failures >= self.config.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            health.trips += 1;
+            let backoff = self.backoff(health.trips);
+            health.state = CircuitState::Open;
+            health.retry_at = Some(now + backoff);
+            warn!(
+                "Settlement engine {} is unavailable after {} consecutive failed requests, not sending requests to it for {:?}",
+                engine_url, health.consecutive_failures, backoff
+            );
+            recorder().increment_counter(
+                Key::from_name_and_labels("settlement_engine.circuit_opened", labels.clone()),
+                1,
+            );
+            recorder().update_gauge(
+                Key::from_name_and_labels("settlement_engine.available", labels),
+                0,
+            );
+        }
+    }
+
+    /// Returns the health information of the engine, if any request was made to it
+    pub fn health(&self, engine_url: &Url) -> Option<EngineHealth> {
+        self.engines.read().get(engine_url).cloned()
+    }
+
+    /// Returns the health information of every engine a request was made to
+    pub fn all(&self) -> HashMap<Url, EngineHealth> {
+        self.engines.read().clone()
+    }
+
+    fn backoff(&self, trips: u32) -> Duration {
+        // Cap the exponent so that the multiplication cannot overflow
+        let factor = 2u32.saturating_pow(min(trips.saturating_sub(1), 16));
+        min(self.config.base_backoff * factor, self.config.max_backoff)
+    }
+}
+
+/// Permission to send a single request to a settlement engine, returned by
+/// [`EngineHealthTracker::try_acquire`](./struct.EngineHealthTracker.html#method.try_acquire)
+pub struct EnginePermit {
+    tracker: EngineHealthTracker,
+    engine_url: Url,
+    probe: bool,
+    completed: bool,
+}
+
+impl EnginePermit {
+    /// Marks the request as successful, closing the engine's circuit
+    pub fn record_success(mut self) {
+        self.completed = true;
+        self.tracker.record_success(&self.engine_url);
+    }
+
+    /// Marks the request as failed
+    pub fn record_failure(mut self) {
+        self.completed = true;
+        self.tracker.record_failure(&self.engine_url);
+    }
+}
+
+impl Drop for EnginePermit {
+    fn drop(&mut self) {
+        // The request was abandoned, e.g. because its future was dropped. If it was
+        // the probe, nobody else can close or reopen the circuit, so reopen it
+        if self.probe && !self.completed {
+            warn!(
+                "Probe request to settlement engine {} was abandoned, counting it as failed",
+                self.engine_url
+            );
+            self.tracker.record_failure(&self.engine_url);
+        }
+    }
+}
+
+impl Default for EngineHealthTracker {
+    fn default() -> Self {
+        EngineHealthTracker::new(CircuitBreakerConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(failure_threshold: u32, base_backoff: Duration) -> EngineHealthTracker {
+        EngineHealthTracker::new(CircuitBreakerConfig {
+            failure_threshold,
+            base_backoff,
+            max_backoff: Duration::from_secs(60),
+        })
+    }
+
+    fn url() -> Url {
+        "http://localhost:3000".parse().unwrap()
+    }
+
+    #[test]
+    fn opens_after_threshold() {
+        let tracker = tracker(3, Duration::from_secs(10));
+        for _ in 0..2 {
+            tracker.record_failure(&url());
+            assert!(tracker.try_acquire(&url()).is_ok());
+        }
+        tracker.record_failure(&url());
+        assert_eq!(tracker.health(&url()).unwrap().state, CircuitState::Open);
+        assert!(tracker.try_acquire(&url()).is_err());
+        assert!(tracker.retry_in(&url()) > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let tracker = tracker(2, Duration::from_secs(10));
+        tracker.record_failure(&url());
+        tracker.record_success(&url());
+        tracker.record_failure(&url());
+        let health = tracker.health(&url()).unwrap();
+        assert_eq!(health.state, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn probes_after_backoff() {
+        let tracker = tracker(1, Duration::from_millis(0));
+        tracker.record_failure(&url());
+        // The backoff already elapsed so a single probe is let through
+        let probe = tracker.try_acquire(&url()).unwrap();
+        assert_eq!(
+            tracker.health(&url()).unwrap().state,
+            CircuitState::HalfOpen
+        );
+        assert!(tracker.try_acquire(&url()).is_err());
+
+        probe.record_success();
+        assert_eq!(tracker.health(&url()).unwrap().state, CircuitState::Closed);
+        assert!(tracker.try_acquire(&url()).is_ok());
+    }
+
+    #[test]
+    fn dropped_probe_reopens_circuit() {
+        let tracker = tracker(1, Duration::from_millis(0));
+        tracker.record_failure(&url());
+        let probe = tracker.try_acquire(&url()).unwrap();
+        drop(probe);
+        let health = tracker.health(&url()).unwrap();
+        assert_eq!(health.state, CircuitState::Open);
+        assert_eq!(health.trips, 2);
+        // Once the backoff elapses the next probe is let through
+        assert!(tracker.try_acquire(&url()).is_ok());
+    }
+
+    #[test]
+    fn dropped_request_is_not_a_failure() {
+        let tracker = tracker(1, Duration::from_secs(10));
+        drop(tracker.try_acquire(&url()).unwrap());
+        let health = tracker.health(&url()).unwrap();
+        assert_eq!(health.state, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let tracker = tracker(1, Duration::from_secs(1));
+        assert_eq!(tracker.backoff(1), Duration::from_secs(1));
+        assert_eq!(tracker.backoff(2), Duration::from_secs(2));
+        assert_eq!(tracker.backoff(4), Duration::from_secs(8));
+        assert_eq!(tracker.backoff(10), Duration::from_secs(60));
+
+        tracker.record_failure(&url());
+        let mut health = tracker.all().remove(&url()).unwrap();
+        assert_eq!(health.trips, 1);
+        // A failed probe reopens the circuit with a longer backoff
+        tracker.engines.write().get_mut(&url()).unwrap().state = CircuitState::HalfOpen;
+        tracker.record_failure(&url());
+        health = tracker.health(&url()).unwrap();
+        assert_eq!(health.trips, 2);
+        assert_eq!(health.state, CircuitState::Open);
+    }
+}
@@ -8,14 +8,23 @@ pub mod backends_common;
 pub mod engines_api;
 
 mod settlement_client;
-pub use settlement_client::SettlementClient;
+pub use settlement_client::{SettlementClient, SettlementClientError};
+
+/// Circuit breaker tracking the availability of settlement engines
+pub mod engine_health;
 
 /// Expose useful utilities for implementing idempotent functionalities
 pub mod idempotency;
 
+/// Comparison of the node's settlement journal with the settlement engines' totals
+pub mod reconciliation;
+
 /// Expose useful traits
 pub mod types;
 
+#[cfg(test)]
+mod test_helpers;
+
 use num_bigint::BigUint;
 use num_traits::Zero;
 use ring::digest::{digest, SHA256};
@@ -0,0 +1,466 @@
+use super::{
+    scale_with_precision_loss,
+    types::{LeftoversStore, Quantity, SettlementAccount, SettlementQueueStore, SettlementStore},
+    SettlementClient,
+};
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use interledger_errors::SettlementStoreError;
+use interledger_service::Username;
+use num_bigint::BigUint;
+use num_traits::cast::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, str::FromStr};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Amounts the node accounted for as settled with an account,
+/// denominated in the account's asset scale
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettlementTotals {
+    /// Total amount of the outgoing settlements sent to the account's engine
+    /// (minus the ones which were refunded)
+    pub sent: u64,
+    /// Total amount of the incoming settlements credited to the account
+    pub received: u64,
+}
+
+/// Settlement totals as reported by an engine's (optional) status endpoint,
+/// `GET /accounts/:id/settlements`, in the engine's asset scale
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineSettlementTotals {
+    /// Total amount the engine has sent to the peer
+    pub sent: Quantity,
+    /// Total amount the engine has received from the peer
+    pub received: Quantity,
+}
+
+/// What the reconciliation of an account needs to remember between runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationState {
+    /// The engine's totals which are not part of the node's journal, because they were
+    /// settled before the node started keeping it. Zero for the accounts created since
+    pub baseline: SettlementTotals,
+    /// The node's and the engine's sent totals when a refund was last found. The refund is
+    /// only applied once a later reconciliation finds the same totals, so that settlements
+    /// the engine accepted but did not report yet are not refunded
+    pub unconfirmed_refund: Option<(u64, u64)>,
+}
+
+/// Store trait keeping a journal of the settlements accounted for by the node
+#[async_trait]
+pub trait SettlementJournalStore {
+    /// Returns the totals of the settlements sent to and received from the account
+    async fn get_settlement_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<SettlementTotals, SettlementStoreError>;
+
+    /// Returns the reconciliation state of the account, or `None` if it was never reconciled
+    /// and its journal may be missing the settlements made before it was introduced
+    async fn get_reconciliation_state(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<ReconciliationState>, SettlementStoreError>;
+
+    /// Saves the reconciliation state of the account
+    async fn set_reconciliation_state(
+        &self,
+        account_id: Uuid,
+        state: ReconciliationState,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Refunds outgoing settlements which the engine never sent.
+    /// Only the first refund with a given idempotency key is applied
+    async fn refund_unsent_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: String,
+    ) -> Result<(), SettlementStoreError>;
+}
+
+/// Corrections applied to an account's balance to match its engine's totals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationCorrection {
+    /// Incoming settlements which the engine received but the node never credited
+    pub credited: u64,
+    /// Outgoing settlements which the node accounted for but the engine never sent
+    pub refunded: u64,
+    /// Outgoing settlements which the engine may not have sent, which will be refunded
+    /// if the next reconciliation still finds them missing
+    pub unconfirmed: u64,
+}
+
+/// Comparison between the node's and the engine's view of the settlements of an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// The account's id
+    pub account_id: Uuid,
+    /// The account's username
+    pub username: Username,
+    /// The account's asset scale, in which all the amounts of the report are denominated
+    pub asset_scale: u8,
+    /// The totals recorded in the node's journal
+    pub node: SettlementTotals,
+    /// The totals reported by the engine, if it exposes them
+    pub engine: Option<SettlementTotals>,
+    /// The part of the engine's totals settled before the node started keeping its journal,
+    /// which is left out of the comparison
+    pub baseline: Option<SettlementTotals>,
+    /// Outgoing settlements which are queued or were not accepted by the engine yet
+    pub pending_settlement: u64,
+    /// Amount received by the engine which could not be credited yet due to precision loss
+    /// (in the engine's asset scale)
+    pub uncredited_amount: Quantity,
+    /// The engine's sent total minus the baseline and the node's total, if the engine
+    /// reported its totals
+    pub sent_difference: Option<i64>,
+    /// The engine's received total minus the baseline and the node's total, if the engine
+    /// reported its totals
+    pub received_difference: Option<i64>,
+    /// The corrections which were applied, if they were requested
+    pub correction: Option<ReconciliationCorrection>,
+}
+
+impl ReconciliationReport {
+    /// Returns true if the node's and the engine's totals do not match
+    pub fn has_discrepancy(&self) -> bool {
+        self.sent_difference.unwrap_or(0) != 0 || self.received_difference.unwrap_or(0) != 0
+    }
+}
+
+fn difference(engine: u64, node: u64) -> i64 {
+    let difference = i128::from(engine) - i128::from(node);
+    i64::try_from(difference).unwrap_or(if difference > 0 { i64::MAX } else { i64::MIN })
+}
+
+fn to_account_scale(quantity: &Quantity, asset_scale: u8) -> Option<u64> {
+    let amount = BigUint::from_str(&quantity.amount).ok()?;
+    let (scaled, _) = scale_with_precision_loss(amount, asset_scale, quantity.scale);
+    scaled.to_u64()
+}
+
+/// Compares the settlements the node has accounted for with an account against the
+/// totals reported by the account's settlement engine and reports any discrepancy.
+///
+/// The first time an account is reconciled, the part of the engine's totals which is
+/// missing from the node's journal is recorded as the account's baseline and left out
+/// of the comparison, since it was settled before the node started keeping its journal.
+///
+/// If `apply` is set, the discrepancies which can be safely corrected are fixed:
+/// incoming settlements the node missed are credited to the account and outgoing
+/// settlements the engine never sent are refunded. Refunds are only applied once two
+/// reconciliations found the same totals and while no settlement of the account is
+/// pending. Settlements the engine sent or received beyond what the node accounted for
+/// are only reported.
+pub async fn reconcile_account<S, A>(
+    store: &S,
+    client: &SettlementClient,
+    account: &A,
+    apply: bool,
+) -> Result<ReconciliationReport, SettlementStoreError>
+where
+    S: SettlementJournalStore
+        + SettlementStore<Account = A>
+        + SettlementQueueStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>,
+    A: SettlementAccount,
+{
+    let account_id = account.id();
+    let asset_scale = account.asset_scale();
+    let engine = match account.settlement_engine_details() {
+        Some(details) => match client.get_settlement_totals(account_id, &details).await {
+            Ok(Some(totals)) => {
+                match (
+                    to_account_scale(&totals.sent, asset_scale),
+                    to_account_scale(&totals.received, asset_scale),
+                ) {
+                    (Some(sent), Some(received)) => Some(SettlementTotals { sent, received }),
+                    _ => {
+                        warn!(
+                            "Settlement engine {} reported invalid totals for account {}: {:?}",
+                            details.url, account_id, totals
+                        );
+                        None
+                    }
+                }
+            }
+            Ok(None) => {
+                debug!(
+                    "Settlement engine {} does not report settlement totals",
+                    details.url
+                );
+                None
+            }
+            Err(err) => {
+                warn!(
+                    "Could not get settlement totals of account {} from engine {}: {}",
+                    account_id, details.url, err
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    reconcile_totals(store, account, engine, apply).await
+}
+
+/// Compares the node's journal with the totals the engine reported, in the account's scale
+async fn reconcile_totals<S, A>(
+    store: &S,
+    account: &A,
+    engine: Option<SettlementTotals>,
+    apply: bool,
+) -> Result<ReconciliationReport, SettlementStoreError>
+where
+    S: SettlementJournalStore
+        + SettlementStore<Account = A>
+        + SettlementQueueStore
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>,
+    A: SettlementAccount,
+{
+    let account_id = account.id();
+    let node = store.get_settlement_totals(account_id).await?;
+    let pending_settlement = store.get_pending_settlement(account_id).await?;
+    let (uncredited_amount, uncredited_scale) = store
+        .get_uncredited_settlement_amount(account_id)
+        .map_err(SettlementStoreError::from)
+        .await?;
+
+    let mut state = store.get_reconciliation_state(account_id).await?;
+    // The discrepancies are only trusted once the baseline was recorded
+    let mut trusted = state.is_some();
+    if let (None, Some(engine)) = (state, engine) {
+        let baseline = SettlementTotals {
+            sent: engine.sent.saturating_sub(node.sent),
+            received: engine.received.saturating_sub(node.received),
+        };
+        info!(
+            "Recording the settlement baseline of account {}: sent {}, received {}",
+            account_id, baseline.sent, baseline.received
+        );
+        let new_state = ReconciliationState {
+            baseline,
+            unconfirmed_refund: None,
+        };
+        store
+            .set_reconciliation_state(account_id, new_state)
+            .await?;
+        state = Some(new_state);
+        trusted = false;
+    }
+    let baseline = state.map(|state| state.baseline);
+    let engine_journal = engine.map(|engine| SettlementTotals {
+        sent: engine
+            .sent
+            .saturating_sub(baseline.unwrap_or_default().sent),
+        received: engine
+            .received
+            .saturating_sub(baseline.unwrap_or_default().received),
+    });
+
+    let mut report = ReconciliationReport {
+        account_id,
+        username: account.username().clone(),
+        asset_scale: account.asset_scale(),
+        node,
+        engine,
+        baseline,
+        pending_settlement,
+        uncredited_amount: Quantity::new(uncredited_amount, uncredited_scale),
+        sent_difference: engine_journal.map(|engine| difference(engine.sent, node.sent)),
+        received_difference: engine_journal
+            .map(|engine| difference(engine.received, node.received)),
+        correction: None,
+    };
+
+    if let (true, true, Some(engine), Some(mut state)) = (apply, trusted, engine_journal, state) {
+        let mut correction = ReconciliationCorrection::default();
+        if engine.received > node.received {
+            correction.credited = engine.received - node.received;
+            // Keyed by the totals so that concurrent reconciliations only credit it once
+            store
+                .update_balance_for_incoming_settlement(
+                    account_id,
+                    correction.credited,
+                    Some(format!(
+                        "reconciliation:{}:received:{}:{}",
+                        account_id, engine.received, node.received
+                    )),
+                )
+                .await?;
+        }
+
+        let unconfirmed_refund = if engine.sent >= node.sent {
+            None
+        } else if pending_settlement > 0 {
+            // The engine cannot have sent the settlements which are still pending
+            debug!(
+                "Not refunding settlements of account {} while {} is pending",
+                account_id, pending_settlement
+            );
+            None
+        } else if state.unconfirmed_refund == Some((node.sent, engine.sent)) {
+            correction.refunded = node.sent - engine.sent;
+            store
+                .refund_unsent_settlement(
+                    account_id,
+                    correction.refunded,
+                    format!(
+                        "reconciliation:{}:sent:{}:{}",
+                        account_id, engine.sent, node.sent
+                    ),
+                )
+                .await?;
+            None
+        } else {
+            correction.unconfirmed = node.sent - engine.sent;
+            Some((node.sent, engine.sent))
+        };
+        if state.unconfirmed_refund != unconfirmed_refund {
+            state.unconfirmed_refund = unconfirmed_refund;
+            store.set_reconciliation_state(account_id, state).await?;
+        }
+
+        if correction != ReconciliationCorrection::default() {
+            info!(
+                "Reconciled settlements of account {}: credited {}, refunded {}, unconfirmed {}",
+                account_id, correction.credited, correction.refunded, correction.unconfirmed
+            );
+        }
+        report.correction = Some(correction);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_helpers::{TestAccount, TestStore};
+
+    #[test]
+    fn computes_saturating_difference() {
+        assert_eq!(difference(10, 4), 6);
+        assert_eq!(difference(4, 10), -6);
+        assert_eq!(difference(u64::MAX, 0), i64::MAX);
+        assert_eq!(difference(0, u64::MAX), i64::MIN);
+    }
+
+    #[test]
+    fn scales_engine_amounts() {
+        assert_eq!(
+            to_account_scale(&Quantity::new(1234, 6), 9),
+            Some(1_234_000)
+        );
+        // Precision loss is dropped, it is tracked separately as the uncredited amount
+        assert_eq!(to_account_scale(&Quantity::new(1234, 6), 3), Some(1));
+        assert_eq!(to_account_scale(&Quantity::new("abc", 6), 6), None);
+    }
+
+    fn totals(sent: u64, received: u64) -> Option<SettlementTotals> {
+        Some(SettlementTotals { sent, received })
+    }
+
+    #[tokio::test]
+    async fn records_baseline_for_pre_existing_settlements() {
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let store = TestStore::new(vec![account.clone()]);
+
+        // The settlements made before the node kept its journal are not credited again
+        let report = reconcile_totals(&store, &account, totals(500, 300), true)
+            .await
+            .unwrap();
+        assert_eq!(report.baseline, totals(500, 300));
+        assert_eq!(report.sent_difference, Some(0));
+        assert_eq!(report.received_difference, Some(0));
+        assert_eq!(report.correction, None);
+        assert_eq!(store.totals.lock().get(&account.id), None);
+
+        // Only the settlements received since are
+        let report = reconcile_totals(&store, &account, totals(500, 400), true)
+            .await
+            .unwrap();
+        assert_eq!(report.received_difference, Some(100));
+        assert_eq!(report.correction.unwrap().credited, 100);
+        let report = reconcile_totals(&store, &account, totals(500, 400), true)
+            .await
+            .unwrap();
+        assert_eq!(report.received_difference, Some(0));
+        assert_eq!(report.correction.unwrap().credited, 0);
+        assert_eq!(store.totals.lock()[&account.id].received, 100);
+    }
+
+    #[tokio::test]
+    async fn does_not_refund_pending_settlements() {
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let store = TestStore::new(vec![account.clone()]);
+        store
+            .set_reconciliation_state(account.id, ReconciliationState::default())
+            .await
+            .unwrap();
+        store.settle(account.id, 100);
+        store
+            .queue_settlement(account.id, 100, "settlement")
+            .await
+            .unwrap();
+
+        let report = reconcile_totals(&store, &account, totals(0, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.pending_settlement, 100);
+        assert_eq!(report.sent_difference, Some(-100));
+        assert_eq!(report.correction, Some(ReconciliationCorrection::default()));
+
+        // Once the settlement is no longer pending, the engine must report
+        // the same totals twice before the settlement is refunded
+        store
+            .claim_queued_settlement(account.id, "settlement")
+            .await
+            .unwrap();
+        store.settlement_sent(account.id, 100).await.unwrap();
+        let report = reconcile_totals(&store, &account, totals(0, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.correction.unwrap().unconfirmed, 100);
+        assert!(store.refunded.lock().is_empty());
+        let report = reconcile_totals(&store, &account, totals(0, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.correction.unwrap().refunded, 100);
+        let report = reconcile_totals(&store, &account, totals(0, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.sent_difference, Some(0));
+        assert_eq!(report.correction, Some(ReconciliationCorrection::default()));
+        assert_eq!(store.refunded.lock()[&account.id], 100);
+    }
+
+    #[tokio::test]
+    async fn does_not_refund_settlements_the_engine_reports_late() {
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let store = TestStore::new(vec![account.clone()]);
+        store
+            .set_reconciliation_state(account.id, ReconciliationState::default())
+            .await
+            .unwrap();
+        store.settle(account.id, 100);
+        store.settlement_sent(account.id, 100).await.unwrap();
+
+        let report = reconcile_totals(&store, &account, totals(0, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.correction.unwrap().unconfirmed, 100);
+        let report = reconcile_totals(&store, &account, totals(100, 0), true)
+            .await
+            .unwrap();
+        assert_eq!(report.correction, Some(ReconciliationCorrection::default()));
+        assert!(store.refunded.lock().is_empty());
+        assert_eq!(
+            store.reconciliation.lock()[&account.id].unconfirmed_refund,
+            None
+        );
+    }
+}
@@ -1,18 +1,67 @@
-use crate::core::types::Quantity;
+use crate::core::{
+    engine_health::{CircuitBreakerConfig, EngineHealthTracker, EnginePermit},
+    reconciliation::EngineSettlementTotals,
+    types::{
+        Quantity, SettlementAccount, SettlementEngineDetails, SettlementQueueStore, SettlementStore,
+    },
+};
+use futures::TryFutureExt;
 use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
-use reqwest::Client;
+use interledger_errors::SettlementStoreError;
+use interledger_service::AccountStore;
+use metrics::{labels, recorder, Key};
+use parking_lot::Mutex;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use secrecy::ExposeSecret;
 use serde_json::json;
-use std::time::Duration;
-use tracing::{debug, trace};
+use std::{cmp::min, collections::HashMap, sync::Arc, time::Duration};
+use thiserror::Error;
+use tracing::{debug, error, trace, warn};
 use url::Url;
 use uuid::Uuid;
 
-type Response = Result<reqwest::Response, reqwest::Error>;
+type Response = Result<reqwest::Response, SettlementClientError>;
 
 // The account creation endpoint set by the engines in the [RFC](https://github.com/interledger/rfcs/pull/536)
 static ACCOUNTS_ENDPOINT: &str = "accounts";
 const MAX_RETRIES: usize = 10;
 const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_millis(5000);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Number of times a queued settlement is retried before it is refunded
+const MAX_QUEUED_ATTEMPTS: u32 = 20;
+
+/// Errors returned by the [`SettlementClient`](./struct.SettlementClient.html)
+#[derive(Error, Debug)]
+pub enum SettlementClientError {
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+    #[error("settlement engine {url} is unavailable, next attempt in {retry_in:?}")]
+    EngineUnavailable { url: Url, retry_in: Duration },
+}
+
+impl SettlementClientError {
+    /// Returns true if the engine handled the request and rejected it,
+    /// meaning that retrying the same request would not help
+    pub fn is_rejected_by_engine(&self) -> bool {
+        match self {
+            SettlementClientError::Request(err) => err
+                .status()
+                .map(|status| status.is_client_error())
+                .unwrap_or(false),
+            SettlementClientError::EngineUnavailable { .. } => false,
+        }
+    }
+}
+
+/// A settlement which could not be delivered to the engine and awaits a retry.
+/// Its amount is kept in the store
+#[derive(Debug, Clone, Copy)]
+struct QueuedSettlement {
+    account_id: Uuid,
+    asset_scale: u8,
+    /// Number of requests the engine failed to handle
+    attempts: u32,
+}
 
 /// Helper struct to execute settlements
 #[derive(Clone)]
@@ -20,22 +69,57 @@ pub struct SettlementClient {
     /// Asynchronous reqwest client
     client: Client,
     max_retries: usize,
+    /// Availability of the engines, shared between all clones of the client
+    health: EngineHealthTracker,
+    /// Settlements awaiting for their engine to become available again, keyed by engine URL
+    /// and idempotency key
+    queue: Arc<Mutex<HashMap<Url, HashMap<String, QueuedSettlement>>>>,
+    max_queued_attempts: u32,
 }
 
 impl SettlementClient {
     /// Simple constructor
     pub fn new(timeout: Duration, max_retries: usize) -> Self {
+        Self::with_circuit_breaker(timeout, max_retries, CircuitBreakerConfig::default())
+    }
+
+    /// Constructor allowing to configure when the circuit breaker of each engine opens
+    pub fn with_circuit_breaker(
+        timeout: Duration,
+        max_retries: usize,
+        circuit_breaker: CircuitBreakerConfig,
+    ) -> Self {
         SettlementClient {
             client: Client::builder().timeout(timeout).build().unwrap(),
             max_retries,
+            health: EngineHealthTracker::new(circuit_breaker),
+            queue: Arc::new(Mutex::new(HashMap::new())),
+            max_queued_attempts: MAX_QUEUED_ATTEMPTS,
         }
     }
 
+    /// Sets how many times the engine may fail to handle a queued settlement before
+    /// it is refunded. Defaults to 20
+    pub fn with_max_queued_attempts(mut self, max_queued_attempts: u32) -> Self {
+        self.max_queued_attempts = max_queued_attempts;
+        self
+    }
+
+    /// Returns the tracker of the availability of the engines this client talks to
+    pub fn engine_health(&self) -> &EngineHealthTracker {
+        &self.health
+    }
+
     /// Sends an idempotent account creation request to the engine (will retry if it fails)
     /// This is done by sending a POST to /accounts with the provided `id` as the request's body
-    pub async fn create_engine_account(&self, id: Uuid, engine_url: Url) -> Response {
+    pub async fn create_engine_account(
+        &self,
+        id: Uuid,
+        engine: SettlementEngineDetails,
+    ) -> Response {
+        let engine = &engine;
         FutureRetry::new(
-            move || self.create_engine_account_once(id, engine_url.clone()),
+            move || self.create_engine_account_once(id, engine),
             RequestErrorHandler::new(self.max_retries),
         )
         .await
@@ -44,20 +128,33 @@ impl SettlementClient {
     /// Sends a message to the engine (will retry idempotently if it fails) which will get forwarded to the peer's engine
     /// This is done by sending a POST to /accounts/:id/messages with the provided `message`
     /// as the request's body
-    pub async fn send_message(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+    pub async fn send_message(
+        &self,
+        id: Uuid,
+        engine: SettlementEngineDetails,
+        message: Vec<u8>,
+    ) -> Response {
+        let engine = &engine;
         FutureRetry::new(
-            move || self.send_message_once(id, engine_url.clone(), message.clone()),
+            move || self.send_message_once(id, engine, message.clone()),
             RequestErrorHandler::new(self.max_retries),
         )
         .await
     }
 
-    async fn send_message_once(&self, id: Uuid, engine_url: Url, message: Vec<u8>) -> Response {
+    async fn send_message_once(
+        &self,
+        id: Uuid,
+        engine: &SettlementEngineDetails,
+        message: Vec<u8>,
+    ) -> Response {
+        let engine_url = &engine.url;
+        let permit = self.acquire(engine_url)?;
         // The `Prepare` packet's data was sent by the peer's settlement
         // engine so we assume it is in a format that our settlement engine
         // will understand
         // format.
-        let mut settlement_engine_url = engine_url;
+        let mut settlement_engine_url = engine_url.clone();
         settlement_engine_url
             .path_segments_mut()
             .expect("Invalid settlement engine URL")
@@ -65,34 +162,44 @@ impl SettlementClient {
             .push(&id.to_string())
             .push("messages");
         let idempotency_uuid = uuid::Uuid::new_v4().to_hyphenated().to_string();
-        self.client
-            .post(settlement_engine_url.as_ref())
+        let response = authorize(self.client.post(settlement_engine_url.as_ref()), engine)
             .header("Content-Type", "application/octet-stream")
             .header("Idempotency-Key", idempotency_uuid.clone())
             .body(message.clone())
             .send()
-            .await
+            .await;
+        record_outcome(permit, &response);
+        Ok(response?)
     }
 
     /// Sends an idempotent settlement request to the engine (will retry if it fails)
     /// This is done by sending a POST to /accounts/:id/settlements with the provided `amount` and `asset_scale`
-    /// as the request's body
+    /// as the request's body. Every attempt uses the same `idempotency_key`, so that the engine
+    /// settles the amount only once even if it handled a request which timed out
     pub async fn send_settlement(
         &self,
         id: Uuid,
-        engine_url: Url,
+        engine: SettlementEngineDetails,
         amount: u64,
         asset_scale: u8,
+        idempotency_key: &str,
     ) -> Response {
+        let engine = &engine;
         FutureRetry::new(
-            move || self.send_settlement_once(id, engine_url.clone(), amount, asset_scale),
+            move || self.send_settlement_once(id, engine, amount, asset_scale, idempotency_key),
             RequestErrorHandler::new(self.max_retries),
         )
         .await
     }
 
-    async fn create_engine_account_once(&self, id: Uuid, engine_url: Url) -> Response {
-        let mut se_url = engine_url;
+    async fn create_engine_account_once(
+        &self,
+        id: Uuid,
+        engine: &SettlementEngineDetails,
+    ) -> Response {
+        let engine_url = &engine.url;
+        let permit = self.acquire(engine_url)?;
+        let mut se_url = engine_url.clone();
         // $URL/accounts
         se_url
             .path_segments_mut()
@@ -104,22 +211,25 @@ impl SettlementClient {
             se_url.clone()
         );
 
-        Ok(self
-            .client
-            .post(se_url.as_ref())
+        let response = authorize(self.client.post(se_url.as_ref()), engine)
             .json(&json!({ "id": id.to_string() }))
             .send()
-            .await?)
+            .await;
+        record_outcome(permit, &response);
+        Ok(response?)
     }
 
     pub async fn send_settlement_once(
         &self,
         id: Uuid,
-        engine_url: Url,
+        engine: &SettlementEngineDetails,
         amount: u64,
         asset_scale: u8,
+        idempotency_key: &str,
     ) -> Response {
-        let mut settlement_engine_url = engine_url;
+        let engine_url = &engine.url;
+        let permit = self.acquire(engine_url)?;
+        let mut settlement_engine_url = engine_url.clone();
 
         // $URL/accounts/:account_id/settlements
         settlement_engine_url
@@ -133,19 +243,315 @@ impl SettlementClient {
             amount, settlement_engine_url
         );
 
-        // Mark the request as idempotent
-        let idempotency_uuid = Uuid::new_v4().to_hyphenated().to_string();
-
         // Make the POST request future
-        let response = self
-            .client
-            .post(settlement_engine_url.as_ref())
-            .header("Idempotency-Key", idempotency_uuid)
+        let response = authorize(self.client.post(settlement_engine_url.as_ref()), engine)
+            .header("Idempotency-Key", idempotency_key)
             .json(&json!(Quantity::new(amount, asset_scale)))
             .send()
-            .await?;
+            .await;
+        record_outcome(permit, &response);
+
+        Ok(response?.error_for_status()?)
+    }
+
+    /// Fetches the totals of the settlements the engine has sent to and received from the account.
+    /// This is done by sending a GET to /accounts/:id/settlements. Since this endpoint is
+    /// optional, `None` is returned if the engine does not expose it.
+    pub async fn get_settlement_totals(
+        &self,
+        id: Uuid,
+        engine: &SettlementEngineDetails,
+    ) -> Result<Option<EngineSettlementTotals>, SettlementClientError> {
+        let engine_url = &engine.url;
+        let permit = self.acquire(engine_url)?;
+        let mut settlement_engine_url = engine_url.clone();
+        // $URL/accounts/:account_id/settlements
+        settlement_engine_url
+            .path_segments_mut()
+            .expect("Invalid settlement engine URL")
+            .push(ACCOUNTS_ENDPOINT)
+            .push(&id.to_string())
+            .push("settlements");
+
+        let response = authorize(self.client.get(settlement_engine_url.as_ref()), engine)
+            .send()
+            .await;
+        record_outcome(permit, &response);
+        let response = response?;
+        match response.status() {
+            StatusCode::NOT_FOUND
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::NOT_IMPLEMENTED => Ok(None),
+            _ => Ok(Some(response.error_for_status()?.json().await?)),
+        }
+    }
 
-        Ok(response.error_for_status()?)
+    /// Queues an in-flight settlement which could not be sent because the engine was unavailable.
+    /// The queue is kept in the store and retried once the engine's circuit breaker allows it,
+    /// always with the idempotency key the settlement was first sent with: the engine may have
+    /// handled a request whose response was lost, so queued settlements are never merged.
+    /// If the engine fails to handle the settlement too many times, or if it rejects it,
+    /// the amount is refunded to the account.
+    pub async fn queue_settlement<S>(
+        &self,
+        store: S,
+        id: Uuid,
+        engine: SettlementEngineDetails,
+        amount: u64,
+        asset_scale: u8,
+        idempotency_key: String,
+    ) -> Result<(), SettlementStoreError>
+    where
+        S: SettlementStore + SettlementQueueStore + Send + Sync + 'static,
+    {
+        store.queue_settlement(id, amount, &idempotency_key).await?;
+        debug!(
+            "Queued settlement of amount {} for account {} until settlement engine {} is available",
+            amount, id, engine.url
+        );
+        recorder().increment_counter(
+            Key::from_name_and_labels(
+                "settlement_engine.settlements.queued",
+                labels!("engine_url" => engine.url.to_string()),
+            ),
+            1,
+        );
+        self.watch_queued_settlement(store, id, engine, asset_scale, idempotency_key);
+        Ok(())
+    }
+
+    /// Resumes retrying the settlements which are queued in the store, e.g. after a restart.
+    /// The settlements of accounts which no longer have an engine are refunded
+    pub async fn resume_queued_settlements<S, A>(
+        &self,
+        store: S,
+    ) -> Result<(), SettlementStoreError>
+    where
+        S: SettlementStore<Account = A>
+            + SettlementQueueStore
+            + AccountStore<Account = A>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        A: SettlementAccount,
+    {
+        let queued = store.get_queued_settlements().await?;
+        if queued.is_empty() {
+            return Ok(());
+        }
+        debug!("Resuming {} queued settlements", queued.len());
+        for (id, idempotency_key, amount) in queued {
+            let account = match store.get_accounts(vec![id]).await {
+                Ok(mut accounts) if !accounts.is_empty() => accounts.remove(0),
+                // The amount is dropped along with the account
+                _ => {
+                    warn!(
+                        "Account {} with a queued settlement of amount {} no longer exists",
+                        id, amount
+                    );
+                    store.claim_queued_settlement(id, &idempotency_key).await?;
+                    continue;
+                }
+            };
+            match account.settlement_engine_details() {
+                Some(engine) => self.watch_queued_settlement(
+                    store.clone(),
+                    id,
+                    engine,
+                    account.asset_scale(),
+                    idempotency_key,
+                ),
+                None => {
+                    let amount = store.claim_queued_settlement(id, &idempotency_key).await?;
+                    warn!(
+                        "Account {} no longer has a settlement engine, refunding its queued settlement of amount {}",
+                        id, amount
+                    );
+                    store.refund_settlement(id, amount).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure the queued settlement is retried by a task of the engine
+    fn watch_queued_settlement<S>(
+        &self,
+        store: S,
+        id: Uuid,
+        engine: SettlementEngineDetails,
+        asset_scale: u8,
+        idempotency_key: String,
+    ) where
+        S: SettlementStore + SettlementQueueStore + Send + Sync + 'static,
+    {
+        let spawn_retry_task = {
+            let mut queue = self.queue.lock();
+            let spawn_retry_task = !queue.contains_key(&engine.url);
+            queue
+                .entry(engine.url.clone())
+                .or_default()
+                .entry(idempotency_key)
+                .or_insert(QueuedSettlement {
+                    account_id: id,
+                    asset_scale,
+                    attempts: 0,
+                });
+            spawn_retry_task
+        };
+
+        // A single task per engine drains its queue, it exits once the queue is empty
+        if spawn_retry_task {
+            let client = self.clone();
+            tokio::spawn(async move { client.retry_queued_settlements(store, engine).await });
+        }
+    }
+
+    fn forget_queued_settlement(&self, engine_url: &Url, idempotency_key: &str) {
+        if let Some(pending) = self.queue.lock().get_mut(engine_url) {
+            pending.remove(idempotency_key);
+        }
+    }
+
+    async fn retry_queued_settlements<S>(&self, store: S, engine: SettlementEngineDetails)
+    where
+        S: SettlementStore + SettlementQueueStore + Send + Sync + 'static,
+    {
+        let engine_url = &engine.url;
+        loop {
+            tokio::time::delay_for(self.health.retry_in(engine_url)).await;
+
+            let pending: Vec<(String, QueuedSettlement)> = match self.queue.lock().get(engine_url) {
+                Some(pending) => pending
+                    .iter()
+                    .map(|(key, queued)| (key.clone(), *queued))
+                    .collect(),
+                None => return,
+            };
+
+            for (idempotency_key, queued) in pending {
+                let id = queued.account_id;
+                let amount = match store.claim_queued_settlement(id, &idempotency_key).await {
+                    Ok(amount) => amount,
+                    Err(err) => {
+                        error!(
+                            "Error claiming queued settlement of account {}: {}",
+                            id, err
+                        );
+                        continue;
+                    }
+                };
+                if amount == 0 {
+                    // Another instance sent it
+                    self.forget_queued_settlement(engine_url, &idempotency_key);
+                    continue;
+                }
+
+                let result = self
+                    .send_settlement_once(id, &engine, amount, queued.asset_scale, &idempotency_key)
+                    .await;
+                // Requests short-circuited by the circuit breaker do not count as attempts
+                let attempts = match result {
+                    Err(SettlementClientError::EngineUnavailable { .. }) => queued.attempts,
+                    _ => queued.attempts + 1,
+                };
+                match result {
+                    Ok(_) => {
+                        debug!(
+                            "Sent queued settlement of amount {} for account {} to settlement engine {}",
+                            amount, id, engine_url
+                        );
+                        let _ = store
+                            .settlement_sent(id, amount)
+                            .map_err(|err| error!("Error marking settlement as sent: {}", err))
+                            .await;
+                        self.forget_queued_settlement(engine_url, &idempotency_key);
+                    }
+                    Err(err)
+                        if err.is_rejected_by_engine() || attempts >= self.max_queued_attempts =>
+                    {
+                        error!(
+                            "Giving up on queued settlement of amount {} for account {} after {} attempts, refunding it: {}",
+                            amount, id, attempts, err
+                        );
+                        let _ = store
+                            .refund_settlement(id, amount)
+                            .map_err(|err| error!("Error refunding settlement: {}", err))
+                            .await;
+                        self.forget_queued_settlement(engine_url, &idempotency_key);
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Queued settlement for account {} failed, will retry: {}",
+                            id, err
+                        );
+                        if let Err(err) = store.queue_settlement(id, amount, &idempotency_key).await
+                        {
+                            error!("Error queueing settlement again: {}", err);
+                        }
+                        if let Some(requeued) = self
+                            .queue
+                            .lock()
+                            .get_mut(engine_url)
+                            .and_then(|pending| pending.get_mut(&idempotency_key))
+                        {
+                            requeued.attempts = attempts;
+                        }
+                    }
+                }
+            }
+
+            let drained = {
+                let mut queue = self.queue.lock();
+                let drained = queue
+                    .get(engine_url)
+                    .map(|pending| pending.is_empty())
+                    .unwrap_or(true);
+                if drained {
+                    queue.remove(engine_url);
+                }
+                drained
+            };
+            if drained {
+                return;
+            }
+            // Avoid hammering an engine which keeps failing without opening its circuit
+            if self.health.retry_in(engine_url) == Duration::from_secs(0) {
+                tokio::time::delay_for(Duration::from_secs(1)).await;
+            }
+        }
+    }
+
+    /// Fails fast if the circuit breaker of the engine is open
+    fn acquire(&self, engine_url: &Url) -> Result<EnginePermit, SettlementClientError> {
+        self.health.try_acquire(engine_url).map_err(|retry_in| {
+            trace!(
+                "Not sending request to settlement engine {}, its circuit breaker is open",
+                engine_url
+            );
+            SettlementClientError::EngineUnavailable {
+                url: engine_url.clone(),
+                retry_in,
+            }
+        })
+    }
+}
+
+/// Timeouts, connection errors and 5xx responses count against the engine's health.
+/// Any other response shows that the engine is up.
+fn record_outcome(permit: EnginePermit, response: &Result<reqwest::Response, reqwest::Error>) {
+    match response {
+        Ok(response) if !response.status().is_server_error() => permit.record_success(),
+        _ => permit.record_failure(),
+    }
+}
+
+/// Adds the engine's bearer token to the request, if the engine is configured with one
+fn authorize(request: RequestBuilder, engine: &SettlementEngineDetails) -> RequestBuilder {
+    match engine.auth_token {
+        Some(ref auth_token) => request.bearer_auth(auth_token.expose_secret()),
+        None => request,
     }
 }
 
@@ -161,34 +567,45 @@ impl RequestErrorHandler {
             current_attempt: 0,
         }
     }
+
+    /// Exponential backoff starting from `base`, capped at `MAX_RETRY_DELAY`
+    fn backoff(&self, base: Duration) -> Duration {
+        let exponent = min(self.current_attempt.saturating_sub(1), 16) as u32;
+        min(base * 2u32.pow(exponent), MAX_RETRY_DELAY)
+    }
 }
 
-impl ErrorHandler<reqwest::Error> for RequestErrorHandler {
-    type OutError = reqwest::Error;
+impl ErrorHandler<SettlementClientError> for RequestErrorHandler {
+    type OutError = SettlementClientError;
 
     /// Handler of errors for the retry logic
-    fn handle(&mut self, e: reqwest::Error) -> RetryPolicy<reqwest::Error> {
+    fn handle(&mut self, e: SettlementClientError) -> RetryPolicy<SettlementClientError> {
         self.current_attempt += 1;
         if self.current_attempt > self.max_attempts {
             return RetryPolicy::ForwardError(e);
         }
-        if e.is_timeout() {
-            RetryPolicy::WaitRetry(Duration::from_secs(5))
-        } else if let Some(status) = e.status() {
+        let err = match e {
+            SettlementClientError::Request(ref err) => err,
+            // The circuit breaker is open, there is no point in retrying
+            SettlementClientError::EngineUnavailable { .. } => return RetryPolicy::ForwardError(e),
+        };
+        if err.is_timeout() {
+            RetryPolicy::WaitRetry(self.backoff(Duration::from_secs(5)))
+        } else if let Some(status) = err.status() {
             if status.is_client_error() {
                 // do not retry 4xx
                 RetryPolicy::ForwardError(e)
             } else if status.is_server_error() {
-                // Retry 5xx every 5 seconds
-                RetryPolicy::WaitRetry(Duration::from_secs(5))
+                // Retry 5xx starting after 5 seconds
+                RetryPolicy::WaitRetry(self.backoff(Duration::from_secs(5)))
             } else {
-                // Otherwise just retry every second
-                RetryPolicy::WaitRetry(Duration::from_secs(1))
+                // Otherwise just retry starting after a second
+                RetryPolicy::WaitRetry(self.backoff(Duration::from_secs(1)))
             }
         } else {
             // Retry other errors slightly more frequently since they may be
             // related to the engine not having started yet
-            RetryPolicy::WaitRetry(Duration::from_secs(1))
+            RetryPolicy::WaitRetry(self.backoff(Duration::from_secs(1)))
         }
     }
 }
@@ -202,8 +619,10 @@ impl Default for SettlementClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::test_helpers::{TestAccount, TestStore};
     use mockito::{mock, Matcher};
     use once_cell::sync::Lazy;
+    use secrecy::SecretString;
 
     pub static SETTLEMENT_API: Lazy<Matcher> = Lazy::new(|| {
         Matcher::Regex(r"^/accounts/[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}/settlements$".to_string())
@@ -226,9 +645,10 @@ mod tests {
         let ret = client
             .send_settlement(
                 Uuid::new_v4(),
-                "http://localhost:1234".parse().unwrap(),
+                SettlementEngineDetails::new("http://localhost:1234".parse().unwrap()),
                 100,
                 6,
+                "key",
             )
             .await;
 
@@ -236,6 +656,25 @@ mod tests {
         assert!(ret.is_ok());
     }
 
+    #[tokio::test]
+    async fn sends_auth_token() {
+        let m = mock_settlement(200)
+            .match_header("Authorization", "Bearer secret")
+            .create();
+        let client = SettlementClient::default();
+        let engine = SettlementEngineDetails {
+            url: "http://localhost:1234".parse().unwrap(),
+            auth_token: Some(SecretString::new("secret".to_string())),
+        };
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine, 100, 6, "key")
+            .await;
+
+        m.assert();
+        assert!(ret.is_ok());
+    }
+
     #[tokio::test]
     async fn engine_rejects() {
         let m = mock_settlement(500)
@@ -247,13 +686,227 @@ mod tests {
         let ret = client
             .send_settlement(
                 Uuid::new_v4(),
-                "http://localhost:1234".parse().unwrap(),
+                SettlementEngineDetails::new("http://localhost:1234".parse().unwrap()),
                 100,
                 6,
+                "key",
             )
             .await;
 
         m.assert();
         assert!(ret.is_err());
     }
+
+    #[tokio::test]
+    async fn circuit_breaker_fails_fast() {
+        // Nothing listens on this port so every request fails
+        let engine = SettlementEngineDetails::new("http://127.0.0.1:1".parse().unwrap());
+        let client = SettlementClient::with_circuit_breaker(
+            Duration::from_secs(1),
+            0,
+            CircuitBreakerConfig {
+                failure_threshold: 1,
+                base_backoff: Duration::from_secs(60),
+                max_backoff: Duration::from_secs(60),
+            },
+        );
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine.clone(), 100, 6, "key")
+            .await;
+        match ret {
+            Err(SettlementClientError::Request(_)) => {}
+            other => panic!("expected a request error, got {:?}", other),
+        }
+
+        let ret = client
+            .send_settlement(Uuid::new_v4(), engine.clone(), 100, 6, "key")
+            .await;
+        match ret {
+            Err(SettlementClientError::EngineUnavailable { .. }) => {}
+            other => panic!("expected the engine to be unavailable, got {:?}", other),
+        }
+        assert_eq!(
+            client.engine_health().health(&engine.url).unwrap().state,
+            crate::core::engine_health::CircuitState::Open
+        );
+    }
+
+    fn open_circuit_breaker() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: 1,
+            base_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_the_same_idempotency_key() {
+        let m = mock_settlement(500)
+            .match_header("Idempotency-Key", "key")
+            .create()
+            .expect(2);
+        let client = SettlementClient::new(Duration::from_secs(1), 1);
+
+        let ret = client
+            .send_settlement(
+                Uuid::new_v4(),
+                SettlementEngineDetails::new("http://localhost:1234".parse().unwrap()),
+                100,
+                6,
+                "key",
+            )
+            .await;
+
+        m.assert();
+        assert!(ret.is_err());
+    }
+
+    #[tokio::test]
+    async fn queues_settlements_separately() {
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let engine = account.settlement_engine_details().unwrap();
+        let store = TestStore::new(vec![account.clone()]);
+        // The engine's circuit is open so the settlements are not retried during the test
+        let client = SettlementClient::with_circuit_breaker(
+            Duration::from_secs(1),
+            0,
+            open_circuit_breaker(),
+        );
+        client.engine_health().record_failure(&engine.url);
+
+        store.settle(account.id, 100);
+        store.settle(account.id, 50);
+        client
+            .queue_settlement(
+                store.clone(),
+                account.id,
+                engine.clone(),
+                100,
+                9,
+                "a".into(),
+            )
+            .await
+            .unwrap();
+        client
+            .queue_settlement(store.clone(), account.id, engine.clone(), 50, 9, "b".into())
+            .await
+            .unwrap();
+
+        // The engine may already have handled the first one, so they must keep their own keys
+        assert_eq!(store.queued.lock()["a"], (account.id, 100));
+        assert_eq!(store.queued.lock()["b"], (account.id, 50));
+        assert_eq!(store.in_flight.lock()[&account.id], 0);
+        assert_eq!(store.get_pending_settlement(account.id).await.unwrap(), 150);
+        assert_eq!(client.queue.lock()[&engine.url].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn refunds_queued_settlement_after_max_attempts() {
+        // Nothing listens on this port so every attempt fails
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let engine = account.settlement_engine_details().unwrap();
+        let store = TestStore::new(vec![account.clone()]);
+        let client = SettlementClient::with_circuit_breaker(
+            Duration::from_secs(1),
+            0,
+            CircuitBreakerConfig {
+                failure_threshold: 100,
+                ..Default::default()
+            },
+        )
+        .with_max_queued_attempts(2);
+
+        store.settle(account.id, 100);
+        client
+            .queue_settlement(
+                store.clone(),
+                account.id,
+                engine.clone(),
+                100,
+                9,
+                "a".into(),
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..100 {
+            if client.queue.lock().is_empty() {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+        assert!(client.queue.lock().is_empty());
+        assert_eq!(store.refunded.lock()[&account.id], 100);
+        assert_eq!(store.get_pending_settlement(account.id).await.unwrap(), 0);
+        assert!(store.sent.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_queued_settlement_with_its_idempotency_key() {
+        let m = mock_settlement(200)
+            .match_header("Idempotency-Key", "a")
+            .create();
+        let account = TestAccount::new("http://localhost:1234");
+        let engine = account.settlement_engine_details().unwrap();
+        let store = TestStore::new(vec![account.clone()]);
+        let client = SettlementClient::new(Duration::from_secs(1), 0);
+
+        store.settle(account.id, 100);
+        client
+            .queue_settlement(
+                store.clone(),
+                account.id,
+                engine.clone(),
+                100,
+                9,
+                "a".into(),
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..100 {
+            if client.queue.lock().is_empty() {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+        m.assert();
+        assert_eq!(store.sent.lock()[&account.id], 100);
+        assert!(store.queued.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn resumes_queued_settlements() {
+        let account = TestAccount::new("http://127.0.0.1:1");
+        let engine = account.settlement_engine_details().unwrap();
+        let without_engine = TestAccount {
+            id: Uuid::new_v4(),
+            engine_url: None,
+        };
+        let store = TestStore::new(vec![account.clone(), without_engine.clone()]);
+        // Settlements queued before the node restarted
+        store.queued.lock().insert("a".into(), (account.id, 100));
+        store
+            .queued
+            .lock()
+            .insert("b".into(), (without_engine.id, 50));
+        store.queued.lock().insert("c".into(), (Uuid::new_v4(), 10));
+        let client = SettlementClient::with_circuit_breaker(
+            Duration::from_secs(1),
+            0,
+            open_circuit_breaker(),
+        );
+        client.engine_health().record_failure(&engine.url);
+
+        client
+            .resume_queued_settlements(store.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(client.queue.lock()[&engine.url]["a"].account_id, account.id);
+        assert_eq!(store.queued.lock().len(), 1);
+        assert_eq!(store.queued.lock()["a"], (account.id, 100));
+        assert_eq!(store.refunded.lock()[&without_engine.id], 50);
+    }
 }
@@ -0,0 +1,310 @@
+use super::{
+    reconciliation::{ReconciliationState, SettlementJournalStore, SettlementTotals},
+    types::{
+        LeftoversStore, SettlementAccount, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
+};
+use async_trait::async_trait;
+use interledger_errors::{AccountStoreError, LeftoversStoreError, SettlementStoreError};
+use interledger_packet::Address;
+use interledger_service::{Account, AccountStore, Username};
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+};
+use url::Url;
+use uuid::Uuid;
+
+pub static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+pub static ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+#[derive(Debug, Clone)]
+pub struct TestAccount {
+    pub id: Uuid,
+    pub engine_url: Option<Url>,
+}
+
+impl TestAccount {
+    pub fn new(engine_url: &str) -> Self {
+        TestAccount {
+            id: Uuid::new_v4(),
+            engine_url: Some(engine_url.parse().unwrap()),
+        }
+    }
+}
+
+impl Account for TestAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &ALICE
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &ILP_ADDRESS
+    }
+}
+
+impl SettlementAccount for TestAccount {
+    fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
+        self.engine_url.clone().map(SettlementEngineDetails::new)
+    }
+}
+
+/// Store keeping the settlements of its accounts in memory
+#[derive(Clone, Default)]
+pub struct TestStore {
+    pub accounts: Vec<TestAccount>,
+    pub in_flight: Arc<Mutex<HashMap<Uuid, u64>>>,
+    /// Queued settlements by idempotency key
+    pub queued: Arc<Mutex<HashMap<String, (Uuid, u64)>>>,
+    pub refunded: Arc<Mutex<HashMap<Uuid, u64>>>,
+    pub sent: Arc<Mutex<HashMap<Uuid, u64>>>,
+    pub totals: Arc<Mutex<HashMap<Uuid, SettlementTotals>>>,
+    pub reconciliation: Arc<Mutex<HashMap<Uuid, ReconciliationState>>>,
+    pub idempotency_keys: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TestStore {
+    pub fn new(accounts: Vec<TestAccount>) -> Self {
+        TestStore {
+            accounts,
+            ..Default::default()
+        }
+    }
+
+    /// Marks a settlement as in flight, like processing a fulfill does
+    pub fn settle(&self, account_id: Uuid, amount: u64) {
+        *self.in_flight.lock().entry(account_id).or_default() += amount;
+        self.totals.lock().entry(account_id).or_default().sent += amount;
+    }
+
+    fn refund(&self, account_id: Uuid, amount: u64) {
+        *self.refunded.lock().entry(account_id).or_default() += amount;
+        let mut totals = self.totals.lock();
+        let totals = totals.entry(account_id).or_default();
+        totals.sent = totals.sent.saturating_sub(amount);
+    }
+}
+
+fn decrement(amounts: &Mutex<HashMap<Uuid, u64>>, account_id: Uuid, amount: u64) {
+    let mut amounts = amounts.lock();
+    let remaining = amounts.entry(account_id).or_default();
+    *remaining = remaining.saturating_sub(amount);
+}
+
+#[async_trait]
+impl SettlementStore for TestStore {
+    type Account = TestAccount;
+
+    async fn update_balance_for_incoming_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: Option<String>,
+    ) -> Result<(), SettlementStoreError> {
+        if let Some(idempotency_key) = idempotency_key {
+            if !self.idempotency_keys.lock().insert(idempotency_key) {
+                return Ok(());
+            }
+        }
+        self.totals.lock().entry(account_id).or_default().received += amount;
+        Ok(())
+    }
+
+    async fn refund_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        decrement(&self.in_flight, account_id, settle_amount);
+        self.refund(account_id, settle_amount);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SettlementQueueStore for TestStore {
+    async fn settlement_sent(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        decrement(&self.in_flight, account_id, amount);
+        *self.sent.lock().entry(account_id).or_default() += amount;
+        Ok(())
+    }
+
+    async fn queue_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        decrement(&self.in_flight, account_id, amount);
+        self.queued
+            .lock()
+            .entry(idempotency_key.to_string())
+            .or_insert((account_id, 0))
+            .1 += amount;
+        Ok(())
+    }
+
+    async fn claim_queued_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<u64, SettlementStoreError> {
+        let mut queued = self.queued.lock();
+        let amount = match queued.get(idempotency_key) {
+            Some((id, amount)) if *id == account_id => *amount,
+            _ => return Ok(0),
+        };
+        queued.remove(idempotency_key);
+        *self.in_flight.lock().entry(account_id).or_default() += amount;
+        Ok(amount)
+    }
+
+    async fn get_queued_settlements(
+        &self,
+    ) -> Result<Vec<(Uuid, String, u64)>, SettlementStoreError> {
+        Ok(self
+            .queued
+            .lock()
+            .iter()
+            .map(|(key, (account_id, amount))| (*account_id, key.clone(), *amount))
+            .collect())
+    }
+
+    async fn get_pending_settlement(&self, account_id: Uuid) -> Result<u64, SettlementStoreError> {
+        let in_flight = self.in_flight.lock().get(&account_id).cloned();
+        let queued: u64 = self
+            .queued
+            .lock()
+            .values()
+            .filter(|(id, _)| *id == account_id)
+            .map(|(_, amount)| amount)
+            .sum();
+        Ok(in_flight.unwrap_or_default() + queued)
+    }
+}
+
+#[async_trait]
+impl AccountStore for TestStore {
+    type Account = TestAccount;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<TestAccount>, AccountStoreError> {
+        let accounts: Vec<TestAccount> = self
+            .accounts
+            .iter()
+            .filter(|account| account_ids.contains(&account.id))
+            .cloned()
+            .collect();
+        if accounts.len() == account_ids.len() {
+            Ok(accounts)
+        } else {
+            Err(AccountStoreError::WrongLength {
+                expected: account_ids.len(),
+                actual: accounts.len(),
+            })
+        }
+    }
+
+    async fn get_account_id_from_username(&self, _: &Username) -> Result<Uuid, AccountStoreError> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl SettlementJournalStore for TestStore {
+    async fn get_settlement_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<SettlementTotals, SettlementStoreError> {
+        Ok(self
+            .totals
+            .lock()
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_reconciliation_state(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<ReconciliationState>, SettlementStoreError> {
+        Ok(self.reconciliation.lock().get(&account_id).cloned())
+    }
+
+    async fn set_reconciliation_state(
+        &self,
+        account_id: Uuid,
+        state: ReconciliationState,
+    ) -> Result<(), SettlementStoreError> {
+        self.reconciliation.lock().insert(account_id, state);
+        Ok(())
+    }
+
+    async fn refund_unsent_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: String,
+    ) -> Result<(), SettlementStoreError> {
+        if self.idempotency_keys.lock().insert(idempotency_key) {
+            self.refund(account_id, amount);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LeftoversStore for TestStore {
+    type AccountId = Uuid;
+    type AssetType = BigUint;
+
+    async fn save_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _: (BigUint, u8),
+    ) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn load_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+        _: u8,
+    ) -> Result<BigUint, LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn clear_uncredited_settlement_amount(&self, _: Uuid) -> Result<(), LeftoversStoreError> {
+        unimplemented!()
+    }
+
+    async fn get_uncredited_settlement_amount(
+        &self,
+        _: Uuid,
+    ) -> Result<(BigUint, u8), LeftoversStoreError> {
+        Ok((BigUint::from(0u32), 9))
+    }
+}
@@ -7,6 +7,7 @@ use interledger_packet::Address;
 use interledger_service::Account;
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
+use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use std::ops::{Div, Mul};
 use std::str::FromStr;
@@ -96,9 +97,23 @@ pub trait SettlementEngine {
 // All instances of this struct should be replaced with Url instances once/if we
 // agree that there is no more info required to refer to an engine.
 /// The details associated with a settlement engine
+#[derive(Clone, Debug)]
 pub struct SettlementEngineDetails {
     /// Base URL of the settlement engine
     pub url: Url,
+    /// Bearer token sent along every request to the engine. If set, the engine
+    /// must also present it when calling the node's settlement API
+    pub auth_token: Option<SecretString>,
+}
+
+impl SettlementEngineDetails {
+    /// Returns the details of an engine which does not use authentication
+    pub fn new(url: Url) -> Self {
+        SettlementEngineDetails {
+            url,
+            auth_token: None,
+        }
+    }
 }
 
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [settlement](https://interledger.org/rfcs/0038-settlement-engines/) related information
@@ -136,6 +151,49 @@ pub trait SettlementStore {
     ) -> Result<(), SettlementStoreError>;
 }
 
+/// Trait used by the connector to keep track of the outgoing settlements which were
+/// deducted from the accounts' balances but not yet accepted by their engine.
+///
+/// A settlement is in flight while it is being sent to the engine. If the engine is
+/// unavailable, the settlement is queued until it is retried, so that the queue survives
+/// restarts of the node and is shared by all the instances using the same store.
+#[async_trait]
+pub trait SettlementQueueStore {
+    /// Marks the in-flight settlement of the account as accepted by the engine
+    async fn settlement_sent(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Moves the in-flight settlement of the account to its queue, along with the
+    /// idempotency key it must be retried with. Queued settlements are never merged
+    /// since the engine may already have processed some of them
+    async fn queue_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Takes the settlement queued for the account with the idempotency key so that it can
+    /// be sent, marking it as in flight. Returns 0 if it is not queued, e.g. because another
+    /// instance took it
+    async fn claim_queued_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<u64, SettlementStoreError>;
+
+    /// Returns the account, idempotency key and amount of each queued settlement
+    async fn get_queued_settlements(
+        &self,
+    ) -> Result<Vec<(Uuid, String, u64)>, SettlementStoreError>;
+
+    /// Returns the total amount of the account's settlements which are queued or in flight
+    async fn get_pending_settlement(&self, account_id: Uuid) -> Result<u64, SettlementStoreError>;
+}
+
 /// Trait used by the connector and engine to track amounts which should have been
 /// settled but were not due to precision loss
 #[async_trait]
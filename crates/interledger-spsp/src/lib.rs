@@ -44,6 +44,13 @@ pub struct SpspResponse {
     shared_secret: Vec<u8>,
 }
 
+impl SpspResponse {
+    /// The ILP Address payments for this SPSP connection are sent to
+    pub fn destination_account(&self) -> &Address {
+        &self.destination_account
+    }
+}
+
 // From https://github.com/serde-rs/json/issues/360#issuecomment-330095360
 #[doc(hidden)]
 mod serde_base64 {
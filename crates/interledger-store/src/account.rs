@@ -7,7 +7,8 @@ use interledger_http::HttpAccount;
 use interledger_packet::Address;
 use interledger_service::{Account as AccountTrait, Username};
 use interledger_service_util::{
-    MaxPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
+    BalanceAlertAccount, MaxPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount,
+    DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
 use ring::aead;
@@ -83,6 +84,15 @@ pub struct Account {
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
     pub(crate) settlement_engine_url: Option<Url>,
+    /// The token authenticating the requests to and from the account's settlement engine.
+    /// It is configured along with the global settlement engines and looked up by the
+    /// URL of the engine the account uses
+    #[serde(skip)]
+    pub(crate) settlement_engine_auth_token: Option<SecretBytesMut>,
+    /// Percentage of the credit limit the account may use before an alert is raised
+    pub(crate) credit_limit_alert_percent: Option<u8>,
+    /// Time in milliseconds the balance may stay above the settle threshold before an alert is raised
+    pub(crate) settle_threshold_alert_delay: Option<u64>,
 }
 
 fn address_to_string<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
@@ -173,6 +183,9 @@ impl Account {
             packets_per_minute_limit: details.packets_per_minute_limit,
             amount_per_minute_limit: details.amount_per_minute_limit,
             settlement_engine_url,
+            settlement_engine_auth_token: None,
+            credit_limit_alert_percent: details.credit_limit_alert_percent,
+            settle_threshold_alert_delay: details.settle_threshold_alert_delay,
         })
     }
 
@@ -262,6 +275,17 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
+        if let Some(ref encrypted) = self.account.settlement_engine_auth_token {
+            self.account.settlement_engine_auth_token =
+                decrypt_token(decryption_key, encrypted.expose_secret())
+                    .map_err(|_| {
+                        error!(
+                            "Unable to decrypt settlement_engine_auth_token for account {}",
+                            self.account.id
+                        )
+                    })
+                    .ok();
+        }
 
         self.account
     }
@@ -349,10 +373,37 @@ impl RateLimitAccount for Account {
     }
 }
 
+impl BalanceAlertAccount for Account {
+    fn min_balance(&self) -> Option<i64> {
+        self.min_balance
+    }
+
+    fn settle_threshold(&self) -> Option<i64> {
+        self.settle_threshold
+    }
+
+    fn credit_limit_alert_percent(&self) -> Option<u8> {
+        self.credit_limit_alert_percent
+    }
+
+    fn settle_threshold_alert_delay(&self) -> Option<u64> {
+        self.settle_threshold_alert_delay
+    }
+}
+
 impl SettlementAccount for Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         match &self.settlement_engine_url {
-            Some(url) => Some(SettlementEngineDetails { url: url.clone() }),
+            Some(url) => Some(SettlementEngineDetails {
+                url: url.clone(),
+                auth_token: self.settlement_engine_auth_token.as_ref().map(|token| {
+                    SecretString::new(
+                        str::from_utf8(token.expose_secret())
+                            .unwrap_or("")
+                            .to_string(),
+                    )
+                }),
+            }),
             _ => None,
         }
     }
@@ -385,6 +436,8 @@ mod test {
         amount_per_minute_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
+        credit_limit_alert_percent: None,
+        settle_threshold_alert_delay: None,
     });
 
     #[test]
@@ -0,0 +1,13 @@
+local remainders = 'remainders:' .. ARGV[1]
+local to_account = ARGV[2]
+local remainder = tonumber(ARGV[3])
+local precision = tonumber(ARGV[4])
+
+local total = redis.call('HINCRBY', remainders, to_account, remainder)
+-- Take out the whole units, leaving only a fraction of a unit
+if total < precision then
+    return 0
+end
+local units = math.floor(total / precision)
+redis.call('HINCRBY', remainders, to_account, -units * precision)
+return units
@@ -0,0 +1,45 @@
+-- Adds the fees earned in a batch of revenue entries. ARGV holds groups of
+-- bucket start (in ms), field prefix, amount, reporting amount and packets.
+-- HINCRBY only handles signed 64 bit integers, so the totals are added as
+-- decimal strings and capped at the largest unsigned 64 bit integer instead
+local MAX_TOTAL = '18446744073709551615'
+
+local function add(a, b)
+    local digits = {}
+    local carry = 0
+    local i, j = #a, #b
+    while i > 0 or j > 0 or carry > 0 do
+        local digit = carry
+        if i > 0 then
+            digit = digit + tonumber(string.sub(a, i, i))
+            i = i - 1
+        end
+        if j > 0 then
+            digit = digit + tonumber(string.sub(b, j, j))
+            j = j - 1
+        end
+        table.insert(digits, 1, digit % 10)
+        carry = math.floor(digit / 10)
+    end
+    local sum = table.concat(digits)
+    if #sum > #MAX_TOTAL or (#sum == #MAX_TOTAL and sum > MAX_TOTAL) then
+        return MAX_TOTAL
+    end
+    return sum
+end
+
+local function increase(key, field, amount)
+    local total = redis.call('HGET', key, field) or '0'
+    redis.call('HSET', key, field, add(total, amount))
+end
+
+for i = 1, #ARGV, 5 do
+    local bucket_start = ARGV[i]
+    local key = 'revenue:' .. bucket_start
+    local prefix = ARGV[i + 1]
+    redis.call('ZADD', 'revenue:buckets', bucket_start, bucket_start)
+    increase(key, prefix .. '|amount', ARGV[i + 2])
+    increase(key, prefix .. '|reporting_amount', ARGV[i + 3])
+    increase(key, prefix .. '|packets', ARGV[i + 4])
+end
+return #ARGV / 5
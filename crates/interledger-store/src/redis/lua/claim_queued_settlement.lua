@@ -0,0 +1,14 @@
+local account = 'accounts:' .. ARGV[1]
+local settlement = ARGV[1] .. ':' .. ARGV[2]
+
+local amount = tonumber(redis.call('HGET', 'settlements:queued', settlement) or 0)
+redis.call('HDEL', 'settlements:queued', settlement)
+-- The queued amount is dropped along with the account
+if amount > 0 and redis.call('EXISTS', account) == 1 then
+    if tonumber(redis.call('HINCRBY', account, 'settlement_queued', -amount)) < 0 then
+        redis.call('HSET', account, 'settlement_queued', 0)
+    end
+    redis.call('HINCRBY', account, 'settlement_in_flight', amount)
+    return amount
+end
+return 0
@@ -0,0 +1,14 @@
+-- Replaces the updates of the advertised routing table ARGV[1] up to epoch ARGV[2]
+-- with the snapshot ARGV[3], which combines all of them
+local table_id = redis.call('GET', 'routes:forwarding:id')
+if table_id ~= ARGV[1] then
+    return 0
+end
+local epoch = tonumber(ARGV[2])
+for _, field in ipairs(redis.call('HKEYS', 'routes:forwarding:log')) do
+    if tonumber(field) < epoch then
+        redis.call('HDEL', 'routes:forwarding:log', field)
+    end
+end
+redis.call('HSET', 'routes:forwarding:log', ARGV[2], ARGV[3])
+return 1
@@ -8,6 +8,7 @@ local function into_dictionary(flat_map)
 end
 
 local settlement_engines = into_dictionary(redis.call('HGETALL', 'settlement_engines'))
+local settlement_engine_auth_tokens = into_dictionary(redis.call('HGETALL', 'settlement_engine_auth_tokens'))
 local accounts = {}
 
 -- TODO get rid of the two representations of account
@@ -38,6 +39,18 @@ for index, id in ipairs(ARGV) do
             end
         end
 
+        -- The (encrypted) auth tokens are keyed by the settlement engine's URL,
+        -- so the account uses the token of whichever engine it talks to
+        local engine_url = account_dict.settlement_engine_url or settlement_engines[account_dict.asset_code]
+        local auth_token = nil
+        if engine_url ~= nil then
+            auth_token = settlement_engine_auth_tokens[engine_url]
+        end
+        if auth_token ~= nil then
+            table.insert(account, 'settlement_engine_auth_token')
+            table.insert(account, auth_token)
+        end
+
         table.insert(accounts, account)
     end
 end
@@ -17,6 +17,10 @@ if (settle_threshold and settle_to) and (balance >= tonumber(settle_threshold))
     -- the balance change by re-adding the amount back to the balance
     balance = settle_to
     redis.call('HSET', to_account, 'balance', balance)
+    -- Keep track of the total settled, for reconciliation with the settlement engine
+    redis.call('HINCRBY', to_account, 'settled_outgoing', settle_amount)
+    -- The settlement is in flight until the settlement engine accepts it
+    redis.call('HINCRBY', to_account, 'settlement_in_flight', settle_amount)
 end
 
 return {balance + prepaid_amount, settle_amount}
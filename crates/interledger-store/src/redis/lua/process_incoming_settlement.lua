@@ -12,6 +12,9 @@ end
 -- Otherwise, set it to true and make it expire after 24h (86400 sec)
 redis.call('SET', idempotency_key, 'true', 'EX', 86400)
 
+-- Keep track of the total received, for reconciliation with the settlement engine
+redis.call('HINCRBY', account, 'settled_incoming', amount)
+
 -- Credit the incoming settlement to the balance and/or prepaid amount,
 -- depending on whether that account currently owes money or not
 if tonumber(balance) >= 0 then
@@ -0,0 +1,13 @@
+local account = 'accounts:' .. ARGV[1]
+local amount = tonumber(ARGV[2])
+-- Each settlement is queued along with the idempotency key it is retried with
+local settlement = ARGV[1] .. ':' .. ARGV[3]
+
+-- The settlement is no longer in flight, it waits in the queue until it is retried
+if redis.call('EXISTS', account) == 1 then
+    if tonumber(redis.call('HINCRBY', account, 'settlement_in_flight', -amount)) < 0 then
+        redis.call('HSET', account, 'settlement_in_flight', 0)
+    end
+    redis.call('HINCRBY', account, 'settlement_queued', amount)
+    redis.call('HINCRBY', 'settlements:queued', settlement, amount)
+end
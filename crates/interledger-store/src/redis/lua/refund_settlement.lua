@@ -1,5 +1,19 @@
 local account = 'accounts:' .. ARGV[1]
 local settle_amount = tonumber(ARGV[2])
+local idempotency_key = ARGV[3]
+
+-- Refunds found by the reconciliation with the settlement engine are only applied once
+if idempotency_key then
+    if redis.call('EXISTS', idempotency_key) == 1 then
+        return tonumber(redis.call('HGET', account, 'balance')) or 0
+    end
+    redis.call('SET', idempotency_key, 'true', 'EX', 86400)
+end
 
 local balance = redis.call('HINCRBY', account, 'balance', settle_amount)
+-- The settlement was never sent so it no longer counts towards the total settled
+redis.call('HINCRBY', account, 'settled_outgoing', -settle_amount)
+if tonumber(redis.call('HINCRBY', account, 'settlement_in_flight', -settle_amount)) < 0 then
+    redis.call('HSET', account, 'settlement_in_flight', 0)
+end
 return balance
@@ -0,0 +1,8 @@
+-- Removes the revenue of the periods starting before ARGV[1] (in ms)
+local before = '(' .. ARGV[1]
+local buckets = redis.call('ZRANGEBYSCORE', 'revenue:buckets', '-inf', before)
+for _, bucket in ipairs(buckets) do
+    redis.call('DEL', 'revenue:' .. bucket)
+end
+redis.call('ZREMRANGEBYSCORE', 'revenue:buckets', '-inf', before)
+return #buckets
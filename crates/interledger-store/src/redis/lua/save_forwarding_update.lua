@@ -0,0 +1,14 @@
+-- Saves the update ARGV[3] of the advertised routing table ARGV[1] at epoch ARGV[2].
+-- The updates of any other table are discarded first, since they cannot be
+-- applied on top of the new table
+local table_id = redis.call('GET', 'routes:forwarding:id')
+local started = table_id ~= ARGV[1]
+if started then
+    redis.call('DEL', 'routes:forwarding:log')
+    redis.call('SET', 'routes:forwarding:id', ARGV[1])
+end
+redis.call('HSET', 'routes:forwarding:log', ARGV[2], ARGV[3])
+if started then
+    return 1
+end
+return 0
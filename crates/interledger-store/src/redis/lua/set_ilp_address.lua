@@ -0,0 +1,39 @@
+-- Sets the node's address to ARGV[1] and updates the addresses and routes of the
+-- children and non-routing accounts whose address was derived from the old one.
+-- ARGV[2] is the old address, in case none was saved yet
+local parent_address_key = 'parent_node_account_address'
+local routes_key = 'routes:current'
+
+-- The address of a child is the node's address with its username appended,
+-- unless the username is the last segment of the node's address
+local function child_address(node_address, username)
+    if string.match(node_address, '[^.]+$') == username then
+        return node_address
+    end
+    return node_address .. '.' .. username
+end
+
+local new_address = ARGV[1]
+local old_address = redis.call('GET', parent_address_key)
+if not old_address then
+    old_address = ARGV[2]
+end
+
+redis.call('SET', parent_address_key, new_address)
+local updated = 0
+for _, id in ipairs(redis.call('SMEMBERS', 'accounts')) do
+    local account_key = 'accounts:' .. id
+    local account = redis.call('HMGET', account_key, 'username', 'ilp_address', 'routing_relation')
+    local username, ilp_address = account[1], account[2]
+    local relation = string.lower(account[3] or '')
+    -- Parents and peers, and the accounts whose address was configured, are left as they are
+    if username and relation ~= 'parent' and relation ~= 'peer'
+        and ilp_address == child_address(old_address, username) then
+        local new_ilp_address = child_address(new_address, username)
+        redis.call('HDEL', routes_key, ilp_address)
+        redis.call('HSET', account_key, 'ilp_address', new_ilp_address)
+        redis.call('HSET', routes_key, new_ilp_address, id)
+        updated = updated + 1
+    end
+end
+return updated
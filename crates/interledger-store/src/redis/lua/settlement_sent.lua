@@ -0,0 +1,8 @@
+local account = 'accounts:' .. ARGV[1]
+local amount = tonumber(ARGV[2])
+
+if redis.call('EXISTS', account) == 1 then
+    if tonumber(redis.call('HINCRBY', account, 'settlement_in_flight', -amount)) < 0 then
+        redis.call('HSET', account, 'settlement_in_flight', 0)
+    end
+end
@@ -2,10 +2,26 @@
 //   send_routes_to         set         used for CCP routing
 //   receive_routes_from    set         used for CCP routing
 //   next_account_id        string      unique ID for each new account
-//   rates:current          hash        exchange rates
+//   rates:current          hash        exchange rates, JSON-encoded, keyed by asset code
+//   rates:updates          channel     pubsub channel exchange rate updates are published on
+//   rates:history:<code>   list        most recent exchange rates of each asset, JSON-encoded
+//   fees:schedule          string      JSON-encoded fee schedule
+//   fees:updates           channel     pubsub channel fee schedule updates are published on
+//   revenue:buckets        sorted set  start (in ms) of the periods revenue was earned in
+//   revenue:<start>        hash        fees earned in a period, per account pair and assets
 //   routes:current         hash        dynamic routing table
 //   routes:static          hash        static routing table
+//   routes:alternates      hash        alternate next hops of each prefix, JSON-encoded
+//   routes:multipath       hash        members and weights of multipath static routes, JSON-encoded
+//   routes:policies        hash        route policy of each account, JSON-encoded
+//   routes:policies:updates channel    pubsub channel the accounts whose route policy changed are published on
+//   routes:forwarding:id   string      ID of the routing table advertised over CCP
+//   routes:forwarding:log  hash        updates of the advertised routing table, keyed by epoch,
+//                                      the first one being a snapshot of the updates before it
+//   routes:details         string      snapshot of the CCP routing tables, JSON-encoded
 //   accounts:<id>          hash        information for each account
+//   balance_alerts         list        most recent balance alerts of all accounts
+//   balance_alerts:<id>    list        most recent balance alerts of each account
 //   btp_outgoing
 // For interactive exploration of the store,
 // use the redis-cli tool included with your redis install.
@@ -18,27 +34,39 @@ mod reconnect;
 use reconnect::RedisReconnect;
 
 use super::account::{Account, AccountWithEncryptedTokens};
-use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::crypto::{decrypt_token, encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, TimeZone, Utc};
 use futures::channel::mpsc::UnboundedSender;
 use http::StatusCode;
 use interledger_api::{AccountDetails, AccountSettings, EncryptedAccountSettings, NodeStore};
 use interledger_btp::BtpStore;
-use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
+use interledger_ccp::{
+    CcpRoutingAccount, CcpRoutingStore, ForwardingTableState, RouteDetailsStore, RoutePolicy,
+    RoutePolicyStore, RoutingRelation, RoutingTableDetails,
+};
 use interledger_errors::*;
 use interledger_http::HttpStore;
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{
+    ExchangeRate, ExchangeRateStore, ExchangeRateSubscriptionStore, RateHistoryStore,
+};
 use interledger_router::RouterStore;
 use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
 use interledger_service_util::{
-    BalanceStore, RateLimitError, RateLimitStore, DEFAULT_ROUND_TRIP_TIME,
+    BalanceAlert, BalanceAlertStore, BalanceStore, FeeSchedule, FeeStore, RateLimitError,
+    RateLimitStore, RemainderStore, RevenueEntry, RevenueStore, DEFAULT_ROUND_TRIP_TIME,
+    REMAINDER_PRECISION,
 };
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
+    reconciliation::{ReconciliationState, SettlementJournalStore, SettlementTotals},
     scale_with_precision_loss,
-    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+    types::{
+        Convert, ConvertDetails, LeftoversStore, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
 };
 use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use num_bigint::BigUint;
@@ -49,10 +77,10 @@ use redis_crate::{
     self, cmd, from_redis_value, Client, ConnectionInfo, ControlFlow, ErrorKind, FromRedisValue,
     PubSubCommands, RedisError, RedisWrite, Script, ToRedisArgs, Value,
 };
-use secrecy::{ExposeSecret, Secret, SecretBytesMut};
+use secrecy::{ExposeSecret, Secret, SecretBytesMut, SecretString};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
 };
 use std::{
@@ -69,14 +97,41 @@ use uuid::Uuid;
 use zeroize::Zeroize;
 
 const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
-const ACCOUNT_DETAILS_FIELDS: usize = 21;
+const ACCOUNT_DETAILS_FIELDS: usize = 23;
+/// Number of alerts kept in each of the balance alert lists
+const MAX_BALANCE_ALERTS: isize = 1000;
+/// Number of rates kept in the history of each asset
+const MAX_RATE_HISTORY: isize = 1000;
 
 static PARENT_ILP_KEY: &str = "parent_node_account_address";
 static ROUTES_KEY: &str = "routes:current";
 static STATIC_ROUTES_KEY: &str = "routes:static";
 static DEFAULT_ROUTE_KEY: &str = "routes:default";
+static ROUTE_POLICIES_KEY: &str = "routes:policies";
+static ROUTE_POLICIES_CHANNEL: &str = "routes:policies:updates";
+static ALTERNATE_ROUTES_KEY: &str = "routes:alternates";
+static MULTIPATH_ROUTES_KEY: &str = "routes:multipath";
+static FORWARDING_TABLE_ID_KEY: &str = "routes:forwarding:id";
+static FORWARDING_TABLE_LOG_KEY: &str = "routes:forwarding:log";
+static ROUTE_DETAILS_KEY: &str = "routes:details";
 static STREAM_NOTIFICATIONS_PREFIX: &str = "stream_notifications:";
 static SETTLEMENT_ENGINES_KEY: &str = "settlement_engines";
+static SETTLEMENT_ENGINE_AUTH_TOKENS_KEY: &str = "settlement_engine_auth_tokens";
+static QUEUED_SETTLEMENTS_KEY: &str = "settlements:queued";
+static BALANCE_ALERTS_KEY: &str = "balance_alerts";
+static RATES_KEY: &str = "rates:current";
+static RATES_CHANNEL: &str = "rates:updates";
+static FEES_KEY: &str = "fees:schedule";
+static FEES_CHANNEL: &str = "fees:updates";
+static REVENUE_BUCKETS_KEY: &str = "revenue:buckets";
+
+/// Data published to every instance sharing the store when it is changed
+#[derive(Serialize, Deserialize)]
+struct SharedUpdate<T> {
+    /// Instance of the store which made the change
+    instance_id: Uuid,
+    data: T,
+}
 
 /// Domain separator for leftover amounts
 fn uncredited_amount_key(account_id: impl ToString) -> String {
@@ -93,6 +148,68 @@ fn accounts_key(account_id: Uuid) -> String {
     format!("accounts:{}", account_id)
 }
 
+/// Domain separator for the rate history of an asset
+fn rate_history_key(asset_code: &str) -> String {
+    format!("rates:history:{}", asset_code)
+}
+
+/// Domain separator for the remainders owed to the accounts packets are forwarded to from an account
+fn remainders_key(account_id: Uuid) -> String {
+    format!("remainders:{}", account_id)
+}
+
+/// Domain separator for the revenue earned in a period
+fn revenue_key(bucket_start: i64) -> String {
+    format!("revenue:{}", bucket_start)
+}
+
+/// Escapes the `|` separating the parts of a revenue field (and the `%` escaping it)
+fn escape_revenue_part(part: &str) -> String {
+    part.replace('%', "%25").replace('|', "%7C")
+}
+
+fn unescape_revenue_part(part: &str) -> String {
+    part.replace("%7C", "|").replace("%25", "%")
+}
+
+/// Prefix of the fields of a revenue hash holding the totals of an account pair and assets
+fn revenue_field_prefix(revenue: &RevenueEntry) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}",
+        revenue.from_account,
+        revenue.to_account,
+        escape_revenue_part(&revenue.asset_code),
+        revenue.asset_scale,
+        escape_revenue_part(&revenue.reporting_asset),
+        revenue.reporting_scale
+    )
+}
+
+/// Parses the prefix of the fields of a revenue hash into an entry without any totals
+fn parse_revenue_field_prefix(bucket_start: DateTime<Utc>, prefix: &str) -> Option<RevenueEntry> {
+    let parts: Vec<&str> = prefix.split('|').collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    Some(RevenueEntry {
+        bucket_start,
+        from_account: Username::from_str(parts[0]).ok()?,
+        to_account: Username::from_str(parts[1]).ok()?,
+        asset_code: unescape_revenue_part(parts[2]),
+        asset_scale: parts[3].parse().ok()?,
+        amount: 0,
+        reporting_asset: unescape_revenue_part(parts[4]),
+        reporting_scale: parts[5].parse().ok()?,
+        reporting_amount: 0,
+        packets: 0,
+    })
+}
+
+/// Domain separator for the balance alerts of an account
+fn balance_alerts_key(account_id: Uuid) -> String {
+    format!("{}:{}", BALANCE_ALERTS_KEY, account_id)
+}
+
 // TODO: Add descriptive errors inside the lua scripts!
 
 // The following are Lua scripts that are used to atomically execute the given logic
@@ -132,10 +249,47 @@ static PROCESS_REJECT: Lazy<Script> =
 static REFUND_SETTLEMENT: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/refund_settlement.lua")));
 
+/// Lua script which marks an outgoing settlement as accepted by the settlement engine
+static SETTLEMENT_SENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/settlement_sent.lua")));
+
+/// Lua script which moves an outgoing settlement to the queue of the settlements to retry
+static QUEUE_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/queue_settlement.lua")));
+
+/// Lua script which takes a settlement queued for an account to send it
+static CLAIM_QUEUED_SETTLEMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/claim_queued_settlement.lua")));
+
 /// Lua script which increases the provided account's balance after an incoming settlement succeeded
 static PROCESS_INCOMING_SETTLEMENT: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/process_incoming_settlement.lua")));
 
+/// Lua script which adds a conversion remainder and takes out the whole units accumulated
+static ADD_REMAINDER: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/add_remainder.lua")));
+
+/// Lua script which adds the fees earned in a batch of revenue entries without overflowing
+static ADD_REVENUE: Lazy<Script> = Lazy::new(|| Script::new(include_str!("lua/add_revenue.lua")));
+
+/// Lua script which removes the revenue of the periods starting before a given time
+static REMOVE_REVENUE_BEFORE: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/remove_revenue_before.lua")));
+
+/// Lua script which sets the node's address and the addresses of the accounts derived from it
+static SET_ILP_ADDRESS: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/set_ilp_address.lua")));
+
+/// Lua script which saves an update of the forwarding routing table, discarding the
+/// updates of the previous table if the table changed
+static SAVE_FORWARDING_UPDATE: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/save_forwarding_update.lua")));
+
+/// Lua script which replaces the updates of the forwarding routing table up to an epoch
+/// with a snapshot of all of them
+static COMPACT_FORWARDING_LOG: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/compact_forwarding_log.lua")));
+
 /// Builder for the Redis Store
 pub struct RedisStoreBuilder {
     redis_url: ConnectionInfo,
@@ -174,8 +328,10 @@ impl RedisStoreBuilder {
     /// 1. Generates encryption and decryption keys
     /// 1. Connects to the redis store (ensuring that it reconnects in case of drop)
     /// 1. Gets the Node address assigned to us by our parent (if it exists)
+    /// 1. Loads the exchange rates and fee schedule persisted in the store
     /// 1. Starts polling for routing table updates
     /// 1. Spawns a thread to notify incoming payments over WebSockets
+    ///    and to receive the exchange rates and fees set by other instances
     pub async fn connect(&mut self) -> Result<RedisStore, ()> {
         let redis_info = self.redis_url.clone();
         let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
@@ -211,15 +367,53 @@ impl RedisStoreBuilder {
             ilp_address
         };
 
+        // Start with the rates last set by any instance sharing the store, if any
+        let stored_rates: HashMap<String, String> = connection
+            .hgetall(RATES_KEY)
+            .map_err(|err| error!("Error loading exchange rates: {:?}", err))
+            .await?;
+        let exchange_rates: HashMap<String, ExchangeRate> = stored_rates
+            .into_iter()
+            .filter_map(|(code, rate)| match serde_json::from_str(&rate) {
+                Ok(rate) => Some((code, rate)),
+                Err(err) => {
+                    warn!(
+                        "Ignoring invalid stored exchange rate for {}: {}",
+                        code, err
+                    );
+                    None
+                }
+            })
+            .collect();
+        debug!("Loaded {} exchange rates", exchange_rates.len());
+
+        let fee_schedule: Option<String> = connection
+            .get(FEES_KEY)
+            .map_err(|err| error!("Error loading fee schedule: {:?}", err))
+            .await?;
+        let fee_schedule: FeeSchedule = match fee_schedule {
+            Some(schedule) => serde_json::from_str(&schedule)
+                .map_err(|err| error!("Invalid stored fee schedule: {}", err))?,
+            None => FeeSchedule::default(),
+        };
+
         let (all_payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
+        let (rate_publisher, _) = broadcast::channel(16);
+        let (route_policy_publisher, _) = broadcast::channel(16);
 
         let store = RedisStore {
             ilp_address: Arc::new(RwLock::new(node_ilp_address)),
             connection,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
             payment_publisher: all_payment_publisher,
-            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            instance_id: Uuid::new_v4(),
+            exchange_rates: Arc::new(RwLock::new(exchange_rates)),
+            rate_publisher,
+            route_policy_publisher,
+            fee_schedule: Arc::new(RwLock::new(Arc::new(fee_schedule))),
             routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            alternate_routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
+            multipath_routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
             encryption_key: Arc::new(encryption_key),
             decryption_key: Arc::new(decryption_key),
         };
@@ -229,6 +423,8 @@ impl RedisStoreBuilder {
         let connection_clone = Arc::downgrade(&store.connection.conn);
         let redis_info = store.connection.redis_info.clone();
         let routing_table = store.routes.clone();
+        let alternate_routes = store.alternate_routes.clone();
+        let multipath_routes = store.multipath_routes.clone();
 
         let poll_routes = async move {
             let mut interval = tokio::time::interval(Duration::from_millis(poll_interval));
@@ -236,15 +432,19 @@ impl RedisStoreBuilder {
             loop {
                 interval.tick().await;
                 if let Some(conn) = connection_clone.upgrade() {
-                    let _ = update_routes(
-                        RedisReconnect {
-                            conn,
-                            redis_info: redis_info.clone(),
-                        },
-                        routing_table.clone(),
-                    )
-                    .map_err(|err| error!("{}", err))
-                    .await;
+                    let connection = RedisReconnect {
+                        conn,
+                        redis_info: redis_info.clone(),
+                    };
+                    let _ = update_routes(connection.clone(), routing_table.clone())
+                        .map_err(|err| error!("{}", err))
+                        .await;
+                    let _ = update_alternate_routes(connection.clone(), alternate_routes.clone())
+                        .map_err(|err| error!("{}", err))
+                        .await;
+                    let _ = update_multipath_routes(connection, multipath_routes.clone())
+                        .map_err(|err| error!("{}", err))
+                        .await;
                 } else {
                     debug!("Not polling routes anymore because connection was closed");
                     break;
@@ -260,6 +460,11 @@ impl RedisStoreBuilder {
         // not yet supporting asynchronous subscriptions (see https://github.com/mitsuhiko/redis-rs/issues/183).
         let subscriptions_clone = store.subscriptions.clone();
         let payment_publisher = store.payment_publisher.clone();
+        let exchange_rates = store.exchange_rates.clone();
+        let rate_publisher = store.rate_publisher.clone();
+        let fee_schedule = store.fee_schedule.clone();
+        let route_policy_publisher = store.route_policy_publisher.clone();
+        let instance_id = store.instance_id;
         std::thread::spawn(move || {
             #[allow(clippy::cognitive_complexity)]
             let sub_status =
@@ -291,6 +496,45 @@ impl RedisStoreBuilder {
                         } else {
                             error!("Invalid Uuid in channel name: {}", channel_name);
                         }
+                    } else if channel_name == RATES_CHANNEL {
+                        let update: SharedUpdate<HashMap<String, ExchangeRate>> = match serde_json::from_slice(msg.get_payload_bytes()) {
+                            Ok(update) => update,
+                            Err(e) => {
+                                error!("Failed to parse exchange rate update: {}", e);
+                                return ControlFlow::Continue;
+                            }
+                        };
+                        // This instance's rates were already updated when they were set
+                        if update.instance_id != instance_id {
+                            trace!("Received exchange rates set by another instance: {:?}", update.data);
+                            *exchange_rates.write() = update.data.clone();
+                            // Sending only fails if nobody is subscribed
+                            let _ = rate_publisher.send(Arc::new(update.data));
+                        }
+                    } else if channel_name == FEES_CHANNEL {
+                        let update: SharedUpdate<FeeSchedule> = match serde_json::from_slice(msg.get_payload_bytes()) {
+                            Ok(update) => update,
+                            Err(e) => {
+                                error!("Failed to parse fee schedule update: {}", e);
+                                return ControlFlow::Continue;
+                            }
+                        };
+                        if update.instance_id != instance_id {
+                            trace!("Received fee schedule set by another instance: {:?}", update.data);
+                            *fee_schedule.write() = Arc::new(update.data);
+                        }
+                    } else if channel_name == ROUTE_POLICIES_CHANNEL {
+                        let update: SharedUpdate<Uuid> = match serde_json::from_slice(msg.get_payload_bytes()) {
+                            Ok(update) => update,
+                            Err(e) => {
+                                error!("Failed to parse route policy update: {}", e);
+                                return ControlFlow::Continue;
+                            }
+                        };
+                        if update.instance_id != instance_id {
+                            trace!("Received route policy of account {} set by another instance", update.data);
+                            let _ = route_policy_publisher.send(update.data);
+                        }
                     } else {
                         warn!("Ignoring unexpected message from Redis subscription for channel: {}", channel_name);
                     }
@@ -310,8 +554,10 @@ impl RedisStoreBuilder {
 ///
 /// This store leverages atomic Redis transactions to do operations such as balance updates.
 ///
-/// Currently the RedisStore polls the database for the routing table and rate updates, but
+/// Currently the RedisStore polls the database for the routing table updates, but
 /// future versions of it will use PubSub to subscribe to updates.
+/// Exchange rates are persisted in the database and every update is published
+/// to the other instances sharing it, while reads are served from memory.
 #[derive(Clone)]
 pub struct RedisStore {
     /// The Store's ILP Address
@@ -322,7 +568,18 @@ pub struct RedisStore {
     subscriptions: Arc<RwLock<HashMap<Uuid, UnboundedSender<PaymentNotification>>>>,
     /// A subscriber to all payment notifications, exposed via a WebSocket
     payment_publisher: broadcast::Sender<PaymentNotification>,
-    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// Identifies this instance in the exchange rate and fee updates it publishes
+    instance_id: Uuid,
+    /// The store keeps the exchange rates in memory so that they can be returned
+    /// synchronously while packets are being converted. They are kept in sync
+    /// with the other instances sharing the database via PubSub.
+    exchange_rates: Arc<RwLock<HashMap<String, ExchangeRate>>>,
+    /// Publishes the exchange rates whenever they are set by this or another instance
+    rate_publisher: broadcast::Sender<Arc<HashMap<String, ExchangeRate>>>,
+    /// Publishes the ID of the accounts whose route policy is set by this or another instance
+    route_policy_publisher: broadcast::Sender<Uuid>,
+    /// The fee schedule is kept in memory and in sync the same way as the exchange rates
+    fee_schedule: Arc<RwLock<Arc<FeeSchedule>>>,
     /// The store keeps the routing table in memory so that it can be returned
     /// synchronously while the Router is processing packets.
     /// The outer `Arc<RwLock>` is used so that we can update the stored routing
@@ -330,6 +587,10 @@ pub struct RedisStore {
     /// The inner `Arc<HashMap>` is used so that the `routing_table` method can
     /// return a reference to the routing table without cloning the underlying data.
     routes: Arc<RwLock<Arc<HashMap<String, Uuid>>>>,
+    /// The alternate next hops of each prefix, kept in memory like the routing table
+    alternate_routes: Arc<RwLock<Arc<AlternateRouteTable>>>,
+    /// The members of the multipath static routes, kept in memory like the routing table
+    multipath_routes: Arc<RwLock<Arc<MultipathRouteTable>>>,
     /// Encryption Key so that the no cleartext data are stored
     encryption_key: Arc<Secret<EncryptionKey>>,
     /// Decryption Key to provide cleartext data to users
@@ -358,9 +619,6 @@ impl RedisStore {
         let mut pipe = redis_crate::pipe();
         pipe.exists(accounts_key(account.id));
         pipe.hexists("usernames", account.username().as_ref());
-        if account.routing_relation == RoutingRelation::Parent {
-            pipe.exists(PARENT_ILP_KEY);
-        }
 
         let results: Vec<bool> = pipe.query_async(&mut connection).await?;
         if results.iter().any(|val| *val) {
@@ -388,6 +646,13 @@ impl RedisStore {
         // Set balance-related details
         pipe.hset_multiple(&id, &[("balance", 0), ("prepaid_amount", 0)])
             .ignore();
+        // The settlement journal of the account is complete from the start
+        pipe.hset(
+            &id,
+            "reconciliation",
+            serde_json::to_string(&ReconciliationState::default()).unwrap(),
+        )
+        .ignore();
 
         if account.should_send_routes() {
             pipe.sadd("send_routes_to", RedisAccountId(account.id))
@@ -615,6 +880,9 @@ impl RedisStore {
             .ignore();
 
         pipe.del(uncredited_amount_key(id));
+        pipe.del(balance_alerts_key(id)).ignore();
+        pipe.del(remainders_key(id)).ignore();
+        pipe.hdel(ROUTE_POLICIES_KEY, RedisAccountId(id)).ignore();
 
         let mut connection = self.connection.clone();
         pipe.query_async(&mut connection).await?;
@@ -813,9 +1081,143 @@ impl BalanceStore for RedisStore {
     }
 }
 
+#[async_trait]
+impl BalanceAlertStore for RedisStore {
+    async fn save_balance_alert(&self, alert: BalanceAlert) -> Result<(), BalanceStoreError> {
+        let account_key = balance_alerts_key(alert.account_id);
+        let alert =
+            serde_json::to_string(&alert).map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for key in &[BALANCE_ALERTS_KEY, account_key.as_str()] {
+            pipe.lpush(*key, &alert).ignore();
+            pipe.ltrim(*key, 0, MAX_BALANCE_ALERTS - 1).ignore();
+        }
+        pipe.query_async(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    async fn get_balance_alerts(
+        &self,
+        account_id: Option<Uuid>,
+    ) -> Result<Vec<BalanceAlert>, BalanceStoreError> {
+        let key = match account_id {
+            Some(id) => balance_alerts_key(id),
+            None => BALANCE_ALERTS_KEY.to_string(),
+        };
+        let alerts: Vec<String> = self.connection.clone().lrange(key, 0, -1).await?;
+        alerts
+            .iter()
+            .map(|alert| {
+                serde_json::from_str(alert).map_err(|err| BalanceStoreError::Other(Box::new(err)))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RevenueStore for RedisStore {
+    async fn add_revenue(&self, revenue: Vec<RevenueEntry>) -> Result<(), RevenueStoreError> {
+        if revenue.is_empty() {
+            return Ok(());
+        }
+        let mut script = ADD_REVENUE.prepare_invoke();
+        for revenue in revenue.iter() {
+            script
+                .arg(revenue.bucket_start.timestamp_millis())
+                .arg(revenue_field_prefix(revenue))
+                .arg(revenue.amount.to_string())
+                .arg(revenue.reporting_amount.to_string())
+                .arg(revenue.packets.to_string());
+        }
+        let _: u64 = script.invoke_async(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    async fn remove_revenue_before(&self, before: DateTime<Utc>) -> Result<(), RevenueStoreError> {
+        let removed: u64 = REMOVE_REVENUE_BEFORE
+            .arg(before.timestamp_millis())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        if removed > 0 {
+            debug!(
+                "Removed the revenue of {} periods before {}",
+                removed, before
+            );
+        }
+        Ok(())
+    }
+
+    async fn get_revenue(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<RevenueEntry>, RevenueStoreError> {
+        let min = since.map_or("-inf".to_string(), |time| {
+            time.timestamp_millis().to_string()
+        });
+        let max = until.map_or("+inf".to_string(), |time| {
+            time.timestamp_millis().to_string()
+        });
+        let mut connection = self.connection.clone();
+        let buckets: Vec<i64> = connection
+            .zrangebyscore(REVENUE_BUCKETS_KEY, min, max)
+            .await?;
+        if buckets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipe = redis_crate::pipe();
+        for bucket_start in buckets.iter() {
+            pipe.hgetall(revenue_key(*bucket_start));
+        }
+        let totals: Vec<HashMap<String, u64>> = pipe.query_async(&mut connection).await?;
+
+        let mut revenue = Vec::new();
+        for (bucket_start, totals) in buckets.into_iter().zip(totals) {
+            let bucket_start = match Utc.timestamp_millis_opt(bucket_start).single() {
+                Some(bucket_start) => bucket_start,
+                None => continue,
+            };
+            let mut entries: BTreeMap<String, RevenueEntry> = BTreeMap::new();
+            for (field, total) in totals {
+                let (prefix, name) = match field.rfind('|') {
+                    Some(index) => (&field[..index], &field[index + 1..]),
+                    None => continue,
+                };
+                if !entries.contains_key(prefix) {
+                    match parse_revenue_field_prefix(bucket_start, prefix) {
+                        Some(entry) => {
+                            entries.insert(prefix.to_string(), entry);
+                        }
+                        None => {
+                            warn!("Ignoring invalid revenue field: {}", field);
+                            continue;
+                        }
+                    }
+                }
+                let entry = entries.get_mut(prefix).unwrap();
+                match name {
+                    "amount" => entry.amount = total,
+                    "reporting_amount" => entry.reporting_amount = total,
+                    "packets" => entry.packets = total,
+                    _ => warn!("Ignoring invalid revenue field: {}", field),
+                }
+            }
+            revenue.extend(entries.into_values());
+        }
+        Ok(revenue)
+    }
+}
+
+#[async_trait]
 impl ExchangeRateStore for RedisStore {
-    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
-        let rates: Vec<f64> = asset_codes
+    fn get_exchange_rates(
+        &self,
+        asset_codes: &[&str],
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
+        let rates: Vec<ExchangeRate> = asset_codes
             .iter()
             .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
             .collect();
@@ -830,16 +1232,149 @@ impl ExchangeRateStore for RedisStore {
         }
     }
 
-    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+    fn get_all_exchange_rates(
+        &self,
+    ) -> Result<HashMap<String, ExchangeRate>, ExchangeRateStoreError> {
         Ok((*self.exchange_rates.read()).clone())
     }
 
-    fn set_exchange_rates(
+    async fn set_exchange_rates(
         &self,
-        rates: HashMap<String, f64>,
+        rates: HashMap<String, ExchangeRate>,
     ) -> Result<(), ExchangeRateStoreError> {
-        // TODO publish rate updates through a pubsub mechanism to support horizontally scaling nodes
-        (*self.exchange_rates.write()) = rates;
+        let encoded: Vec<(String, String)> = rates
+            .iter()
+            .map(|(code, rate)| (code.clone(), serde_json::to_string(rate).unwrap()))
+            .collect();
+        let update = serde_json::to_string(&SharedUpdate {
+            instance_id: self.instance_id,
+            data: &rates,
+        })
+        .unwrap();
+
+        // The rates are persisted and shared with the other instances in a single
+        // transaction, so that successive updates are applied in the same order
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic().del(RATES_KEY).ignore();
+        if !encoded.is_empty() {
+            pipe.hset_multiple(RATES_KEY, &encoded).ignore();
+        }
+        for (code, rate) in encoded.iter() {
+            let key = rate_history_key(code);
+            pipe.lpush(&key, rate).ignore();
+            pipe.ltrim(&key, 0, MAX_RATE_HISTORY - 1).ignore();
+        }
+        pipe.publish(RATES_CHANNEL, update).ignore();
+        pipe.query_async(&mut self.connection.clone()).await?;
+        trace!("Saved and published exchange rates");
+
+        (*self.exchange_rates.write()) = rates.clone();
+        // Sending only fails if nobody is subscribed
+        let _ = self.rate_publisher.send(Arc::new(rates));
+        Ok(())
+    }
+}
+
+impl ExchangeRateSubscriptionStore for RedisStore {
+    fn subscribe_to_exchange_rates(
+        &self,
+    ) -> broadcast::Receiver<Arc<HashMap<String, ExchangeRate>>> {
+        self.rate_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl RateHistoryStore for RedisStore {
+    async fn get_rate_history(
+        &self,
+        asset_code: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
+        let rates: Vec<String> = self
+            .connection
+            .clone()
+            .lrange(rate_history_key(asset_code), 0, -1)
+            .await?;
+        // The history is stored newest first
+        let mut history = Vec::with_capacity(rates.len());
+        for rate in rates.iter().rev() {
+            let rate: ExchangeRate = serde_json::from_str(rate)
+                .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))?;
+            if since.iter().all(|since| rate.updated_at >= *since) {
+                history.push(rate);
+            }
+        }
+        Ok(history)
+    }
+}
+
+#[async_trait]
+impl FeeStore for RedisStore {
+    fn get_fee_schedule(&self) -> Arc<FeeSchedule> {
+        self.fee_schedule.read().clone()
+    }
+
+    async fn set_fee_schedule(&self, schedule: FeeSchedule) -> Result<(), FeeStoreError> {
+        let update = serde_json::to_string(&SharedUpdate {
+            instance_id: self.instance_id,
+            data: &schedule,
+        })
+        .unwrap();
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic()
+            .set(FEES_KEY, serde_json::to_string(&schedule).unwrap())
+            .ignore()
+            .publish(FEES_CHANNEL, update)
+            .ignore();
+        pipe.query_async(&mut self.connection.clone()).await?;
+        trace!("Saved and published fee schedule: {:?}", schedule);
+        *self.fee_schedule.write() = Arc::new(schedule);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RemainderStore for RedisStore {
+    async fn add_remainder(
+        &self,
+        from_account: Uuid,
+        to_account: Uuid,
+        remainder: u64,
+    ) -> Result<u64, RemainderStoreError> {
+        let units: u64 = ADD_REMAINDER
+            .arg(RedisAccountId(from_account))
+            .arg(RedisAccountId(to_account))
+            .arg(remainder)
+            .arg(REMAINDER_PRECISION)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        trace!(
+            "Added remainder of {} from account {} to account {}, took out {} units",
+            remainder,
+            from_account,
+            to_account,
+            units
+        );
+        Ok(units)
+    }
+
+    async fn revert_remainder(
+        &self,
+        from_account: Uuid,
+        to_account: Uuid,
+        remainder: u64,
+        units: u64,
+    ) -> Result<(), RemainderStoreError> {
+        let change = (units * REMAINDER_PRECISION) as i64 - remainder as i64;
+        let _: i64 = self
+            .connection
+            .clone()
+            .hincr(
+                remainders_key(from_account),
+                RedisAccountId(to_account),
+                change,
+            )
+            .await?;
         Ok(())
     }
 }
@@ -941,6 +1476,14 @@ impl RouterStore for RedisStore {
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
         self.routes.read().clone()
     }
+
+    fn alternate_routes(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+        self.alternate_routes.read().clone()
+    }
+
+    fn multipath_routes(&self) -> Arc<HashMap<String, Vec<(Uuid, u32)>>> {
+        self.multipath_routes.read().clone()
+    }
 }
 
 #[async_trait]
@@ -1062,64 +1605,154 @@ impl NodeStore for RedisStore {
     where
         R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
     {
-        let mut connection = self.connection.clone();
-        let routes: Vec<(String, RedisAccountId)> = routes
-            .into_iter()
-            .map(|(s, id)| (s, RedisAccountId(id)))
-            .collect();
-        let accounts: HashSet<_> =
-            HashSet::from_iter(routes.iter().map(|(_prefix, account_id)| account_id));
-        let mut pipe = redis_crate::pipe();
-        for account_id in accounts {
-            pipe.exists(accounts_key((*account_id).0));
-        }
+        self.set_static_routes_with_multipath(routes.into_iter().collect(), Vec::new())
+            .await
+    }
 
+    async fn set_static_route(
+        &self,
+        prefix: String,
+        account_id: Uuid,
+    ) -> Result<(), NodeStoreError> {
         let routing_table = self.routes.clone();
+        let mut connection = self.connection.clone();
 
-        let accounts_exist: Vec<bool> = pipe.query_async(&mut connection).await?;
-
-        if !accounts_exist.iter().all(|a| *a) {
-            error!("Error setting static routes because not all of the given accounts exist");
-            // TODO add proper error variant for "not all accoutns were found"
-            return Err(NodeStoreError::MissingAccounts);
+        let exists: bool = connection.exists(accounts_key(account_id)).await?;
+        if !exists {
+            error!(
+                "Cannot set static route for prefix: {} because account {} does not exist",
+                prefix, account_id
+            );
+            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
         }
 
         let mut pipe = redis_crate::pipe();
         pipe.atomic()
-            .del(STATIC_ROUTES_KEY)
+            .hset(STATIC_ROUTES_KEY, &prefix, RedisAccountId(account_id))
             .ignore()
-            .hset_multiple(STATIC_ROUTES_KEY, &routes)
+            .hdel(MULTIPATH_ROUTES_KEY, &prefix)
             .ignore();
-
         pipe.query_async(&mut connection).await?;
 
-        update_routes(connection, routing_table).await?;
+        update_routes(connection.clone(), routing_table).await?;
+        update_multipath_routes(connection, self.multipath_routes.clone()).await?;
+
         Ok(())
     }
 
-    async fn set_static_route(
+    async fn set_static_multipath_route(
         &self,
         prefix: String,
-        account_id: Uuid,
+        members: Vec<(Uuid, u32)>,
     ) -> Result<(), NodeStoreError> {
-        let routing_table = self.routes.clone();
         let mut connection = self.connection.clone();
+        // The routing table lists the member with the highest weight
+        let primary = match members.iter().max_by_key(|(_, weight)| *weight) {
+            Some((account_id, _)) => *account_id,
+            None => return Err(NodeStoreError::MissingAccounts),
+        };
 
-        let exists: bool = connection.exists(accounts_key(account_id)).await?;
-        if !exists {
+        let mut pipe = redis_crate::pipe();
+        for (account_id, _) in members.iter() {
+            pipe.exists(accounts_key(*account_id));
+        }
+        let accounts_exist: Vec<bool> = pipe.query_async(&mut connection).await?;
+        if !accounts_exist.iter().all(|a| *a) {
             error!(
-                "Cannot set static route for prefix: {} because account {} does not exist",
-                prefix, account_id
+                "Cannot set multipath static route for prefix: {} because not all of the given accounts exist",
+                prefix
             );
-            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+            return Err(NodeStoreError::MissingAccounts);
         }
 
-        connection
-            .hset(STATIC_ROUTES_KEY, prefix, RedisAccountId(account_id))
-            .await?;
+        let members: Vec<(RedisAccountId, u32)> = members
+            .into_iter()
+            .map(|(account_id, weight)| (RedisAccountId(account_id), weight))
+            .collect();
+        let members =
+            serde_json::to_string(&members).map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic()
+            .hset(STATIC_ROUTES_KEY, &prefix, RedisAccountId(primary))
+            .ignore()
+            .hset(MULTIPATH_ROUTES_KEY, &prefix, members)
+            .ignore();
+        pipe.query_async(&mut connection).await?;
+        debug!("Set multipath static route for prefix: {}", prefix);
 
-        update_routes(connection, routing_table).await?;
+        update_routes(connection.clone(), self.routes.clone()).await?;
+        update_multipath_routes(connection, self.multipath_routes.clone()).await?;
+        Ok(())
+    }
 
+    async fn set_static_routes_with_multipath(
+        &self,
+        routes: Vec<(String, Uuid)>,
+        multipath_routes: Vec<(String, Vec<(Uuid, u32)>)>,
+    ) -> Result<(), NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let accounts: HashSet<Uuid> = routes
+            .iter()
+            .map(|(_prefix, account_id)| *account_id)
+            .chain(
+                multipath_routes
+                    .iter()
+                    .flat_map(|(_prefix, members)| members.iter().map(|(id, _weight)| *id)),
+            )
+            .collect();
+        let mut pipe = redis_crate::pipe();
+        for account_id in accounts {
+            pipe.exists(accounts_key(account_id));
+        }
+        let accounts_exist: Vec<bool> = pipe.query_async(&mut connection).await?;
+        if !accounts_exist.iter().all(|a| *a) {
+            error!("Error setting static routes because not all of the given accounts exist");
+            return Err(NodeStoreError::MissingAccounts);
+        }
+
+        let mut static_routes: Vec<(String, RedisAccountId)> = routes
+            .into_iter()
+            .map(|(prefix, account_id)| (prefix, RedisAccountId(account_id)))
+            .collect();
+        let mut encoded_multipath_routes: Vec<(String, String)> =
+            Vec::with_capacity(multipath_routes.len());
+        for (prefix, members) in multipath_routes {
+            // The routing table lists the member with the highest weight
+            let primary = match members.iter().max_by_key(|(_, weight)| *weight) {
+                Some((account_id, _)) => *account_id,
+                None => return Err(NodeStoreError::MissingAccounts),
+            };
+            let members: Vec<(RedisAccountId, u32)> = members
+                .into_iter()
+                .map(|(account_id, weight)| (RedisAccountId(account_id), weight))
+                .collect();
+            let members = serde_json::to_string(&members)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            static_routes.push((prefix.clone(), RedisAccountId(primary)));
+            encoded_multipath_routes.push((prefix, members));
+        }
+
+        // Replace all the routes in a single transaction, so that the routes are never
+        // seen without their multipath members
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic()
+            .del(STATIC_ROUTES_KEY)
+            .ignore()
+            .del(MULTIPATH_ROUTES_KEY)
+            .ignore();
+        if !static_routes.is_empty() {
+            pipe.hset_multiple(STATIC_ROUTES_KEY, &static_routes)
+                .ignore();
+        }
+        if !encoded_multipath_routes.is_empty() {
+            pipe.hset_multiple(MULTIPATH_ROUTES_KEY, &encoded_multipath_routes)
+                .ignore();
+        }
+        pipe.query_async(&mut connection).await?;
+        debug!("Set static routes");
+
+        update_routes(connection.clone(), self.routes.clone()).await?;
+        update_multipath_routes(connection, self.multipath_routes.clone()).await?;
         Ok(())
     }
 
@@ -1146,32 +1779,77 @@ impl NodeStore for RedisStore {
 
     async fn set_settlement_engines(
         &self,
-        asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
+        asset_to_engine_map: impl IntoIterator<Item = (String, SettlementEngineDetails)>
+            + Send
+            + 'async_trait,
     ) -> Result<(), NodeStoreError> {
         let mut connection = self.connection.clone();
-        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
-            .into_iter()
-            .map(|(asset_code, url)| (asset_code, url.to_string()))
-            .collect();
+        let mut asset_to_url_map: Vec<(String, String)> = Vec::new();
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        for (asset_code, engine) in asset_to_engine_map.into_iter() {
+            // The tokens are encrypted like the accounts' ones and keyed by the engine's
+            // URL, so accounts configured with that URL use them too. An engine
+            // configured without a token clears the one it previously had
+            let engine_url = engine.url.to_string();
+            match engine.auth_token {
+                Some(auth_token) => {
+                    let encrypted = encrypt_token(
+                        &self.encryption_key.expose_secret().0,
+                        auth_token.expose_secret().as_bytes(),
+                    );
+                    pipe.hset(
+                        SETTLEMENT_ENGINE_AUTH_TOKENS_KEY,
+                        &engine_url,
+                        encrypted.as_ref(),
+                    )
+                    .ignore();
+                }
+                None => {
+                    pipe.hdel(SETTLEMENT_ENGINE_AUTH_TOKENS_KEY, &engine_url)
+                        .ignore();
+                }
+            }
+            asset_to_url_map.push((asset_code, engine_url));
+        }
         debug!("Setting settlement engines to {:?}", asset_to_url_map);
-        connection
-            .hset_multiple(SETTLEMENT_ENGINES_KEY, &asset_to_url_map)
-            .await?;
+        if !asset_to_url_map.is_empty() {
+            pipe.hset_multiple(SETTLEMENT_ENGINES_KEY, &asset_to_url_map)
+                .ignore();
+        }
+        pipe.query_async(&mut connection).await?;
         Ok(())
     }
 
     async fn get_asset_settlement_engine(
         &self,
         asset_code: &str,
-    ) -> Result<Option<Url>, NodeStoreError> {
-        let url: Option<String> = self
-            .connection
-            .clone()
-            .hget(SETTLEMENT_ENGINES_KEY, asset_code)
-            .await?;
+    ) -> Result<Option<SettlementEngineDetails>, NodeStoreError> {
+        let mut connection = self.connection.clone();
+        let url: Option<String> = connection.hget(SETTLEMENT_ENGINES_KEY, asset_code).await?;
+        let auth_token: Option<Vec<u8>> = match url {
+            Some(ref url) => {
+                connection
+                    .hget(SETTLEMENT_ENGINE_AUTH_TOKENS_KEY, url)
+                    .await?
+            }
+            None => None,
+        };
+        let auth_token = auth_token.and_then(|encrypted| {
+            decrypt_token(&self.decryption_key.expose_secret().0, &encrypted)
+                .map_err(|_| {
+                    error!(
+                        "Unable to decrypt the settlement engine token of asset {}",
+                        asset_code
+                    )
+                })
+                .ok()
+                .and_then(|token| str::from_utf8(token.expose_secret()).map(String::from).ok())
+                .map(SecretString::new)
+        });
         if let Some(url) = url {
             match Url::parse(url.as_str()) {
-                Ok(url) => Ok(Some(url)),
+                Ok(url) => Ok(Some(SettlementEngineDetails { url, auth_token })),
                 Err(err) => {
                     error!(
                         "Settlement engine URL loaded from Redis was not a valid URL: {:?}",
@@ -1189,71 +1867,37 @@ impl NodeStore for RedisStore {
 #[async_trait]
 impl AddressStore for RedisStore {
     // Updates the ILP address of the store & iterates over all children and
-    // updates their ILP Address to match the new address.
+    // updates the ILP Address of those whose address was derived from the old one.
     async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
         debug!("Setting ILP address to: {}", ilp_address);
         let routing_table = self.routes.clone();
         let mut connection = self.connection.clone();
+        let old_ilp_address = self.ilp_address.read().clone();
 
-        // Set the ILP address we have in memory
-        (*self.ilp_address.write()) = ilp_address.clone();
-
-        // Save it to Redis
-        connection
-            .set(PARENT_ILP_KEY, ilp_address.as_bytes())
-            .await?;
-
-        let accounts = self.get_all_accounts().await?;
         // TODO: This can be an expensive operation if this function
         // gets called often. This currently only gets called when
-        // inserting a new parent account in the API. It'd be nice
+        // inserting a new parent account in the API or switching to
+        // another parent after the node's parent became unreachable. It'd be nice
         // if we could generate a child's ILP address on the fly,
         // instead of having to store the username appended to the
         // node's ilp address. Currently this is not possible, as
         // account.ilp_address() cannot access any state that exists
         // on the store.
-        let first_segment = ilp_address
-            .segments()
-            .rev()
-            .next()
-            .expect("address did not have a first segment, this should be impossible");
-        let mut pipe = redis_crate::pipe();
-        for account in &accounts {
-            // Update the address and routes of all children and non-routing accounts.
-            if account.routing_relation() != RoutingRelation::Parent
-                && account.routing_relation() != RoutingRelation::Peer
-            {
-                // remove the old route
-                pipe.hdel(ROUTES_KEY, account.ilp_address.as_bytes())
-                    .ignore();
-
-                // if the username of the account ends with the
-                // node's address, we're already configured so no
-                // need to append anything.
-                let new_ilp_address = if first_segment == account.username().to_string() {
-                    ilp_address.clone()
-                } else {
-                    ilp_address
-                        .with_suffix(account.username().as_bytes())
-                        .unwrap()
-                };
-                pipe.hset(
-                    accounts_key(account.id()),
-                    "ilp_address",
-                    new_ilp_address.as_bytes(),
-                )
-                .ignore();
-
-                pipe.hset(
-                    ROUTES_KEY,
-                    new_ilp_address.as_bytes(),
-                    RedisAccountId(account.id()),
-                )
-                .ignore();
-            }
-        }
+        // The script changes the node's address and the addresses derived from it at once,
+        // so that the children and their routes are never left with stale ones, even if
+        // accounts are added or changed in the meantime
+        let updated: u64 = SET_ILP_ADDRESS
+            .arg(ilp_address.as_bytes())
+            .arg(old_ilp_address.as_bytes())
+            .invoke_async(&mut connection)
+            .await?;
+        debug!(
+            "Updated the addresses of {} accounts from {} to {}",
+            updated, old_ilp_address, ilp_address
+        );
 
-        pipe.query_async(&mut connection.clone()).await?;
+        // Set the ILP address we have in memory
+        (*self.ilp_address.write()) = ilp_address;
         update_routes(connection, routing_table).await?;
         Ok(())
     }
@@ -1379,6 +2023,179 @@ impl CcpRoutingStore for RedisStore {
         update_routes(connection, self.routes.clone()).await?;
         Ok(())
     }
+
+    async fn set_alternate_routes(
+        &mut self,
+        routes: impl IntoIterator<Item = (String, Vec<Account>)> + Send + 'async_trait,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let routes: Vec<(String, String)> = routes
+            .into_iter()
+            .map(|(prefix, accounts)| {
+                let ids: Vec<RedisAccountId> = accounts
+                    .into_iter()
+                    .map(|account| RedisAccountId(account.id))
+                    .collect();
+                let ids = serde_json::to_string(&ids)
+                    .map_err(|err| CcpRoutingStoreError::Other(Box::new(err)))?;
+                Ok((prefix, ids))
+            })
+            .collect::<Result<_, CcpRoutingStoreError>>()?;
+        let mut connection = self.connection.clone();
+
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic().del(ALTERNATE_ROUTES_KEY).ignore();
+        if !routes.is_empty() {
+            pipe.hset_multiple(ALTERNATE_ROUTES_KEY, &routes).ignore();
+        }
+        pipe.query_async(&mut connection).await?;
+        trace!(
+            "Saved alternate routes for {} prefixes to Redis",
+            routes.len()
+        );
+
+        update_alternate_routes(connection, self.alternate_routes.clone()).await?;
+        Ok(())
+    }
+
+    async fn get_forwarding_table(
+        &self,
+    ) -> Result<Option<ForwardingTableState>, CcpRoutingStoreError> {
+        let mut connection = self.connection.clone();
+        let table_id: Option<Vec<u8>> = connection.get(FORWARDING_TABLE_ID_KEY).await?;
+        let table_id = match table_id {
+            Some(ref id) if id.len() == 16 => {
+                let mut table_id = [0; 16];
+                table_id.copy_from_slice(id);
+                table_id
+            }
+            _ => return Ok(None),
+        };
+
+        let updates: BTreeMap<u32, Vec<u8>> = connection.hgetall(FORWARDING_TABLE_LOG_KEY).await?;
+        Ok(Some(ForwardingTableState { table_id, updates }))
+    }
+
+    async fn save_forwarding_table_update(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        update: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let started: bool = SAVE_FORWARDING_UPDATE
+            .arg(&table_id[..])
+            .arg(epoch)
+            .arg(update)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        if started {
+            trace!("Discarded the saved updates of the previous forwarding routing table");
+        }
+        trace!(
+            "Saved the update of the forwarding routing table at epoch {}",
+            epoch
+        );
+        Ok(())
+    }
+
+    async fn compact_forwarding_table(
+        &mut self,
+        table_id: [u8; 16],
+        epoch: u32,
+        snapshot: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let compacted: bool = COMPACT_FORWARDING_LOG
+            .arg(&table_id[..])
+            .arg(epoch)
+            .arg(snapshot)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        if compacted {
+            trace!(
+                "Compacted the updates of the forwarding routing table up to epoch {}",
+                epoch
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RoutePolicyStore for RedisStore {
+    async fn get_route_policies(&self) -> Result<HashMap<Uuid, RoutePolicy>, CcpRoutingStoreError> {
+        let policies: HashMap<RedisAccountId, String> =
+            self.connection.clone().hgetall(ROUTE_POLICIES_KEY).await?;
+        policies
+            .into_iter()
+            .map(|(id, policy)| {
+                let policy = serde_json::from_str(&policy)
+                    .map_err(|err| CcpRoutingStoreError::Other(Box::new(err)))?;
+                Ok((id.0, policy))
+            })
+            .collect()
+    }
+
+    async fn set_route_policy(
+        &self,
+        account_id: Uuid,
+        policy: RoutePolicy,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let update = serde_json::to_string(&SharedUpdate {
+            instance_id: self.instance_id,
+            data: account_id,
+        })
+        .map_err(|err| CcpRoutingStoreError::Other(Box::new(err)))?;
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        if policy == RoutePolicy::default() {
+            pipe.hdel(ROUTE_POLICIES_KEY, RedisAccountId(account_id))
+                .ignore();
+        } else {
+            let policy = serde_json::to_string(&policy)
+                .map_err(|err| CcpRoutingStoreError::Other(Box::new(err)))?;
+            pipe.hset(ROUTE_POLICIES_KEY, RedisAccountId(account_id), policy)
+                .ignore();
+        }
+        pipe.publish(ROUTE_POLICIES_CHANNEL, update).ignore();
+        pipe.query_async(&mut self.connection.clone()).await?;
+        trace!("Set route policy of account {}: {:?}", account_id, policy);
+
+        // Sending only fails if nobody is subscribed
+        let _ = self.route_policy_publisher.send(account_id);
+        Ok(())
+    }
+
+    fn subscribe_to_route_policies(&self) -> broadcast::Receiver<Uuid> {
+        self.route_policy_publisher.subscribe()
+    }
+}
+
+#[async_trait]
+impl RouteDetailsStore for RedisStore {
+    async fn get_route_details(&self) -> Result<RoutingTableDetails, CcpRoutingStoreError> {
+        let details: Option<String> = self.connection.clone().get(ROUTE_DETAILS_KEY).await?;
+        match details {
+            Some(details) => serde_json::from_str(&details)
+                .map_err(|err| CcpRoutingStoreError::Other(Box::new(err))),
+            None => Ok(RoutingTableDetails::default()),
+        }
+    }
+
+    async fn set_route_details(
+        &self,
+        details: RoutingTableDetails,
+    ) -> Result<(), CcpRoutingStoreError> {
+        let serialized = serde_json::to_string(&details)
+            .map_err(|err| CcpRoutingStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .set(ROUTE_DETAILS_KEY, serialized)
+            .await?;
+        trace!(
+            "Saved the details of the routing tables with {} prefixes",
+            details.prefixes.len()
+        );
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -1587,6 +2404,164 @@ impl SettlementStore for RedisStore {
     }
 }
 
+#[async_trait]
+impl SettlementQueueStore for RedisStore {
+    async fn settlement_sent(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        SETTLEMENT_SENT
+            .arg(RedisAccountId(account_id))
+            .arg(amount)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn queue_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: &str,
+    ) -> Result<(), SettlementStoreError> {
+        QUEUE_SETTLEMENT
+            .arg(RedisAccountId(account_id))
+            .arg(amount)
+            .arg(idempotency_key)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        trace!(
+            "Queued settlement for account: {} of amount: {}",
+            account_id,
+            amount
+        );
+        Ok(())
+    }
+
+    async fn claim_queued_settlement(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<u64, SettlementStoreError> {
+        let amount: u64 = CLAIM_QUEUED_SETTLEMENT
+            .arg(RedisAccountId(account_id))
+            .arg(idempotency_key)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(amount)
+    }
+
+    async fn get_queued_settlements(
+        &self,
+    ) -> Result<Vec<(Uuid, String, u64)>, SettlementStoreError> {
+        // Queued settlements are stored as "account_id:idempotency_key"
+        let queued: HashMap<String, u64> = self
+            .connection
+            .clone()
+            .hgetall(QUEUED_SETTLEMENTS_KEY)
+            .await?;
+        Ok(queued
+            .into_iter()
+            .filter_map(|(settlement, amount)| {
+                let mut parts = settlement.splitn(2, ':');
+                let account_id = Uuid::from_str(parts.next()?).ok()?;
+                let idempotency_key = parts.next()?.to_string();
+                Some((account_id, idempotency_key, amount))
+            })
+            .collect())
+    }
+
+    async fn get_pending_settlement(&self, account_id: Uuid) -> Result<u64, SettlementStoreError> {
+        let (in_flight, queued): (Option<i64>, Option<i64>) = self
+            .connection
+            .clone()
+            .hget(
+                accounts_key(account_id),
+                &["settlement_in_flight", "settlement_queued"],
+            )
+            .await?;
+        Ok((in_flight.unwrap_or_default().max(0) + queued.unwrap_or_default().max(0)) as u64)
+    }
+}
+
+#[async_trait]
+impl SettlementJournalStore for RedisStore {
+    async fn get_settlement_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<SettlementTotals, SettlementStoreError> {
+        // Refunds decrement the outgoing total so it could only become negative
+        // if a settlement was refunded without having been accounted for
+        let (sent, received): (Option<i64>, Option<i64>) = self
+            .connection
+            .clone()
+            .hget(
+                accounts_key(account_id),
+                &["settled_outgoing", "settled_incoming"],
+            )
+            .await?;
+        Ok(SettlementTotals {
+            sent: sent.unwrap_or_default().max(0) as u64,
+            received: received.unwrap_or_default().max(0) as u64,
+        })
+    }
+
+    async fn get_reconciliation_state(
+        &self,
+        account_id: Uuid,
+    ) -> Result<Option<ReconciliationState>, SettlementStoreError> {
+        let state: Option<String> = self
+            .connection
+            .clone()
+            .hget(accounts_key(account_id), "reconciliation")
+            .await?;
+        match state {
+            Some(state) => {
+                Ok(Some(serde_json::from_str(&state).map_err(|err| {
+                    SettlementStoreError::Other(Box::new(err))
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_reconciliation_state(
+        &self,
+        account_id: Uuid,
+        state: ReconciliationState,
+    ) -> Result<(), SettlementStoreError> {
+        let state = serde_json::to_string(&state)
+            .map_err(|err| SettlementStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .hset(accounts_key(account_id), "reconciliation", state)
+            .await?;
+        Ok(())
+    }
+
+    async fn refund_unsent_settlement(
+        &self,
+        account_id: Uuid,
+        amount: u64,
+        idempotency_key: String,
+    ) -> Result<(), SettlementStoreError> {
+        let balance: i64 = REFUND_SETTLEMENT
+            .arg(RedisAccountId(account_id))
+            .arg(amount)
+            .arg(idempotency_key)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        trace!(
+            "Refunded unsent settlement for account: {} of amount: {}. Balance is now: {}",
+            account_id,
+            amount,
+            balance
+        );
+        Ok(())
+    }
+}
+
 // TODO: AmountWithScale is re-implemented on Interledger-Settlement. It'd be nice
 // if we could deduplicate this by extracting it to a separate crate which would make
 // logical sense
@@ -1769,6 +2744,8 @@ impl LeftoversStore for RedisStore {
 }
 
 type RouteVec = Vec<(String, RedisAccountId)>;
+type AlternateRouteTable = HashMap<String, Vec<Uuid>>;
+type MultipathRouteTable = HashMap<String, Vec<(Uuid, u32)>>;
 
 use futures::future::TryFutureExt;
 
@@ -1811,6 +2788,49 @@ async fn update_routes(
     Ok(())
 }
 
+async fn update_alternate_routes(
+    mut connection: RedisReconnect,
+    alternate_routes: Arc<RwLock<Arc<AlternateRouteTable>>>,
+) -> Result<(), RedisError> {
+    let routes: HashMap<String, String> = connection.hgetall(ALTERNATE_ROUTES_KEY).await?;
+    let routes =
+        HashMap::from_iter(routes.into_iter().filter_map(
+            |(prefix, ids)| match serde_json::from_str::<Vec<RedisAccountId>>(&ids) {
+                Ok(ids) => Some((prefix, ids.into_iter().map(|id| id.0).collect())),
+                Err(err) => {
+                    error!("Invalid alternate routes for prefix {}: {}", prefix, err);
+                    None
+                }
+            },
+        ));
+    *alternate_routes.write() = Arc::new(routes);
+    Ok(())
+}
+
+async fn update_multipath_routes(
+    mut connection: RedisReconnect,
+    multipath_routes: Arc<RwLock<Arc<MultipathRouteTable>>>,
+) -> Result<(), RedisError> {
+    let routes: HashMap<String, String> = connection.hgetall(MULTIPATH_ROUTES_KEY).await?;
+    let routes = HashMap::from_iter(routes.into_iter().filter_map(|(prefix, members)| {
+        match serde_json::from_str::<Vec<(RedisAccountId, u32)>>(&members) {
+            Ok(members) => Some((
+                prefix,
+                members
+                    .into_iter()
+                    .map(|(id, weight)| (id.0, weight))
+                    .collect(),
+            )),
+            Err(err) => {
+                error!("Invalid multipath route for prefix {}: {}", prefix, err);
+                None
+            }
+        }
+    }));
+    *multipath_routes.write() = Arc::new(routes);
+    Ok(())
+}
+
 // Uuid does not implement ToRedisArgs and FromRedisValue.
 // Rust does not allow implementing foreign traits on foreign data types.
 // As a result, we wrap Uuid in a local data type, and implement the necessary
@@ -1942,6 +2962,14 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "settlement_engine_url".write_redis_args(&mut rv);
             settlement_engine_url.as_str().write_redis_args(&mut rv);
         }
+        if let Some(percent) = account.credit_limit_alert_percent {
+            "credit_limit_alert_percent".write_redis_args(&mut rv);
+            percent.write_redis_args(&mut rv);
+        }
+        if let Some(delay) = account.settle_threshold_alert_delay {
+            "settle_threshold_alert_delay".write_redis_args(&mut rv);
+            delay.write_redis_args(&mut rv);
+        }
 
         debug_assert!(rv.len() <= ACCOUNT_DETAILS_FIELDS * 2);
         debug_assert!((rv.len() % 2) == 0);
@@ -2009,6 +3037,16 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                 packets_per_minute_limit: get_value_option("packets_per_minute_limit", &hash)?,
                 amount_per_minute_limit: get_value_option("amount_per_minute_limit", &hash)?,
                 settlement_engine_url: get_url_option("settlement_engine_url", &hash)?,
+                settlement_engine_auth_token: get_bytes_option(
+                    "settlement_engine_auth_token",
+                    &hash,
+                )?
+                .map(SecretBytesMut::from),
+                credit_limit_alert_percent: get_value_option("credit_limit_alert_percent", &hash)?,
+                settle_threshold_alert_delay: get_value_option(
+                    "settle_threshold_alert_delay",
+                    &hash,
+                )?,
             },
         })
     }
@@ -2080,4 +3118,26 @@ mod tests {
         .await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn escapes_revenue_field_separator() {
+        let entry = RevenueEntry {
+            bucket_start: Utc.timestamp_millis_opt(60_000).unwrap(),
+            from_account: Username::from_str("alice").unwrap(),
+            to_account: Username::from_str("bob").unwrap(),
+            asset_code: "X|Y%7C".to_string(),
+            asset_scale: 9,
+            amount: 0,
+            reporting_asset: "U|SD".to_string(),
+            reporting_scale: 6,
+            reporting_amount: 0,
+            packets: 0,
+        };
+        let prefix = revenue_field_prefix(&entry);
+        assert_eq!(prefix.matches('|').count(), 5);
+        assert_eq!(
+            parse_revenue_field_prefix(entry.bucket_start, &prefix),
+            Some(entry)
+        );
+    }
 }
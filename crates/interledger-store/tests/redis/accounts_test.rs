@@ -139,21 +139,42 @@ async fn update_ilp_and_children_addresses() {
 }
 
 #[tokio::test]
-async fn only_one_parent_allowed() {
+async fn only_updates_derived_children_addresses() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let mut configured = ACCOUNT_DETAILS_2.clone();
+    configured.routing_relation = Some("Child".to_owned());
+    configured.ilp_address = Some(Address::from_str("example.elsewhere.charlie").unwrap());
+    let configured = store.insert_account(configured).await.unwrap();
+
+    let ilp_address = Address::from_str("test.parent.our_address").unwrap();
+    store.set_ilp_address(ilp_address.clone()).await.unwrap();
+    // Setting it again does not append the username twice
+    store.set_ilp_address(ilp_address.clone()).await.unwrap();
+
+    let accounts = store
+        .get_accounts(vec![accs[1].id(), configured.id()])
+        .await
+        .unwrap();
+    assert_eq!(
+        *accounts[0].ilp_address(),
+        ilp_address.with_suffix(b"bob").unwrap()
+    );
+    assert_eq!(
+        *accounts[1].ilp_address(),
+        Address::from_str("example.elsewhere.charlie").unwrap()
+    );
+}
+
+#[tokio::test]
+async fn multiple_parents_allowed() {
     let mut acc = ACCOUNT_DETAILS_2.clone();
     acc.routing_relation = Some("Parent".to_owned());
     acc.username = Username::from_str("another_name").unwrap();
     acc.ilp_address = Some(Address::from_str("example.another_name").unwrap());
     let (store, _context, accs) = test_store().await.unwrap();
-    let res = store.insert_account(acc.clone()).await;
-    // This should fail
-    assert!(res.is_err());
-    store.delete_account(accs[0].id()).await.unwrap();
-    // must also clear the ILP Address to indicate that we no longer
-    // have a parent account configured
-    store.clear_ilp_address().await.unwrap();
-    let res = store.insert_account(acc).await;
-    assert!(res.is_ok());
+    assert_eq!(accs[0].routing_relation(), RoutingRelation::Parent);
+    let parent = store.insert_account(acc).await.unwrap();
+    assert_eq!(parent.routing_relation(), RoutingRelation::Parent);
 }
 
 #[tokio::test]
@@ -4,7 +4,8 @@ use interledger_api::NodeStore;
 use interledger_packet::Address;
 use interledger_service::Account as AccountTrait;
 use interledger_service::{AccountStore, Username};
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceAlert, BalanceAlertKind, BalanceAlertStore, BalanceStore};
+use interledger_store::account::Account;
 use redis_crate::AsyncCommands;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -336,3 +337,36 @@ async fn netting_fulfilled_balances() {
     assert_eq!(balance0, -20);
     assert_eq!(balance1, 20);
 }
+
+#[tokio::test]
+async fn saves_and_loads_balance_alerts() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let alert = |account: &Account, balance: i64| BalanceAlert {
+        account_id: account.id(),
+        username: account.username().clone(),
+        kind: BalanceAlertKind::CreditLimit,
+        balance,
+        threshold: -800,
+        timestamp: "2020-01-01T00:00:00+00:00".to_string(),
+    };
+    store
+        .save_balance_alert(alert(&accs[0], -900))
+        .await
+        .unwrap();
+    store
+        .save_balance_alert(alert(&accs[1], -850))
+        .await
+        .unwrap();
+    store
+        .save_balance_alert(alert(&accs[0], -950))
+        .await
+        .unwrap();
+
+    let alerts = store.get_balance_alerts(Some(accs[0].id())).await.unwrap();
+    assert_eq!(alerts, vec![alert(&accs[0], -950), alert(&accs[0], -900)]);
+
+    let alerts = store.get_balance_alerts(None).await.unwrap();
+    assert_eq!(alerts.len(), 3);
+    assert_eq!(alerts[0], alert(&accs[0], -950));
+    assert_eq!(alerts[1], alert(&accs[1], -850));
+}
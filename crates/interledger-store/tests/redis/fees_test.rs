@@ -0,0 +1,35 @@
+use super::store_helpers::*;
+
+use interledger_service_util::{FeeSchedule, FeeStore};
+use interledger_store::redis::RedisStoreBuilder;
+use serde_json::json;
+use std::time::Duration;
+
+#[tokio::test]
+async fn saves_and_shares_fee_schedule() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert!(store.get_fee_schedule().rules.is_empty());
+
+    let schedule: FeeSchedule = serde_json::from_value(json!([
+        {"same_asset": true, "spread": "0"},
+        {"from_account": "alice", "to_asset": "XYZ", "fixed_fee": 10, "min_fee": 20},
+    ]))
+    .unwrap();
+    store.set_fee_schedule(schedule.clone()).await.unwrap();
+    assert_eq!(*store.get_fee_schedule(), schedule);
+
+    // Instances sharing the store are notified of the new schedule
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(*other_store.get_fee_schedule(), schedule);
+
+    // New instances load the saved schedule
+    let new_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(*new_store.get_fee_schedule(), schedule);
+}
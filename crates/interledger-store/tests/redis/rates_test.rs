@@ -1,6 +1,12 @@
 use super::store_helpers::*;
 
-use interledger_rates::ExchangeRateStore;
+use chrono::Utc;
+use interledger_rates::{
+    ExchangeRate, ExchangeRateStore, ExchangeRateSubscriptionStore, Rate, RateHistoryStore,
+};
+use interledger_store::redis::RedisStoreBuilder;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[tokio::test]
 async fn set_rates() {
@@ -9,14 +15,112 @@ async fn set_rates() {
     assert!(rates.is_err());
     store
         .set_exchange_rates(
-            [("ABC".to_string(), 500.0), ("XYZ".to_string(), 0.005)]
+            [
+                ("ABC".to_string(), ExchangeRate::new(Rate::from(500), "api")),
+                (
+                    "XYZ".to_string(),
+                    ExchangeRate::new(Rate::from_str("0.005").unwrap(), "CoinCap"),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .await
+        .unwrap();
+
+    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].rate.to_string(), "0.005");
+    assert_eq!(rates[0].source, "CoinCap");
+    assert_eq!(rates[1].rate.to_string(), "500");
+    assert_eq!(rates[1].source, "api");
+}
+
+#[tokio::test]
+async fn persists_and_shares_rates() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let mut updates = store.subscribe_to_exchange_rates();
+    let mut other_updates = other_store.subscribe_to_exchange_rates();
+    store
+        .set_exchange_rates(
+            [("ABC".to_string(), ExchangeRate::new(Rate::from(2), "api"))]
                 .iter()
                 .cloned()
                 .collect(),
         )
+        .await
         .unwrap();
+    tokio::time::delay_for(Duration::from_millis(100)).await;
 
-    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
-    assert_eq!(rates[0].to_string(), "0.005");
-    assert_eq!(rates[1].to_string(), "500");
+    // Instances sharing the store are notified of the new rates
+    let rates = other_store.get_all_exchange_rates().unwrap();
+    assert_eq!(rates["ABC"].rate, Rate::from(2));
+    assert_eq!(rates["ABC"].source, "api");
+
+    // Subscribers of every instance are notified
+    assert_eq!(updates.recv().await.unwrap()["ABC"].rate, Rate::from(2));
+    assert_eq!(
+        other_updates.recv().await.unwrap()["ABC"].rate,
+        Rate::from(2)
+    );
+
+    // New instances load the saved rates
+    let new_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(
+        new_store.get_all_exchange_rates().unwrap(),
+        store.get_all_exchange_rates().unwrap()
+    );
+
+    // Clearing the rates clears them everywhere
+    store.set_exchange_rates(Default::default()).await.unwrap();
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert!(other_store.get_all_exchange_rates().unwrap().is_empty());
+    assert!(new_store.get_all_exchange_rates().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn keeps_rate_history() {
+    let (store, _context, _) = test_store().await.unwrap();
+    for rate in 1..=3 {
+        store
+            .set_exchange_rates(
+                [(
+                    "ABC".to_string(),
+                    ExchangeRate::new(Rate::from(rate), "api"),
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+            )
+            .await
+            .unwrap();
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+    }
+
+    let history = store.get_rate_history("ABC", None).await.unwrap();
+    let rates: Vec<Rate> = history.iter().map(|rate| rate.rate.clone()).collect();
+    assert_eq!(rates, vec![Rate::from(1), Rate::from(2), Rate::from(3)]);
+
+    let history = store
+        .get_rate_history("ABC", Some(history[1].updated_at))
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(store
+        .get_rate_history("ABC", Some(Utc::now()))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(store
+        .get_rate_history("XYZ", None)
+        .await
+        .unwrap()
+        .is_empty());
 }
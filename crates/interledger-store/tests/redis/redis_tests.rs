@@ -1,9 +1,12 @@
 mod accounts_test;
 mod balances_test;
 mod btp_test;
+mod fees_test;
 mod http_test;
 mod rate_limiting_test;
 mod rates_test;
+mod remainders_test;
+mod revenue_test;
 mod routing_test;
 mod settlement_test;
 
@@ -37,6 +40,8 @@ mod fixtures {
         amount_per_minute_limit: Some(1000),
         packets_per_minute_limit: Some(2),
         settlement_engine_url: Some("http://settlement.example".to_string()),
+        credit_limit_alert_percent: None,
+        settle_threshold_alert_delay: None,
     });
     pub static ACCOUNT_DETAILS_1: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
         ilp_address: None,
@@ -59,6 +64,8 @@ mod fixtures {
         amount_per_minute_limit: Some(1000),
         packets_per_minute_limit: Some(20),
         settlement_engine_url: None,
+        credit_limit_alert_percent: None,
+        settle_threshold_alert_delay: None,
     });
     pub static ACCOUNT_DETAILS_2: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
         ilp_address: None,
@@ -80,6 +87,8 @@ mod fixtures {
         amount_per_minute_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
+        credit_limit_alert_percent: None,
+        settle_threshold_alert_delay: None,
     });
 }
 
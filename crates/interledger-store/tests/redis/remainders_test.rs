@@ -0,0 +1,28 @@
+use super::store_helpers::*;
+
+use interledger_service_util::{RemainderStore, REMAINDER_PRECISION};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn accumulates_remainders() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+    let third = REMAINDER_PRECISION / 3 + 1;
+
+    assert_eq!(store.add_remainder(alice, bob, third).await.unwrap(), 0);
+    assert_eq!(store.add_remainder(alice, bob, third).await.unwrap(), 0);
+    // Remainders are kept separately for each pair of accounts
+    assert_eq!(store.add_remainder(bob, alice, third).await.unwrap(), 0);
+    assert_eq!(store.add_remainder(alice, bob, third).await.unwrap(), 1);
+
+    // The unit taken out is put back along with the remainder
+    store.revert_remainder(alice, bob, third, 1).await.unwrap();
+    assert_eq!(store.add_remainder(alice, bob, third).await.unwrap(), 1);
+    assert_eq!(
+        store
+            .add_remainder(alice, bob, REMAINDER_PRECISION - 2)
+            .await
+            .unwrap(),
+        1
+    );
+}
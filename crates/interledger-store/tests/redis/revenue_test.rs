@@ -0,0 +1,109 @@
+use super::store_helpers::*;
+
+use chrono::{DateTime, TimeZone, Utc};
+use interledger_service::Username;
+use interledger_service_util::{RevenueEntry, RevenueStore};
+use std::str::FromStr;
+
+fn time(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis).unwrap()
+}
+
+fn entry(bucket_start: i64, from: &str, amount: u64) -> RevenueEntry {
+    RevenueEntry {
+        bucket_start: time(bucket_start),
+        from_account: Username::from_str(from).unwrap(),
+        to_account: Username::from_str("bob").unwrap(),
+        asset_code: "XYZ".to_string(),
+        asset_scale: 9,
+        amount,
+        reporting_asset: "USD".to_string(),
+        reporting_scale: 6,
+        reporting_amount: amount * 2,
+        packets: 1,
+    }
+}
+
+#[tokio::test]
+async fn accumulates_revenue() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .add_revenue(vec![entry(60_000, "alice", 10), entry(60_000, "alice", 5)])
+        .await
+        .unwrap();
+    store
+        .add_revenue(vec![
+            entry(60_000, "charlie", 1),
+            entry(120_000, "alice", 7),
+        ])
+        .await
+        .unwrap();
+
+    let revenue = store.get_revenue(None, None).await.unwrap();
+    assert_eq!(revenue.len(), 3);
+    assert_eq!(revenue[0].from_account.to_string(), "alice");
+    assert_eq!(revenue[0].amount, 15);
+    assert_eq!(revenue[0].reporting_amount, 30);
+    assert_eq!(revenue[0].packets, 2);
+    assert_eq!(revenue[1].from_account.to_string(), "charlie");
+    assert_eq!(revenue[2].bucket_start, time(120_000));
+
+    let revenue = store.get_revenue(Some(time(100_000)), None).await.unwrap();
+    assert_eq!(revenue, vec![entry(120_000, "alice", 7)]);
+    let revenue = store.get_revenue(None, Some(time(100_000))).await.unwrap();
+    assert_eq!(revenue.len(), 2);
+}
+
+#[tokio::test]
+async fn keeps_asset_codes_with_separator() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut revenue = entry(60_000, "alice", 10);
+    revenue.asset_code = "X|Y".to_string();
+    store.add_revenue(vec![revenue.clone()]).await.unwrap();
+    assert_eq!(store.get_revenue(None, None).await.unwrap(), vec![revenue]);
+}
+
+#[tokio::test]
+async fn removes_expired_revenue() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .add_revenue(vec![
+            entry(60_000, "alice", 10),
+            entry(120_000, "alice", 7),
+            entry(180_000, "alice", 3),
+        ])
+        .await
+        .unwrap();
+    store.remove_revenue_before(time(120_000)).await.unwrap();
+    let revenue = store.get_revenue(None, None).await.unwrap();
+    assert_eq!(
+        revenue,
+        vec![entry(120_000, "alice", 7), entry(180_000, "alice", 3)]
+    );
+}
+
+#[tokio::test]
+async fn accumulates_amounts_larger_than_signed_integers() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut revenue = entry(60_000, "alice", u64::MAX - 10);
+    revenue.reporting_amount = 10_000_000_000_000_000_000;
+    store
+        .add_revenue(vec![revenue.clone(), entry(60_000, "alice", 5)])
+        .await
+        .unwrap();
+    let totals = store.get_revenue(None, None).await.unwrap();
+    assert_eq!(totals[0].amount, u64::MAX - 5);
+    assert_eq!(totals[0].reporting_amount, 10_000_000_000_000_000_010);
+    assert_eq!(totals[0].packets, 2);
+
+    // Totals which would overflow are capped instead of losing the whole batch
+    store
+        .add_revenue(vec![revenue, entry(60_000, "charlie", 1)])
+        .await
+        .unwrap();
+    let totals = store.get_revenue(None, None).await.unwrap();
+    assert_eq!(totals[0].amount, u64::MAX);
+    assert_eq!(totals[0].reporting_amount, u64::MAX);
+    assert_eq!(totals[0].packets, 3);
+    assert_eq!(totals[1].amount, 1);
+}
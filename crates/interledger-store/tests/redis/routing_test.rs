@@ -1,13 +1,20 @@
 use super::{fixtures::*, redis_helpers::*, store_helpers::*};
 
 use interledger_api::{AccountDetails, NodeStore};
-use interledger_ccp::CcpRoutingStore;
+use interledger_ccp::{
+    CcpRoutingStore, PeerTableDetails, RouteDetailsStore, RoutePolicy, RoutePolicyStore,
+    RoutingTableDetails,
+};
 use interledger_packet::Address;
 use interledger_router::RouterStore;
 use interledger_service::{Account as AccountTrait, AddressStore, Username};
 use interledger_store::{account::Account, redis::RedisStoreBuilder};
+use std::iter::FromIterator;
 use std::str::FromStr;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
 use uuid::Uuid;
 
 #[tokio::test]
@@ -52,6 +59,8 @@ async fn polls_for_route_updates() {
             amount_per_minute_limit: None,
             packets_per_minute_limit: None,
             settlement_engine_url: None,
+            credit_limit_alert_percent: None,
+            settle_threshold_alert_delay: None,
         })
         .await
         .unwrap();
@@ -170,6 +179,119 @@ async fn saves_routes_to_db() {
     assert_eq!(routes.len(), 3);
 }
 
+#[tokio::test]
+async fn saves_alternate_routes_to_db() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let account0_id = Uuid::new_v4();
+    let account1_id = Uuid::new_v4();
+    let account0 = Account::try_from(
+        account0_id,
+        ACCOUNT_DETAILS_0.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    let account1 = Account::try_from(
+        account1_id,
+        ACCOUNT_DETAILS_1.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+
+    store
+        .clone()
+        .set_alternate_routes(vec![
+            (
+                "example.a".to_string(),
+                vec![account1.clone(), account0.clone()],
+            ),
+            ("example.b".to_string(), vec![account0.clone()]),
+        ])
+        .await
+        .unwrap();
+    let routes = store.alternate_routes();
+    assert_eq!(routes["example.a"], vec![account1_id, account0_id]);
+    assert_eq!(routes["example.b"], vec![account0_id]);
+    assert_eq!(routes.len(), 2);
+
+    store
+        .clone()
+        .set_alternate_routes(vec![("example.b".to_string(), vec![account1])])
+        .await
+        .unwrap();
+    let routes = store.alternate_routes();
+    assert_eq!(routes["example.b"], vec![account1_id]);
+    assert_eq!(routes.len(), 1);
+}
+
+#[tokio::test]
+async fn saves_forwarding_table_updates() {
+    let (mut store, _context, _accs) = test_store().await.unwrap();
+    assert!(store.get_forwarding_table().await.unwrap().is_none());
+
+    store
+        .save_forwarding_table_update([1; 16], 0, vec![0, 0])
+        .await
+        .unwrap();
+    store
+        .save_forwarding_table_update([1; 16], 1, vec![1, 0])
+        .await
+        .unwrap();
+    let state = store.get_forwarding_table().await.unwrap().unwrap();
+    assert_eq!(state.table_id, [1; 16]);
+    assert_eq!(
+        state.updates,
+        BTreeMap::from_iter(vec![(0, vec![0, 0]), (1, vec![1, 0])])
+    );
+
+    // The updates up to the snapshot are replaced by it
+    store
+        .save_forwarding_table_update([1; 16], 2, vec![2, 0])
+        .await
+        .unwrap();
+    store
+        .compact_forwarding_table([1; 16], 1, vec![1, 1])
+        .await
+        .unwrap();
+    let state = store.get_forwarding_table().await.unwrap().unwrap();
+    assert_eq!(
+        state.updates,
+        BTreeMap::from_iter(vec![(1, vec![1, 1]), (2, vec![2, 0])])
+    );
+
+    // The updates of the previous table are discarded
+    store
+        .save_forwarding_table_update([2; 16], 0, vec![2, 0])
+        .await
+        .unwrap();
+    let state = store.get_forwarding_table().await.unwrap().unwrap();
+    assert_eq!(state.table_id, [2; 16]);
+    assert_eq!(state.updates, BTreeMap::from_iter(vec![(0, vec![2, 0])]));
+}
+
+#[tokio::test]
+async fn saves_route_details() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    assert_eq!(
+        store.get_route_details().await.unwrap(),
+        RoutingTableDetails::default()
+    );
+
+    let details = RoutingTableDetails {
+        routing_table_id: "01".repeat(16),
+        epoch: 3,
+        prefixes: Default::default(),
+        peers: vec![PeerTableDetails {
+            account_id: accs[0].id(),
+            username: accs[0].username().clone(),
+            routing_table_id: "02".repeat(16),
+            epoch: 5,
+            routes: 1,
+        }],
+    };
+    store.set_route_details(details.clone()).await.unwrap();
+    assert_eq!(store.get_route_details().await.unwrap(), details);
+}
+
 #[tokio::test]
 async fn adds_static_routes_to_redis() {
     let (store, context, accs) = test_store().await.unwrap();
@@ -229,6 +351,71 @@ async fn static_routes_override_others() {
     assert_eq!(routes.len(), 3);
 }
 
+#[tokio::test]
+async fn saves_multipath_static_routes() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .set_static_multipath_route(
+            "example.a".to_string(),
+            vec![(accs[0].id(), 1), (accs[1].id(), 3)],
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.routing_table()["example.a"], accs[1].id());
+    assert_eq!(
+        store.multipath_routes()["example.a"],
+        vec![(accs[0].id(), 1), (accs[1].id(), 3)]
+    );
+
+    assert!(store
+        .set_static_multipath_route(
+            "example.b".to_string(),
+            vec![(accs[0].id(), 1), (Uuid::new_v4(), 1)],
+        )
+        .await
+        .is_err());
+
+    // Setting a single next hop for the prefix replaces the multipath route
+    store
+        .set_static_route("example.a".to_string(), accs[0].id())
+        .await
+        .unwrap();
+    assert_eq!(store.routing_table()["example.a"], accs[0].id());
+    assert!(store.multipath_routes().is_empty());
+}
+
+#[tokio::test]
+async fn replaces_static_routes_with_multipath_routes() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .set_static_multipath_route(
+            "example.a".to_string(),
+            vec![(accs[0].id(), 1), (accs[1].id(), 3)],
+        )
+        .await
+        .unwrap();
+
+    store
+        .set_static_routes_with_multipath(
+            vec![("example.a".to_string(), accs[0].id())],
+            vec![(
+                "example.b".to_string(),
+                vec![(accs[0].id(), 2), (accs[1].id(), 1)],
+            )],
+        )
+        .await
+        .unwrap();
+    let routes = store.routing_table();
+    assert_eq!(routes["example.a"], accs[0].id());
+    assert_eq!(routes["example.b"], accs[0].id());
+    let multipath_routes = store.multipath_routes();
+    assert_eq!(multipath_routes.len(), 1);
+    assert_eq!(
+        multipath_routes["example.b"],
+        vec![(accs[0].id(), 2), (accs[1].id(), 1)]
+    );
+}
+
 #[tokio::test]
 async fn default_route() {
     let (store, _context, accs) = test_store().await.unwrap();
@@ -271,3 +458,33 @@ async fn returns_configured_routes_for_route_manager() {
     assert_eq!(configured["example.a"].id(), accs[0].id());
     assert_eq!(configured["example.b"].id(), accs[1].id());
 }
+
+#[tokio::test]
+async fn saves_route_policies() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let policy = RoutePolicy {
+        import_allow: vec!["example.a".to_string()],
+        export_deny: vec!["example.b".to_string()],
+        max_routes: Some(10),
+        ..RoutePolicy::default()
+    };
+    store
+        .set_route_policy(accs[0].id(), policy.clone())
+        .await
+        .unwrap();
+    let policies = store.get_route_policies().await.unwrap();
+    assert_eq!(policies.len(), 1);
+    assert_eq!(policies[&accs[0].id()], policy);
+
+    // Setting the default policy removes it
+    store
+        .set_route_policy(accs[0].id(), RoutePolicy::default())
+        .await
+        .unwrap();
+    assert!(store.get_route_policies().await.unwrap().is_empty());
+
+    // Deleting the account removes its policy too
+    store.set_route_policy(accs[1].id(), policy).await.unwrap();
+    store.delete_account(accs[1].id()).await.unwrap();
+    assert!(store.get_route_policies().await.unwrap().is_empty());
+}
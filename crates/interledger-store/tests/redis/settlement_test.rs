@@ -7,12 +7,17 @@ use interledger_service::{Account, AccountStore};
 use interledger_service_util::BalanceStore;
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
-    types::{LeftoversStore, SettlementAccount, SettlementStore},
+    reconciliation::{ReconciliationState, SettlementJournalStore, SettlementTotals},
+    types::{
+        LeftoversStore, SettlementAccount, SettlementEngineDetails, SettlementQueueStore,
+        SettlementStore,
+    },
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use redis_crate::cmd;
 use redis_crate::AsyncCommands;
+use secrecy::{ExposeSecret, SecretString};
 use url::Url;
 use uuid::Uuid;
 
@@ -105,6 +110,95 @@ async fn idempotent_settlement_calls() {
     assert_eq!(balance, 100);
 }
 
+#[tokio::test]
+async fn records_settlement_totals() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    assert_eq!(
+        store.get_settlement_totals(id).await.unwrap(),
+        SettlementTotals::default()
+    );
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    // Idempotent retries are not counted twice
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    store.refund_settlement(id, 30).await.unwrap();
+    assert_eq!(
+        store.get_settlement_totals(id).await.unwrap(),
+        SettlementTotals {
+            sent: 0,
+            received: 100,
+        }
+    );
+}
+
+#[tokio::test]
+async fn saves_reconciliation_state_and_refunds_once() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    // New accounts have a complete journal
+    assert_eq!(
+        store.get_reconciliation_state(id).await.unwrap(),
+        Some(ReconciliationState::default())
+    );
+    let state = ReconciliationState {
+        baseline: SettlementTotals {
+            sent: 10,
+            received: 20,
+        },
+        unconfirmed_refund: Some((30, 0)),
+    };
+    store.set_reconciliation_state(id, state).await.unwrap();
+    assert_eq!(
+        store.get_reconciliation_state(id).await.unwrap(),
+        Some(state)
+    );
+
+    store
+        .refund_unsent_settlement(id, 30, "reconciliation:test".to_string())
+        .await
+        .unwrap();
+    store
+        .refund_unsent_settlement(id, 30, "reconciliation:test".to_string())
+        .await
+        .unwrap();
+    assert_eq!(store.get_balance(id).await.unwrap(), 30);
+}
+
+#[tokio::test]
+async fn queues_and_claims_settlements() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    store.queue_settlement(id, 100, "a").await.unwrap();
+    store.queue_settlement(id, 50, "b").await.unwrap();
+    // Each settlement keeps the idempotency key it is retried with
+    let mut queued = store.get_queued_settlements().await.unwrap();
+    queued.sort();
+    assert_eq!(
+        queued,
+        vec![(id, "a".to_string(), 100), (id, "b".to_string(), 50)]
+    );
+    assert_eq!(store.get_pending_settlement(id).await.unwrap(), 150);
+
+    // The claimed amount is in flight until the engine accepts it
+    assert_eq!(store.claim_queued_settlement(id, "a").await.unwrap(), 100);
+    assert_eq!(store.claim_queued_settlement(id, "a").await.unwrap(), 0);
+    assert_eq!(store.get_queued_settlements().await.unwrap().len(), 1);
+    assert_eq!(store.get_pending_settlement(id).await.unwrap(), 150);
+    store.settlement_sent(id, 100).await.unwrap();
+    assert_eq!(store.get_pending_settlement(id).await.unwrap(), 50);
+
+    // Refunds are no longer pending either
+    assert_eq!(store.claim_queued_settlement(id, "b").await.unwrap(), 50);
+    store.refund_settlement(id, 50).await.unwrap();
+    assert_eq!(store.get_pending_settlement(id).await.unwrap(), 0);
+}
+
 #[tokio::test]
 async fn credits_prepaid_amount() {
     let (store, context, accs) = test_store().await.unwrap();
@@ -205,11 +299,11 @@ async fn loads_globally_configured_settlement_engine_url() {
         .set_settlement_engines(vec![
             (
                 "ABC".to_string(),
-                Url::parse("http://settle-abc.example").unwrap(),
+                SettlementEngineDetails::new(Url::parse("http://settle-abc.example").unwrap()),
             ),
             (
                 "XYZ".to_string(),
-                Url::parse("http://settle-xyz.example").unwrap(),
+                SettlementEngineDetails::new(Url::parse("http://settle-xyz.example").unwrap()),
             ),
         ])
         .await
@@ -236,3 +330,67 @@ async fn loads_globally_configured_settlement_engine_url() {
         "http://settle-abc.example/"
     );
 }
+
+#[tokio::test]
+async fn loads_globally_configured_settlement_engine_auth_token() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let engine = |url: &str| SettlementEngineDetails {
+        url: Url::parse(url).unwrap(),
+        auth_token: Some(SecretString::new(format!("{}-token", url))),
+    };
+    store
+        .set_settlement_engines(vec![
+            ("ABC".to_string(), engine("http://settle-abc.example")),
+            ("XYZ".to_string(), engine("http://settlement.example")),
+        ])
+        .await
+        .unwrap();
+    let accounts = store
+        .get_accounts(vec![accs[0].id(), accs[1].id()])
+        .await
+        .unwrap();
+    // The account with its own engine gets the token configured for that engine's URL
+    // rather than the one of its asset's engine
+    assert_eq!(
+        accounts[0]
+            .settlement_engine_details()
+            .unwrap()
+            .auth_token
+            .unwrap()
+            .expose_secret(),
+        "http://settlement.example-token"
+    );
+    assert_eq!(
+        accounts[1]
+            .settlement_engine_details()
+            .unwrap()
+            .auth_token
+            .unwrap()
+            .expose_secret(),
+        "http://settle-abc.example-token"
+    );
+    let engine = store
+        .get_asset_settlement_engine("ABC")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        engine.auth_token.unwrap().expose_secret(),
+        "http://settle-abc.example-token"
+    );
+
+    // Configuring the engine without a token removes it
+    store
+        .set_settlement_engines(vec![(
+            "ABC".to_string(),
+            SettlementEngineDetails::new(Url::parse("http://settle-abc.example").unwrap()),
+        )])
+        .await
+        .unwrap();
+    let accounts = store.get_accounts(vec![accs[1].id()]).await.unwrap();
+    assert!(accounts[0]
+        .settlement_engine_details()
+        .unwrap()
+        .auth_token
+        .is_none());
+}
@@ -9,13 +9,12 @@ use interledger_packet::{
     Address, ErrorClass, ErrorCode as IlpErrorCode, PacketType as IlpPacketType, PrepareBuilder,
     Reject,
 };
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, Rate};
 use interledger_service::*;
 use num::rational::BigRational;
 use num::traits::cast::{FromPrimitive, ToPrimitive};
 use num::traits::identities::{One, Zero};
 use num::traits::ops::checked::CheckedDiv;
-use num::traits::pow::pow;
 use num::BigInt;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
@@ -667,9 +666,6 @@ where
     }
 }
 
-// TODO Abstract duplicated conversion logic from interledger-settlement &
-//      exchange rate service into interledger-rates
-
 /// Calculate the scaled rate between the source and destination assets,
 /// fetching from the provider's exchange rates, subtracting slippage, and adjusting scales.
 /// Returns None if destination asset details are unknown or rate cannot be calculated.
@@ -686,25 +682,20 @@ fn get_rate<S: ExchangeRateStore>(
     let dest_scale = dest_scale?;
 
     // Fetch the exchange rate
-    let rate: BigRational = if source_code == dest_code {
-        BigRational::one()
+    let rate = if source_code == dest_code {
+        Rate::one()
     } else if let Ok(prices) = store.get_exchange_rates(&[&source_code, &dest_code]) {
-        BigRational::from_f64(prices[0])? / BigRational::from_f64(prices[1])?
+        prices[0].rate.checked_div(&prices[1].rate)?
     } else {
         return None;
     };
 
     // Subtract slippage from rate
-    let slippage = BigRational::from_f64(slippage)?;
-    let mut rate = rate * (BigRational::one() - slippage);
-
-    // Scale rate based on source scale
-    rate /= pow(BigRational::from_u64(10)?, source_scale as usize);
-
-    // Scale rate based on destination scale
-    rate *= pow(BigRational::from_u64(10)?, dest_scale as usize);
+    let slippage = Rate::from_f64(slippage)?;
+    let rate = rate * (Rate::one() - slippage);
 
-    Some(rate)
+    // Scale rate based on source and destination scales
+    Some(rate.scaled(source_scale, dest_scale).into_ratio())
 }
 
 /// Convert the given source amount into a destination amount
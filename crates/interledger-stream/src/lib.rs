@@ -27,13 +27,19 @@ pub use server::{
 pub mod test_helpers {
     use super::*;
     use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
     use futures::channel::mpsc::UnboundedSender;
-    use interledger_errors::{AccountStoreError, AddressStoreError, ExchangeRateStoreError};
+    use interledger_errors::{
+        AccountStoreError, AddressStoreError, ExchangeRateStoreError, FeeStoreError,
+        RemainderStoreError, RevenueStoreError,
+    };
     use interledger_packet::Address;
-    use interledger_rates::ExchangeRateStore;
+    use interledger_rates::{ExchangeRate, ExchangeRateStore, Rate};
     use interledger_router::RouterStore;
     use interledger_service::{Account, AccountStore, AddressStore, Username};
-    use interledger_service_util::MaxPacketAmountAccount;
+    use interledger_service_util::{
+        FeeSchedule, FeeStore, MaxPacketAmountAccount, RemainderStore, RevenueEntry, RevenueStore,
+    };
     use once_cell::sync::Lazy;
     use std::collections::HashMap;
     use std::iter::FromIterator;
@@ -142,6 +148,14 @@ pub mod test_helpers {
                 .into_iter(),
             ))
         }
+
+        fn alternate_routes(&self) -> Arc<HashMap<String, Vec<Uuid>>> {
+            Arc::new(HashMap::new())
+        }
+
+        fn multipath_routes(&self) -> Arc<HashMap<String, Vec<(Uuid, u32)>>> {
+            Arc::new(HashMap::new())
+        }
     }
 
     #[async_trait]
@@ -163,9 +177,15 @@ pub mod test_helpers {
 
     #[async_trait]
     impl ExchangeRateStore for TestStore {
-        fn get_exchange_rates(&self, codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        fn get_exchange_rates(
+            &self,
+            codes: &[&str],
+        ) -> Result<Vec<ExchangeRate>, ExchangeRateStoreError> {
             match (self.price_1, self.price_2) {
-                (Some(price_1), Some(price_2)) => Ok(vec![price_1, price_2]),
+                (Some(price_1), Some(price_2)) => Ok(vec![
+                    ExchangeRate::new(Rate::from_f64(price_1).unwrap(), "test"),
+                    ExchangeRate::new(Rate::from_f64(price_2).unwrap(), "test"),
+                ]),
                 _ => Err(ExchangeRateStoreError::PairNotFound {
                     from: codes[0].to_string(),
                     to: codes[1].to_string(),
@@ -173,17 +193,74 @@ pub mod test_helpers {
             }
         }
 
-        fn set_exchange_rates(
+        async fn set_exchange_rates(
             &self,
-            _rates: HashMap<String, f64>,
+            _rates: HashMap<String, ExchangeRate>,
         ) -> Result<(), ExchangeRateStoreError> {
             unimplemented!("Cannot set exchange rates")
         }
 
-        fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        fn get_all_exchange_rates(
+            &self,
+        ) -> Result<HashMap<String, ExchangeRate>, ExchangeRateStoreError> {
             unimplemented!("Cannot get all exchange rates")
         }
     }
+
+    #[async_trait]
+    impl RevenueStore for TestStore {
+        async fn add_revenue(&self, _revenue: Vec<RevenueEntry>) -> Result<(), RevenueStoreError> {
+            Ok(())
+        }
+
+        async fn remove_revenue_before(
+            &self,
+            _before: DateTime<Utc>,
+        ) -> Result<(), RevenueStoreError> {
+            Ok(())
+        }
+
+        async fn get_revenue(
+            &self,
+            _since: Option<DateTime<Utc>>,
+            _until: Option<DateTime<Utc>>,
+        ) -> Result<Vec<RevenueEntry>, RevenueStoreError> {
+            unimplemented!("Cannot get the revenue")
+        }
+    }
+
+    #[async_trait]
+    impl FeeStore for TestStore {
+        fn get_fee_schedule(&self) -> Arc<FeeSchedule> {
+            Arc::new(FeeSchedule::default())
+        }
+
+        async fn set_fee_schedule(&self, _schedule: FeeSchedule) -> Result<(), FeeStoreError> {
+            unimplemented!("Cannot set the fee schedule")
+        }
+    }
+
+    #[async_trait]
+    impl RemainderStore for TestStore {
+        async fn add_remainder(
+            &self,
+            _from_account: Uuid,
+            _to_account: Uuid,
+            _remainder: u64,
+        ) -> Result<u64, RemainderStoreError> {
+            Ok(0)
+        }
+
+        async fn revert_remainder(
+            &self,
+            _from_account: Uuid,
+            _to_account: Uuid,
+            _remainder: u64,
+            _units: u64,
+        ) -> Result<(), RemainderStoreError> {
+            Ok(())
+        }
+    }
 }
 
 #[cfg(test)]
@@ -193,6 +270,7 @@ mod send_money_to_receiver {
     use bytes::Bytes;
     use interledger_packet::Address;
     use interledger_packet::{ErrorCode, RejectBuilder};
+    use interledger_rates::Rate;
     use interledger_router::Router;
     use interledger_service::outgoing_service_fn;
     use interledger_service_util::ExchangeRateService;
@@ -303,7 +381,8 @@ mod send_money_to_receiver {
             }),
         );
 
-        let server = ExchangeRateService::new(0.02, store.clone(), server);
+        let server =
+            ExchangeRateService::new(Rate::from_str("0.02").unwrap(), store.clone(), server);
         let server = Router::new(store.clone(), server);
 
         let (destination_account, shared_secret) =